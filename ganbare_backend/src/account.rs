@@ -0,0 +1,461 @@
+use super::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Duration, UTC};
+
+/// The ways a user can withdraw from the study. Deleting removes the account and all the data;
+/// anonymizing removes the email address, the password and the audio recordings,
+/// but keeps the answers for the research data.
+pub const WITHDRAWALS: &'static [&'static str] = &["delete", "anonymize"];
+
+/// The user's own data, as it's packaged in `profile.json` of the data archive.
+#[derive(Debug, Serialize)]
+pub struct Profile {
+    pub exported: DateTime<UTC>,
+    pub user: User,
+    pub metrics: Option<UserMetrics>,
+    pub stats: Option<UserStats>,
+    pub groups: Vec<String>,
+    pub events: Vec<(String, EventExperience)>,
+    pub consents: Vec<(Consent, i32)>,
+}
+
+/// An asked item with the details of the question, exercise or word that was asked,
+/// and the answer, if there was one.
+#[derive(Debug, Serialize)]
+pub struct AnsweredItem {
+    pub item: PendingItem,
+    pub question: Option<(QAskedData, Option<QAnsweredData>)>,
+    pub exercise: Option<(EAskedData, Option<EAnsweredData>)>,
+    pub word: Option<(WAskedData, Option<WAnsweredData>)>,
+}
+
+fn profile(conn: &Connection, user_id: i32) -> Result<Profile> {
+    use schema::{user_metrics, user_stats, group_memberships, event_experiences, events};
+
+    let groups = user::all_groups(conn)?;
+    let mut memberships: Vec<GroupMembership> =
+        group_memberships::table.filter(group_memberships::user_id.eq(user_id))
+            .get_results(&**conn)?;
+    pseudonym::hide_anonymous(&mut memberships, &groups);
+
+    let experiences: Vec<(EventExperience, Event)> =
+        event_experiences::table.inner_join(events::table)
+            .filter(event_experiences::user_id.eq(user_id))
+            .order(event_experiences::event_init)
+            .get_results(&**conn)?;
+
+    Ok(Profile {
+           exported: UTC::now(),
+           user: user::get_user(conn, user_id)?,
+           metrics: user_metrics::table.filter(user_metrics::id.eq(user_id))
+               .get_result(&**conn)
+               .optional()?,
+           stats: user_stats::table.filter(user_stats::id.eq(user_id))
+               .get_result(&**conn)
+               .optional()?,
+           groups: memberships.iter()
+               .filter_map(|m| groups.iter().find(|g| g.id == m.group_id))
+               .map(|g| g.group_name.clone())
+               .collect(),
+           events: experiences.into_iter().map(|(exp, ev)| (ev.name, exp)).collect(),
+           consents: consent::get_consents(conn, user_id)?,
+       })
+}
+
+fn answered_items(conn: &Connection, user_id: i32) -> Result<Vec<AnsweredItem>> {
+    use schema::{pending_items, q_asked_data, q_answered_data, e_asked_data, e_answered_data,
+                 w_asked_data, w_answered_data};
+
+    let items: Vec<PendingItem> = pending_items::table.filter(pending_items::user_id.eq(user_id))
+        .order(pending_items::id)
+        .get_results(&**conn)?;
+
+    let mut answered = vec![];
+    for item in items {
+        let question = match q_asked_data::table.filter(q_asked_data::id.eq(item.id))
+                  .get_result::<QAskedData>(&**conn)
+                  .optional()? {
+            Some(asked) => {
+                let answer = q_answered_data::table.filter(q_answered_data::id.eq(item.id))
+                    .get_result(&**conn)
+                    .optional()?;
+                Some((asked, answer))
+            }
+            None => None,
+        };
+        let exercise = match e_asked_data::table.filter(e_asked_data::id.eq(item.id))
+                  .get_result::<EAskedData>(&**conn)
+                  .optional()? {
+            Some(asked) => {
+                let answer = e_answered_data::table.filter(e_answered_data::id.eq(item.id))
+                    .get_result(&**conn)
+                    .optional()?;
+                Some((asked, answer))
+            }
+            None => None,
+        };
+        let word = match w_asked_data::table.filter(w_asked_data::id.eq(item.id))
+                  .get_result::<WAskedData>(&**conn)
+                  .optional()? {
+            Some(asked) => {
+                let answer = w_answered_data::table.filter(w_answered_data::id.eq(item.id))
+                    .get_result(&**conn)
+                    .optional()?;
+                Some((asked, answer))
+            }
+            None => None,
+        };
+        answered.push(AnsweredItem {
+                          item: item,
+                          question: question,
+                          exercise: exercise,
+                          word: word,
+                      });
+    }
+    Ok(answered)
+}
+
+fn userdata(conn: &Connection, user_id: i32) -> Result<Vec<EventUserdata>> {
+    use schema::event_userdata;
+
+    Ok(event_userdata::table.filter(event_userdata::user_id.eq(user_id))
+           .order(event_userdata::id)
+           .get_results(&**conn)?)
+}
+
+/// The userdata entries that refer to the audio recordings of the user.
+fn recordings(conn: &Connection, user_id: i32) -> Result<Vec<EventUserdata>> {
+    use schema::event_userdata;
+
+    Ok(event_userdata::table.filter(event_userdata::user_id.eq(user_id))
+           .filter(event_userdata::key.like("quiz_%_rec_%"))
+           .get_results(&**conn)?)
+}
+
+fn append_json<T: serde::Serialize>(builder: &mut tar::Builder<Vec<u8>>,
+                                    name: &str,
+                                    value: &T)
+                                    -> Result<()> {
+    let json = serde_json::to_vec_pretty(value)
+        .chain_err(|| format!("Couldn't serialize {}!", name))?;
+    let mut header = tar::Header::new_gnu();
+    header.set_path(name)?;
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(UTC::now().timestamp() as u64);
+    header.set_cksum();
+    builder.append(&header, &json[..])?;
+    Ok(())
+}
+
+/// Packages the user's profile, answers, event userdata and audio recordings into a tar
+/// archive, and records the export in `account_requests`.
+pub fn export(conn: &Connection, user_id: i32, user_audio_dir: &Path) -> Result<Vec<u8>> {
+    use schema::account_requests;
+
+    let recordings: Vec<String> = recordings(conn, user_id)?.into_iter().map(|r| r.data).collect();
+    let archive = package(&profile(conn, user_id)?,
+                          &answered_items(conn, user_id)?,
+                          &userdata(conn, user_id)?,
+                          &recordings,
+                          user_audio_dir)?;
+
+    diesel::insert(&NewAccountRequest {
+                        user_id: user_id,
+                        action: "export",
+                        executed: Some(UTC::now()),
+                    }).into(account_requests::table)
+        .execute(&**conn)?;
+    info!("User {} downloaded their data.", user_id);
+
+    Ok(archive)
+}
+
+/// Builds the data archive. The recordings that are missing from the disk are skipped.
+fn package<P, A, U>(profile: &P,
+                    answers: &A,
+                    userdata: &U,
+                    recordings: &[String],
+                    user_audio_dir: &Path)
+                    -> Result<Vec<u8>>
+    where P: serde::Serialize,
+          A: serde::Serialize,
+          U: serde::Serialize
+{
+    let mut builder = tar::Builder::new(vec![]);
+
+    append_json(&mut builder, "profile.json", profile)?;
+    append_json(&mut builder, "answers.json", answers)?;
+    append_json(&mut builder, "event_userdata.json", userdata)?;
+
+    for rec in recordings {
+        let path = user_audio_dir.join(rec);
+        if path.is_file() {
+            builder.append_path_with_name(&path, format!("recordings/{}", rec))
+                .chain_err(|| format!("Couldn't add {:?} to the archive!", path))?;
+        } else {
+            warn!("The recording {:?} doesn't exist!", path);
+        }
+    }
+
+    Ok(builder.into_inner()?)
+}
+
+/// The pending deletion or anonymization request of the user:
+/// one that is neither cancelled nor executed yet.
+pub fn open_request(conn: &Connection, user_id: i32) -> Result<Option<AccountRequest>> {
+    use schema::account_requests;
+
+    Ok(account_requests::table.filter(account_requests::user_id.eq(user_id))
+           .filter(account_requests::action.ne("export"))
+           .filter(account_requests::cancelled.is_null())
+           .filter(account_requests::executed.is_null())
+           .order(account_requests::requested.desc())
+           .first(&**conn)
+           .optional()?)
+}
+
+/// Starts a deletion or anonymization request. The request doesn't do anything before
+/// it's confirmed. A new request replaces the earlier open request.
+pub fn request(conn: &Connection, user_id: i32, action: &str) -> Result<AccountRequest> {
+    use schema::account_requests;
+
+    if !WITHDRAWALS.contains(&action) {
+        bail!(ErrorKind::InvalidInput);
+    }
+
+    cancel(conn, user_id)?;
+
+    let request: AccountRequest = diesel::insert(&NewAccountRequest {
+                                                      user_id: user_id,
+                                                      action: action,
+                                                      executed: None,
+                                                  }).into(account_requests::table)
+            .get_result(&**conn)?;
+    info!("User {} requested {} of their account (request {}).",
+          user_id,
+          action,
+          request.id);
+
+    Ok(request)
+}
+
+/// Confirms the open request. It's executed after the grace period, unless it's cancelled
+/// before that. Returns `None` if there is no unconfirmed request.
+pub fn confirm(conn: &Connection,
+               user_id: i32,
+               grace_period: Duration)
+               -> Result<Option<AccountRequest>> {
+
+    let mut request = match open_request(conn, user_id)? {
+        Some(ref r) if r.confirmed.is_some() => return Ok(None),
+        Some(r) => r,
+        None => return Ok(None),
+    };
+
+    let now = UTC::now();
+    request.confirmed = Some(now);
+    request.due = Some(now + grace_period);
+    let request: AccountRequest = request.save_changes(&**conn)?;
+    info!("User {} confirmed the request {}; {} is due at {:?}.",
+          user_id,
+          request.id,
+          request.action,
+          request.due);
+
+    Ok(Some(request))
+}
+
+/// Cancels the open request, confirmed or not.
+pub fn cancel(conn: &Connection, user_id: i32) -> Result<Option<AccountRequest>> {
+
+    let mut request = try_or!(open_request(conn, user_id)?, else return Ok(None));
+
+    request.cancelled = Some(UTC::now());
+    let request: AccountRequest = request.save_changes(&**conn)?;
+    info!("The request {} ({} of user {}) was cancelled.",
+          request.id,
+          request.action,
+          user_id);
+
+    Ok(Some(request))
+}
+
+/// All the requests, for the admins to see.
+pub fn get_all(conn: &Connection) -> Result<Vec<AccountRequest>> {
+    use schema::account_requests;
+
+    Ok(account_requests::table.order(account_requests::requested.desc())
+           .get_results(&**conn)?)
+}
+
+/// Removes the userdata entries that refer to the audio recordings of the user.
+/// Returns the paths of the recordings; the files are to be removed with `remove_files`
+/// only after the transaction has been committed, so that a rollback can't leave
+/// the entries pointing to files that are gone.
+fn delete_recordings(conn: &Connection,
+                     user_id: i32,
+                     user_audio_dir: &Path)
+                     -> Result<Vec<PathBuf>> {
+    use schema::event_userdata;
+
+    let recs = recordings(conn, user_id)?;
+    for rec in &recs {
+        diesel::delete(event_userdata::table.filter(event_userdata::id.eq(rec.id)))
+            .execute(&**conn)?;
+    }
+    Ok(recs.iter().map(|rec| user_audio_dir.join(&rec.data)).collect())
+}
+
+/// Removes the files. The ones that are already gone are skipped. Returns the files
+/// that couldn't be removed.
+fn remove_files(paths: &[PathBuf]) -> Vec<(PathBuf, io::Error)> {
+    paths.iter()
+        .filter_map(|path| match fs::remove_file(path) {
+                        Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+                        Err(e) => Some((path.clone(), e)),
+                        Ok(()) => None,
+                    })
+        .collect()
+}
+
+fn delete_queued_mails(conn: &Connection, email: &str) -> Result<()> {
+    use schema::email_queue;
+
+    diesel::delete(email_queue::table.filter(email_queue::to_address.eq(email)))
+        .execute(&**conn)?;
+    Ok(())
+}
+
+/// Removes everything that identifies the user: the email address (also from the mail queue),
+/// the password, the sessions and the audio recordings. The answers and the events stay,
+/// referred to by the user id only. Returns the recording files to remove after committing.
+fn anonymize(conn: &Connection,
+             user_id: i32,
+             user_audio_dir: &Path)
+             -> Result<Option<(User, Vec<PathBuf>)>> {
+    use schema::{sessions, reset_email_secrets};
+
+    let files = delete_recordings(conn, user_id, user_audio_dir)?;
+
+    let user = try_or!(user::deactivate_user(conn, None, user_id)?, else return Ok(None));
+    if let Some(ref email) = user.email {
+        delete_queued_mails(conn, email)?;
+    }
+    diesel::delete(sessions::table.filter(sessions::user_id.eq(user_id))).execute(&**conn)?;
+    diesel::delete(reset_email_secrets::table.filter(reset_email_secrets::user_id.eq(user_id)))
+        .execute(&**conn)?;
+
+    Ok(Some((user, files)))
+}
+
+/// Removes the user with all of their data. Returns the recording files to remove
+/// after committing.
+fn delete(conn: &Connection,
+          user_id: i32,
+          user_audio_dir: &Path)
+          -> Result<Option<(User, Vec<PathBuf>)>> {
+
+    let files = delete_recordings(conn, user_id, user_audio_dir)?;
+
+    let user = try_or!(user::remove_user_completely(conn, user_id)?, else return Ok(None));
+    if let Some(ref email) = user.email {
+        delete_queued_mails(conn, email)?;
+    }
+
+    Ok(Some((user, files)))
+}
+
+/// Executes the confirmed requests whose grace period has passed.
+pub fn execute_due_requests(conn: &Connection,
+                            user_audio_dir: &Path)
+                            -> Result<Vec<AccountRequest>> {
+    use schema::account_requests;
+
+    let due: Vec<AccountRequest> = account_requests::table.filter(account_requests::due
+                                                                       .le(UTC::now()))
+        .filter(account_requests::cancelled.is_null())
+        .filter(account_requests::executed.is_null())
+        .get_results(&**conn)?;
+
+    let mut executed = vec![];
+    for request in due {
+        let (request, files) = conn.transaction(move || {
+                let mut request = request;
+                let files = match request.action.as_str() {
+                    "delete" => delete(conn, request.user_id, user_audio_dir)?,
+                    "anonymize" => anonymize(conn, request.user_id, user_audio_dir)?,
+                    _ => bail!(ErrorKind::InvalidInput),
+                }
+                    .map(|(_, files)| files)
+                    .unwrap_or_default();
+                request.executed = Some(UTC::now());
+                let request = request.save_changes::<AccountRequest>(&**conn)
+                    .chain_err(|| "Couldn't mark the request as executed!")?;
+                Ok((request, files))
+            })?;
+        for (path, e) in remove_files(&files) {
+            error!("Couldn't remove the recording {:?} of user {} (request {}): {}",
+                   path,
+                   request.user_id,
+                   request.id,
+                   e);
+        }
+        info!("Executed the request {}: {} of user {}.",
+              request.id,
+              request.action,
+              request.user_id);
+        executed.push(request);
+    }
+    Ok(executed)
+}
+
+#[test]
+fn test_package() {
+    use std::io::{Read, Write};
+
+    let dir = tempdir::TempDir::new("ganbare_account_export").unwrap();
+    fs::File::create(dir.path().join("a.ogg")).unwrap().write_all(b"recording").unwrap();
+    let recordings = vec!["a.ogg".to_string(), "missing.ogg".to_string()];
+
+    let archive = package(&vec!["profile"], &vec![1, 2], &(), &recordings, dir.path()).unwrap();
+
+    let mut entries = vec![];
+    for entry in tar::Archive::new(&archive[..]).entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let name = entry.path().unwrap().to_string_lossy().into_owned();
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents).unwrap();
+        entries.push((name, contents));
+    }
+    let names: Vec<&str> = entries.iter().map(|&(ref n, _)| n.as_str()).collect();
+    assert_eq!(names,
+               vec!["profile.json", "answers.json", "event_userdata.json", "recordings/a.ogg"]);
+    assert_eq!(serde_json::from_str::<Vec<i32>>(&entries[1].1).unwrap(), vec![1, 2]);
+    assert_eq!(entries[3].1, "recording");
+}
+
+#[test]
+fn test_remove_files() {
+    use std::io::Write;
+
+    let dir = tempdir::TempDir::new("ganbare_account_delete").unwrap();
+    let a = dir.path().join("a.ogg");
+    let b = dir.path().join("b.ogg");
+    fs::File::create(&a).unwrap().write_all(b"recording").unwrap();
+    fs::File::create(&b).unwrap().write_all(b"recording").unwrap();
+    let not_a_file = dir.path().join("dir");
+    fs::create_dir(&not_a_file).unwrap();
+
+    // The files that are already gone don't count as failures, so that executing
+    // a withdrawal again after a crash goes through.
+    let failed = remove_files(&[a.clone(), dir.path().join("gone.ogg"), b.clone()]);
+    assert!(failed.is_empty());
+    assert!(!a.exists() && !b.exists());
+
+    let failed = remove_files(&[not_a_file.clone()]);
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].0, not_a_file);
+}
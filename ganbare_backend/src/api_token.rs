@@ -0,0 +1,243 @@
+use super::*;
+use session::UserSession;
+use chrono::{DateTime, UTC};
+use crypto::mac::MacResult;
+use data_encoding::base64url::{encode_nopad, decode_nopad};
+
+// Bearer tokens for the clients that don't use the session cookies: personal access
+// tokens that users create for their scripts, and short-lived session tokens that apps
+// get by logging in. A token is scoped to some of the user's roles, so a token for
+// reading the research data can't be used to edit the content.
+//
+// The bearer string is `<token id>.<user id>.<token>.<hmac>`. Like with the session
+// cookies, the HMAC is keyed with the cookie key, and the token is derived from the
+// secret that is stored in the database.
+
+pub const PERSONAL: &'static str = "personal";
+pub const SESSION: &'static str = "session";
+
+/// Stands in for the refresh time that is signed in the session cookies.
+const BEARER: &'static str = "bearer";
+
+fn bearer_hmac(token_id: &str, user_id: &str, token: &[u8], hmac_key: &[u8]) -> String {
+    session::get_hmac_for_sess(token_id, user_id, BEARER, "0", token, hmac_key)
+}
+
+/// A personal access token to create.
+#[derive(Debug, Deserialize)]
+pub struct TokenSpec {
+    pub name: String,
+    pub roles: Vec<String>,
+    /// The token doesn't expire if this isn't set.
+    pub expires_days: Option<i64>,
+}
+
+/// The longest a personal access token can be valid for.
+pub const MAX_EXPIRE_DAYS: i64 = 3650;
+
+impl TokenSpec {
+    /// When the token expires. Fails with `InvalidInput` unless `expires_days` is between
+    /// 1 and `MAX_EXPIRE_DAYS`.
+    pub fn expires(&self) -> Result<Option<DateTime<UTC>>> {
+        match self.expires_days {
+            Some(days) if days < 1 || days > MAX_EXPIRE_DAYS => bail!(ErrorKind::InvalidInput),
+            Some(days) => Ok(Some(UTC::now() + chrono::Duration::days(days))),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Creates a token for the user. The roles must be ones the user has. Returns the token
+/// and its bearer string, which isn't stored, so it can be shown only once.
+pub fn create(conn: &Connection,
+              user_id: i32,
+              name: &str,
+              kind: &str,
+              role_names: &[String],
+              expires: Option<DateTime<UTC>>,
+              hmac_key: &[u8])
+              -> Result<(ApiToken, String)> {
+    use schema::{api_tokens, api_token_roles};
+
+    if (kind != PERSONAL && kind != SESSION) || name.is_empty() || name.len() > 100 ||
+       expires.map(|e| e <= UTC::now()).unwrap_or(false) {
+        bail!(ErrorKind::InvalidInput);
+    }
+
+    let user_roles = role::get_role_names(conn, user_id)?;
+    let mut role_ids = vec![];
+    for role_name in role_names {
+        if !user_roles.contains(role_name) {
+            bail!(ErrorKind::AccessDenied);
+        }
+        let role = try_or!(role::get_by_name(conn, role_name)?,
+                           else bail!(ErrorKind::InvalidInput));
+        role_ids.push(role.id);
+    }
+
+    let secret = session::fresh_token()?;
+
+    conn.transaction(|| {
+        let token: ApiToken = diesel::insert(&NewApiToken {
+                                                  user_id: user_id,
+                                                  name: name,
+                                                  kind: kind,
+                                                  secret: &secret[..],
+                                                  expires: expires,
+                                              }).into(api_tokens::table)
+                .get_result(&**conn)?;
+
+        for role_id in role_ids {
+            diesel::insert(&ApiTokenRole {
+                                token_id: token.id,
+                                role_id: role_id,
+                            }).into(api_token_roles::table)
+                    .execute(&**conn)?;
+        }
+
+        // The session tokens are created at every login, so only the personal ones are audited.
+        if kind == PERSONAL {
+            audit::record(conn,
+                          user_id,
+                          "create_token",
+                          "user",
+                          Some(user_id),
+                          None,
+                          Some(&(&token, role_names)))?;
+        }
+
+        let token_id = token.id.to_string();
+        let user_id = user_id.to_string();
+        let derived = session::get_hmac_for_sess_secret(&secret[..], 0);
+        let hmac = bearer_hmac(&token_id, &user_id, &derived, hmac_key);
+        let bearer = format!("{}.{}.{}.{}", token_id, user_id, encode_nopad(&derived), hmac);
+
+        Ok((token, bearer))
+    })
+}
+
+/// Checks the bearer string. Returns a session for the user if the token is valid
+/// and unexpired.
+pub fn check(conn: &Connection, bearer: &str, hmac_key: &[u8]) -> Result<Option<UserSession>> {
+    use schema::api_tokens;
+
+    let parts: Vec<&str> = bearer.split('.').collect();
+    if parts.len() != 4 {
+        return Ok(None);
+    }
+    let (token_id_str, user_id_str) = (parts[0], parts[1]);
+    let token_id: i32 = try_or!(token_id_str.parse().ok(), else return Ok(None));
+    let user_id: i32 = try_or!(user_id_str.parse().ok(), else return Ok(None));
+    let token = try_or!(decode_nopad(parts[2].as_bytes()).ok(), else return Ok(None));
+    let hmac = try_or!(decode_nopad(parts[3].as_bytes()).ok(), else return Ok(None));
+
+    let expected = decode_nopad(bearer_hmac(token_id_str, user_id_str, &token, hmac_key)
+                                    .as_bytes())?;
+    if MacResult::new_from_owned(hmac) != MacResult::new_from_owned(expected) {
+        warn!("The HMAC doesn't agree with the bearer token!");
+        return Ok(None);
+    }
+
+    let now = UTC::now();
+    let db_token: ApiToken = try_or!(api_tokens::table.filter(api_tokens::id.eq(token_id))
+                                         .filter(api_tokens::user_id.eq(user_id))
+                                         .get_result(&**conn)
+                                         .optional()?,
+                                     else return Ok(None)); // Revoked
+    if db_token.expires.map(|e| e < now).unwrap_or(false) {
+        return Ok(None);
+    }
+    if !session::verify_hmac_for_sess_secret(&db_token.secret, 0, &token) {
+        return Ok(None);
+    }
+
+    diesel::update(api_tokens::table.filter(api_tokens::id.eq(token_id)))
+        .set(api_tokens::last_used.eq(Some(now)))
+        .execute(&**conn)?;
+
+    Ok(Some(UserSession {
+                sess_id: 0,
+                user_id: user_id,
+                refreshed: now,
+                refresh_now: false,
+                token: token,
+                refresh_count: 0,
+                api_token: Some(token_id),
+            }))
+}
+
+/// The user's unexpired tokens with the names of their roles, newest first.
+pub fn get_tokens(conn: &Connection, user_id: i32) -> Result<Vec<(ApiToken, Vec<String>)>> {
+    use schema::{api_tokens, api_token_roles, roles};
+    use diesel::expression::dsl::any;
+
+    let tokens: Vec<ApiToken> = api_tokens::table.filter(api_tokens::user_id.eq(user_id))
+        .filter(api_tokens::expires.is_null().or(api_tokens::expires.gt(UTC::now())))
+        .order(api_tokens::id.desc())
+        .get_results(&**conn)?;
+
+    let token_ids: Vec<i32> = tokens.iter().map(|t| t.id).collect();
+    let token_roles: Vec<(i32, String)> = api_token_roles::table.inner_join(roles::table)
+        .filter(api_token_roles::token_id.eq(any(token_ids)))
+        .select((api_token_roles::token_id, roles::role_name))
+        .order(roles::role_name)
+        .get_results(&**conn)?;
+
+    Ok(tokens.into_iter()
+           .map(|t| {
+                    let roles = token_roles.iter()
+                        .filter(|&&(token_id, _)| token_id == t.id)
+                        .map(|&(_, ref name)| name.clone())
+                        .collect();
+                    (t, roles)
+                })
+           .collect())
+}
+
+/// Revokes the user's token. The actor is `None` when a session token is revoked by
+/// logging out with it.
+pub fn revoke(conn: &Connection,
+              actor: Option<i32>,
+              user_id: i32,
+              token_id: i32)
+              -> Result<Option<ApiToken>> {
+    use schema::api_tokens;
+
+    conn.transaction(|| -> Result<Option<ApiToken>> {
+
+        let token: Option<ApiToken> =
+            diesel::delete(api_tokens::table.filter(api_tokens::id.eq(token_id))
+                    .filter(api_tokens::user_id.eq(user_id))).get_result(&**conn)
+                .optional()?;
+
+        if let (Some(actor), Some(token)) = (actor, token.as_ref()) {
+            audit::record(conn, actor, "revoke_token", "user", Some(user_id), Some(token), None)?;
+        }
+        Ok(token)
+    })
+}
+
+pub fn clean_expired(conn: &Connection) -> Result<usize> {
+    use schema::api_tokens;
+
+    Ok(diesel::delete(api_tokens::table.filter(api_tokens::expires.lt(UTC::now())))
+           .execute(&**conn)?)
+}
+
+#[test]
+fn test_token_spec_expires() {
+    let spec = |days| {
+        TokenSpec {
+            name: "notebook".into(),
+            roles: vec![],
+            expires_days: days,
+        }
+    };
+    assert_eq!(spec(None).expires().unwrap(), None);
+    let expires = spec(Some(30)).expires().unwrap().unwrap();
+    assert!(expires > UTC::now() + chrono::Duration::days(29));
+    assert!(spec(Some(MAX_EXPIRE_DAYS)).expires().is_ok());
+    for days in vec![0, -1, MAX_EXPIRE_DAYS + 1, i64::max_value()] {
+        assert!(spec(Some(days)).expires().is_err(), "{} days", days);
+    }
+}
@@ -0,0 +1,720 @@
+use super::*;
+use std::collections::{HashMap, HashSet};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use data_encoding::hex;
+use regex::Regex;
+use chrono::{DateTime, UTC};
+
+/// The version of the archive format. Bumped when the contents change incompatibly.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+lazy_static! {
+    static ref IMG_REGEX: Regex = Regex::new(r#"<img[^>]* src="[^"]*/([^"]*)"[^>]*>"#)
+        .expect("<- that is a valid regex there");
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchivedAudioFile {
+    pub id: i32,
+    pub narrators_id: i32,
+    pub bundle_id: i32,
+    pub file_path: String,
+    pub mime: String,
+    /// Hex-encoded SHA-512 of the file. Audio files are matched by this when importing.
+    pub file_sha2: String,
+}
+
+/// The course content, for moving it between instances. The archive is a tar file with this
+/// in `content.json`, the original audio files in `audio/` and the images referenced
+/// by the texts in `images/`. User data isn't included.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Content {
+    pub version: u32,
+    pub exported: DateTime<UTC>,
+    pub narrators: Vec<Narrator>,
+    pub skill_nuggets: Vec<SkillNugget>,
+    pub audio_bundles: Vec<AudioBundle>,
+    pub audio_files: Vec<ArchivedAudioFile>,
+    pub words: Vec<Word>,
+    pub quiz_questions: Vec<QuizQuestion>,
+    pub question_answers: Vec<Answer>,
+    pub exercises: Vec<Exercise>,
+    pub exercise_variants: Vec<ExerciseVariant>,
+    pub images: Vec<String>,
+}
+
+/// How many rows of each kind were created, and how many matched existing ones
+/// (and were updated, if they differed).
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub created: BTreeMap<&'static str, usize>,
+    pub matched: BTreeMap<&'static str, usize>,
+}
+
+impl ImportReport {
+    fn created(&mut self, kind: &'static str) {
+        *self.created.entry(kind).or_insert(0) += 1;
+    }
+
+    fn matched(&mut self, kind: &'static str) {
+        *self.matched.entry(kind).or_insert(0) += 1;
+    }
+}
+
+impl std::fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut kinds: Vec<&&str> = self.created.keys().chain(self.matched.keys()).collect();
+        kinds.sort();
+        kinds.dedup();
+        for kind in kinds {
+            writeln!(f,
+                     "{}: {} created, {} matched",
+                     kind,
+                     self.created.get(kind).unwrap_or(&0),
+                     self.matched.get(kind).unwrap_or(&0))?;
+        }
+        Ok(())
+    }
+}
+
+fn referenced_images(texts: &[&str]) -> Vec<String> {
+    let mut images: Vec<String> = texts.iter()
+        .flat_map(|text| IMG_REGEX.captures_iter(text))
+        .filter_map(|c| c.get(1).map(|m| m.as_str().to_owned()))
+        .collect();
+    images.sort();
+    images.dedup();
+    images
+}
+
+fn append_file(builder: &mut tar::Builder<fs::File>, path: &Path, name: &str) -> Result<()> {
+    builder.append_path_with_name(path, name)
+        .chain_err(|| format!("Couldn't add {:?} to the archive!", path))
+}
+
+/// Exports all the content into an archive at `target`. Returns the exported content.
+/// The items in the trash are left out, along with the rows that belong to them.
+pub fn export(conn: &Connection,
+              audio_dir: &Path,
+              image_dir: &Path,
+              target: &Path)
+              -> Result<Content> {
+    use schema::{narrators, skill_nuggets, audio_bundles, audio_files, words, quiz_questions,
+                 question_answers, exercises, exercise_variants};
+
+    let audio_bundles: Vec<AudioBundle> =
+        audio_bundles::table.filter(audio_bundles::deleted.is_null())
+            .order(audio_bundles::id)
+            .get_results(&**conn)?;
+    let words: Vec<Word> = words::table.filter(words::deleted.is_null())
+        .order(words::id)
+        .get_results(&**conn)?;
+    let quiz_questions: Vec<QuizQuestion> =
+        quiz_questions::table.filter(quiz_questions::deleted.is_null())
+            .order(quiz_questions::id)
+            .get_results(&**conn)?;
+    let question_ids: HashSet<i32> = quiz_questions.iter().map(|q| q.id).collect();
+    let question_answers: Vec<Answer> = question_answers::table.order(question_answers::id)
+        .get_results::<Answer>(&**conn)?
+        .into_iter()
+        .filter(|a| question_ids.contains(&a.question_id))
+        .collect();
+    let exercises: Vec<Exercise> = exercises::table.filter(exercises::deleted.is_null())
+        .order(exercises::id)
+        .get_results(&**conn)?;
+    let exercise_ids: HashSet<i32> = exercises.iter().map(|e| e.id).collect();
+    let exercise_variants: Vec<ExerciseVariant> =
+        exercise_variants::table.order(exercise_variants::id)
+            .get_results::<ExerciseVariant>(&**conn)?
+            .into_iter()
+            .filter(|v| exercise_ids.contains(&v.exercise_id))
+            .collect();
+
+    let images = {
+        let mut texts: Vec<&str> = vec![];
+        texts.extend(words.iter().map(|w| w.explanation.as_str()));
+        texts.extend(quiz_questions.iter().map(|q| q.question_text.as_str()));
+        texts.extend(question_answers.iter().map(|a| a.answer_text.as_str()));
+        referenced_images(&texts)
+    };
+
+    let bundle_ids: HashSet<i32> = audio_bundles.iter().map(|b| b.id).collect();
+    let audio_files: Vec<AudioFile> = audio_files::table.order(audio_files::id)
+        .get_results::<AudioFile>(&**conn)?
+        .into_iter()
+        .filter(|f| bundle_ids.contains(&f.bundle_id))
+        .collect();
+    let mut archived_audio_files = Vec::with_capacity(audio_files.len());
+    for f in audio_files {
+        let file_sha2 = match f.file_sha2 {
+            Some(hash) => hash,
+            None => audio::audio_file_hash(&f.file_path, audio_dir)?.to_vec(),
+        };
+        archived_audio_files.push(ArchivedAudioFile {
+                                      id: f.id,
+                                      narrators_id: f.narrators_id,
+                                      bundle_id: f.bundle_id,
+                                      file_path: f.file_path,
+                                      mime: f.mime,
+                                      file_sha2: hex::encode(&file_sha2),
+                                  });
+    }
+
+    let content = Content {
+        version: ARCHIVE_VERSION,
+        exported: UTC::now(),
+        narrators: narrators::table.order(narrators::id).get_results(&**conn)?,
+        skill_nuggets: skill_nuggets::table.order(skill_nuggets::id).get_results(&**conn)?,
+        audio_bundles: audio_bundles,
+        audio_files: archived_audio_files,
+        words: words,
+        quiz_questions: quiz_questions,
+        question_answers: question_answers,
+        exercises: exercises,
+        exercise_variants: exercise_variants,
+        images: images,
+    };
+
+    write_archive(&content, audio_dir, image_dir, target)?;
+
+    Ok(content)
+}
+
+/// Writes the content, the audio files and the images into a tar file at `target`.
+fn write_archive(content: &Content,
+                 audio_dir: &Path,
+                 image_dir: &Path,
+                 target: &Path)
+                 -> Result<()> {
+    let json = serde_json::to_vec_pretty(content)
+        .chain_err(|| "Couldn't serialize the content!")?;
+
+    let file = fs::File::create(target)
+        .chain_err(|| format!("Couldn't create the archive {:?}!", target))?;
+    let mut builder = tar::Builder::new(file);
+
+    let mut header = tar::Header::new_gnu();
+    header.set_path("content.json")?;
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(content.exported.timestamp() as u64);
+    header.set_cksum();
+    builder.append(&header, &json[..])?;
+
+    for f in &content.audio_files {
+        append_file(&mut builder,
+                    &audio_dir.join(&f.file_path),
+                    &format!("audio/{}", f.file_path))?;
+    }
+    for image in &content.images {
+        let path = image_dir.join(image);
+        if path.is_file() {
+            append_file(&mut builder, &path, &format!("images/{}", image))?;
+        } else {
+            warn!("The image {:?} is referenced but it doesn't exist!", path);
+        }
+    }
+    builder.finish()?;
+
+    Ok(())
+}
+
+/// Maps the ids in the archive to the ids in the database.
+fn mapped(map: &HashMap<i32, i32>, id: i32, kind: &'static str) -> Result<i32> {
+    match map.get(&id) {
+        Some(&id) => Ok(id),
+        None => {
+            let reason = format!("{} {} is referenced but not included", kind, id);
+            bail!(ErrorKind::InvalidArchive(reason))
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut buf = Vec::new();
+    fs::File::open(path)?.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// Copies a file from the unpacked archive, unless an identical file is there already.
+fn copy_file(source: &Path, target: &Path) -> Result<()> {
+    if target.exists() {
+        if read_file(source)? != read_file(target)? {
+            let reason = format!("{:?} exists with different contents", target);
+            bail!(ErrorKind::InvalidArchive(reason));
+        }
+        return Ok(());
+    }
+    fs::copy(source, target).chain_err(|| format!("Couldn't copy {:?}!", source))?;
+    Ok(())
+}
+
+/// Checks that a file name from the archive names a file right in the target directory,
+/// so that joining it to the directory can't escape it.
+fn check_file_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains("..") || name.contains('/') || name.contains('\\') {
+        bail!(ErrorKind::InvalidArchive(format!("Invalid file name {:?}", name)));
+    }
+    Ok(())
+}
+
+/// Unpacks the archive into `dir` and reads the content. The version and the file names
+/// are checked before anything is imported.
+fn unpack(archive: &Path, dir: &Path) -> Result<Content> {
+    let file = fs::File::open(archive)
+        .chain_err(|| format!("Couldn't open the archive {:?}!", archive))?;
+    tar::Archive::new(file).unpack(dir)
+        .chain_err(|| "Couldn't unpack the archive!")?;
+
+    let json = fs::File::open(dir.join("content.json"))
+        .chain_err(|| ErrorKind::InvalidArchive("content.json is missing".into()))?;
+    let content: Content = serde_json::from_reader(json)
+        .chain_err(|| ErrorKind::InvalidArchive("Couldn't parse content.json".into()))?;
+
+    if content.version != ARCHIVE_VERSION {
+        bail!(ErrorKind::InvalidArchive(format!("Version {}, but only version {} is supported",
+                                                content.version,
+                                                ARCHIVE_VERSION)));
+    }
+
+    for name in content.images.iter().chain(content.audio_files.iter().map(|f| &f.file_path)) {
+        check_file_name(name)?;
+    }
+
+    Ok(content)
+}
+
+fn copy_images(content: &Content, unpacked: &Path, image_dir: &Path) -> Result<()> {
+    for image in &content.images {
+        let source = unpacked.join("images").join(image);
+        if source.is_file() {
+            copy_file(&source, &image_dir.join(image))?;
+        }
+    }
+    Ok(())
+}
+
+/// Imports an archive created by `export`. Everything is matched to the existing content
+/// by natural keys, so importing the same archive again changes nothing:
+/// audio files by their SHA-512, narrators by name, skill nuggets by summary, words by the word,
+/// questions by the skill nugget and the name, answers by the question and the question audio,
+/// and exercises by the words they consist of. The matched rows are updated to match
+/// the archive. Nothing is deleted.
+pub fn import(conn: &Connection,
+              archive: &Path,
+              audio_dir: &Path,
+              image_dir: &Path)
+              -> Result<ImportReport> {
+
+    let temp_dir = tempdir::TempDir::new("ganbare_content_import")
+        .chain_err(|| "Couldn't create a temp dir!")?;
+    let content = unpack(archive, temp_dir.path())?;
+
+    let mut report = ImportReport::default();
+
+    copy_images(&content, temp_dir.path(), image_dir)?;
+
+    let created_audio_files = conn.transaction(|| {
+            import_rows(conn, &content, temp_dir.path(), audio_dir, &mut report)
+        })?;
+
+    for file in created_audio_files {
+        audio::try_create_renditions(conn, &file, audio_dir)?;
+    }
+
+    Ok(report)
+}
+
+/// Imports the database rows. Returns the audio files that were created.
+fn import_rows(conn: &Connection,
+               content: &Content,
+               unpacked: &Path,
+               audio_dir: &Path,
+               report: &mut ImportReport)
+               -> Result<Vec<AudioFile>> {
+    use schema::{narrators, audio_bundles, audio_files, words, quiz_questions, question_answers,
+                 exercises, exercise_variants};
+    use diesel::expression::dsl::any;
+
+    let mut narrator_ids = HashMap::new();
+    for n in &content.narrators {
+        let existing: Option<Narrator> = narrators::table.filter(narrators::name.eq(&n.name))
+            .get_result(&**conn)
+            .optional()?;
+        let id = match existing {
+            Some(existing) => {
+                report.matched("narrators");
+                existing.id
+            }
+            None => {
+                report.created("narrators");
+                let new: Narrator = diesel::insert(&NewNarrator { name: &n.name })
+                    .into(narrators::table)
+                    .get_result(&**conn)?;
+                new.id
+            }
+        };
+        diesel::update(narrators::table.filter(narrators::id.eq(id)))
+            .set(narrators::published.eq(n.published))
+            .execute(&**conn)?;
+        narrator_ids.insert(n.id, id);
+    }
+
+    let mut nugget_ids = HashMap::new();
+    for s in &content.skill_nuggets {
+        let existing = skill::get_create_by_name(conn, &s.skill_summary)?;
+        nugget_ids.insert(s.id, existing.id);
+    }
+
+    // A bundle is matched through its audio files. If none of them exist yet,
+    // a new bundle is created; bundles without any audio are matched by their name.
+    let mut existing_files = HashMap::new();
+    let mut bundle_ids = HashMap::new();
+    for f in &content.audio_files {
+        let hash = hex::decode(f.file_sha2.as_bytes())?;
+        let existing: Option<AudioFile> = audio_files::table.filter(audio_files::file_sha2
+                                                                         .eq(&hash))
+            .get_result(&**conn)
+            .optional()?;
+        if let Some(existing) = existing {
+            bundle_ids.entry(f.bundle_id).or_insert(existing.bundle_id);
+            existing_files.insert(f.id, existing);
+        }
+    }
+    for b in &content.audio_bundles {
+        if bundle_ids.contains_key(&b.id) {
+            report.matched("audio_bundles");
+            continue;
+        }
+        let has_audio = content.audio_files.iter().any(|f| f.bundle_id == b.id);
+        let existing: Option<AudioBundle> = if has_audio {
+            None
+        } else {
+            audio_bundles::table.filter(audio_bundles::listname.eq(&b.listname))
+                .first(&**conn)
+                .optional()?
+        };
+        let id = match existing {
+            Some(existing) => {
+                report.matched("audio_bundles");
+                existing.id
+            }
+            None => {
+                report.created("audio_bundles");
+                let new: AudioBundle = diesel::insert(&NewAudioBundle { listname: &b.listname })
+                    .into(audio_bundles::table)
+                    .get_result(&**conn)?;
+                new.id
+            }
+        };
+        bundle_ids.insert(b.id, id);
+    }
+
+    let mut created_files = vec![];
+    for f in &content.audio_files {
+        if existing_files.contains_key(&f.id) {
+            report.matched("audio_files");
+            continue;
+        }
+        copy_file(&unpacked.join("audio").join(&f.file_path),
+                  &audio_dir.join(&f.file_path))?;
+        let hash = hex::decode(f.file_sha2.as_bytes())?;
+        let new_file = NewAudioFile {
+            narrators_id: mapped(&narrator_ids, f.narrators_id, "Narrator")?,
+            bundle_id: mapped(&bundle_ids, f.bundle_id, "Audio bundle")?,
+            file_path: &f.file_path,
+            mime: &f.mime,
+            file_sha2: &hash,
+        };
+        let file: AudioFile = diesel::insert(&new_file).into(audio_files::table)
+            .get_result(&**conn)?;
+        report.created("audio_files");
+        created_files.push(file);
+    }
+
+    let mut word_ids = HashMap::new();
+    for w in &content.words {
+        let existing: Option<Word> = words::table.filter(words::word.eq(&w.word))
+            .get_result(&**conn)
+            .optional()?;
+        let audio_bundle = mapped(&bundle_ids, w.audio_bundle, "Audio bundle")?;
+        let skill_nugget = mapped(&nugget_ids, w.skill_nugget, "Skill nugget")?;
+        let (id, before) = match existing {
+            Some(existing) => {
+                report.matched("words");
+                (existing.id, Some(existing.explanation))
+            }
+            None => {
+                report.created("words");
+                let new: Word = diesel::insert(&NewWord {
+                                                   word: &w.word,
+                                                   explanation: &w.explanation,
+                                                   audio_bundle: audio_bundle,
+                                                   skill_nugget: skill_nugget,
+                                                   skill_level: w.skill_level,
+                                                   priority: w.priority,
+                                               }).into(words::table)
+                        .get_result(&**conn)?;
+                (new.id, None)
+            }
+        };
+        let update = UpdateWord {
+            word: None,
+            explanation: Some(w.explanation.clone()),
+            audio_bundle: Some(audio_bundle),
+            skill_nugget: Some(skill_nugget),
+            published: Some(w.published),
+            skill_level: Some(w.skill_level),
+            priority: Some(w.priority),
+        };
+        diesel::update(words::table.filter(words::id.eq(id))).set(&update)
+            .execute(&**conn)?;
+        if let Some(before) = before {
+            revision::record(conn,
+                             revision::WORD,
+                             id,
+                             &before,
+                             &w.explanation,
+                             None,
+                             revision::IMPORT)?;
+        }
+        word_ids.insert(w.id, id);
+    }
+
+    let mut question_ids = HashMap::new();
+    for q in &content.quiz_questions {
+        let skill_id = mapped(&nugget_ids, q.skill_id, "Skill nugget")?;
+        let existing: Option<QuizQuestion> =
+            quiz_questions::table.filter(quiz_questions::skill_id.eq(skill_id))
+                .filter(quiz_questions::q_name.eq(&q.q_name))
+                .first(&**conn)
+                .optional()?;
+        let (id, before) = match existing {
+            Some(existing) => {
+                report.matched("quiz_questions");
+                (existing.id, Some(existing.question_text))
+            }
+            None => {
+                report.created("quiz_questions");
+                let new: QuizQuestion = diesel::insert(&NewQuizQuestion {
+                                                           skill_id: skill_id,
+                                                           q_name: &q.q_name,
+                                                           q_explanation: &q.q_explanation,
+                                                           question_text: &q.question_text,
+                                                           skill_level: q.skill_level,
+                                                       }).into(quiz_questions::table)
+                        .get_result(&**conn)?;
+                (new.id, None)
+            }
+        };
+        diesel::update(quiz_questions::table.filter(quiz_questions::id.eq(id)))
+            .set(&UpdateQuestion {
+                      skill_id: None,
+                      q_name: None,
+                      q_explanation: Some(q.q_explanation.clone()),
+                      question_text: Some(q.question_text.clone()),
+                      published: Some(q.published),
+                      skill_level: Some(q.skill_level),
+                  })
+            .execute(&**conn)?;
+        if let Some(before) = before {
+            revision::record(conn,
+                             revision::QUESTION,
+                             id,
+                             &before,
+                             &q.question_text,
+                             None,
+                             revision::IMPORT)?;
+        }
+        question_ids.insert(q.id, id);
+    }
+
+    for a in &content.question_answers {
+        let question_id = mapped(&question_ids, a.question_id, "Question")?;
+        let q_audio_bundle = mapped(&bundle_ids, a.q_audio_bundle, "Audio bundle")?;
+        let a_audio_bundle = match a.a_audio_bundle {
+            Some(id) => Some(mapped(&bundle_ids, id, "Audio bundle")?),
+            None => None,
+        };
+        let existing: Option<Answer> =
+            question_answers::table.filter(question_answers::question_id.eq(question_id))
+                .filter(question_answers::q_audio_bundle.eq(q_audio_bundle))
+                .first(&**conn)
+                .optional()?;
+        match existing {
+            Some(existing) => {
+                report.matched("question_answers");
+                diesel::update(question_answers::table.filter(question_answers::id
+                                                                  .eq(existing.id)))
+                    .set(&UpdateAnswer {
+                              question_id: None,
+                              a_audio_bundle: Some(a_audio_bundle),
+                              q_audio_bundle: None,
+                              answer_text: Some(a.answer_text.clone()),
+                          })
+                    .execute(&**conn)?;
+                revision::record(conn,
+                                 revision::ANSWER,
+                                 existing.id,
+                                 &existing.answer_text,
+                                 &a.answer_text,
+                                 None,
+                                 revision::IMPORT)?;
+            }
+            None => {
+                report.created("question_answers");
+                diesel::insert(&NewAnswer {
+                                    question_id: question_id,
+                                    a_audio_bundle: a_audio_bundle,
+                                    q_audio_bundle: q_audio_bundle,
+                                    answer_text: &a.answer_text,
+                                }).into(question_answers::table)
+                    .execute(&**conn)?;
+            }
+        }
+    }
+
+    // A word belongs to at most one exercise (the id of a variant is the id of the word),
+    // so an exercise is matched by the exercise that any of its words already belong to.
+    for e in &content.exercises {
+        let skill_id = mapped(&nugget_ids, e.skill_id, "Skill nugget")?;
+        let mut variant_word_ids = vec![];
+        for v in content.exercise_variants.iter().filter(|v| v.exercise_id == e.id) {
+            variant_word_ids.push(mapped(&word_ids, v.id, "Word")?);
+        }
+
+        let existing: Option<i32> =
+            exercise_variants::table.filter(exercise_variants::id.eq(any(&variant_word_ids)))
+                .select(exercise_variants::exercise_id)
+                .first(&**conn)
+                .optional()?;
+        let id = match existing {
+            Some(id) => {
+                report.matched("exercises");
+                id
+            }
+            None => {
+                report.created("exercises");
+                let new: Exercise = diesel::insert(&NewExercise {
+                                                       skill_id: skill_id,
+                                                       skill_level: e.skill_level,
+                                                   }).into(exercises::table)
+                        .get_result(&**conn)?;
+                new.id
+            }
+        };
+        diesel::update(exercises::table.filter(exercises::id.eq(id)))
+            .set(&UpdateExercise {
+                      skill_id: Some(skill_id),
+                      published: Some(e.published),
+                      skill_level: Some(e.skill_level),
+                  })
+            .execute(&**conn)?;
+
+        for word_id in variant_word_ids {
+            let variant = ExerciseVariant {
+                id: word_id,
+                exercise_id: id,
+            };
+            let updated = diesel::update(exercise_variants::table.filter(exercise_variants::id
+                                                                             .eq(word_id)))
+                .set(exercise_variants::exercise_id.eq(id))
+                .execute(&**conn)?;
+            if updated == 0 {
+                diesel::insert(&variant).into(exercise_variants::table).execute(&**conn)?;
+            }
+        }
+    }
+
+    Ok(created_files)
+}
+
+#[test]
+fn test_referenced_images() {
+    let texts = ["<img src=\"/api/images/a.png\"> ja <img class=\"x\" src=\"/api/images/b.jpg\">",
+                 "ei kuvia",
+                 "<img src=\"/api/images/a.png\">"];
+    assert_eq!(referenced_images(&texts), vec!["a.png".to_string(), "b.jpg".to_string()]);
+}
+
+#[test]
+fn test_check_file_name() {
+    assert!(check_file_name("2017-03-01T12:00:00+00:00.mp3").is_ok());
+    assert!(check_file_name("kuva.png").is_ok());
+    for name in &["", "..", "../kuva.png", "a/../../b.png", "/etc/passwd", "audio/a.mp3",
+                  "..\\a.mp3"] {
+        assert!(check_file_name(name).is_err(), "{:?} was accepted", name);
+    }
+}
+
+#[test]
+fn test_archive_roundtrip() {
+    use std::io::Write;
+
+    let source = tempdir::TempDir::new("ganbare_archive_source").unwrap();
+    let audio_dir = source.path().join("audio");
+    let image_dir = source.path().join("images");
+    fs::create_dir(&audio_dir).unwrap();
+    fs::create_dir(&image_dir).unwrap();
+    fs::File::create(audio_dir.join("a.mp3")).unwrap().write_all(b"audio").unwrap();
+    fs::File::create(image_dir.join("a.png")).unwrap().write_all(b"image").unwrap();
+
+    let content = Content {
+        version: ARCHIVE_VERSION,
+        exported: UTC::now(),
+        narrators: vec![],
+        skill_nuggets: vec![],
+        audio_bundles: vec![],
+        audio_files: vec![ArchivedAudioFile {
+                              id: 1,
+                              narrators_id: 1,
+                              bundle_id: 1,
+                              file_path: "a.mp3".into(),
+                              mime: "audio/mpeg".into(),
+                              file_sha2: "00".into(),
+                          }],
+        words: vec![],
+        quiz_questions: vec![],
+        question_answers: vec![],
+        exercises: vec![],
+        exercise_variants: vec![],
+        images: vec!["a.png".into()],
+    };
+    let archive = source.path().join("content.tar");
+    write_archive(&content, &audio_dir, &image_dir, &archive).unwrap();
+
+    let target = tempdir::TempDir::new("ganbare_archive_target").unwrap();
+    let target_images = target.path().join("images");
+    fs::create_dir(&target_images).unwrap();
+
+    // Importing the same archive twice leaves the same files.
+    for _ in 0..2 {
+        let unpacked = tempdir::TempDir::new("ganbare_archive_unpacked").unwrap();
+        let imported = unpack(&archive, unpacked.path()).unwrap();
+        assert_eq!(serde_json::to_value(&imported).unwrap(),
+                   serde_json::to_value(&content).unwrap());
+        assert_eq!(read_file(&unpacked.path().join("audio/a.mp3")).unwrap(), b"audio");
+        copy_images(&imported, unpacked.path(), &target_images).unwrap();
+        assert_eq!(read_file(&target_images.join("a.png")).unwrap(), b"image");
+    }
+    assert_eq!(fs::read_dir(&target_images).unwrap().count(), 1);
+
+    // A different image with the same name isn't overwritten.
+    fs::File::create(image_dir.join("a.png")).unwrap().write_all(b"other").unwrap();
+    write_archive(&content, &audio_dir, &image_dir, &archive).unwrap();
+    let unpacked = tempdir::TempDir::new("ganbare_archive_unpacked").unwrap();
+    let imported = unpack(&archive, unpacked.path()).unwrap();
+    assert!(copy_images(&imported, unpacked.path(), &target_images).is_err());
+    assert_eq!(read_file(&target_images.join("a.png")).unwrap(), b"image");
+
+    // Neither is an archive that names files outside the directories.
+    let mut evil = content;
+    evil.images = vec!["../a.png".into()];
+    write_archive(&evil, &audio_dir, &image_dir, &archive).unwrap();
+    let unpacked = tempdir::TempDir::new("ganbare_archive_unpacked").unwrap();
+    assert!(unpack(&archive, unpacked.path()).is_err());
+}
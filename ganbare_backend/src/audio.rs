@@ -8,6 +8,339 @@ use std::fs;
 use std::mem;
 use mime;
 
+/// The formats that can be imported: file extension, MIME type and the aliases
+/// of the MIME type that browsers and tools tend to use.
+const IMPORT_FORMATS: &'static [(&'static str, &'static str, &'static [&'static str])] =
+    &[("mp3", "audio/mpeg", &["audio/mp3", "audio/mpeg3", "audio/x-mpeg"]),
+      ("wav", "audio/wav", &["audio/x-wav", "audio/wave", "audio/vnd.wave"]),
+      ("flac", "audio/flac", &["audio/x-flac"]),
+      ("ogg", "audio/ogg", &["application/ogg", "audio/vorbis"]),
+      ("m4a", "audio/mp4", &["audio/x-m4a", "audio/m4a", "audio/aac"])];
+
+/// The formats served to the browsers, in the order of preference, with the ffmpeg
+/// codec arguments to produce them. Every audio file has a processed rendition in each of these;
+/// the canonical file is the original as it was imported, and it's served only as a fallback.
+const RENDITION_FORMATS: &'static [(&'static str, &'static str, &'static [&'static str])] =
+    &[("mp3", "audio/mpeg", &["-codec:a", "libmp3lame", "-qscale:a", "3"]),
+      ("ogg", "audio/ogg", &["-codec:a", "libvorbis", "-qscale:a", "5"])];
+
+/// Finds out the canonical MIME type of an audio file to be imported,
+/// first by the given MIME type, then by the file extension.
+pub fn import_mime(mime: &mime::Mime, orig_filename: &str) -> Result<&'static str> {
+    let mime = format!("{}/{}", mime.0, mime.1).to_lowercase();
+    let extension = Path::new(orig_filename)
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    IMPORT_FORMATS.iter()
+        .find(|&&(_, m, aliases)| m == mime || aliases.contains(&mime.as_str()))
+        .or_else(|| IMPORT_FORMATS.iter().find(|&&(ext, _, _)| ext == extension))
+        .map(|&(_, m, _)| m)
+        .ok_or_else(|| ErrorKind::UnsupportedAudioFormat(format!("{} ({})", mime, orig_filename))
+            .into())
+}
+
+/// The canonical MIME type of the format that has the file extension, if it can be imported.
+pub fn mime_by_extension(extension: &str) -> Option<&'static str> {
+    let extension = extension.to_lowercase();
+    IMPORT_FORMATS.iter().find(|&&(ext, _, _)| ext == extension).map(|&(_, m, _)| m)
+}
+
+/// The loudness of a file as measured by the first pass of the ffmpeg `loudnorm` filter.
+/// The values are kept as ffmpeg prints them, to be passed to the second pass as they are.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoudnessMeasurement {
+    pub input_i: String,
+    pub input_tp: String,
+    pub input_lra: String,
+    pub input_thresh: String,
+    pub target_offset: String,
+}
+
+/// The processing that the renditions go through: the silence at the start and at the end
+/// of the clip is trimmed, and the loudness is normalized. Recorded in the `processing`
+/// column of the audio file as JSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioProcessing {
+    /// Anything quieter than this counts as silence.
+    pub silence_threshold_db: f64,
+    /// How much of the silence is left at both ends, so that the clips don't start abruptly.
+    pub kept_silence_s: f64,
+    /// The integrated loudness to normalize to.
+    pub target_lufs: f64,
+    pub true_peak_db: f64,
+    pub loudness_range_lu: f64,
+    /// The loudness of the original after trimming. `None` until measured.
+    pub measured: Option<LoudnessMeasurement>,
+}
+
+impl Default for AudioProcessing {
+    fn default() -> Self {
+        AudioProcessing {
+            silence_threshold_db: -50.0,
+            kept_silence_s: 0.05,
+            target_lufs: -16.0,
+            true_peak_db: -1.5,
+            loudness_range_lu: 11.0,
+            measured: None,
+        }
+    }
+}
+
+impl AudioProcessing {
+    /// Whether the settings are the same, regardless of the measurements.
+    pub fn same_settings(&self, other: &AudioProcessing) -> bool {
+        AudioProcessing { measured: None, ..self.clone() } ==
+        AudioProcessing { measured: None, ..other.clone() }
+    }
+
+    /// Trims the silence from the start, then reverses the clip to trim the end the same way.
+    fn trim_filter(&self) -> String {
+        let trim = format!("silenceremove=start_periods=1:start_threshold={}dB:start_silence={}",
+                           self.silence_threshold_db,
+                           self.kept_silence_s);
+        format!("{0},areverse,{0},areverse", trim)
+    }
+
+    fn loudnorm_filter(&self) -> String {
+        let loudnorm = format!("loudnorm=I={}:TP={}:LRA={}",
+                               self.target_lufs,
+                               self.true_peak_db,
+                               self.loudness_range_lu);
+        match self.measured {
+            Some(ref m) => {
+                format!("{}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:\
+                         offset={}:linear=true",
+                        loudnorm,
+                        m.input_i,
+                        m.input_tp,
+                        m.input_lra,
+                        m.input_thresh,
+                        m.target_offset)
+            }
+            None => format!("{}:print_format=json", loudnorm),
+        }
+    }
+
+    /// The whole filter chain. Without a measurement, this is the measuring pass.
+    fn filter(&self) -> String {
+        format!("{},{}", self.trim_filter(), self.loudnorm_filter())
+    }
+}
+
+/// Runs the measuring pass of the loudness normalization.
+fn measure_loudness(source: &Path, processing: &AudioProcessing) -> Result<LoudnessMeasurement> {
+    use std::process::Command;
+
+    let measuring = AudioProcessing { measured: None, ..processing.clone() };
+    let output = Command::new(&*FFMPEG).args(&["-nostdin", "-hide_banner", "-nostats", "-i"])
+        .arg(source)
+        .args(&["-vn", "-af", &measuring.filter(), "-f", "null", "-"])
+        .output()
+        .map_err(|e| ErrorKind::TranscodingFailed(format!("Couldn't run {}: {}", &*FFMPEG, e)))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        bail!(ErrorKind::TranscodingFailed(stderr.into_owned()));
+    }
+
+    // loudnorm prints the measurement as the last thing in the log.
+    match (stderr.rfind('{'), stderr.rfind('}')) {
+        (Some(start), Some(end)) if start < end => {
+            serde_json::from_str(&stderr[start..end + 1])
+                .chain_err(|| "Couldn't parse the loudness measurement!")
+        }
+        _ => bail!(ErrorKind::TranscodingFailed(stderr.into_owned())),
+    }
+}
+
+fn transcode(source: &Path, target: &Path, filter: &str, codec_args: &[&str]) -> Result<()> {
+    use std::process::Command;
+
+    // loudnorm upsamples to 192 kHz, so the sample rate is set back to something sensible.
+    let output = Command::new(&*FFMPEG).args(&["-nostdin", "-y", "-loglevel", "error", "-i"])
+        .arg(source)
+        .args(&["-vn", "-af", filter, "-ar", "44100"])
+        .args(codec_args)
+        .arg(target)
+        .output()
+        .map_err(|e| ErrorKind::TranscodingFailed(format!("Couldn't run {}: {}", &*FFMPEG, e)))?;
+
+    if !output.status.success() {
+        bail!(ErrorKind::TranscodingFailed(String::from_utf8_lossy(&output.stderr).into_owned()));
+    }
+    Ok(())
+}
+
+/// Creates the renditions that the audio file is missing, processing the original file.
+/// Called on import, and for backfilling the renditions of files imported before they existed.
+/// The processing parameters are recorded on the audio file.
+pub fn create_renditions(conn: &Connection,
+                         file: &AudioFile,
+                         audio_dir: &Path)
+                         -> Result<Vec<AudioRendition>> {
+    use schema::{audio_files, audio_renditions};
+
+    let existing: Vec<String> =
+        audio_renditions::table.filter(audio_renditions::audio_file_id.eq(file.id))
+            .select(audio_renditions::mime)
+            .get_results(&**conn)?;
+
+    if RENDITION_FORMATS.iter().all(|&(_, mime, _)| existing.iter().any(|m| m == mime)) {
+        return Ok(vec![]);
+    }
+
+    let source = audio_dir.join(&file.file_path);
+    let mut processing = AudioProcessing::default();
+    processing.measured = Some(measure_loudness(&source, &processing)?);
+    let filter = processing.filter();
+
+    let mut created = vec![];
+    for &(extension, mime, codec_args) in RENDITION_FORMATS {
+        if existing.iter().any(|m| m == mime) {
+            continue;
+        }
+        let file_path = format!("{}.{}", file.file_path, extension);
+        transcode(&source, &audio_dir.join(&file_path), &filter, codec_args)?;
+
+        let rendition: AudioRendition = diesel::insert(&NewAudioRendition {
+                                                            audio_file_id: file.id,
+                                                            file_path: &file_path,
+                                                            mime: mime,
+                                                        }).into(audio_renditions::table)
+                .get_result(&**conn)?;
+        info!("Created a rendition: {:?}", rendition);
+        created.push(rendition);
+    }
+
+    let processing = serde_json::to_string(&processing)
+        .chain_err(|| "Couldn't serialize the processing parameters!")?;
+    diesel::update(audio_files::table.filter(audio_files::id.eq(file.id)))
+        .set((audio_files::processing.eq(processing),
+              audio_files::processed.eq(chrono::UTC::now())))
+        .execute(&**conn)?;
+
+    Ok(created)
+}
+
+/// Whether the renditions of the audio file were processed with the current settings.
+pub fn is_processed(file: &AudioFile) -> bool {
+    file.processing
+        .as_ref()
+        .and_then(|p| serde_json::from_str::<AudioProcessing>(p).ok())
+        .map_or(false, |p| p.same_settings(&AudioProcessing::default()))
+}
+
+/// Throws away the renditions of the audio file and creates them again from the original.
+/// The old rendition files are overwritten.
+pub fn reprocess(conn: &Connection,
+                 file: &AudioFile,
+                 audio_dir: &Path)
+                 -> Result<Vec<AudioRendition>> {
+    use schema::audio_renditions;
+
+    conn.transaction(|| {
+        diesel::delete(audio_renditions::table.filter(audio_renditions::audio_file_id
+                                                          .eq(file.id)))
+            .execute(&**conn)?;
+        create_renditions(conn, file, audio_dir)
+    })
+}
+
+/// Whether browsers can play a file of the MIME type as it is.
+fn is_servable(mime: &str) -> bool {
+    RENDITION_FORMATS.iter().any(|&(_, m, _)| m == mime)
+}
+
+/// Creates the renditions of a newly imported file. If the original can be served as it is,
+/// failing to process it (e.g. when ffmpeg isn't installed) only logs a warning; the original
+/// is served until the `clean` tool backfills the missing renditions.
+pub fn try_create_renditions(conn: &Connection, file: &AudioFile, audio_dir: &Path) -> Result<()> {
+    match create_renditions(conn, file, audio_dir) {
+        Ok(_) => Ok(()),
+        Err(Error(ErrorKind::TranscodingFailed(ref msg), _)) if is_servable(&file.mime) => {
+            warn!("Couldn't create the renditions of {}; serving the original. {}",
+                  file.file_path,
+                  msg);
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// The quality the `Accept` header gives to the MIME type. The most specific media range wins.
+fn accept_quality(accept: &str, mime: &str) -> f32 {
+    let top_level = mime.split('/').next().unwrap_or("");
+    let mut best: Option<(u8, f32)> = None;
+    for range in accept.split(',') {
+        let mut parts = range.split(';');
+        let media_range = parts.next().unwrap_or("").trim().to_lowercase();
+        let quality = parts.filter_map(|p| {
+                let p = p.trim();
+                if p.starts_with("q=") {
+                    p[2..].parse().ok()
+                } else {
+                    None
+                }
+            })
+            .next()
+            .unwrap_or(1.0);
+        let specificity = if media_range == mime {
+            2
+        } else if media_range == format!("{}/*", top_level) {
+            1
+        } else if media_range == "*/*" {
+            0
+        } else {
+            continue;
+        };
+        if best.map_or(true, |(s, _)| specificity > s) {
+            best = Some((specificity, quality));
+        }
+    }
+    best.map(|(_, q)| q).unwrap_or(0.0)
+}
+
+/// Picks the file to serve. The candidates are expected to be in the order of preference;
+/// among equally acceptable ones, the first one wins. If there's no `Accept` header,
+/// anything goes.
+fn pick_rendition(candidates: Vec<(String, String)>,
+                  accept: Option<&str>)
+                  -> Option<(String, mime::Mime)> {
+    let accept = accept.unwrap_or("*/*");
+    let mut best: Option<(f32, (String, String))> = None;
+    for candidate in candidates {
+        let quality = accept_quality(accept, &candidate.1);
+        if quality > 0.0 && best.as_ref().map_or(true, |&(q, _)| quality > q) {
+            best = Some((quality, candidate));
+        }
+    }
+    best.map(|(_, (path, mime))| {
+                 (path, mime.parse().expect("The mimetype from the database should be always valid."))
+             })
+}
+
+/// The renditions and the canonical file as (path, MIME type) pairs, in the order of
+/// `RENDITION_FORMATS`. The canonical file comes after the rendition in the same format,
+/// and last if it's in some other format.
+fn renditions_of(conn: &Connection, file: AudioFile) -> Result<Vec<(String, String)>> {
+    use schema::audio_renditions;
+
+    let mut renditions: Vec<(String, String)> =
+        audio_renditions::table.filter(audio_renditions::audio_file_id.eq(file.id))
+            .select((audio_renditions::file_path, audio_renditions::mime))
+            .get_results(&**conn)?;
+    renditions.push((file.file_path, file.mime));
+
+    let preference = |mime: &str| {
+        RENDITION_FORMATS.iter().position(|&(_, m, _)| m == mime).unwrap_or(RENDITION_FORMATS.len())
+    };
+    renditions.sort_by_key(|&(_, ref mime)| preference(mime.as_str()));
+    Ok(renditions)
+}
+
 fn save_file(path: &mut PathBuf, orig_filename: &str, audio_dir: &Path) -> Result<()> {
     info!("Saving file {:?}", &orig_filename);
     use rand::Rng;
@@ -73,11 +406,16 @@ pub fn get_create_narrator(conn: &Connection, mut name: &str) -> Result<Narrator
        })
 }
 
-pub fn del_narrator(conn: &Connection, id: i32) -> Result<bool> {
+pub fn del_narrator(conn: &Connection, actor: i32, id: i32) -> Result<bool> {
     use schema::{narrators, audio_files};
 
     conn.transaction(|| {
 
+        let narrator: Narrator = try_or!(narrators::table.filter(narrators::id.eq(id))
+                                             .get_result(&**conn)
+                                             .optional()?,
+                                         else return Ok(false));
+
         info!("Deleting audio_files with narrators_id {:?}", id);
 
         let audio_files_count = diesel::delete(audio_files::table
@@ -93,12 +431,18 @@ pub fn del_narrator(conn: &Connection, id: i32) -> Result<bool> {
 
         info!("Rows deleted {:?}", narrators_count);
 
+        audit::record(conn, actor, "delete", "narrator", Some(id), Some(&narrator), None)?;
+
         Ok(narrators_count == 1)
 
     })
 }
 
-pub fn merge_narrator(conn: &Connection, narrator_id: i32, new_narrator_id: i32) -> Result<()> {
+pub fn merge_narrator(conn: &Connection,
+                      actor: i32,
+                      narrator_id: i32,
+                      new_narrator_id: i32)
+                      -> Result<()> {
     use schema::{audio_files, narrators};
 
     info!("Replacing old narrator references (id {}) with new ones (id {}).",
@@ -117,12 +461,24 @@ pub fn merge_narrator(conn: &Connection, narrator_id: i32, new_narrator_id: i32)
 
         diesel::delete(narrators::table.filter(narrators::id.eq(narrator_id))).execute(&**conn)?;
 
+        audit::record(conn,
+                      actor,
+                      "merge",
+                      "narrator",
+                      Some(narrator_id),
+                      None,
+                      Some(&new_narrator_id))?;
+
         Ok(())
 
     })
 }
 
-pub fn merge_audio_bundle(conn: &Connection, bundle_id: i32, new_bundle_id: i32) -> Result<()> {
+pub fn merge_audio_bundle(conn: &Connection,
+                          actor: i32,
+                          bundle_id: i32,
+                          new_bundle_id: i32)
+                          -> Result<()> {
     use schema::{audio_files, audio_bundles};
 
     info!("Replacing old bundle references (id {}) with new ones (id {}).",
@@ -144,67 +500,94 @@ pub fn merge_audio_bundle(conn: &Connection, bundle_id: i32, new_bundle_id: i32)
         manage::replace_audio_bundle(conn, bundle_id, new_bundle_id)?;
 
         diesel::delete(audio_bundles::table.filter(audio_bundles::id.eq(bundle_id))).execute(&**conn)?;
+
+        audit::record(conn,
+                      actor,
+                      "merge",
+                      "bundle",
+                      Some(bundle_id),
+                      None,
+                      Some(&new_bundle_id))?;
         Ok(())
 
     })
 }
 
-pub fn del_bundle(conn: &Connection, id: i32) -> Result<bool> {
-    use schema::{audio_bundles, audio_files, question_answers, words};
+/// Moves the bundle to the trash. If there's another bundle with the same name, the words
+/// and questions are moved to use that one. Otherwise they, and the exercises with the words,
+/// are moved to the trash with the bundle, and restored with it.
+pub fn del_bundle(conn: &Connection, actor: i32, id: i32) -> Result<bool> {
+    use schema::{audio_bundles, question_answers, quiz_questions, words, exercises,
+                 exercise_variants};
+    use diesel::expression::dsl::any;
 
     conn.transaction(|| {
 
+        let bundle: AudioBundle = try_or!(audio_bundles::table.filter(audio_bundles::id.eq(id))
+                                              .filter(audio_bundles::deleted.is_null())
+                                              .get_result(&**conn)
+                                              .optional()?,
+                                          else return Ok(false));
+
         // To avoid deleting words and questions,
         // let's find a replacement bundle for all the things that depend on this!
 
-        let bundle: AudioBundle = audio_bundles::table.filter(audio_bundles::id.eq(id))
-            .get_result(&**conn)?;
-
         let replacement_bundles = get_bundles_by_name(conn, &bundle.listname)?;
-        for bundle in replacement_bundles {
-            if bundle.id != id {
+        for replacement in replacement_bundles {
+            if replacement.id != id {
                 // A proper replacement found!
-                manage::replace_audio_bundle(conn, id, bundle.id)?;
+                manage::replace_audio_bundle(conn, id, replacement.id)?;
             }
         }
 
-        info!("Deleting audio_files with bundle_id {:?}", id);
-
-        let count =
-            diesel::delete(audio_files::table.filter(audio_files::bundle_id.eq(id))).execute(&**conn)?;
+        // Everything gets the same timestamp, so that restoring finds them.
+        let now = Some(chrono::UTC::now());
 
-        info!("Rows deleted {:?}", count);
+        let word_ids: Vec<i32> = words::table.filter(words::audio_bundle.eq(id))
+            .filter(words::deleted.is_null())
+            .select(words::id)
+            .get_results(&**conn)?;
 
-        info!("Deleting words with bundle_id {:?}", id);
-
-        let count = diesel::delete(words::table.filter(words::audio_bundle.eq(id))).execute(&**conn)?;
-
-        info!("Rows deleted {:?}", count);
+        diesel::update(words::table.filter(words::id.eq(any(&word_ids))))
+            .set(words::deleted.eq(now))
+            .execute(&**conn)?;
 
-        info!("Deleting q_answers with bundle_id {:?}", id);
+        info!("{} words with bundle_id {:?} moved to the trash.", word_ids.len(), id);
 
-        let count = diesel::delete(question_answers::table
-            .filter(question_answers::a_audio_bundle.eq(id)))
-            .execute(&**conn)?;
+        let exercise_ids: Vec<i32> = exercise_variants::table
+            .filter(exercise_variants::id.eq(any(word_ids)))
+            .select(exercise_variants::exercise_id)
+            .get_results(&**conn)?;
 
-        info!("Rows deleted {:?}", count);
+        let count = diesel::update(exercises::table.filter(exercises::id.eq(any(exercise_ids)))
+                                       .filter(exercises::deleted.is_null()))
+                .set(exercises::deleted.eq(now))
+                .execute(&**conn)?;
 
-        info!("Deleting q_answers with bundle_id {:?}", id);
+        info!("{} exercises with the words moved to the trash.", count);
 
-        let count = diesel::delete(question_answers::table
-            .filter(question_answers::q_audio_bundle.eq(id)))
-            .execute(&**conn)?;
+        let question_ids: Vec<i32> = question_answers::table
+            .filter(question_answers::a_audio_bundle.eq(id)
+                        .or(question_answers::q_audio_bundle.eq(id)))
+            .select(question_answers::question_id)
+            .get_results(&**conn)?;
 
-        info!("Rows deleted {:?}", count);
+        let count = diesel::update(quiz_questions::table
+                                       .filter(quiz_questions::id.eq(any(question_ids)))
+                                       .filter(quiz_questions::deleted.is_null()))
+                .set(quiz_questions::deleted.eq(now))
+                .execute(&**conn)?;
 
-        info!("Deleting bundle with id {:?}", id);
+        info!("{} questions with bundle_id {:?} moved to the trash.", count, id);
 
-        let count =
-            diesel::delete(audio_bundles::table.filter(audio_bundles::id.eq(id))).execute(&**conn)?;
+        let deleted: AudioBundle = diesel::update(audio_bundles::table
+                                                      .filter(audio_bundles::id.eq(id)))
+                .set(audio_bundles::deleted.eq(now))
+                .get_result(&**conn)?;
 
-        info!("Rows deleted {:?}", count);
+        audit::record(conn, actor, "delete", "bundle", Some(id), Some(&bundle), Some(&deleted))?;
 
-        Ok(count == 1)
+        Ok(true)
 
     })
 }
@@ -239,41 +622,87 @@ pub fn new_bundle(conn: &Connection, name: &str) -> Result<AudioBundle> {
 }
 
 pub fn change_bundle_name(conn: &Connection,
+                          actor: i32,
                           id: i32,
                           new_name: &str)
                           -> Result<Option<AudioBundle>> {
     use schema::audio_bundles;
 
-    let bundle: Option<AudioBundle> = diesel::update(audio_bundles::table
-        .filter(audio_bundles::id.eq(id)))
-        .set(audio_bundles::listname.eq(new_name))
-        .get_result(&**conn)
-        .optional()?;
+    conn.transaction(|| -> Result<Option<AudioBundle>> {
 
-    Ok(bundle)
+        let before: AudioBundle = try_or!(audio_bundles::table.filter(audio_bundles::id.eq(id))
+                                              .get_result(&**conn)
+                                              .optional()?,
+                                          else return Ok(None));
+
+        let bundle: Option<AudioBundle> = diesel::update(audio_bundles::table
+            .filter(audio_bundles::id.eq(id)))
+            .set(audio_bundles::listname.eq(new_name))
+            .get_result(&**conn)
+            .optional()?;
+
+        if let Some(ref bundle) = bundle {
+            audit::record(conn, actor, "update", "bundle", Some(id), Some(&before), Some(bundle))?;
+        }
+
+        Ok(bundle)
+    })
 }
 
-pub fn update_narrator(conn: &Connection, narrator: &Narrator) -> Result<Option<Narrator>> {
+pub fn update_narrator(conn: &Connection,
+                       actor: i32,
+                       narrator: &Narrator)
+                       -> Result<Option<Narrator>> {
     use schema::narrators;
 
-    let narrator: Option<Narrator> =
-        diesel::update(narrators::table.filter(narrators::id.eq(narrator.id))).set(narrator)
-            .get_result(&**conn)
-            .optional()?;
+    conn.transaction(|| -> Result<Option<Narrator>> {
+
+        let before: Narrator = try_or!(narrators::table.filter(narrators::id.eq(narrator.id))
+                                           .get_result(&**conn)
+                                           .optional()?,
+                                       else return Ok(None));
+
+        let narrator: Option<Narrator> =
+            diesel::update(narrators::table.filter(narrators::id.eq(narrator.id))).set(narrator)
+                .get_result(&**conn)
+                .optional()?;
+
+        if let Some(ref narrator) = narrator {
+            audit::record(conn,
+                          actor,
+                          "update",
+                          "narrator",
+                          Some(narrator.id),
+                          Some(&before),
+                          Some(narrator))?;
+        }
 
-    Ok(narrator)
+        Ok(narrator)
+    })
 }
 
 pub fn update_file(conn: &Connection,
+                   actor: i32,
                    id: i32,
                    file: &UpdateAudioFile)
                    -> Result<Option<AudioFile>> {
     use schema::audio_files;
 
-    let file: Option<AudioFile> = diesel::update(audio_files::table.filter(audio_files::id.eq(id))).set(file)
-        .get_result(&**conn)
-        .optional()?;
-    Ok(file)
+    conn.transaction(|| -> Result<Option<AudioFile>> {
+
+        let before: AudioFile = try_or!(audio_files::table.filter(audio_files::id.eq(id))
+                                            .get_result(&**conn)
+                                            .optional()?,
+                                        else return Ok(None));
+
+        let file: Option<AudioFile> = diesel::update(audio_files::table.filter(audio_files::id.eq(id))).set(file)
+            .get_result(&**conn)
+            .optional()?;
+        if let Some(ref file) = file {
+            audit::record(conn, actor, "update", "audio_file", Some(id), Some(&before), Some(file))?;
+        }
+        Ok(file)
+    })
 }
 
 pub fn get_create_bundle(conn: &Connection, listname: &str) -> Result<AudioBundle> {
@@ -281,6 +710,7 @@ pub fn get_create_bundle(conn: &Connection, listname: &str) -> Result<AudioBundl
 
     let bundle: Option<AudioBundle> = {
         audio_bundles::table.filter(audio_bundles::listname.eq(listname))
+            .filter(audio_bundles::deleted.is_null())
             .get_result(&**conn)
             .optional()?
     };
@@ -298,7 +728,9 @@ pub fn get_bundles_by_name(conn: &Connection, listname: &str) -> Result<Vec<Audi
     use schema::audio_bundles;
 
     let bundle: Vec<AudioBundle> = {
-        audio_bundles::table.filter(audio_bundles::listname.eq(listname)).get_results(&**conn)?
+        audio_bundles::table.filter(audio_bundles::listname.eq(listname))
+            .filter(audio_bundles::deleted.is_null())
+            .get_results(&**conn)?
     };
 
     Ok(bundle)
@@ -346,6 +778,9 @@ pub fn save(conn: &Connection,
             -> Result<AudioFile> {
     use schema::audio_files;
 
+    let orig_filename = file.1.clone().unwrap_or_default();
+    let mime = import_mime(&file.2, &orig_filename)?;
+
     let hash = &audio_file_hash("", &file.0)?[..];
 
     if audio_files::table.filter(audio_files::file_sha2.eq(hash))
@@ -356,47 +791,59 @@ pub fn save(conn: &Connection,
         return Err(ErrorKind::FileAlreadyExists(hash.to_owned()).into());
     };
 
-    save_file(&mut file.0,
-              file.1
-                  .as_ref()
-                  .map(|s| s.as_str())
-                  .unwrap_or(""),
-              audio_dir)?;
-
-    let bundle_id = if let Some(ref bundle) = *bundle {
-        bundle.id
-    } else {
-        let new_bundle = new_bundle(&*conn, "")?;
-        let bundle_id = new_bundle.id;
-        *bundle = Some(new_bundle);
-        bundle_id
-    };
+    save_file(&mut file.0, &orig_filename, audio_dir)?;
 
     let file_path = file.0
         .file_name()
         .expect("We just set the file name")
         .to_str()
-        .expect("this is an ascii path");
-    let mime = &format!("{}", file.2);
-    let narrators_id = default_narrator_id(&*conn, &mut narrator)?;
-
-    let new_q_audio = NewAudioFile {
-        narrators_id: narrators_id,
-        bundle_id: bundle_id,
-        file_path: file_path,
-        mime: mime,
-        file_sha2: hash,
-    };
+        .expect("this is an ascii path")
+        .to_owned();
+
+    let had_bundle = bundle.is_some();
+    let result = conn.transaction(|| {
+        let bundle_id = if let Some(ref bundle) = *bundle {
+            bundle.id
+        } else {
+            let new_bundle = new_bundle(&*conn, "")?;
+            let bundle_id = new_bundle.id;
+            *bundle = Some(new_bundle);
+            bundle_id
+        };
+
+        let narrators_id = default_narrator_id(&*conn, &mut narrator)?;
+
+        let new_q_audio = NewAudioFile {
+            narrators_id: narrators_id,
+            bundle_id: bundle_id,
+            file_path: &file_path,
+            mime: mime,
+            file_sha2: hash,
+        };
+
+        let audio_file: AudioFile = diesel::insert(&new_q_audio).into(audio_files::table)
+            .get_result(&**conn)
+            .chain_err(|| "Couldn't create a new audio file!")?;
 
-    let audio_file: AudioFile = diesel::insert(&new_q_audio).into(audio_files::table)
-        .get_result(&**conn)
-        .chain_err(|| "Couldn't create a new audio file!")?;
+        info!("{:?}", &audio_file);
 
-    info!("{:?}", &audio_file);
+        try_create_renditions(conn, &audio_file, audio_dir)?;
 
+        Ok(audio_file)
+    });
 
+    if result.is_err() {
+        if !had_bundle {
+            *bundle = None;
+        }
+        // The row is gone with the transaction, so the files would be orphans.
+        let _ = fs::remove_file(&file.0);
+        for &(extension, _, _) in RENDITION_FORMATS {
+            let _ = fs::remove_file(audio_dir.join(format!("{}.{}", file_path, extension)));
+        }
+    }
 
-    Ok(audio_file)
+    result
 }
 
 pub fn load_all_from_bundles(conn: &Connection,
@@ -448,7 +895,8 @@ pub fn load_random_from_bundle(conn: &Connection, bundle_id: i32) -> Result<Audi
 
 pub fn get_all_bundles(conn: &Connection) -> Result<Vec<(AudioBundle, Vec<AudioFile>)>> {
     use schema::audio_bundles;
-    let bundles: Vec<AudioBundle> = audio_bundles::table.order(audio_bundles::listname.asc())
+    let bundles: Vec<AudioBundle> = audio_bundles::table.filter(audio_bundles::deleted.is_null())
+        .order(audio_bundles::listname.asc())
         .get_results(&**conn)?;
 
     let audio_files =
@@ -484,7 +932,13 @@ pub fn get_bundle_by_id(conn: &Connection, bundle_id: i32) -> Result<AudioBundle
     Ok(audio_bundles::table.filter(audio_bundles::id.eq(bundle_id)).get_result(&**conn)?)
 }
 
-pub fn get_file_path(conn: &Connection, file_id: i32) -> Result<(String, mime::Mime)> {
+/// The path of the file to serve, chosen by the `Accept` header. If the extension is given,
+/// only the renditions with that extension are considered.
+pub fn get_file_path(conn: &Connection,
+                     file_id: i32,
+                     extension: Option<&str>,
+                     accept: Option<&str>)
+                     -> Result<(String, mime::Mime)> {
     use schema::audio_files::dsl::*;
     use diesel::result::Error::NotFound;
 
@@ -495,26 +949,41 @@ pub fn get_file_path(conn: &Connection, file_id: i32) -> Result<(String, mime::M
                      e => Error::with_chain(e, "Couldn't get the file!"),
                  })?;
 
-    Ok((file.file_path,
-        file.mime.parse().expect("The mimetype from the database should be always valid.")))
+    let mut renditions = renditions_of(conn, file)?;
+    if let Some(extension) = extension {
+        let wanted = try_or!(mime_by_extension(extension), else bail!(ErrorKind::FileNotFound));
+        renditions.retain(|&(_, ref m)| m == wanted);
+    }
+
+    Ok(try_or!(pick_rendition(renditions, accept), else bail!(ErrorKind::FileNotFound)))
 }
 
+/// All the files in the audio directory that are in use, renditions included.
 pub fn get_all_files(conn: &Connection) -> Result<Vec<(String, mime::Mime)>> {
-    use schema::audio_files::dsl::*;
+    use schema::{audio_files, audio_renditions};
 
-    let files: Vec<AudioFile> = audio_files.get_results(&**conn)?;
+    let mut files: Vec<(String, String)> =
+        audio_files::table.select((audio_files::file_path, audio_files::mime))
+            .get_results(&**conn)?;
+    files.extend(audio_renditions::table.select((audio_renditions::file_path,
+                                                  audio_renditions::mime))
+                     .get_results::<(String, String)>(&**conn)?);
 
     let files = files.into_iter()
-        .map(|f| {
-                 (f.file_path,
-                  f.mime.parse().expect("The mimetype from the database should be always valid."))
+        .map(|(file_path, mime)| {
+                 (file_path,
+                  mime.parse().expect("The mimetype from the database should be always valid."))
              })
         .collect();
 
     Ok(files)
 }
 
-pub fn for_quiz(conn: &Connection, user_id: i32, pending_id: i32) -> Result<(String, mime::Mime)> {
+pub fn for_quiz(conn: &Connection,
+                user_id: i32,
+                pending_id: i32,
+                accept: Option<&str>)
+                -> Result<(String, mime::Mime)> {
     use schema::audio_files;
     use schema::pending_items;
     use diesel::result::Error::NotFound;
@@ -528,7 +997,55 @@ pub fn for_quiz(conn: &Connection, user_id: i32, pending_id: i32) -> Result<(Str
                      e @ NotFound => Error::with_chain(e, ErrorKind::FileNotFound),
                      e => Error::with_chain(e, "Couldn't get the file!"),
                  })?;
-    Ok((file.file_path,
-        file.mime.parse().expect("The mimetype from the database should be always valid.")))
 
+    let renditions = renditions_of(conn, file)?;
+    Ok(try_or!(pick_rendition(renditions, accept), else bail!(ErrorKind::FileNotFound)))
+}
+
+#[test]
+fn test_pick_rendition() {
+    let candidates = || {
+        vec![("a.mp3".to_string(), "audio/mpeg".to_string()),
+             ("a.ogg".to_string(), "audio/ogg".to_string()),
+             ("a".to_string(), "audio/flac".to_string())]
+    };
+    let picked = |accept| pick_rendition(candidates(), accept).map(|(path, _)| path);
+
+    assert_eq!(picked(None), Some("a.mp3".to_string()));
+    assert_eq!(picked(Some("*/*")), Some("a.mp3".to_string()));
+    assert_eq!(picked(Some("audio/webm,audio/ogg,audio/wav,audio/*;q=0.9,*/*;q=0.5")),
+               Some("a.ogg".to_string()));
+    assert_eq!(picked(Some("audio/flac")), Some("a".to_string()));
+    assert_eq!(picked(Some("audio/*, audio/mpeg;q=0")), Some("a.ogg".to_string()));
+    assert_eq!(picked(Some("image/png")), None);
+}
+
+#[test]
+fn test_import_mime() {
+    let mp3: mime::Mime = "audio/mp3".parse().unwrap();
+    let octets: mime::Mime = "application/octet-stream".parse().unwrap();
+    assert_eq!(import_mime(&mp3, "").unwrap(), "audio/mpeg");
+    assert_eq!(import_mime(&octets, "word.FLAC").unwrap(), "audio/flac");
+    assert!(import_mime(&octets, "word.txt").is_err());
+}
+
+#[test]
+fn test_audio_processing() {
+    let processing = AudioProcessing::default();
+    assert!(processing.filter().ends_with("loudnorm=I=-16:TP=-1.5:LRA=11:print_format=json"));
+
+    let measured = AudioProcessing {
+        measured: Some(LoudnessMeasurement {
+                           input_i: "-27.61".into(),
+                           input_tp: "-4.47".into(),
+                           input_lra: "18.06".into(),
+                           input_thresh: "-39.20".into(),
+                           target_offset: "0.58".into(),
+                       }),
+        ..processing.clone()
+    };
+    assert!(measured.filter().contains("measured_I=-27.61:measured_TP=-4.47"));
+    assert!(measured.filter().ends_with("offset=0.58:linear=true"));
+    assert!(measured.same_settings(&processing));
+    assert!(!processing.same_settings(&AudioProcessing { target_lufs: -23.0, ..processing.clone() }));
 }
@@ -0,0 +1,112 @@
+use super::*;
+use serde::Serialize;
+
+/// Records a change made by a user in the audit log. The state of the target before and
+/// after the change is saved as JSON.
+///
+/// The mutation functions that are also used by the server itself (like the event
+/// transitions) take the actor as an `Option`: the changes without an actor aren't
+/// recorded here, as they have their own logs.
+pub fn record<T: Serialize + ?Sized>(conn: &Connection,
+                                     actor: i32,
+                                     action: &str,
+                                     target_type: &str,
+                                     target_id: Option<i32>,
+                                     before: Option<&T>,
+                                     after: Option<&T>)
+                                     -> Result<AuditEntry> {
+    use schema::audit_log;
+
+    let before = serialize_state(before)?;
+    let after = serialize_state(after)?;
+
+    let entry = diesel::insert(&NewAuditEntry {
+                                    actor: Some(actor),
+                                    action: action,
+                                    target_type: target_type,
+                                    target_id: target_id,
+                                    before: before.as_ref().map(|s| &**s),
+                                    after: after.as_ref().map(|s| &**s),
+                                }).into(audit_log::table)
+            .get_result(&**conn)?;
+
+    Ok(entry)
+}
+
+fn serialize_state<T: Serialize + ?Sized>(state: Option<&T>) -> Result<Option<String>> {
+    state.try_map(|s| serde_json::to_string(s))
+        .chain_err(|| "Couldn't serialize the audited item!")
+}
+
+/// Records a change that has no target state to save, like merging or removing
+/// a user's items.
+pub fn record_action(conn: &Connection,
+                     actor: i32,
+                     action: &str,
+                     target_type: &str,
+                     target_id: Option<i32>)
+                     -> Result<AuditEntry> {
+    record::<()>(conn, actor, action, target_type, target_id, None, None)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditFilter {
+    pub actor: Option<i32>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<i32>,
+    /// For paging: only the entries older than this one.
+    pub before_id: Option<i32>,
+}
+
+pub const MAX_ENTRIES: i64 = 500;
+
+/// The entries matching the filter, newest first.
+pub fn get_entries(conn: &Connection, filter: &AuditFilter, limit: i64) -> Result<Vec<AuditEntry>> {
+    use schema::audit_log;
+
+    let mut query = audit_log::table.order(audit_log::id.desc()).into_boxed();
+    if let Some(actor) = filter.actor {
+        query = query.filter(audit_log::actor.eq(actor));
+    }
+    if let Some(ref action) = filter.action {
+        query = query.filter(audit_log::action.eq(action.as_str()));
+    }
+    if let Some(ref target_type) = filter.target_type {
+        query = query.filter(audit_log::target_type.eq(target_type.as_str()));
+    }
+    if let Some(target_id) = filter.target_id {
+        query = query.filter(audit_log::target_id.eq(target_id));
+    }
+    if let Some(before_id) = filter.before_id {
+        query = query.filter(audit_log::id.lt(before_id));
+    }
+
+    Ok(query.limit(clamp_limit(limit)).get_results(&**conn)?)
+}
+
+fn clamp_limit(limit: i64) -> i64 {
+    if limit > 0 && limit < MAX_ENTRIES {
+        limit
+    } else {
+        MAX_ENTRIES
+    }
+}
+
+#[test]
+fn test_serialize_state() {
+    assert_eq!(serialize_state::<()>(None).unwrap(), None);
+    assert_eq!(serialize_state(Some(&42)).unwrap(), Some("42".to_string()));
+    assert_eq!(serialize_state(Some(&vec!["a", "b"])).unwrap(),
+               Some(r#"["a","b"]"#.to_string()));
+    assert_eq!(serialize_state(Some("word")).unwrap(), Some(r#""word""#.to_string()));
+}
+
+#[test]
+fn test_clamp_limit() {
+    assert_eq!(clamp_limit(100), 100);
+    assert_eq!(clamp_limit(1), 1);
+    assert_eq!(clamp_limit(0), MAX_ENTRIES);
+    assert_eq!(clamp_limit(-5), MAX_ENTRIES);
+    assert_eq!(clamp_limit(MAX_ENTRIES + 1), MAX_ENTRIES);
+}
@@ -46,7 +46,8 @@ lazy_static! {
 
 pub fn tidy_span_and_br_tags() -> Result<Vec<String>> {
     use ganbare_backend::schema::{words, question_answers};
-    let conn = db::connect(&*DATABASE_URL).unwrap();
+    let pooled_conn = get_pooled_conn();
+    let conn = &*pooled_conn;
 
     let mut logger = vec![];
 
@@ -57,10 +58,11 @@ pub fn tidy_span_and_br_tags() -> Result<Vec<String>> {
 
     let words: Vec<Word> = words::table.filter(words::explanation.like("%span%")
                                                    .or(words::explanation.like("%<br %")))
-        .get_results(&conn)?;
+        .get_results(conn)?;
 
     for mut w in words {
         let before = format!("{:?}", w);
+        let original = w.explanation.clone();
 
         w.explanation = r2.replace_all(&w.explanation, "").into_owned();
         w.explanation = w.explanation.replace(r3, "");
@@ -68,16 +70,18 @@ pub fn tidy_span_and_br_tags() -> Result<Vec<String>> {
 
         logger.push(format!("Tidied a span/br tag!\n{}\n→\n{:?}\n", before, w));
 
-        let _: Word = w.save_changes(&conn)?;
+        let _: Word = w.save_changes(conn)?;
+        record_cleanup(&pooled_conn, revision::WORD, w.id, &original, &w.explanation)?;
     }
 
     let answers: Vec<Answer> =
         question_answers::table.filter(question_answers::answer_text.like("%span%")
                                            .or(question_answers::answer_text.like("%<br %")))
-            .get_results(&conn)?;
+            .get_results(conn)?;
 
     for mut a in answers {
         let before = format!("{:?}", a);
+        let original = a.answer_text.clone();
 
         a.answer_text = r2.replace_all(&a.answer_text, "").into_owned();
         a.answer_text = a.answer_text.replace(r3, "");
@@ -85,7 +89,8 @@ pub fn tidy_span_and_br_tags() -> Result<Vec<String>> {
 
         logger.push(format!("Tidied a span/br tag!\n{}\n→\n{:?}\n", before, a));
 
-        let _: Answer = a.save_changes(&conn)?;
+        let _: Answer = a.save_changes(conn)?;
+        record_cleanup(&pooled_conn, revision::ANSWER, a.id, &original, &a.answer_text)?;
     }
 
     Ok(logger)
@@ -95,42 +100,58 @@ pub fn outbound_urls_to_inbound() -> Result<Vec<String>> {
     use ganbare_backend::schema::{words, question_answers};
     use ganbare_backend::manage::sanitize_links;
 
-    let conn = db::connect(&*DATABASE_URL).unwrap();
+    let pooled_conn = get_pooled_conn();
+    let conn = &*pooled_conn;
 
     let mut logger = vec![];
 
     let words: Vec<Word> = words::table.filter(words::explanation.like("%http://%")
                                                    .or(words::explanation.like("%https://%")))
-        .get_results(&conn)?;
+        .get_results(conn)?;
 
     for mut w in words {
         let before = format!("{:?}", w);
+        let original = w.explanation.clone();
         w.explanation = sanitize_links(&w.explanation, &*IMAGE_DIR)?;
         logger.push(format!("Converted an outbound image link to inbound!\n{}\n→\n{:?}\n",
                             before,
                             w));
 
-        let _: Word = w.save_changes(&conn)?;
+        let _: Word = w.save_changes(conn)?;
+        record_cleanup(&pooled_conn, revision::WORD, w.id, &original, &w.explanation)?;
     }
 
     let answers: Vec<Answer> =
         question_answers::table.filter(question_answers::answer_text.like("%http://%")
                 .or(question_answers::answer_text.like("%https://%")))
-            .get_results(&conn)?;
+            .get_results(conn)?;
 
     for mut a in answers {
         let before = format!("{:?}", a);
+        let original = a.answer_text.clone();
         a.answer_text = sanitize_links(&a.answer_text, &*IMAGE_DIR)?;
         logger.push(format!("Converted an outbound image link to inbound!\n{}\n→\n{:?}\n",
                             before,
                             a));
 
-        let _: Answer = a.save_changes(&conn)?;
+        let _: Answer = a.save_changes(conn)?;
+        record_cleanup(&pooled_conn, revision::ANSWER, a.id, &original, &a.answer_text)?;
     }
 
     Ok(logger)
 }
 
+/// Saves a text rewritten by the cleanup as a new revision of the item.
+fn record_cleanup(conn: &Connection,
+                  item_type: &str,
+                  id: i32,
+                  before: &str,
+                  after: &str)
+                  -> Result<()> {
+    revision::record(conn, item_type, id, before, after, None, revision::CLEANUP)?;
+    Ok(())
+}
+
 fn get_pooled_conn() -> Connection {
     let config = r2d2::Config::default();
     let manager = ConnManager::new(DATABASE_URL.as_str());
@@ -282,10 +303,11 @@ lazy_static! {
 fn add_br_between_images_and_text() {
     use ganbare_backend::schema::{question_answers, words};
 
-    let conn = db::connect(&*DATABASE_URL).unwrap();
+    let pooled_conn = get_pooled_conn();
+    let conn = &*pooled_conn;
 
     let words: Vec<Word> =
-        words::table.filter(words::explanation.like("%<img%")).get_results(&conn).unwrap();
+        words::table.filter(words::explanation.like("%<img%")).get_results(conn).unwrap();
 
     for mut w in words {
         let new_text = BR_IMG_REGEX.replace_all(&w.explanation, "$1<br>$2").into_owned();
@@ -293,14 +315,16 @@ fn add_br_between_images_and_text() {
             println!("Added a br tag:\n{:?}\n→\n{:?}\n",
                      w.explanation,
                      new_text);
-            w.explanation = new_text;
-            let _: Word = w.save_changes(&conn).unwrap();
+            let original = std::mem::replace(&mut w.explanation, new_text);
+            let _: Word = w.save_changes(conn).unwrap();
+            record_cleanup(&pooled_conn, revision::WORD, w.id, &original, &w.explanation)
+                .unwrap();
         }
     }
 
     let answers: Vec<Answer> =
         question_answers::table.filter(question_answers::answer_text.like("%<img%"))
-            .get_results(&conn)
+            .get_results(conn)
             .unwrap();
 
     for mut a in answers {
@@ -309,8 +333,10 @@ fn add_br_between_images_and_text() {
             println!("Added a br tag:\n{:?}\n→\n{:?}\n",
                      a.answer_text,
                      new_text);
-            a.answer_text = new_text;
-            let _: Answer = a.save_changes(&conn).unwrap();
+            let original = std::mem::replace(&mut a.answer_text, new_text);
+            let _: Answer = a.save_changes(conn).unwrap();
+            record_cleanup(&pooled_conn, revision::ANSWER, a.id, &original, &a.answer_text)
+                .unwrap();
         }
     }
 }
@@ -448,6 +474,46 @@ fn add_audio_file_hashes() {
     }
 }
 
+fn create_missing_renditions() {
+    use schema::audio_files;
+
+    let config = r2d2::Config::default();
+    let manager = ConnManager::new(DATABASE_URL.as_str());
+    let pool = r2d2::Pool::new(config, manager).expect("Failed to create pool.");
+    let pooled_conn = pool.get().unwrap();
+
+    let files: Vec<AudioFile> = audio_files::table.get_results(&*pooled_conn).unwrap();
+
+    for file in files {
+        match audio::create_renditions(&pooled_conn, &file, &*AUDIO_DIR) {
+            Ok(ref created) if created.is_empty() => (),
+            Ok(created) => println!("Created {} renditions for {}", created.len(), file.file_path),
+            Err(e) => println!("Couldn't create renditions for {}: {}", file.file_path, e),
+        }
+    }
+}
+
+fn normalize_audio(force: bool) {
+    use schema::audio_files;
+
+    let config = r2d2::Config::default();
+    let manager = ConnManager::new(DATABASE_URL.as_str());
+    let pool = r2d2::Pool::new(config, manager).expect("Failed to create pool.");
+    let pooled_conn = pool.get().unwrap();
+
+    let files: Vec<AudioFile> = audio_files::table.get_results(&*pooled_conn).unwrap();
+
+    for file in files {
+        if !force && audio::is_processed(&file) {
+            continue;
+        }
+        match audio::reprocess(&pooled_conn, &file, &*AUDIO_DIR) {
+            Ok(_) => println!("Processed {}", file.file_path),
+            Err(e) => println!("Couldn't process {}: {}", file.file_path, e),
+        }
+    }
+}
+
 fn check_skill_levels() {
     use schema::{quiz_questions, exercises};
 
@@ -548,10 +614,11 @@ fn fix_image_filenames() {
         }
     }
 
-    let conn = db::connect(&*DATABASE_URL).unwrap();
+    let pooled_conn = get_pooled_conn();
+    let conn = &*pooled_conn;
 
     let words: Vec<Word> =
-        words::table.filter(words::explanation.like("%<img%")).get_results(&conn).unwrap();
+        words::table.filter(words::explanation.like("%<img%")).get_results(conn).unwrap();
 
     for mut w in words {
 
@@ -565,16 +632,17 @@ fn fix_image_filenames() {
             if let Some(new_img) = files.get(img) {
                 w.explanation = w.explanation.replace(img, new_img);
 
-                let _: Word = w.save_changes(&conn).unwrap();
+                let _: Word = w.save_changes(conn).unwrap();
 
                 println!("{:?} → {:?}", img, new_img);
             }
         }
+        record_cleanup(&pooled_conn, revision::WORD, w.id, &w_expl, &w.explanation).unwrap();
     }
 
     let answers: Vec<Answer> =
         question_answers::table.filter(question_answers::answer_text.like("%<img%"))
-            .get_results(&conn)
+            .get_results(conn)
             .unwrap();
 
     for mut a in answers {
@@ -587,11 +655,12 @@ fn fix_image_filenames() {
             if let Some(new_img) = files.get(img) {
                 a.answer_text = a.answer_text.replace(img, new_img);
 
-                let _: Answer = a.save_changes(&conn).unwrap();
+                let _: Answer = a.save_changes(conn).unwrap();
 
                 println!("{:?} → {:?}", img, new_img);
             }
         }
+        record_cleanup(&pooled_conn, revision::ANSWER, a.id, &a_text, &a.answer_text).unwrap();
     }
 
 }
@@ -615,12 +684,14 @@ fn replace_images() {
         original_filenames.insert(fname[0..fname.len() - 4].to_owned(), None);
     }
 
-    let conn = db::connect(&*DATABASE_URL).unwrap();
+    let pooled_conn = get_pooled_conn();
+    let conn = &*pooled_conn;
 
     let words: Vec<Word> =
-        words::table.filter(words::explanation.like("%<img%")).get_results(&conn).unwrap();
+        words::table.filter(words::explanation.like("%<img%")).get_results(conn).unwrap();
 
     for mut w in words {
+        let original = w.explanation.clone();
         for &(ref fname, ref ext) in &image_names {
             if w.explanation.contains(fname) {
 
@@ -639,18 +710,20 @@ fn replace_images() {
                 if w.explanation != stitched {
                     println!("{} → {}", w.explanation, stitched);
                     w.explanation = stitched;
-                    let _: Word = w.save_changes(&conn).unwrap();
+                    let _: Word = w.save_changes(conn).unwrap();
                 }
             }
         }
+        record_cleanup(&pooled_conn, revision::WORD, w.id, &original, &w.explanation).unwrap();
     }
 
     let answers: Vec<Answer> =
         question_answers::table.filter(question_answers::answer_text.like("%<img%"))
-            .get_results(&conn)
+            .get_results(conn)
             .unwrap();
 
     for mut a in answers {
+        let original = a.answer_text.clone();
         for &(ref fname, ref ext) in &image_names {
             if a.answer_text.contains(fname) {
 
@@ -669,11 +742,12 @@ fn replace_images() {
                 if a.answer_text != stitched {
                     println!("{} → {}", a.answer_text, stitched);
                     a.answer_text = stitched;
-                    let _: Answer = a.save_changes(&conn).unwrap();
+                    let _: Answer = a.save_changes(conn).unwrap();
                 }
 
             }
         }
+        record_cleanup(&pooled_conn, revision::ANSWER, a.id, &original, &a.answer_text).unwrap();
     }
 
     let original_images =
@@ -805,17 +879,27 @@ fn missing_images() {
 }
 
 fn check_tests() {
-    use quiz::QuizSerialized;
-
-    let pre_quiz = include!("../../../pretest.rs");
-    let post_quiz = include!("../../../posttest.rs");
-    let quizes = pre_quiz.into_iter().chain(post_quiz.into_iter());
 
     let conn = get_pooled_conn();
 
-    for i in quizes {
-        match i {
-            QuizSerialized::Word(s, audio_id) => {
+    let items = test::get_all_items(&conn).unwrap();
+
+    for i in items {
+        let audio_id = match i.audio_id {
+            Some(audio_id) => audio_id,
+            None => {
+                if i.item_type != "retelling" {
+                    println!("Test item {} ({} {:?}) has no audio! Skipping it.",
+                             i.id,
+                             i.item_type,
+                             i.item_text);
+                }
+                continue;
+            }
+        };
+        let s = i.item_text.as_str();
+        match i.item_type.as_str() {
+            "word" => {
                 let w = quiz::get_word_by_str(&conn, s)
                     .chain_err(|| format!("Word {} not found", s))
                     .unwrap();
@@ -829,7 +913,7 @@ fn check_tests() {
                            a.id);
                 }
             }
-            QuizSerialized::Question(s, audio_id) => {
+            "question" => {
 
                 let (_, ans) = quiz::get_question(&conn, s)
                     .chain_err(|| format!("Question {} not found", s))
@@ -845,10 +929,10 @@ fn check_tests() {
                            a.id);
                 }
             }
-            QuizSerialized::Exercise(word, audio_id) => {
+            "exercise" => {
 
-                let (_, var) = quiz::get_exercise(&conn, word)
-                    .chain_err(|| format!("Exercise {} not found", word))
+                let (_, var) = quiz::get_exercise(&conn, s)
+                    .chain_err(|| format!("Exercise {} not found", s))
                     .unwrap();
 
                 let w = quiz::get_word_by_id(&conn, var.id).unwrap();
@@ -862,6 +946,7 @@ fn check_tests() {
                            a.id);
                 }
             }
+            _ => (),
         };
     }
 }
@@ -872,8 +957,20 @@ fn main() {
     env_logger::init().unwrap();
     info!("Starting.");
 
-    App::new("ganba.re audio cleaning tool").version(crate_version!());
+    let matches = App::new("ganba.re audio cleaning tool")
+        .version(crate_version!())
+        .subcommand(SubCommand::with_name("normalize_audio")
+                        .about("Trim the silence and normalize the loudness of the audio files \
+                                processed with older settings, or not at all")
+                        .arg(Arg::with_name("force")
+                                 .long("force")
+                                 .help("Process all the audio files again")))
+        .get_matches();
 
+    if let Some(args) = matches.subcommand_matches("normalize_audio") {
+        normalize_audio(args.is_present("force"));
+        return;
+    }
 
     for line in outbound_urls_to_inbound().unwrap() {
         println!("{}", line);
@@ -895,6 +992,8 @@ fn main() {
     fix_skill_names();
     println!("Add audio file hashes for files that are still missing them.");
     add_audio_file_hashes();
+    println!("Create the audio renditions that are missing.");
+    create_missing_renditions();
     println!("Fix skill levels (questions and exercises ought to have at least skill level 2).");
     check_skill_levels();
     println!("Fix priority levels (words that are accompanied by sentences ought to have higher priority levels).");
@@ -90,14 +90,14 @@ fn import_batch(path: &str, narrator: &str, sentences: bool) {
 
         std::fs::copy(path, &temp_file_path).expect("copying files");
 
-        if extension != "mp3" {
-            println!("Only mp3 supported at the moment.");
-            continue;
+        let mime: mime::Mime = match audio::mime_by_extension(&extension) {
+            Some(mime) => mime.parse().unwrap(),
+            None => {
+                println!("Unsupported audio format: {:?}. Skipping.", extension);
+                continue;
+            }
         };
 
-        use std::str::FromStr;
-        let mime = mime::Mime::from_str("audio/mpeg").unwrap();
-
         let files = vec![(temp_file_path, Some(file_name), mime)];
 
         let w = if sentences {
@@ -111,8 +111,72 @@ fn import_batch(path: &str, narrator: &str, sentences: bool) {
 
         println!("{:?}", w);
 
-        manage::create_or_update_word(&pooled_conn, w, &AUDIO_DIR).unwrap();
+        manage::create_or_update_word(&pooled_conn, None, w, &AUDIO_DIR).unwrap();
+    }
+}
+
+fn import_manifest(path: &str, narrator: &str, dry_run: bool) {
+    use manifest::Action;
+
+    let config = r2d2::Config::default();
+    let manager = ConnManager::new(DATABASE_URL.as_str());
+    let pool = r2d2::Pool::new(config, manager).expect("Failed to create pool.");
+    let pooled_conn = pool.get().unwrap();
+
+    let path = std::path::Path::new(path);
+    let base_dir = path.parent().unwrap_or(std::path::Path::new(""));
+    let mut text = String::new();
+    {
+        use std::io::Read;
+        std::fs::File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut text))
+            .expect("Couldn't read the manifest!");
     }
+
+    let rows = match manifest::parse(&text, manifest::delimiter_of(path)) {
+        Ok(rows) => rows,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let (mut created, mut updated, mut skipped, mut failed) = (0, 0, 0, 0);
+    for (line, row) in rows {
+        let action = row.and_then(|row| {
+            let action = if dry_run {
+                manifest::plan(&pooled_conn, &row, base_dir)?
+            } else {
+                manifest::apply(&pooled_conn, &row, base_dir, &AUDIO_DIR, narrator)?
+            };
+            Ok((row.word, action))
+        });
+        match action {
+            Ok((word, Action::Create)) => {
+                created += 1;
+                println!("Line {}: create {}", line, word);
+            }
+            Ok((word, Action::Update(changed))) => {
+                updated += 1;
+                println!("Line {}: update {} ({})", line, word, changed.join(", "));
+            }
+            Ok((word, Action::Skip)) => {
+                skipped += 1;
+                println!("Line {}: skip {} (unchanged)", line, word);
+            }
+            Err(e) => {
+                failed += 1;
+                println!("Line {}: error: {}", line, e);
+            }
+        }
+    }
+
+    println!("{}{} created, {} updated, {} skipped, {} errors.",
+             if dry_run { "Dry run: " } else { "" },
+             created,
+             updated,
+             skipped,
+             failed);
 }
 
 fn full_sentence<'a>(conn: &Connection,
@@ -255,6 +319,17 @@ fn main() {
             .short("s")
             .long("sentences")
             .help("Flag to enable importing sentences"))
+        .arg(Arg::with_name("manifest")
+            .short("m")
+            .long("manifest")
+            .help("Import the words listed in a CSV or TSV manifest at PATH. \
+                   NARRATOR is used for the rows that don't name a narrator")
+            .conflicts_with("sentences"))
+        .arg(Arg::with_name("dry-run")
+            .short("n")
+            .long("dry-run")
+            .requires("manifest")
+            .help("Print what importing the manifest would create, update or skip"))
         .arg(Arg::with_name("PATH")
             .required(true)
             .value_name("PATH")
@@ -272,5 +347,9 @@ fn main() {
     let path = matches.value_of("PATH").unwrap();
     let narrator = matches.value_of("NARRATOR").unwrap();
 
-    import_batch(path, narrator, sentences);
+    if matches.is_present("manifest") {
+        import_manifest(path, narrator, matches.is_present("dry-run"));
+    } else {
+        import_batch(path, narrator, sentences);
+    }
 }
@@ -18,17 +18,16 @@ use handlebars::Handlebars;
 use ganbare_backend::errors::*;
 use ganbare_backend::user::*;
 use ganbare_backend::email;
+use ganbare_backend::password;
+use ganbare_backend::mailer;
+use ganbare_backend::i18n;
+use ganbare_backend::role;
 use rustc_serialize::base64::FromBase64;
 use std::net::{SocketAddr, ToSocketAddrs};
 use diesel::LoadDsl;
 use std::env;
 use std::time::Duration;
 use ganbare_backend::ConnManager;
-use std::sync::RwLock;
-use std::collections::VecDeque;
-use lettre::email::Email;
-use lettre::transport::smtp::SmtpTransportBuilder;
-use lettre::transport::EmailTransport;
 
 
 lazy_static! {
@@ -49,8 +48,12 @@ lazy_static! {
         hmac_key
     };
 
-    pub static ref MAIL_QUEUE: RwLock<VecDeque<Email>> =
-        RwLock::new(VecDeque::new());
+    pub static ref EMAIL_MAX_ATTEMPTS: i32 = {
+        dotenv::dotenv().ok();
+        env::var("GANBARE_EMAIL_MAX_ATTEMPTS")
+            .map(|s| s.parse().unwrap_or(10))
+            .unwrap_or(10)
+    };
 
     pub static ref PASSWORD_STRETCHING_TIME: Duration = {
         dotenv::dotenv().ok();
@@ -83,6 +86,16 @@ lazy_static! {
         pepper
     };
 
+    static ref OLD_RUNTIME_PEPPERS : Vec<Vec<u8>> = {
+        dotenv::dotenv().ok();
+        env::var("GANBARE_OLD_RUNTIME_PEPPERS")
+            .map(|s| password::parse_peppers(&s).expect(
+                "Environmental variable GANBARE_OLD_RUNTIME_PEPPERS isn't valid! \
+                (format: comma-separated list of 256-bit values encoded as base64)"
+            ))
+            .unwrap_or_else(|_| vec![])
+    };
+
     pub static ref SITE_DOMAIN : String = {
         dotenv::dotenv().ok();
         env::var("GANBARE_SITE_DOMAIN")
@@ -123,6 +136,25 @@ lazy_static! {
             .unwrap_or_else(|_| "".into())
     };
 
+    pub static ref EMAIL_SMTP_ENCRYPT : bool = {
+        dotenv::dotenv().ok();
+        env::var("GANBARE_EMAIL_SMTP_ENCRYPT")
+            .map(|s| s.parse().unwrap_or(true))
+            .unwrap_or(true)
+    };
+
+    pub static ref EMAIL_TRANSPORT : String = {
+        dotenv::dotenv().ok();
+        env::var("GANBARE_EMAIL_TRANSPORT")
+            .unwrap_or_else(|_| "smtp".into())
+    };
+
+    pub static ref EMAIL_DIR : String = {
+        dotenv::dotenv().ok();
+        env::var("GANBARE_EMAIL_DIR")
+            .unwrap_or_else(|_| "emails".into())
+    };
+
     pub static ref EMAIL_DOMAIN : String = {
         dotenv::dotenv().ok();
         env::var("GANBARE_EMAIL_DOMAIN")
@@ -171,12 +203,24 @@ fn main() {
                                                                              .required(true)))
         .subcommand(SubCommand::with_name("add")
                         .about("Add a new user")
-                        .arg(Arg::with_name("email").required(true)))
+                        .arg(Arg::with_name("email").required(true))
+                        .arg(Arg::with_name("locale")
+                                 .long("locale")
+                                 .takes_value(true)
+                                 .help("The language of the emails and the UI (fi, en or ja)")))
         .subcommand(SubCommand::with_name("force_add")
                         .about("Add a new user without email confirmation")
                         .arg(Arg::with_name("email").required(true)))
         .subcommand(SubCommand::with_name("login").about("Login").arg(Arg::with_name("email")
                                                                           .required(true)))
+        .subcommand(SubCommand::with_name("grant")
+                        .about("Give a role (like admin, editor or researcher) to a user")
+                        .arg(Arg::with_name("email").required(true))
+                        .arg(Arg::with_name("role").required(true)))
+        .subcommand(SubCommand::with_name("revoke")
+                        .about("Take a role away from a user")
+                        .arg(Arg::with_name("email").required(true))
+                        .arg(Arg::with_name("role").required(true)))
         .get_matches();
     let config = r2d2::Config::default();
     let manager = ConnManager::new(DATABASE_URL.as_str());
@@ -232,6 +276,7 @@ fn main() {
         ("add", Some(args)) => {
             use ganbare_backend::errors::ErrorKind::NoSuchUser;
             let email = args.value_of("email").unwrap();
+            let locale = args.value_of("locale").unwrap_or(i18n::DEFAULT_LOCALE);
             match get_user_by_email(&pooled_conn, email) {
                 Err(Error(kind, _)) => {
                     match kind {
@@ -250,36 +295,45 @@ fn main() {
             let (secret, hmac) = match email::add_pending_email_confirm(&pooled_conn,
                                                    &**COOKIE_HMAC_KEY,
                                                    email,
-                                                   &[]) {
+                                                   &[],
+                                                   locale) {
                 Ok(secret) => secret,
                 Err(e) => {
                     println!("Error: {:?}", e);
                     return;
                 }
             };
-            match email::send_confirmation(&*MAIL_QUEUE,
+            match email::send_confirmation(&pooled_conn,
                                            email,
                                            secret.as_str(),
                                            hmac.as_str(),
                                            &*SITE_DOMAIN,
                                            &*SITE_LINK,
                                            &handlebars,
-                                           (&*EMAIL_ADDRESS, &*EMAIL_NAME)) {
+                                           (&*EMAIL_ADDRESS, &*EMAIL_NAME),
+                                           locale) {
                 Ok(u) => {
 
-                    let mut mailer = SmtpTransportBuilder::new(&*EMAIL_SERVER)
-                        .expect("Couldn't setup the email transport!")
-                        .encrypt()
-                        .credentials(EMAIL_SMTP_USERNAME.as_str(), EMAIL_SMTP_PASSWORD.as_str())
-                        .build();
+                    let mut mailer = mailer::from_config(&*EMAIL_TRANSPORT, &*EMAIL_DIR, || {
+                            mailer::SmtpConfig {
+                                server: *EMAIL_SERVER,
+                                username: &*EMAIL_SMTP_USERNAME,
+                                password: &*EMAIL_SMTP_PASSWORD,
+                                encrypt: *EMAIL_SMTP_ENCRYPT,
+                            }
+                        })
+                        .expect("Couldn't setup the email transport!");
 
-                    if let Ok(mut mails) = MAIL_QUEUE.write() {
-                        for email in mails.drain(..) {
-                            mailer.send(email).expect("Couldn't send email!");
+                    match email::send_queued_mails(&pooled_conn,
+                                                   *EMAIL_MAX_ATTEMPTS,
+                                                   &mut *mailer) {
+                        Ok((0, _)) => {
+                            println!("Couldn't send the email right now. It stays in the queue \
+                                      and the server retries sending it.")
                         }
+                        Ok(_) => println!("Sent an email confirmation! {:?}", u),
+                        Err(e) => println!("Error: {}", e),
                     }
-
-                    println!("Sent an email confirmation! {:?}", u)
                 }
                 Err(err_chain) => {
                     for err in err_chain.iter() {
@@ -329,7 +383,12 @@ fn main() {
                 }
                 Ok(pw) => pw,
             };
-            match auth_user(&pooled_conn, email, &password, &*RUNTIME_PEPPER) {
+            match auth_user(&pooled_conn,
+                            email,
+                            &password,
+                            &*RUNTIME_PEPPER,
+                            &*OLD_RUNTIME_PEPPERS,
+                            *PASSWORD_STRETCHING_TIME) {
                 Ok(u) => println!("Logged in successfully: {:?}", u),
                 Err(err_chain) => {
                     for err in err_chain.iter() {
@@ -338,6 +397,30 @@ fn main() {
                 }
             }
         }
+        (subcommand @ "grant", Some(args)) |
+        (subcommand @ "revoke", Some(args)) => {
+            let email = args.value_of("email").unwrap();
+            let role_name = args.value_of("role").unwrap();
+            let user = match get_user_by_email(&pooled_conn, email) {
+                Ok(Some(u)) => u,
+                Ok(None) => return println!("Error: No such user: {}", email),
+                Err(e) => return println!("Error: {:?}", e),
+            };
+            let role = match role::get_by_name(&pooled_conn, role_name) {
+                Ok(Some(r)) => r,
+                Ok(None) => return println!("Error: No such role: {}", role_name),
+                Err(e) => return println!("Error: {:?}", e),
+            };
+            let result = if subcommand == "grant" {
+                role::assign(&pooled_conn, None, user.id, role.id)
+            } else {
+                role::unassign(&pooled_conn, None, user.id, role.id).map(|_| ())
+            };
+            match result {
+                Ok(()) => println!("Success! The roles of {} were updated.", email),
+                Err(e) => println!("Error: {}", e),
+            }
+        }
         _ => {
             // clap should exit before reaching here if none of the subcommands are entered.
             unreachable!();
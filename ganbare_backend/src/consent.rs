@@ -0,0 +1,142 @@
+use super::*;
+use std::collections::{HashMap, HashSet};
+
+/// The newest published version of the consent document, if any is published.
+pub fn latest_version(conn: &Connection) -> Result<Option<i32>> {
+    use schema::consent_documents;
+    use diesel::expression::dsl::max;
+
+    Ok(consent_documents::table.select(max(consent_documents::version))
+           .first::<Option<i32>>(&**conn)?)
+}
+
+/// The version of the document in the locale. Falls back to the default locale, and then
+/// to any translation there is.
+pub fn get_document(conn: &Connection,
+                    version: i32,
+                    locale: &str)
+                    -> Result<Option<ConsentDocument>> {
+    use schema::consent_documents;
+
+    let mut documents: Vec<ConsentDocument> =
+        consent_documents::table.filter(consent_documents::version.eq(version))
+            .order(consent_documents::id)
+            .get_results(&**conn)?;
+
+    let index = documents.iter()
+        .position(|d| d.locale == locale)
+        .or_else(|| documents.iter().position(|d| d.locale == i18n::DEFAULT_LOCALE));
+    match index {
+        Some(i) => Ok(Some(documents.swap_remove(i))),
+        None => Ok(documents.into_iter().next()),
+    }
+}
+
+pub fn latest_document(conn: &Connection, locale: &str) -> Result<Option<ConsentDocument>> {
+    match latest_version(conn)? {
+        Some(version) => get_document(conn, version, locale),
+        None => Ok(None),
+    }
+}
+
+/// Whether the document is a translation of the newest version. Decisions on the older
+/// versions aren't accepted anymore.
+pub fn is_current(conn: &Connection, document_id: i32) -> Result<bool> {
+    use schema::consent_documents;
+
+    let version: Option<i32> =
+        consent_documents::table.filter(consent_documents::id.eq(document_id))
+            .select(consent_documents::version)
+            .get_result(&**conn)
+            .optional()?;
+    Ok(version.is_some() && version == latest_version(conn)?)
+}
+
+pub fn get_all(conn: &Connection) -> Result<Vec<ConsentDocument>> {
+    use schema::consent_documents;
+
+    Ok(consent_documents::table.order((consent_documents::version, consent_documents::locale))
+           .get_results(&**conn)?)
+}
+
+/// Publishes a new version of the consent document, or a translation of the newest version.
+/// A new version asks the users who agreed to the earlier versions to consent again.
+pub fn publish(conn: &Connection, document: &NewConsentDocument) -> Result<ConsentDocument> {
+    use schema::consent_documents;
+
+    i18n::check_locale(&document.locale)?;
+
+    let latest = latest_version(conn)?.unwrap_or(0);
+    if document.version != latest && document.version != latest + 1 {
+        bail!(ErrorKind::InvalidInput);
+    }
+
+    let document: ConsentDocument = diesel::insert(document).into(consent_documents::table)
+        .get_result(&**conn)?;
+    info!("Published the consent document version {} in {}.",
+          document.version,
+          document.locale);
+
+    Ok(document)
+}
+
+/// Records the user's decision on the document.
+pub fn decide(conn: &Connection, user_id: i32, document_id: i32, agreed: bool) -> Result<Consent> {
+    use schema::consents;
+
+    let consent: Consent = diesel::insert(&NewConsent {
+                                               user_id: user_id,
+                                               document_id: document_id,
+                                               agreed: agreed,
+                                           }).into(consents::table)
+            .get_result(&**conn)?;
+    info!("User {} {} the consent document {}.",
+          user_id,
+          if agreed { "agreed to" } else { "declined" },
+          document_id);
+
+    Ok(consent)
+}
+
+/// The decisions of the user, with the versions of the documents, oldest first.
+pub fn get_consents(conn: &Connection, user_id: i32) -> Result<Vec<(Consent, i32)>> {
+    use schema::{consents, consent_documents};
+
+    let consents: Vec<(Consent, ConsentDocument)> =
+        consents::table.inner_join(consent_documents::table)
+            .filter(consents::user_id.eq(user_id))
+            .order(consents::decided)
+            .get_results(&**conn)?;
+
+    Ok(consents.into_iter().map(|(c, d)| (c, d.version)).collect())
+}
+
+/// Whether the user has to be asked for consent again: they have decided on an earlier
+/// version, or declined the newest one. Users that haven't decided on any version
+/// are asked by the consent event instead.
+pub fn needs_consent(conn: &Connection, user_id: i32) -> Result<bool> {
+    let latest = try_or!(latest_version(conn)?, else return Ok(false));
+
+    match get_consents(conn, user_id)?.pop() {
+        Some((consent, version)) => Ok(!consent.agreed || version != latest),
+        None => Ok(false),
+    }
+}
+
+/// The users whose latest decision is agreeing to a version of the consent document.
+pub fn consenting_users(conn: &Connection) -> Result<HashSet<i32>> {
+    use schema::consents;
+
+    let consents: Vec<Consent> = consents::table.order(consents::decided)
+        .get_results(&**conn)?;
+
+    let mut latest = HashMap::new();
+    for c in consents {
+        latest.insert(c.user_id, c.agreed);
+    }
+
+    Ok(latest.into_iter()
+           .filter(|&(_, agreed)| agreed)
+           .map(|(user_id, _)| user_id)
+           .collect())
+}
@@ -5,10 +5,11 @@ use std::time::Duration;
 
 use self::lettre::email::{EmailBuilder, Email};
 use self::pencil::Handlebars;
-use std::sync::RwLock;
-use std::collections::{VecDeque, BTreeMap};
+use std::collections::BTreeMap;
+use std::cmp::{min, max};
 use rustc_serialize::json::{Json, ToJson};
 use email::lettre::email::SendableEmail;
+use mailer::{Mailer, OutgoingMail};
 
 use schema::pending_email_confirms;
 use super::*;
@@ -19,6 +20,9 @@ struct EmailData<'a> {
     hmac: &'a str,
     site_link: &'a str,
     site_name: &'a str,
+    locale: &'a str,
+    /// The texts of the template in the locale of the recipient.
+    t: BTreeMap<&'static str, &'static str>,
 }
 
 impl<'a> ToJson for EmailData<'a> {
@@ -28,31 +32,197 @@ impl<'a> ToJson for EmailData<'a> {
         m.insert("hmac".to_string(), self.hmac.to_json());
         m.insert("site_link".to_string(), self.site_link.to_json());
         m.insert("site_name".to_string(), self.site_name.to_json());
+        m.insert("locale".to_string(), self.locale.to_json());
+        let t: BTreeMap<String, Json> = self.t
+            .iter()
+            .map(|(&key, &text)| (key.to_string(), text.to_json()))
+            .collect();
+        m.insert("t".to_string(), t.to_json());
         m.to_json()
     }
 }
 
-fn enqueue_mail(email: Email, queue: &RwLock<VecDeque<Email>>) -> Result<()> {
+/// The delay before the first retry. It's doubled on every failed attempt.
+const RETRY_BASE_DELAY_SECS: i64 = 60;
+const MAX_RETRY_DELAY_SECS: i64 = 6 * 60 * 60;
+/// How many mails are sent from the queue at once.
+const SEND_BATCH_SIZE: i64 = 50;
+/// A claimed mail isn't picked up by other senders for this long. If the sender dies
+/// while sending, the mail is tried again after that.
+const CLAIM_LEASE_SECS: i64 = 10 * 60;
+
+/// The sender, the recipients and the message of the mail as they are stored in the queue.
+fn envelope(email: &Email) -> (String, String, String) {
+    (email.from_address(), email.to_addresses().join(","), email.message())
+}
+
+fn enqueue_mail(conn: &Connection, email: Email, subject: &str) -> Result<()> {
+    use schema::email_queue;
+
+    let (from_address, to_address, message) = envelope(&email);
+
+    info!("Enqueuing mail to {:?}", to_address);
+
+    diesel::insert(&NewQueuedEmail {
+                        from_address: &from_address,
+                        to_address: &to_address,
+                        subject: subject,
+                        message: &message,
+                    }).into(email_queue::table)
+            .execute(&**conn)
+            .chain_err(|| "Couldn't enqueue the email.")?;
+    Ok(())
+}
+
+/// How long to wait before the next attempt after `attempts` failed attempts.
+pub fn retry_delay(attempts: i32) -> chrono::Duration {
+    let doublings = min(max(attempts - 1, 0), 20) as u32;
+    chrono::Duration::seconds(min(RETRY_BASE_DELAY_SECS << doublings, MAX_RETRY_DELAY_SECS))
+}
+
+/// Hands the mail over to the mailer and records the outcome in it: either sent,
+/// due again after the backoff, or failed for good after `max_attempts`.
+fn deliver(mail: &mut QueuedEmail, mailer: &mut Mailer, max_attempts: i32) {
+    let outgoing = OutgoingMail {
+        from_address: mail.from_address.clone(),
+        to_addresses: mail.to_address.split(',').map(|a| a.to_string()).collect(),
+        message: mail.message.clone(),
+    };
+    mail.attempts += 1;
 
-    info!("Enqueuing mail to {:?}", email.to_addresses());
+    match mailer.send(outgoing) {
+        Ok(()) => {
+            mail.status = "sent".to_string();
+            mail.sent = Some(chrono::UTC::now());
+            mail.last_error = None;
+        }
+        Err(e) => {
+            warn!("Couldn't send email {} to {} (attempt {}): {}",
+                  mail.id,
+                  mail.to_address,
+                  mail.attempts,
+                  e);
+            if mail.attempts >= max_attempts {
+                mail.status = "failed".to_string();
+            } else {
+                mail.next_attempt = chrono::UTC::now() + retry_delay(mail.attempts);
+            }
+            mail.last_error = Some(e);
+        }
+    }
+}
+
+/// Sends the queued mails that are due. Failed mails are retried
+/// with an exponential backoff until `max_attempts` is reached; then they are marked as failed.
+/// Returns the number of sent and failed mails.
+///
+/// Both the server and the command line tools send mails, so each mail is claimed
+/// before sending it, and a mail claimed by somebody else is skipped.
+pub fn send_queued_mails(conn: &Connection,
+                         max_attempts: i32,
+                         mailer: &mut Mailer)
+                         -> Result<(usize, usize)> {
+    use schema::email_queue;
+
+    let now = chrono::UTC::now();
+
+    let due: Vec<i32> = email_queue::table.filter(email_queue::status.eq("pending"))
+        .filter(email_queue::next_attempt.le(now))
+        .order(email_queue::id.asc())
+        .limit(SEND_BATCH_SIZE)
+        .select(email_queue::id)
+        .get_results(&**conn)?;
 
-    match queue.write() {
-        Ok(mut q) => {
-            q.push_back(email);
-            Ok(())
+    let (mut sent, mut failed) = (0, 0);
+
+    for id in due {
+        // The conditions are checked again when the row is updated, so only one
+        // of the concurrent senders gets the mail.
+        let claimed: Option<QueuedEmail> =
+            diesel::update(email_queue::table.filter(email_queue::id.eq(id))
+                               .filter(email_queue::status.eq("pending"))
+                               .filter(email_queue::next_attempt.le(now)))
+                .set(email_queue::next_attempt
+                         .eq(chrono::UTC::now() + chrono::Duration::seconds(CLAIM_LEASE_SECS)))
+                .get_result(&**conn)
+                .optional()?;
+        let mut mail = try_or!(claimed, else continue);
+
+        deliver(&mut mail, mailer, max_attempts);
+        match mail.status.as_str() {
+            "sent" => sent += 1,
+            "failed" => failed += 1,
+            _ => (),
         }
-        _ => Err(Error::from_kind("Couldn't open the email queue for writing.".into())),
+
+        let _: QueuedEmail = mail.save_changes(&**conn)?;
     }
+
+    Ok((sent, failed))
+}
+
+pub fn get_queued_mails(conn: &Connection, status: &str) -> Result<Vec<QueuedEmail>> {
+    use schema::email_queue;
+
+    let mails = email_queue::table.filter(email_queue::status.eq(status))
+        .order(email_queue::id.desc())
+        .get_results(&**conn)?;
+    Ok(mails)
+}
+
+/// Puts a failed mail back to the queue with a fresh set of attempts.
+pub fn retry_mail(conn: &Connection, id: i32) -> Result<Option<QueuedEmail>> {
+    use schema::email_queue;
+
+    let mail = diesel::update(email_queue::table.filter(email_queue::id.eq(id))
+                                  .filter(email_queue::status.eq("failed")))
+            .set((email_queue::status.eq("pending"),
+                  email_queue::attempts.eq(0),
+                  email_queue::next_attempt.eq(chrono::UTC::now())))
+            .get_result(&**conn)
+            .optional()?;
+    Ok(mail)
+}
+
+pub fn clean_sent_mails(conn: &Connection, duration: chrono::Duration) -> Result<usize> {
+    use schema::email_queue;
+    let deadline = chrono::UTC::now() - duration;
+    diesel::delete(email_queue::table.filter(email_queue::status.eq("sent"))
+                       .filter(email_queue::sent.lt(deadline)))
+        .execute(&**conn)
+        .chain_err(|| "Couldn't delete the old sent mails.")
+}
+
+fn confirmation_email(email_addr: &str,
+                      data: &EmailData,
+                      hb_registry: &Handlebars,
+                      from: (&str, &str),
+                      locale: &str)
+                      -> Result<(Email, String)> {
+    let subject = format!("【{}】{}",
+                          data.site_name,
+                          i18n::message(locale, "email_confirm_subject"));
+    let email = EmailBuilder::new()
+        .to(email_addr)
+        .from(from)
+        .subject(&subject)
+        .html(hb_registry.render("email_confirm_email.html", data)
+                  .chain_err(|| "Handlebars template render error!")?
+                  .as_ref())
+        .build()
+        .expect("Building email shouldn't fail.");
+    Ok((email, subject))
 }
 
-pub fn send_confirmation(queue: &RwLock<VecDeque<Email>>,
+pub fn send_confirmation(conn: &Connection,
                          email_addr: &str,
                          secret: &str,
                          hmac: &str,
                          site_name: &str,
                          site_link: &str,
                          hb_registry: &Handlebars,
-                         from: (&str, &str))
+                         from: (&str, &str),
+                         locale: &str)
                          -> Result<()> {
 
     let data = EmailData {
@@ -60,21 +230,15 @@ pub fn send_confirmation(queue: &RwLock<VecDeque<Email>>,
         hmac: hmac,
         site_link: site_link,
         site_name: site_name,
+        locale: locale,
+        t: i18n::template_messages(locale, "email_confirm_email.html"),
     };
-    let email = EmailBuilder::new()
-        .to(email_addr)
-        .from(from)
-        .subject(&format!("【{}】Tervetuloa!", site_name))
-        .html(hb_registry.render("email_confirm_email.html", &data)
-                  .chain_err(|| "Handlebars template render error!")?
-                  .as_ref())
-        .build()
-        .expect("Building email shouldn't fail.");
-    enqueue_mail(email, queue)?;
+    let (email, subject) = confirmation_email(email_addr, &data, hb_registry, from, locale)?;
+    enqueue_mail(conn, email, &subject)?;
     Ok(())
 }
 
-pub fn send_pw_reset_email(queue: &RwLock<VecDeque<Email>>,
+pub fn send_pw_reset_email(conn: &Connection,
                            secret: &ResetEmailSecrets,
                            hmac: &str,
                            site_name: &str,
@@ -83,33 +247,50 @@ pub fn send_pw_reset_email(queue: &RwLock<VecDeque<Email>>,
                            from: (&str, &str))
                            -> Result<()> {
 
+    let locale = user::get_user(conn, secret.user_id)?.locale;
+
     let data = EmailData {
         secret: &secret.secret,
         hmac: hmac,
         site_link: site_link,
         site_name: site_name,
+        locale: &locale,
+        t: i18n::template_messages(&locale, "pw_reset_email.html"),
     };
+    let subject = format!("【{}】{}",
+                          site_name,
+                          i18n::message(&locale, "pw_reset_subject"));
     let email = EmailBuilder::new()
         .to(secret.email.as_str())
         .from(from)
-        .subject(&format!("【{}】Salasanan vaihtaminen", site_name))
+        .subject(&subject)
         .html(hb_registry.render("pw_reset_email.html", &data)
                   .chain_err(|| "Handlebars template render error!")?
                   .as_ref())
         .build()
         .expect("Building email shouldn't fail.");
-    enqueue_mail(email, queue)?;
+    enqueue_mail(conn, email, &subject)?;
     Ok(())
 }
 
-pub fn send_freeform_email<'a, ITER: Iterator<Item = &'a str>>(queue: &RwLock<VecDeque<Email>>,
+#[derive(Serialize)]
+struct FreeformEmail<'a> {
+    subject: &'a str,
+    body: &'a str,
+    recipients: usize,
+}
+
+pub fn send_freeform_email<'a, ITER: Iterator<Item = &'a str>>(conn: &Connection,
+                                                               actor: i32,
                                                                from: (&str, &str),
                                                                to: ITER,
                                                                subject: &str,
                                                                body: &str)
                                                                -> Result<()> {
 
+    let mut recipients = 0;
     for to in to {
+        recipients += 1;
 
         let email = EmailBuilder::new()
             .from(from)
@@ -119,9 +300,21 @@ pub fn send_freeform_email<'a, ITER: Iterator<Item = &'a str>>(queue: &RwLock<Ve
             .build()
             .expect("Building email shouldn't fail.");
 
-        enqueue_mail(email, queue)?;
+        enqueue_mail(conn, email, subject)?;
     }
 
+    audit::record(conn,
+                  actor,
+                  "send_mail",
+                  "email",
+                  None,
+                  None,
+                  Some(&FreeformEmail {
+                            subject: subject,
+                            body: body,
+                            recipients: recipients,
+                        }))?;
+
     Ok(())
 }
 
@@ -130,8 +323,10 @@ pub fn send_freeform_email<'a, ITER: Iterator<Item = &'a str>>(queue: &RwLock<Ve
 pub fn add_pending_email_confirm(conn: &Connection,
                                  hmac_key: &[u8],
                                  email: &str,
-                                 groups: &[i32])
+                                 groups: &[i32],
+                                 locale: &str)
                                  -> Result<(String, String)> {
+    i18n::check_locale(locale)?;
     let (secret, hmac) = session::new_token_and_hmac(hmac_key)?;
 
     {
@@ -139,6 +334,7 @@ pub fn add_pending_email_confirm(conn: &Connection,
             email: email,
             secret: secret.as_ref(),
             groups: groups,
+            locale: locale,
         };
         diesel::insert(&confirm).into(pending_email_confirms::table)
             .execute(&**conn)
@@ -155,15 +351,14 @@ pub fn get_all_pending_email_confirms(conn: &Connection) -> Result<Vec<String>>
     Ok(emails)
 }
 
-pub fn check_pending_email_confirm(conn: &Connection,
-                                   secret: &str)
-                                   -> Result<Option<(String, Vec<i32>)>> {
-    let confirm: Option<PendingEmailConfirm> =
-        pending_email_confirms::table.filter(pending_email_confirms::secret.eq(secret))
-            .first(&**conn)
-            .optional()?;
+pub fn get_pending_email_confirm(conn: &Connection,
+                                 secret: &str)
+                                 -> Result<Option<PendingEmailConfirm>> {
+    let confirm = pending_email_confirms::table.filter(pending_email_confirms::secret.eq(secret))
+        .first(&**conn)
+        .optional()?;
 
-    Ok(confirm.map(|c| (c.email, c.groups)))
+    Ok(confirm)
 }
 
 pub fn complete_pending_email_confirm(conn: &Connection,
@@ -173,12 +368,14 @@ pub fn complete_pending_email_confirm(conn: &Connection,
                                       stretching_time: Duration)
                                       -> Result<User> {
 
-    let (email, group_ids) = try_or!(check_pending_email_confirm(&conn, secret)?,
+    let confirm = try_or!(get_pending_email_confirm(&conn, secret)?,
         else return Err(ErrorKind::NoSuchSess.into()));
-    let user = user::add_user(&*conn, &email, password, pepper, stretching_time)?;
+    let user = user::add_user(&*conn, &confirm.email, password, pepper, stretching_time)?;
+    let user = user::set_locale(conn, None, user.id, &UpdateUserLocale { locale: confirm.locale })?
+        .ok_or_else(|| ErrorKind::DatabaseOdd("We just added the user!"))?;
 
-    for g in group_ids {
-        user::join_user_group_by_id(conn, user.id, g)?;
+    for g in confirm.groups {
+        user::join_user_group_by_id(conn, None, user.id, g)?;
     }
 
     diesel::delete(pending_email_confirms::table
@@ -197,8 +394,7 @@ pub fn clean_old_pendings(conn: &Connection, duration: chrono::Duration) -> Resu
         .chain_err(|| "Couldn't delete the old pending requests.")
 }
 
-pub fn send_nag_emails(queue: &RwLock<VecDeque<Email>>,
-                       conn: &Connection,
+pub fn send_nag_emails(conn: &Connection,
                        how_old: chrono::Duration,
                        nag_grace_period: chrono::Duration,
                        site_name: &str,
@@ -213,7 +409,7 @@ pub fn send_nag_emails(queue: &RwLock<VecDeque<Email>>,
         return Ok(());
     }
 
-    for (user_id, email_addr) in slackers {
+    for (user_id, email_addr, locale) in slackers {
 
         use schema::user_stats;
 
@@ -235,18 +431,21 @@ pub fn send_nag_emails(queue: &RwLock<VecDeque<Email>>,
             hmac: "",
             site_link: site_link,
             site_name: site_name,
+            locale: &locale,
+            t: i18n::template_messages(&locale, "slacker_heatenings.html"),
         };
+        let subject = format!("【{}】{}", site_name, i18n::message(&locale, "nag_subject"));
         let email = EmailBuilder::new()
             .to(email_addr.as_str())
             .from(from)
-            .subject(&format!("【{}】Minne katosit? (´・ω・`)", site_name))
+            .subject(&subject)
             .html(hb_registry.render("slacker_heatenings.html", &data) // FIXME
                 .chain_err(|| "Handlebars template render error!")?
                 .as_ref())
             .build()
             .expect("Building email shouldn't fail.");
 
-        enqueue_mail(email, queue)?;
+        enqueue_mail(conn, email, &subject)?;
 
         stats.last_nag_email = Some(chrono::UTC::now());
         let _: UserStats = stats.save_changes(&**conn)?;
@@ -256,3 +455,117 @@ pub fn send_nag_emails(queue: &RwLock<VecDeque<Email>>,
 
     Ok(())
 }
+
+#[test]
+fn test_retry_delay() {
+    assert_eq!(retry_delay(1), chrono::Duration::minutes(1));
+    assert_eq!(retry_delay(2), chrono::Duration::minutes(2));
+    assert_eq!(retry_delay(4), chrono::Duration::minutes(8));
+    assert_eq!(retry_delay(100), chrono::Duration::hours(6));
+}
+
+#[test]
+fn test_deliver_retries() {
+    use mailer::MemoryMailer;
+
+    struct BrokenMailer;
+
+    impl Mailer for BrokenMailer {
+        fn send(&mut self, _: OutgoingMail) -> std::result::Result<(), String> {
+            Err("Connection refused".into())
+        }
+    }
+
+    let queued = chrono::UTC::now();
+    let mut mail = QueuedEmail {
+        id: 1,
+        from_address: "support@example.com".into(),
+        to_address: "a@example.com,b@example.com".into(),
+        subject: "Hi".into(),
+        message: "Subject: Hi\r\n\r\nHello!".into(),
+        status: "pending".into(),
+        attempts: 0,
+        next_attempt: queued,
+        last_error: None,
+        queued: queued,
+        sent: None,
+    };
+
+    deliver(&mut mail, &mut BrokenMailer, 3);
+    assert_eq!(mail.status, "pending");
+    assert_eq!(mail.attempts, 1);
+    assert_eq!(mail.last_error.as_ref().map(|e| &**e), Some("Connection refused"));
+    assert!(mail.next_attempt >= queued + retry_delay(1));
+
+    deliver(&mut mail, &mut BrokenMailer, 3);
+    assert_eq!(mail.status, "pending");
+    assert!(mail.next_attempt >= queued + retry_delay(2));
+
+    let mut memory = MemoryMailer::new();
+    deliver(&mut mail, &mut memory, 3);
+    assert_eq!(mail.status, "sent");
+    assert_eq!(mail.attempts, 3);
+    assert!(mail.sent.is_some());
+    assert!(mail.last_error.is_none());
+    let sent = memory.sent.lock().unwrap();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].to_addresses, vec!["a@example.com", "b@example.com"]);
+
+    mail.status = "pending".into();
+    mail.attempts = 2;
+    deliver(&mut mail, &mut BrokenMailer, 3);
+    assert_eq!(mail.status, "failed");
+}
+
+#[test]
+fn test_confirmation_through_memory_transport() {
+    use mailer::MEMORY_MAILBOX;
+
+    let mut hb_registry = Handlebars::new();
+    hb_registry.register_template_string("email_confirm_email.html",
+                                      include_str!("../../templates/email_confirm_email.html"))
+        .unwrap();
+
+    let data = EmailData {
+        secret: "s3cr3t",
+        hmac: "4hm4c",
+        site_link: "https://example.com/",
+        site_name: "ganba.re",
+        locale: "fi",
+        t: i18n::template_messages("fi", "email_confirm_email.html"),
+    };
+    let (email, subject) = confirmation_email("new_user@example.com",
+                                              &data,
+                                              &hb_registry,
+                                              ("support@example.com", "Support"),
+                                              "fi")
+            .unwrap();
+    assert!(subject.starts_with("【ganba.re】"));
+
+    // As the mail would be queued and then sent by the sender.
+    let (from_address, to_address, message) = envelope(&email);
+    let now = chrono::UTC::now();
+    let mut queued = QueuedEmail {
+        id: 1,
+        from_address: from_address,
+        to_address: to_address,
+        subject: subject,
+        message: message,
+        status: "pending".into(),
+        attempts: 0,
+        next_attempt: now,
+        last_error: None,
+        queued: now,
+        sent: None,
+    };
+    let mut mailer = mailer::from_config("memory", "", || unreachable!()).unwrap();
+    deliver(&mut queued, &mut *mailer, 3);
+    assert_eq!(queued.status, "sent");
+
+    let mailbox = MEMORY_MAILBOX.lock().unwrap();
+    let mail = mailbox.iter()
+        .find(|m| m.to_addresses == vec!["new_user@example.com"])
+        .expect("The mail should be in the mailbox.");
+    assert_eq!(mail.from_address, "support@example.com");
+    assert!(mail.message.contains("https://example.com/confirm?secret=s3cr3t&hmac=4hm4c"));
+}
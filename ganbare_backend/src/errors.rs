@@ -11,6 +11,7 @@ error_chain! {
             R2D2Error(::r2d2::GetTimeout);
             DataEncodingError(::data_encoding::decode::Error);
             ChronoParseError(::chrono::ParseError);
+            Argon2Error(::argon2::Error);
         }
         errors {
             InvalidInput {
@@ -45,6 +46,10 @@ error_chain! {
                 description("Can't authenticate user")
                 display("Username (= e-mail) or password doesn't match.")
             }
+            TwoFactorRequired {
+                description("Two-factor authentication code required")
+                display("The user has two-factor authentication enabled; a code is required.")
+            }
             BadSessId {
                 description("Malformed session ID!")
                 display("Malformed session ID!")
@@ -85,5 +90,56 @@ error_chain! {
                 description("File already exists!")
                 display("File already exists!")
             }
+            UnsupportedAudioFormat(format: String) {
+                description("Unsupported audio format")
+                display("Unsupported audio format: {}", format)
+            }
+            TranscodingFailed(reason: String) {
+                description("Transcoding the audio file failed")
+                display("Transcoding the audio file failed: {}", reason)
+            }
+            InvalidManifest(reason: String) {
+                description("Invalid import manifest")
+                display("Invalid import manifest: {}", reason)
+            }
+            InvalidArchive(reason: String) {
+                description("Invalid content archive")
+                display("Invalid content archive: {}", reason)
+            }
+        }
+    }
+
+impl ErrorKind {
+    /// The name of the kind of the error in the error responses of the API. The errors
+    /// that the client can't do anything about are all `InternalError`.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ErrorKind::InvalidInput => "InvalidInput",
+            ErrorKind::ParseBoolError(_) |
+            ErrorKind::ParseIntError(_) |
+            ErrorKind::ParseFloatError(_) |
+            ErrorKind::ChronoParseError(_) |
+            ErrorKind::DataEncodingError(_) |
+            ErrorKind::FormParseError => "FormParseError",
+            ErrorKind::NoSuchUser(_) => "NoSuchUser",
+            ErrorKind::EmailAddressTooLong => "EmailAddressTooLong",
+            ErrorKind::EmailAddressNotValid => "EmailAddressNotValid",
+            ErrorKind::PasswordTooShort => "PasswordTooShort",
+            ErrorKind::PasswordTooLong => "PasswordTooLong",
+            ErrorKind::PasswordDoesntMatch => "PasswordDoesntMatch",
+            ErrorKind::AuthError => "AuthError",
+            ErrorKind::TwoFactorRequired => "TwoFactorRequired",
+            ErrorKind::BadSessId => "BadSessId",
+            ErrorKind::NoSuchSess => "NoSuchSess",
+            ErrorKind::FileNotFound => "FileNotFound",
+            ErrorKind::AccessDenied => "AccessDenied",
+            ErrorKind::RateLimitExceeded => "RateLimitExceeded",
+            ErrorKind::FileAlreadyExists(_) => "FileAlreadyExists",
+            ErrorKind::UnsupportedAudioFormat(_) => "UnsupportedAudioFormat",
+            ErrorKind::TranscodingFailed(_) => "TranscodingFailed",
+            ErrorKind::InvalidManifest(_) => "InvalidManifest",
+            ErrorKind::InvalidArchive(_) => "InvalidArchive",
+            _ => "InternalError",
         }
     }
+}
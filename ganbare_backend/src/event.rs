@@ -1,12 +1,39 @@
 use super::*;
 
-pub fn update_event(conn: &Connection, item: &UpdateEvent) -> Result<Option<Event>> {
+/// How the event is presented to the user. "training" is the main quiz page;
+/// the others are served at `events.page`, or at `/event/<name>` if no page is set.
+pub const PAGE_KINDS: &'static [&'static str] = &["text", "survey", "test", "retelling",
+                                                  "sorting", "training", "consent"];
+
+pub fn update_event(conn: &Connection, actor: i32, item: &UpdateEvent) -> Result<Option<Event>> {
     use schema::events;
 
-    let item = diesel::update(events::table.filter(events::id.eq(item.id))).set(item)
+    if let Some(ref kind) = item.page_kind {
+        if !PAGE_KINDS.contains(&kind.as_str()) {
+            bail!(ErrorKind::InvalidInput);
+        }
+    }
+
+    let before: Event = try_or!(events::table.filter(events::id.eq(item.id))
+                                    .get_result(&**conn)
+                                    .optional()?,
+                                else return Ok(None));
+
+    let updated: Option<Event> = diesel::update(events::table.filter(events::id.eq(item.id)))
+        .set(item)
         .get_result(&**conn)
         .optional()?;
-    Ok(item)
+
+    if let Some(ref updated) = updated {
+        audit::record(conn,
+                      actor,
+                      "update",
+                      "event",
+                      Some(item.id),
+                      Some(&before),
+                      Some(updated))?;
+    }
+    Ok(updated)
 }
 
 pub fn get_all(conn: &Connection)
@@ -35,6 +62,24 @@ pub fn get_all(conn: &Connection)
     Ok(event_data)
 }
 
+pub fn get_by_name(conn: &Connection, event_name: &str) -> Result<Option<Event>> {
+    use schema::events;
+
+    let event = events::table.filter(events::name.eq(event_name))
+        .get_result(&**conn)
+        .optional()?;
+    Ok(event)
+}
+
+/// The URL the user is redirected to when the event is dispatched.
+/// `None` means that the main quiz page is shown.
+pub fn page_url(event: &Event) -> Option<String> {
+    if event.page_kind == "training" {
+        return None;
+    }
+    Some(event.page.clone().unwrap_or_else(|| format!("/event/{}", event.name)))
+}
+
 pub fn dispatch_event(conn: &Connection, user_id: i32) -> Result<Option<Event>> {
     use schema::{events, event_experiences, group_memberships};
     use diesel::expression::dsl::{any, all};
@@ -43,12 +88,16 @@ pub fn dispatch_event(conn: &Connection, user_id: i32) -> Result<Option<Event>>
         .filter(group_memberships::user_id.eq(user_id))
         .select(group_memberships::group_id);
 
-    let finished_events = event_experiences::table.filter(event_experiences::user_id.eq(user_id))
+    let finished_events: Vec<i32> = event_experiences::table
+        .filter(event_experiences::user_id.eq(user_id))
         .filter(event_experiences::event_finish.is_not_null())
-        .select(event_experiences::event_id);
+        .select(event_experiences::event_id)
+        .get_results(&**conn)?;
 
     let event = events::table.filter(events::published.eq(true))
         .filter(events::required_group.eq(any(groups).nullable()).or(events::required_group.is_null()))
+        .filter(events::required_event.eq(any(finished_events.clone()).nullable())
+            .or(events::required_event.is_null()))
         .filter(events::id.ne(all(finished_events)))
         .order(events::priority.asc())
         .first(&**conn)
@@ -230,6 +279,36 @@ pub fn is_ongoing(conn: &Connection,
     }
 }
 
+/// Whether the user may see the event: the events that have a required group
+/// are only for its members.
+pub fn is_allowed(conn: &Connection, event: &Event, user_id: i32) -> Result<bool> {
+    match event.required_group {
+        Some(group_id) => is_member(conn, user_id, group_id),
+        None => Ok(true),
+    }
+}
+
+/// The first ongoing event of the given page kind. Used by the pages and APIs
+/// that serve any event of a kind, such as the tests.
+pub fn ongoing_of_kind(conn: &Connection,
+                       page_kind: &str,
+                       user_id: i32)
+                       -> Result<Option<(Event, EventExperience)>> {
+    use schema::events;
+
+    let events: Vec<Event> = events::table.filter(events::page_kind.eq(page_kind))
+        .filter(events::published.eq(true))
+        .order(events::priority.asc())
+        .get_results(&**conn)?;
+
+    for event in events {
+        if let Some(ev_exp) = is_ongoing(conn, &event.name, user_id)? {
+            return Ok(Some(ev_exp));
+        }
+    }
+    Ok(None)
+}
+
 
 pub fn set_done(conn: &Connection,
                 event_name: &str,
@@ -248,9 +327,16 @@ pub fn set_done(conn: &Connection,
     }
 }
 
-pub fn remove_exp(conn: &Connection, event_id: i32, user_id: i32) -> Result<bool> {
+pub fn remove_exp(conn: &Connection, actor: i32, event_id: i32, user_id: i32) -> Result<bool> {
     use schema::{event_userdata, event_experiences};
 
+    let exp: EventExperience =
+        try_or!(event_experiences::table.filter(event_experiences::event_id.eq(event_id))
+                    .filter(event_experiences::user_id.eq(user_id))
+                    .get_result(&**conn)
+                    .optional()?,
+                else return Ok(false));
+
     let count_userdata =
         diesel::delete(event_userdata::table.filter(event_userdata::event_id.eq(event_id))
                            .filter(event_userdata::user_id.eq(user_id))).execute(&**conn)?;
@@ -263,6 +349,14 @@ pub fn remove_exp(conn: &Connection, event_id: i32, user_id: i32) -> Result<bool
            count_userdata,
            count_exp);
 
+    audit::record(conn,
+                  actor,
+                  "delete",
+                  "event_experience",
+                  Some(event_id),
+                  Some(&exp),
+                  None)?;
+
     Ok(count_exp == 1)
 }
 
@@ -324,3 +418,156 @@ pub fn get_userdata(conn: &Connection,
 
     Ok(result)
 }
+
+/* TRANSITIONS */
+
+pub fn get_transitions(conn: &Connection) -> Result<Vec<EventTransition>> {
+    use schema::event_transitions;
+
+    let transitions = event_transitions::table.order(event_transitions::id.asc())
+        .get_results(&**conn)?;
+    Ok(transitions)
+}
+
+pub fn add_transition(conn: &Connection,
+                      actor: i32,
+                      item: &NewEventTransition)
+                      -> Result<EventTransition> {
+    use schema::event_transitions;
+
+    if item.fires_on != "started" && item.fires_on != "finished" || item.delay_hours < 0 ||
+       item.join_group.is_none() && item.leave_group.is_none() {
+        bail!(ErrorKind::InvalidInput);
+    }
+
+    let transition: EventTransition = diesel::insert(item).into(event_transitions::table)
+        .get_result(&**conn)?;
+    audit::record(conn,
+                  actor,
+                  "create",
+                  "event_transition",
+                  Some(transition.id),
+                  None,
+                  Some(&transition))?;
+    Ok(transition)
+}
+
+pub fn remove_transition(conn: &Connection, actor: i32, id: i32) -> Result<bool> {
+    use schema::event_transitions;
+
+    let transition: Option<EventTransition> =
+        diesel::delete(event_transitions::table.filter(event_transitions::id.eq(id)))
+            .get_result(&**conn)
+            .optional()?;
+    if let Some(ref transition) = transition {
+        audit::record(conn,
+                      actor,
+                      "delete",
+                      "event_transition",
+                      Some(id),
+                      Some(transition),
+                      None)?;
+    }
+    Ok(transition.is_some())
+}
+
+fn is_member(conn: &Connection, user_id: i32, group_id: i32) -> Result<bool> {
+    use schema::group_memberships;
+
+    let membership: Option<GroupMembership> =
+        group_memberships::table.filter(group_memberships::group_id.eq(group_id))
+            .filter(group_memberships::user_id.eq(user_id))
+            .get_result(&**conn)
+            .optional()?;
+    Ok(membership.is_some())
+}
+
+/// Fires the transitions of the events the user has started or finished,
+/// once their delay has passed and their conditions hold.
+/// Each transition fires only once per user.
+/// Returns true if some transition fired; the dispatched event might have changed then.
+pub fn run_transitions(conn: &Connection, user_id: i32) -> Result<bool> {
+    use schema::{event_experiences, event_transitions, event_transitions_fired};
+    use diesel::expression::dsl::{any, all};
+    use diesel::pg::upsert::OnConflictExtension;
+
+    let exps: Vec<EventExperience> =
+        event_experiences::table.filter(event_experiences::user_id.eq(user_id))
+            .get_results(&**conn)?;
+
+    let experienced: Vec<i32> = exps.iter().map(|e| e.event_id).collect();
+
+    let fired = event_transitions_fired::table
+        .filter(event_transitions_fired::user_id.eq(user_id))
+        .select(event_transitions_fired::transition_id);
+
+    let transitions: Vec<EventTransition> =
+        event_transitions::table.filter(event_transitions::event_id.eq(any(experienced)))
+            .filter(event_transitions::id.ne(all(fired)))
+            .order(event_transitions::id.asc())
+            .get_results(&**conn)?;
+
+    let now = chrono::UTC::now();
+    let mut any_fired = false;
+
+    for t in transitions {
+        let exp = exps.iter()
+            .find(|e| e.event_id == t.event_id)
+            .expect("Only transitions of experienced events were loaded.");
+
+        let since = match t.fires_on.as_str() {
+            "started" => exp.event_init,
+            "finished" => try_or!(exp.event_finish, else continue),
+            _ => bail!(ErrorKind::DatabaseOdd("Unknown transition trigger!")),
+        };
+
+        if since + chrono::Duration::hours(t.delay_hours as i64) > now {
+            continue;
+        }
+
+        if let Some(group_id) = t.required_group {
+            if !is_member(conn, user_id, group_id)? {
+                continue;
+            }
+        }
+
+        if let Some(event_id) = t.unless_finished {
+            if exps.iter().any(|e| e.event_id == event_id && e.event_finish.is_some()) {
+                continue;
+            }
+        }
+
+        let fired_now = conn.transaction(|| -> Result<bool> {
+                // Claiming the transition first makes concurrent requests of the same user
+                // wait here, and then find it already fired.
+                let fired = NewEventTransitionFired {
+                    transition_id: t.id,
+                    user_id: user_id,
+                };
+                let claimed = diesel::insert(&fired.on_conflict_do_nothing())
+                    .into(event_transitions_fired::table)
+                    .execute(&**conn)?;
+                if claimed == 0 {
+                    return Ok(false);
+                }
+                if let Some(group_id) = t.join_group {
+                    if !is_member(conn, user_id, group_id)? {
+                        user::join_user_group_by_id(conn, None, user_id, group_id)?;
+                    }
+                }
+                if let Some(group_id) = t.leave_group {
+                    user::remove_user_group_by_id(conn, None, user_id, group_id)?;
+                }
+                Ok(true)
+            })?;
+
+        if !fired_now {
+            continue;
+        }
+
+        debug!("Fired event transition {:?} for user {}", t, user_id);
+        any_fired = true;
+    }
+
+    Ok(any_fired)
+}
@@ -0,0 +1,1370 @@
+use super::*;
+use std::collections::BTreeMap;
+
+/// The locale of users that haven't chosen one.
+pub const DEFAULT_LOCALE: &'static str = "fi";
+
+/// The supported locales. The order is the order of the translations in `CATALOG`.
+pub const LOCALES: &'static [&'static str] = &["fi", "en", "ja"];
+
+type Catalog = &'static [(&'static str, [&'static str; 3])];
+
+/// The message catalog: each key is followed by its translations in the order of `LOCALES`.
+static CATALOG: Catalog =
+    &[("no_training_group",
+       ["Et kuulu mihinkään harjoitusryhmään!",
+        "You don't belong to any training group!",
+        "どの練習グループにも属していません！"]),
+      ("no_such_email",
+       ["Tuntematon sähköpostiosoite :(",
+        "No such e-mail address :(",
+        "そのメールアドレスは登録されていません :("]),
+      ("rate_limit_exceeded",
+       ["Liian monta pyyntöä, yritä huomenna uudelleen :(",
+        "Rate limit exceeded :(",
+        "リクエストが多すぎます。明日また試してください :("]),
+      ("email_confirm_subject", ["Tervetuloa!", "Welcome!", "ようこそ！"]),
+      ("pw_reset_subject",
+       ["Salasanan vaihtaminen", "Resetting your password", "パスワードの再設定"]),
+      ("nag_subject",
+       ["Minne katosit? (´・ω・`)",
+        "Where did you go? (´・ω・`)",
+        "どこに行っちゃったの？ (´・ω・`)"])];
+
+/// The messages of the scripts that run in the browser. They are given to the page
+/// as `window.messages` and looked up with `msg(key, args...)`, which replaces
+/// the placeholders `{0}`, `{1}` etc. with the arguments.
+static CLIENT_CATALOG: Catalog =
+    &[("no_recording_support",
+       ["Selaimesi ei tue äänen nauhoitusta!<br>Kokeile Firefoxia tai Chromea.",
+        "Your browser doesn't support recording audio!<br>Try Firefox or Chrome.",
+        "お使いのブラウザは録音に対応していません！<br>FirefoxかChromeをお試しください。"]),
+      ("recording_permission",
+       ["Tarvitsemme selaimesi nauhoitusominaisuutta!<br>\
+         Ole hyvä ja myönnä lupa nauhoitukselle.",
+        "We need the recording feature of your browser!<br>Please allow the recording.",
+        "ブラウザの録音機能を使います！<br>録音を許可してください。"]),
+      ("recording_error",
+       ["Virhe alustaessa nauhoitusta: {0}",
+        "Couldn't start recording: {0}",
+        "録音を開始できませんでした：{0}"]),
+      ("no_microphone",
+       ["Ei ole mikrofonia millä nauhoittaa!",
+        "There's no microphone to record with!",
+        "録音に使えるマイクがありません！"]),
+      ("microphone_denied",
+       ["Selaimesi ei anna käyttää mikrofonia! \
+         Tarkista asetuksista että valittuna on \"Salli\" tai \"Kysy\".",
+        "Your browser doesn't let us use the microphone! \
+         Check that it's set to \"Allow\" or \"Ask\" in the settings.",
+        "ブラウザがマイクの使用を許可していません！\
+         設定で「許可」または「確認」が選ばれているか確かめてください。"]),
+      ("unknown_error",
+       ["En osaa näyttää, mikä virhe :(",
+        "I don't know what the error is :(",
+        "エラーの内容がわかりません :("]),
+      ("recording_too_long",
+       ["Ääni oli niin pitkä että sitä ei voida lähettää :(",
+        "The recording was too long to be sent :(",
+        "録音が長すぎて送信できません :("]),
+      ("mic_check_no_recording",
+       ["Selaimesi ei tue äänen nauhoitusta!<br>",
+        "Your browser doesn't support recording audio!<br>",
+        "お使いのブラウザは録音に対応していません！<br>"]),
+      ("mic_check_no_opus",
+       ["Selaimesi ei tue opus-ääniformaattia!<br>",
+        "Your browser doesn't support the Opus audio format!<br>",
+        "お使いのブラウザはOpus形式の音声に対応していません！<br>"]),
+      ("mic_check_no_autoplay",
+       ["Selaimesi ei tue äänen toistamista ilman käyttäjän syötettä!<br>",
+        "Your browser doesn't play audio without the user's input!<br>",
+        "お使いのブラウザは操作なしで音声を再生できません！<br>"]),
+      ("mic_check_try_desktop",
+       ["Kokeile Firefoxia tai Chromea.<br>(työpöytä-, ei mobiiliversio)",
+        "Try Firefox or Chrome.<br>(the desktop version, not the mobile one)",
+        "FirefoxかChromeをお試しください。<br>（モバイル版ではなくデスクトップ版）"]),
+      ("no_connection",
+       ["Palvelimeen ei saada yhteyttä :(",
+        "Can't connect to the server :(",
+        "サーバーに接続できません :("]),
+      ("no_connection_retrying",
+       ["Palvelimeen ei saada yhteyttä :(<br>Kokeillaan uudestaan...",
+        "Can't connect to the server :(<br>Trying again...",
+        "サーバーに接続できません :(<br>もう一度試しています..."]),
+      ("break_hours_minutes",
+       ["Tauon paikka!<br>Seuraava kysymys avautuu<br>{0} tunnin ja {1} minuutin päästä",
+        "Time for a break!<br>The next question opens<br>in {0} hours and {1} minutes",
+        "休憩の時間です！<br>次の問題は<br>{0}時間{1}分後に始まります"]),
+      ("break_minutes",
+       ["Tauon paikka!<br>Seuraava kysymys avautuu<br>{0} minuutin päästä",
+        "Time for a break!<br>The next question opens<br>in {0} minutes",
+        "休憩の時間です！<br>次の問題は<br>{0}分後に始まります"]),
+      ("break_minutes_seconds",
+       ["Tauon paikka!<br>Seuraava kysymys avautuu<br>{0} minuutin ja {1} sekunnin päästä",
+        "Time for a break!<br>The next question opens<br>in {0} minutes and {1} seconds",
+        "休憩の時間です！<br>次の問題は<br>{0}分{1}秒後に始まります"]),
+      ("break_seconds",
+       ["Tauon paikka!<br>Seuraava kysymys avautuu<br>{0} sekunnin päästä",
+        "Time for a break!<br>The next question opens<br>in {0} seconds",
+        "休憩の時間です！<br>次の問題は<br>{0}秒後に始まります"]),
+      ("correct_next",
+       ["Oikein! Seuraava kysymys.", "Correct! Next question.", "正解！次の問題へ。"]),
+      ("wrong_try_again",
+       ["Pieleen meni, kokeile uudestaan!",
+        "Not quite, try again!",
+        "残念！もう一度やってみましょう！"]),
+      ("time_up", ["Aika loppui!", "Time's up!", "時間切れ！"]),
+      ("answered", ["Vastattu!", "Answered!", "回答しました！"]),
+      ("answered_next",
+       ["Vastattu. Seuraava kysymys!", "Answered. Next question!", "回答しました。次の問題へ！"]),
+      ("loading", ["Ladataan...", "Loading...", "読み込み中..."]),
+      ("answer_time", ["Vastausaikaa {0} s", "{0} s left to answer", "残り{0}秒"]),
+      ("pronounce_recording",
+       ["Äännä parhaasi mukaan!<br>Äänesi nauhoitetaan.",
+        "Pronounce it as well as you can!<br>Your voice is being recorded.",
+        "できるだけ上手に発音してください！<br>声を録音しています。"]),
+      ("pronounce_no_recording",
+       ["Äännä parhaasi mukaan!<br>(Selaimesi ei tue äänen nauhoitusta).",
+        "Pronounce it as well as you can!<br>(Your browser doesn't support recording audio.)",
+        "できるだけ上手に発音してください！<br>（お使いのブラウザは録音に対応していません。）"]),
+      ("word_pronounced", ["Ok, sana äännetty!", "OK, I said the word!", "OK、発音しました！"]),
+      ("self_assessment", ["Itsearvio", "Self-assessment", "自己評価"]),
+      ("nothing_to_ask",
+       ["Ei ole mitään kysyttävää ☹️", "There's nothing to ask ☹️", "出題できる問題がありません ☹️"]),
+      ("retelling_listen",
+       ["Kuuntele, mitä kuvassa tapahtuu.<br>Selitys loppuu äänimerkkiin. Selityksen loputtua\
+         <br>on sinun vuorosi kertoa kuulemasi uudestaan.<br>Äänesi nauhoitetaan.",
+        "Listen to what happens in the picture.<br>The explanation ends with a beep. After that,\
+         <br>it's your turn to retell what you heard.<br>Your voice will be recorded.",
+        "絵の中で何が起きているか聞いてください。<br>説明は合図の音で終わります。そのあと\
+         <br>聞いた内容をあなたが話してください。<br>声は録音されます。"]),
+      ("retelling_recording",
+       ["Kerro, mitä kuvassa tapahtuu.<br>Aikaa on max 24 sekuntia.<br>Nauhoitus käynnissä.",
+        "Tell what happens in the picture.<br>You have 24 seconds at most.<br>Recording.",
+        "絵の中で何が起きているか話してください。<br>時間は最大24秒です。<br>録音中。"]),
+      ("at_least_8_chars",
+       ["Vähintään 8 merkkiä!",
+        "At least 8 characters!",
+        "8文字以上必要です！"]),
+      ("passwords_dont_match", ["Ei täsmää!", "Doesn't match!", "一致しません！"]),
+      ("password_changed",
+       ["Salasana vaihdettu!",
+        "Password changed!",
+        "パスワードを変更しました！"]),
+      ("unknown_device", ["Tuntematon laite", "Unknown device", "不明な端末"]),
+      ("this_device", [" (tämä laite)", " (this device)", "（この端末）"]),
+      ("missing_at_sign",
+       ["@-merkki puuttuu?",
+        "The @ sign is missing?",
+        "@が抜けていませんか？"]),
+      ("session_details",
+       ["IP: {0}, kirjautunut {1}, viimeksi käytetty {2}",
+        "IP: {0}, logged in {1}, last used {2}",
+        "IP: {0}、ログイン {1}、最終使用 {2}"]),
+      ("log_out_session", ["Kirjaa ulos", "Log out", "ログアウト"]),
+      ("wrong_code", ["Koodi oli väärin!", "Wrong code!", "コードが間違っています！"]),
+      ("something_went_wrong",
+       ["Jotain meni pieleen.",
+        "Something went wrong.",
+        "問題が発生しました。"]),
+      ("pronunciation_n", ["Ääntämys {0}", "Pronunciation {0}", "発音 {0}"]),
+      ("question_audio_n", ["Kysymys (audio {0})", "Question (audio {0})", "問題（音声 {0}）"]),
+      ("answer_choice_n", ["{0}. vastausvaihtoehto", "Answer choice {0}", "選択肢 {0}"]),
+      ("survey_ready",
+       ["Kiitos vastauksista!",
+        "Thank you for your answers!",
+        "ご回答ありがとうございました！"]),
+      ("survey_fourfold_0", ["kuuntelu", "listening", "聞く"]),
+      ("survey_fourfold_1", ["puhuminen", "speaking", "話す"]),
+      ("survey_fourfold_2", ["lukeminen", "reading", "読む"]),
+      ("survey_fourfold_3", ["kirjoittaminen", "writing", "書く"]),
+      ("survey_fourfold_4", ["ymmärtäminen", "understanding", "理解"]),
+      ("survey_fourfold_5", ["tuottaminen", "producing", "産出"]),
+      ("survey_fourfold_6", ["suullinen", "spoken", "口頭"]),
+      ("survey_fourfold_7", ["kirjallinen", "written", "筆記"]),
+      ("survey_language_0", ["suomi", "Finnish", "フィンランド語"]),
+      ("survey_language_1", ["ruotsi", "Swedish", "スウェーデン語"]),
+      ("survey_language_2", ["muu, mikä?", "other, which?", "その他（何語？）"]),
+      ("survey_answer_0_0", ["Aloita kysely!", "Start the survey!", "アンケートを始める！"]),
+      ("survey_answer_1_0",
+       ["satunnaisesti; <br class=\"smallscreen\">joitakin kertoja<br>vuodessa tai harvemmin",
+        "occasionally; <br class=\"smallscreen\">a few times<br>a year or less",
+        "ときどき；<br class=\"smallscreen\">年に数回<br>またはそれ以下"]),
+      ("survey_answer_1_1",
+       ["silloin tällöin, mutta <br class=\"smallscreen\">joskus on viikkojen<br>tai kuukausien \
+         taukoja",
+        "now and then, but <br class=\"smallscreen\">sometimes there are breaks<br>of weeks or \
+         months",
+        "ときどき、ただし<br class=\"smallscreen\">数週間や数か月<br>空くこともある"]),
+      ("survey_answer_1_2",
+       ["viikoittain tai ainakin<br>monta kertaa kuussa",
+        "weekly or at least<br>many times a month",
+        "毎週、または少なくとも<br>月に何回も"]),
+      ("survey_answer_1_3", ["monta kertaa viikossa", "many times a week", "週に何回も"]),
+      ("survey_answer_1_4", ["päivittäin", "daily", "毎日"]),
+      ("survey_answer_2_0", ["en ole käynyt", "I haven't been", "行ったことはない"]),
+      ("survey_answer_2_1", ["olen käynyt kerran", "I have been once", "一度行ったことがある"]),
+      ("survey_answer_2_2",
+       ["olen käynyt joitakin kertoja",
+        "I have been a few times",
+        "何回か行ったことがある"]),
+      ("survey_answer_2_3",
+       ["käyn vähintään kerran muutamassa vuodessa",
+        "I go at least once in a few years",
+        "数年に一度以上行く"]),
+      ("survey_answer_2_4",
+       ["käyn kerran vuodessa tai useammin",
+        "I go once a year or more often",
+        "年に一度以上行く"]),
+      ("survey_answer_3_0",
+       ["käyn monta tuntia viikossa",
+        "I attend many hours a week",
+        "週に何時間も通っている"]),
+      ("survey_answer_3_1",
+       ["käyn kerran viikossa",
+        "I attend once a week",
+        "週に一度通っている"]),
+      ("survey_answer_3_2",
+       ["käyn satunnaisesti/<wbr>lyhytkestoisesti",
+        "I attend occasionally/<wbr>short courses",
+        "ときどき/<wbr>短期間通っている"]),
+      ("survey_answer_3_3", ["en tällä hetkellä", "not at the moment", "今は通っていない"]),
+      ("survey_answer_4_0", ["en kiinnitä huomiota", "I don't pay attention", "気にしていない"]),
+      ("survey_answer_4_1",
+       ["välillä yritän kuunnella tarkkaavaisesti",
+        "sometimes I try to listen attentively",
+        "ときどき注意して聞こうとする"]),
+      ("survey_answer_4_2",
+       ["kuuntelen usein tarkkaavaisesti",
+        "I often listen attentively",
+        "よく注意して聞いている"]),
+      ("survey_answer_4_3",
+       ["kiinnitän huomioni jatkuvasti ääntämiseen",
+        "I constantly pay attention to the pronunciation",
+        "いつも発音に注意を払っている"]),
+      ("survey_answer_5_0",
+       ["asialla ei ole minulle suurta väliä",
+        "it doesn't matter much to me",
+        "あまり重要ではない"]),
+      ("survey_answer_5_1",
+       ["olisi ihan kiva, mutta se ei ole prioriteettini",
+        "it would be nice, but it isn't my priority",
+        "できればいいが、優先ではない"]),
+      ("survey_answer_5_2",
+       ["haluan ääntää ainakin kohtalaisen hyvin",
+        "I want to pronounce at least reasonably well",
+        "少なくともある程度上手に発音したい"]),
+      ("survey_answer_5_3",
+       ["hyvä ääntämys on minulle tärkeä asia",
+        "good pronunciation is important to me",
+        "上手な発音は大切だ"]),
+      ("survey_answer_5_4",
+       ["haluaisin kuulostaa japanilaiselta",
+        "I'd like to sound Japanese",
+        "日本人のように聞こえたい"]),
+      ("survey_answer_6_0",
+       ["en ole koskaan opiskellut erityisesti ääntämistä",
+        "I have never studied pronunciation specifically",
+        "発音を特に勉強したことはない"]),
+      ("survey_answer_6_1",
+       ["olen kiinnittänyt johonkin yksittäiseen asiaan huomiota jos minulle on huomautettu \
+         siitä",
+        "I have paid attention to some single thing if someone has pointed it out to me",
+        "指摘されたことについては気をつけたことがある"]),
+      ("survey_answer_6_2",
+       ["olen oma-aloitteisesti opiskellut ääntämistä",
+        "I have studied pronunciation on my own initiative",
+        "自分から発音を勉強したことがある"]),
+      ("survey_answer_6_3",
+       ["käytän paljon aikaa ääntämisen opiskeluun",
+        "I spend a lot of time studying pronunciation",
+        "発音の勉強に多くの時間を使っている"]),
+      ("survey_answer_7_0",
+       ["en ole asunut lainkaan",
+        "I haven't lived there at all",
+        "住んだことはない"]),
+      ("survey_answer_7_1",
+       ["olen asunut kuukauden tai vähemmän",
+        "I have lived there a month or less",
+        "1か月以下住んだことがある"]),
+      ("survey_answer_7_2",
+       ["olen asunut puoli vuotta tai vähemmän",
+        "I have lived there half a year or less",
+        "半年以下住んだことがある"]),
+      ("survey_answer_7_3",
+       ["olen asunut kaksi vuotta tai vähemmän",
+        "I have lived there two years or less",
+        "2年以下住んだことがある"]),
+      ("survey_answer_7_4",
+       ["olen asunut pidempään kuin kaksi vuotta",
+        "I have lived there longer than two years",
+        "2年より長く住んだことがある"]),
+      ("survey_answer_8_0",
+       ["en ole juurikaan puhunut japaniksi (opetustilanteiden ulkopuolella)",
+        "I have hardly spoken Japanese (outside of classes)",
+        "（授業以外で）日本語はほとんど話したことがない"]),
+      ("survey_answer_8_1",
+       ["olen kokeillut jutustella, mutta keskustelu on tökkivää eikä siitä tule mitään",
+        "I have tried chatting, but the conversation stalls and doesn't go anywhere",
+        "おしゃべりしてみたが、会話が途切れてうまくいかない"]),
+      ("survey_answer_8_2",
+       ["pystyn välittämään mitä haluan sanoa, vaikka usein tapahtuu kommunikaatiokatkoksia",
+        "I can get across what I want to say, although communication often breaks down",
+        "よくコミュニケーションが途切れるが、言いたいことは伝えられる"]),
+      ("survey_answer_8_3",
+       ["pystyn juttelemaan kohtalaisen sujuvasti",
+        "I can chat fairly fluently",
+        "かなり流暢におしゃべりできる"]),
+      ("survey_answer_8_4",
+       ["en koe, että minulla olisi mitään suurempia ongelmia jutella japaniksi",
+        "I don't feel I have any bigger problems chatting in Japanese",
+        "日本語でのおしゃべりに特に大きな問題はない"]),
+      ("survey_answer_9_0", ["En ole.", "No.", "いいえ。"]),
+      ("survey_answer_9_1",
+       ["Olen läpäissyt N5-tason.",
+        "I have passed level N5.",
+        "N5に合格した。"]),
+      ("survey_answer_9_2",
+       ["Olen läpäissyt N4-tason.",
+        "I have passed level N4.",
+        "N4に合格した。"]),
+      ("survey_answer_9_3",
+       ["Olen läpäissyt N3-tason.",
+        "I have passed level N3.",
+        "N3に合格した。"]),
+      ("survey_answer_9_4",
+       ["Olen läpäissyt N2-tason.",
+        "I have passed level N2.",
+        "N2に合格した。"]),
+      ("survey_answer_9_5",
+       ["Olen läpäissyt N1-tason.",
+        "I have passed level N1.",
+        "N1に合格した。"]),
+      ("survey_answer_10_0", ["En ole.", "No.", "いいえ。"]),
+      ("survey_answer_10_1",
+       ["0 - 4 kk (lukukausi) viikoittaista opiskelua",
+        "0 - 4 months (a semester) of weekly study",
+        "0～4か月（1学期）毎週勉強"]),
+      ("survey_answer_10_2",
+       ["5 - 8 kk (lukuvuosi) viikoittaista opiskelua",
+        "5 - 8 months (a school year) of weekly study",
+        "5～8か月（1学年）毎週勉強"]),
+      ("survey_answer_10_3",
+       ["9 - 16 kk (2 lukuvuotta) viikoittaista opiskelua",
+        "9 - 16 months (2 school years) of weekly study",
+        "9～16か月（2学年）毎週勉強"]),
+      ("survey_answer_10_4",
+       ["13 - 24 kk (3 lukuvuotta) viikoittaista opiskelua",
+        "13 - 24 months (3 school years) of weekly study",
+        "13～24か月（3学年）毎週勉強"]),
+      ("survey_answer_10_5",
+       ["25 kk tai enemmän viikoittaista opiskelua",
+        "25 months or more of weekly study",
+        "25か月以上毎週勉強"]),
+      ("survey_answer_11_0", ["En ole.", "No.", "いいえ。"]),
+      ("survey_answer_11_1",
+       ["Olen käynyt lyhytkestoisessa vaihdossa (max 3 kk)",
+        "I have been on a short exchange (max 3 months)",
+        "短期留学をしたことがある（最長3か月）"]),
+      ("survey_answer_11_2",
+       ["Olen opiskellut 3 kk - vuoden",
+        "I have studied 3 months - a year",
+        "3か月～1年勉強した"]),
+      ("survey_answer_11_3",
+       ["Olen opiskellut 1 - 3 vuotta",
+        "I have studied 1 - 3 years",
+        "1～3年勉強した"]),
+      ("survey_answer_11_4",
+       ["Olen opiskellut pidempään kuin 3 vuotta",
+        "I have studied longer than 3 years",
+        "3年より長く勉強した"]),
+      ("survey_question_0",
+       ["<p>Kysymme alkuun siitä, millä tavalla olet yleensä tekemisissä japanin kielen kanssa. \
+         Kyselyssä oletetaan että olet ainakin jossain määrin aktiivinen japanin kielen \
+         opiskelija.</p><p>Kyselyn lopussa on mahdollisuus tarkentaa vastauksia omin sanoin, \
+         ja voit aina peruuttaa ja vastata uudelleen kysymyksiin.</p><p>Valitse vaihtoehto, \
+         joka kuvaa sinua parhaiten.</p>",
+        "<p>First, we ask how you usually engage with the Japanese language. The survey assumes \
+         that you are at least somewhat active as a student of Japanese.</p><p>At the end of \
+         the survey you can clarify your answers in your own words, and you can always go back \
+         and answer the questions again.</p><p>Choose the option that describes you best.</p>",
+        "<p>まず、ふだん日本語とどのようにかかわっているかをお聞きします。このアンケートは、\
+         あなたが少なくともある程度積極的に日本語を学んでいることを前提にしています。</p><p>\
+         最後に自分の言葉で回答を補足することができます。また、\
+         いつでも戻って質問に答え直すことができます。</p><p>\
+         自分に一番当てはまる選択肢を選んでください。</p>"]),
+      ("survey_question_1",
+       ["Juttelen ja/tai luen japaniksi sosiaalisessa mediassa, esim. Facebookissa, Twitterissä \
+         tai Linessä.",
+        "I chat and/or read in Japanese on social media, e.g. Facebook, Twitter or Line.",
+        "ソーシャルメディア（Facebook、Twitter、LINEなど）\
+         で日本語でおしゃべりしたり読んだりする。"]),
+      ("survey_question_2",
+       ["Luen japanilaisia tekstipainotteisia web-sivuja (esim. blogit, Q&A-sivustot, \
+         reseptisivustot...)",
+        "I read Japanese text-heavy web pages (e.g. blogs, Q&A sites, recipe sites...)",
+        "文章中心の日本語のウェブサイト（ブログ、Q&Aサイト、レシピサイトなど）を読む。"]),
+      ("survey_question_3",
+       ["Katson YouTubesta ym. videopalveluista japaninkielisiä v-blogeja tai let's play \
+         -videoita.",
+        "I watch Japanese vlogs or let's play videos on YouTube or other video services.",
+        "YouTubeなどの動画サービスで日本語のVlogや実況動画を見る。"]),
+      ("survey_question_4",
+       ["Kuuntelen japanilaisia radio-ohjelmia tai podcasteja.",
+        "I listen to Japanese radio programs or podcasts.",
+        "日本語のラジオ番組やポッドキャストを聞く。"]),
+      ("survey_question_5",
+       ["Kuuntelen japanilaista musiikkia niin että kuuntelen tai selvitän, mitä sanat \
+         tarkoittavat.",
+        "I listen to Japanese music so that I listen to or find out what the lyrics mean.",
+        "歌詞の意味を聞き取ったり調べたりしながら日本の音楽を聞く。"]),
+      ("survey_question_6",
+       ["Puhun japania livenä japanilaisten<br/>tuttavien kanssa",
+        "I speak Japanese in person with Japanese<br/>acquaintances",
+        "日本人の知り合いと<br/>直接日本語で話す"]),
+      ("survey_question_7",
+       ["Kuuntelen tarkkaavaisesti, miten japanilaiset ääntävät japania.",
+        "I listen attentively to how Japanese people pronounce Japanese.",
+        "日本人が日本語をどう発音するか注意して聞く。"]),
+      ("survey_question_8",
+       ["Katson animea ilman tekstityksiä.",
+        "I watch anime without subtitles.",
+        "字幕なしでアニメを見る。"]),
+      ("survey_question_9",
+       ["Katson japanilaisia draamasarjoja tai näytelmäelokuvia ilman tekstityksiä.",
+        "I watch Japanese drama series or feature films without subtitles.",
+        "字幕なしで日本のドラマや映画を見る。"]),
+      ("survey_question_10",
+       ["Katson japanilaisia ajankohtaisohjelmia, komediaa ym. TV-ohjelmia ilman tekstityksiä.",
+        "I watch Japanese current affairs, comedy or other TV programs without subtitles.",
+        "字幕なしで日本のニュース番組やお笑いなどのテレビ番組を見る。"]),
+      ("survey_question_11",
+       ["Katson animea tekstitysten kanssa.",
+        "I watch anime with subtitles.",
+        "字幕つきでアニメを見る。"]),
+      ("survey_question_12",
+       ["Katson japanilaisia draamasarjoja tai näytelmäelokuvia tekstitysten kanssa.",
+        "I watch Japanese drama series or feature films with subtitles.",
+        "字幕つきで日本のドラマや映画を見る。"]),
+      ("survey_question_13",
+       ["Katson japanilaisia ajankohtaisohjelmia, komediaa ym. TV-ohjelmia tekstitysten kanssa.",
+        "I watch Japanese current affairs, comedy or other TV programs with subtitles.",
+        "字幕つきで日本のニュース番組やお笑いなどのテレビ番組を見る。"]),
+      ("survey_question_14",
+       ["Luen mangaa japaniksi.",
+        "I read manga in Japanese.",
+        "日本語で漫画を読む。"]),
+      ("survey_question_15",
+       ["Luen japanilaisia romaaneja, nuortenkirjoja ym. proosaa japaniksi.",
+        "I read Japanese novels, young adult books or other prose in Japanese.",
+        "日本語で小説やヤングアダルト小説などの散文を読む。"]),
+      ("survey_question_16",
+       ["Olen asunut Japanissa yksin tai ei-japaninkielisessä kodissa (asuntola, suomalaisen \
+         puolison kanssa tms.)",
+        "I have lived in Japan alone or in a non-Japanese-speaking home (dormitory, with a \
+         non-Japanese spouse etc.)",
+        "日本で一人で、または日本語を使わない家庭（寮、日本人でない配偶者となど）\
+         で暮らしたことがある。"]),
+      ("survey_question_17",
+       ["Olen asunut Japanissa japaninkielisessä kodissa (vaihtoperhe, japanilainen puoliso \
+         tms.)",
+        "I have lived in Japan in a Japanese-speaking home (host family, Japanese spouse etc.)",
+        "日本で日本語を使う家庭（ホストファミリー、日本人の配偶者など）で暮らしたことがある。"]),
+      ("survey_question_18",
+       ["Olen opiskellut japanilaisessa lukiossa, yliopistossa tai muussa koulussa.",
+        "I have studied at a Japanese high school, university or other school.",
+        "日本の高校、大学、その他の学校で勉強したことがある。"]),
+      ("survey_question_19",
+       ["Olen matkustanut Japaniin.",
+        "I have travelled to Japan.",
+        "日本に旅行したことがある。"]),
+      ("survey_question_20",
+       ["Rento rupattelu japaniksi sujuu minulta.",
+        "Casual chatting in Japanese goes well for me.",
+        "日本語での気軽なおしゃべりができる。"]),
+      ("survey_question_21",
+       ["Haluaisin osata ääntää japania todella hyvin.",
+        "I'd like to be able to pronounce Japanese really well.",
+        "日本語をとても上手に発音できるようになりたい。"]),
+      ("survey_question_22",
+       ["Olen suorittanut JLPT-kokeen.",
+        "I have taken the JLPT.",
+        "日本語能力試験（JLPT）を受けたことがある。"]),
+      ("survey_question_23",
+       ["Mihin kohtaa nelikenttää sijoittaisit vahvuutesi japanin kielitaidossasi?",
+        "Where in the fourfold would you place your strengths in Japanese?",
+        "日本語の力の強みは、四つの領域のどこにあると思いますか？"]),
+      ("survey_question_24",
+       ["Opiskelen tällä hetkellä japania käymällä kursseilla.",
+        "I currently study Japanese by attending courses.",
+        "現在、コースに通って日本語を勉強している。"]),
+      ("survey_question_25",
+       ["Olen opiskellut japania elämäni varrella kursseilla. (Arvio riittää, mutta älä laske \
+         mukaan kesälomia yms. vaan varsinaiset opiskeluviikot)",
+        "Over my life, I have studied Japanese in courses. (An estimate is enough, but don't \
+         count summer holidays and such, only the actual weeks of study)",
+        "これまでにコースで日本語を勉強したことがある。（おおよそで構いませんが、\
+         夏休みなどは数えず、実際に勉強した週だけを数えてください）"]),
+      ("survey_question_26",
+       ["Olen opiskellut ääntämistä.",
+        "I have studied pronunciation.",
+        "発音を勉強したことがある。"]),
+      ("survey_question_27",
+       ["Opiskelen japania jollain muulla tavalla, millä?",
+        "I study Japanese in some other way, how?",
+        "ほかの方法で日本語を勉強している。どのように？"]),
+      ("survey_question_28",
+       ["Mikä on äidinkielesi?",
+        "What is your native language?",
+        "母語は何ですか？"]),
+      ("survey_question_29",
+       ["Jos haluat tarkentaa aiempia vastauksia, sana on vapaa:",
+        "If you want to clarify your earlier answers, feel free:",
+        "これまでの回答を補足したい場合は、自由に書いてください："])];
+
+/// The texts of the templates. Each group lists the templates it's used in; the texts of
+/// the groups with an empty list are used in every template. The templates refer to them
+/// as `t.key`.
+static TEMPLATE_CATALOG: &'static [(&'static [&'static str], Catalog)] =
+    &[(&[],
+       &[("subtitle",
+          ["Tieteellinen koe japanin ääntämyksestä",
+           "A scientific study on Japanese pronunciation",
+           "日本語の発音に関する科学的研究"]),
+         ("password_placeholder", ["Salasana", "Password", "パスワード"]),
+         ("confirm", ["Vahvista", "Confirm", "確認"]),
+         ("email_placeholder", ["Sähköposti", "E-mail", "メールアドレス"]),
+         ("log_in", ["Sisään", "Log in", "ログイン"]),
+         ("change_password_title",
+          ["Salasanan vaihtaminen",
+           "Changing the password",
+           "パスワードの変更"]),
+         ("new_password", ["Uusi salasana:", "New password:", "新しいパスワード："]),
+         ("new_password_again",
+          ["Uusi salasana (varmistus):",
+           "New password (again):",
+           "新しいパスワード（確認）："]),
+         ("change", ["Vaihda", "Change", "変更"]),
+         ("log_out", ["Kirjaudu ulos", "Log out", "ログアウト"]),
+         ("change_password_link", ["Vaihda salasanaa", "Change password", "パスワードの変更"]),
+         ("my_data_link", ["Omat tiedot", "Your data and language", "自分のデータと言語"]),
+         ("previous", ["Edellinen", "Previous", "前へ"]),
+         ("next", ["Seuraava", "Next", "次へ"]),
+         ("wrong_password",
+          ["Salasana oli väärin!",
+           "Wrong password!",
+           "パスワードが間違っています！"]),
+         ("test_over", ["Testi on nyt ohi!", "The test is over!", "テストは終わりました！"]),
+         ("explanation", ["Selitysteksti", "Explanation", "説明"]),
+         ("skill_nugget", ["Taidonjyvä", "Skill nugget", "スキル"])]),
+      (&["pretest_info.html", "posttest_info.html"],
+       &[("test_title", ["Testi", "Test", "テスト"]),
+         ("presentation",
+          ["Ohjelma esittelee ensin sanoja ja lauseita, ja tenttaa niitä sitten hetken päästä. \
+            Voit esittelyvaiheessa kuunnella esiteltävän sanan tai lauseen niin monta kertaa \
+            kuin haluat.",
+           "The program first presents words and sentences, and then quizzes them a moment \
+            later. During the presentation you can listen to the word or sentence as many \
+            times as you want.",
+           "プログラムはまず単語や文を紹介し、少し後でそれを出題します。\
+            紹介のときは単語や文を何回でも聞くことができます。"]),
+         ("answering",
+          ["Vastausvaiheessa sinun tulee välillä valita kahdesta vaihtoehdosta ja välillä \
+            ääntää sana itse – seuraa näytön ohjeita. Vastausaika on rajoitettu 8 sekuntiin.",
+           "When answering, you sometimes choose between two options and sometimes pronounce \
+            the word yourself – follow the instructions on the screen. The answering time is \
+            limited to 8 seconds.",
+           "答えるときは、二つの選択肢から選ぶ場合と自分で単語を発音する場合があります。\
+            画面の指示に従ってください。回答時間は8秒までです。"]),
+         ("reserve_time",
+          ["Varaa itsellesi aikaa noin 10 minuuttia, sekä meluton, häiriötön tila, jossa olet \
+            mieluusti yksin.",
+           "Reserve about 10 minutes, and a quiet place without distractions, preferably where \
+            you are alone.",
+           "10分ほどの時間と、できれば一人でいられる静かな場所を用意してください。"]),
+         ("recording",
+          ["Testissä nauhoitetaan välillä ääntäsi mikrofonin avustuksella (punainen merkkivalo \
+            välkkyy aina nauhoittaessa). Nauhoitus toimii vain Chrome- ja Firefox-selaimilla, \
+            joten varmistathan etukäteen että käytät yhteensopivaa selainta.",
+           "During the test, your voice is sometimes recorded with a microphone (a red light \
+            blinks whenever recording). Recording works only with the Chrome and Firefox \
+            browsers, so make sure beforehand that you use a compatible browser.",
+           "テスト中、ときどきマイクであなたの声を録音します（録音中は赤いランプが点滅します）。\
+            録音はChromeとFirefoxでのみ動作するので、事前に対応したブラウザを使っていることを確\
+            認してください。"]),
+         ("mic_check",
+          ["Ennen testin aloittamista tarkistamme mikrofonin ja kaiuttimien toimivuuden.",
+           "Before the test starts, we check that the microphone and the speakers work.",
+           "テストを始める前に、マイクとスピーカーが動作することを確認します。"]),
+         ("press_record",
+          ["Paina \"nauhoita\"-painiketta ja sano normaaliin ääneen: \"testi, testi\", ja paina \
+            sitten \"OK\".",
+           "Press the \"record\" button and say in a normal voice: \"test, test\", and then \
+            press \"OK\".",
+           "「録音」ボタンを押して普通の声で「テスト、テスト」と言い、それから「OK」\
+            を押してください。"]),
+         ("record", ["Nauhoita", "Record", "録音"]),
+         ("heard_voice",
+          ["Kuulitko oman äänesi? Jos kuulit, kaikki on kunnossa, ja voit aloittaa kokeen.",
+           "Did you hear your own voice? If you did, everything is in order and you can start \
+            the test.",
+           "自分の声が聞こえましたか？聞こえたら問題ありません。テストを始められます。"]),
+         ("didnt_hear",
+          ["Jos et kuullut, varmista että kaiuttimet ovat päällä ja mikrofoni toimii, ja paina \
+            \"tarkista uudelleen\".",
+           "If you didn't, make sure that the speakers are on and the microphone works, and \
+            press \"check again\".",
+           "聞こえなかった場合は、スピーカーがオンになっていてマイクが動作することを確認し、\
+            「もう一度確認」を押してください。"]),
+         ("check_again", ["tarkista uudelleen", "check again", "もう一度確認"]),
+         ("start_test", ["Aloita koe", "Start the test", "テストを始める"])]),
+      (&["confirm.html"],
+       &[("your_username", ["Käyttäjätunnuksesi on", "Your username is", "あなたのユーザー名は"]),
+         ("choose_password",
+          ["Valitse itsellesi salasana:",
+           "Choose a password:",
+           "パスワードを決めてください："]),
+         ("password_again_placeholder",
+          ["Salasanan varmistus",
+           "Password again",
+           "パスワード（確認）"])]),
+      (&["hello.html"],
+       &[("wrong_credentials",
+          ["Käyttäjänimi tai salasana oli väärin!",
+           "The username or the password was wrong!",
+           "メールアドレスまたはパスワードが間違っています！"]),
+         ("reset_forgotten",
+          ["Vaihda unohtunut salasana",
+           "Reset a forgotten password",
+           "パスワードを忘れた場合"]),
+         ("try_again", ["Kokeile uudelleen", "Try again", "もう一度試してください"]),
+         ("participant_log_in",
+          ["Koehenkilö, kirjaudu sisään",
+           "Participant, log in",
+           "被験者の方はログインしてください"])]),
+      (&["login_code.html"],
+       &[("wrong_code_or_expired",
+          ["Koodi oli väärin tai kirjautuminen vanheni!",
+           "The code was wrong or the login expired!",
+           "コードが間違っているか、ログインの有効期限が切れました！"]),
+         ("log_in_again", ["Kirjaudu uudelleen", "Log in again", "もう一度ログイン"]),
+         ("enter_code",
+          ["Anna tunnistussovelluksen koodi",
+           "Enter the code from your authenticator app",
+           "認証アプリのコードを入力してください"]),
+         ("recovery_code_hint",
+          ["Jos puhelimesi ei ole saatavilla, voit käyttää myös varakoodia.",
+           "If your phone isn't available, you can also use a recovery code.",
+           "電話が使えない場合は、リカバリーコードも使えます。"]),
+         ("code_placeholder", ["Koodi", "Code", "コード"])]),
+      (&["welcome.html"],
+       &[("intro",
+          ["Tervetuloa! Tämä ohjelma opettaa sinulle japanin ääntämystä. Samalla autat \
+            edistämään tutkimusta japanin ääntämisen opettamisesta ja oppimisesta.",
+           "Welcome! This program teaches you Japanese pronunciation. At the same time, you \
+            help research on teaching and learning Japanese pronunciation.",
+           "ようこそ！このプログラムは日本語の発音を教えます。同時に、\
+            日本語の発音の教育と学習に関する研究に協力することになります。"]),
+         ("study_period",
+          ["Tutkimusjakso kestää vain reilun viikon, ja pyydänkin että jos vain suinkin \
+            kykenet, käyttäisit ohjelmaa hyvin aktiivisesti tänä aikana. Tämän jälkeen ohjelma \
+            on vapaasti käytettävissäsi jos haluat jatkaa harjoittelua.",
+           "The study period lasts only a little over a week, and I ask that if you can at all, \
+            you use the program very actively during that time. After that, the program is \
+            freely available to you if you want to keep practising.",
+           "研究期間は一週間あまりです。できる限り、この期間中はプログラムを積極的に使ってくださ\
+            い。その後も、練習を続けたければプログラムを自由に使えます。"]),
+         ("before_training",
+          ["Ennen varsinaisen harjoittelun aloittamista:",
+           "Before the actual training starts:",
+           "本格的な練習を始める前に："]),
+         ("ask_consent",
+          ["Pyydämme suostumustasi tietojen keräämiseen.",
+           "We ask for your consent to collecting data.",
+           "データ収集への同意をお願いします。"]),
+         ("ask_background",
+          ["Kysymme suhteestasi japanin kieleen.",
+           "We ask about your relationship with the Japanese language.",
+           "日本語とのかかわりについて質問します。"]),
+         ("teach_accents",
+          ["Opetamme, mitä japanin \"sana-aksentit\" ovat.",
+           "We teach what the \"word accents\" of Japanese are.",
+           "日本語の「アクセント」とは何かを説明します。"]),
+         ("test_skills",
+          ["Testaamme, mitä osaat jo valmiiksi.",
+           "We test what you already know.",
+           "すでにどのくらいできるかをテストします。"]),
+         ("continue_later",
+          ["Kaikkea tätä ei tarvitse tehdä kerralla; ohjelma jatkaa siitä mihin jäätiin, jos \
+            suljet sen välissä.",
+           "You don't need to do all of this at once; the program continues where you left off \
+            if you close it in between.",
+           "一度にすべてを行う必要はありません。途中で閉じても、続きから再開できます。"]),
+         ("forward", ["Eteenpäin", "Next", "次へ"])]),
+      (&["send_pw_reset_email.html"],
+       &[("forgot_password",
+          ["Salasana unohtunut?",
+           "Forgot your password?",
+           "パスワードを忘れましたか？"]),
+         ("sent_check_email",
+          ["Lähetetty! Tarkista sähköpostisi.",
+           "Sent! Check your email.",
+           "送信しました！メールを確認してください。"]),
+         ("error_occurred", ["Tapahtui virhe:", "An error occurred:", "エラーが発生しました："]),
+         ("reset_instructions",
+          ["Kirjoita tähän meiliosoitteesi, niin lähetämme sinulle ohjeet salasanan \
+            vaihtamiseksi.",
+           "Enter your email address, and we will send you instructions for changing the \
+            password.",
+           "メールアドレスを入力すると、パスワードの変更方法をお送りします。"])]),
+      (&["reset_password.html"],
+       &[("password_changed",
+          ["Salasana vaihdettu!",
+           "Password changed!",
+           "パスワードを変更しました！"])]),
+      (&["consent.html"],
+       &[("reconsent",
+          ["Suostumusteksti on päivittynyt. Lue se ja vahvista, että hyväksyt sen, jotta voit \
+            jatkaa.",
+           "The consent text has been updated. Please read it and confirm that you agree to it \
+            to continue.",
+           "同意書が更新されました。続けるには、読んで同意することを確認してください。"]),
+         ("declined",
+          ["Et voi osallistua kokeeseen suostumatta tietojen keräämiseen! Jos haluat vetäytyä \
+            tutkimuksesta, voit poistaa tai anonymisoida tietosi <a href=\"/my_data\">omissa \
+            tiedoissasi</a>.",
+           "You can't take part in the study without agreeing to the data collection! If you \
+            want to withdraw from the study, you can delete or anonymize your data on <a \
+            href=\"/my_data\">your data page</a>.",
+           "データ収集に同意しないと研究に参加できません！研究への参加を取りやめたい場合は、\
+            <a href=\"/my_data\">自分のデータ</a>のページでデータを削除または匿名化できます。"]),
+         ("agree", ["Hyväksyn", "I agree", "同意する"]),
+         ("decline", ["En hyväksy", "I don't agree", "同意しない"])]),
+      (&["info.html"],
+       &[("about_accents", ["Sana-aksentista", "About word accents", "アクセントについて"]),
+         ("what_is_accent",
+          ["Tämä ohjelma opettaa sinulle japanin ääntämystä; tarkemmin ottaen sana-aksentteja. \
+            Mutta mikä oikeastaan on sana-aksentti?",
+           "This program teaches you Japanese pronunciation; more precisely, word accents. But \
+            what actually is a word accent?",
+           "このプログラムは日本語の発音、詳しく言うとアクセントを教えます。でも、\
+            アクセントとはそもそも何でしょうか？"]),
+         ("listen_place_names",
+          ["Kuuntele nämä paikannimet:",
+           "Listen to these place names:",
+           "これらの地名を聞いてください："]),
+         ("pitch_pattern",
+          ["Japanissa jokaisella sanalla on sille ominainen painotus, jota ilmaistaan \
+            äänenkorkeuden vaihteluilla: sana きょうと äännetään laskevalla äänenkorkeudella, \
+            kun taas とうきょう tasaisella. Kuuletko eron? Tätä kullekin sanalle ominaista \
+            äänenkorkeuden kuviota kutsutaan sana-aksentiksi.",
+           "In Japanese, every word has its own characteristic stress, expressed by changes in \
+            pitch: the word きょうと is pronounced with a falling pitch, whereas とうきょう is \
+            flat. Can you hear the difference? This pitch pattern characteristic of each word \
+            is called the word accent.",
+           "日本語では、どの単語にも音の高さの変化で表される特有の型があります。「きょうと」\
+            は下がる高さで、「とうきょう」は平らな高さで発音されます。違いが聞こえますか？\
+            単語ごとに決まっているこの音の高さの型をアクセントと呼びます。"]),
+         ("minimal_pairs",
+          ["Joskus harvoin käy niin, että kaksi sanaa lausutaan lähes täysin samoin, ja ne \
+            eroavat ainoastaan aksentiltaan. Kuuntele:",
+           "Once in a while, two words are pronounced almost exactly the same way, and they \
+            differ only in their accent. Listen:",
+           "まれに、二つの単語がほとんど同じように発音され、アクセントだけが違うことがあります。\
+            聞いてください："]),
+         ("gloss_seat", ["(\"istumapaikka\")", "(\"seat\")", "（「座席」）"]),
+         ("gloss_cough", ["(\"yskä\")", "(\"cough\")", "（「咳」）"]),
+         ("can_you_hear",
+          ["Kuuletko? Sanat ovat lähes samat, mutta eroavat äänenkorkeuden vaihtelultaan.",
+           "Can you hear it? The words are almost the same, but their pitch changes differently.",
+           "聞こえましたか？単語はほとんど同じですが、音の高さの変化が違います。"]),
+         ("confusion",
+          ["Sekaannuksen vaara on siis olemassa, mutta onneksi tällaisia sanapareja ei ole \
+            paljoa. Yleisissä sanoissa niitä on ehkä muutamia satoja.",
+           "So there's a risk of confusion, but luckily there aren't many pairs of words like \
+            this. Among common words there are maybe a few hundred.",
+           "つまり混同のおそれがありますが、幸いこのような単語の組はあまり多くありません。\
+            よく使われる単語では数百ほどでしょう。"]),
+         ("not_essential",
+          ["Sana-aksentti ei ole välttämätön asia ymmärtämisen kannalta; yleensä kontekstin \
+            ymmärtäminen on tärkeämpää. Lisäksi japanin eri murteissa aksentit vaihtelevat \
+            hieman. Tässä ohjelmassa kaikki esimerkit ovat Tokiossa puhuttua yleiskieltä.",
+           "The word accent isn't essential for understanding; usually understanding the \
+            context is more important. Also, the accents vary a bit between the dialects of \
+            Japanese. In this program, all examples are the standard language spoken in Tokyo.",
+           "アクセントは理解に欠かせないものではありません。\
+            たいていは文脈を理解するほうが大切です。また、\
+            アクセントは方言によって少しずつ違います。このプログラムの例はすべて東京で話される\
+            共通語です。"]),
+         ("focus_on_pairs",
+          ["Vaikka japanissa kaikilla sanoilla on niille ominaiset aksentit, keskitymme tässä \
+            nimenomaan äskeisen esimerkin kaltaisiin hämäävän samankuuloisiin pareihin, sillä \
+            ne toimivat erinomaisena harjoitusmateriaalina.",
+           "Even though all words in Japanese have their own accents, here we focus \
+            specifically on deceptively similar-sounding pairs like the previous example, \
+            because they make excellent training material.",
+           "日本語のすべての単語にはそれぞれのアクセントがありますが、\
+            ここでは特に先ほどの例のような紛らわしい組に注目します。\
+            これらは練習の題材として非常に優れているからです。"]),
+         ("basic_rule",
+          ["Miten sana-aksentit sitten toimivat? Perus&shy;sääntö on, että joko sana on \
+            \"tasa-aksen&shy;tilli&shy;nen\" tai sitten se sisältää yhden \
+            \"aksentti&shy;ytimen\".",
+           "So how do word accents work? The basic rule is that a word is either \"flat\" or it \
+            contains one \"accent nucleus\".",
+           "では、アクセントはどのような仕組みでしょうか？基本的なルールは、単語は「平板型」\
+            であるか、一つの「アクセント核」を含むかのどちらかだということです。"]),
+         ("flat_words",
+          ["Tasa-aksentilliset sanat alkavat pienellä äänen&shy;korkeuden nou&shy;sulla. Tämän \
+            jälkeen äänen&shy;korkeus pysyy tasaisena tai laskee hyvin loivasti.",
+           "Flat words start with a small rise in pitch. After that, the pitch stays level or \
+            falls very gently.",
+           "平板型の単語は、音の高さが少し上がって始まります。その後、高さは平らなままか、\
+            ごくゆるやかに下がります。"]),
+         ("example", ["Esimerkki:", "Example:", "例："]),
+         ("gloss_intention", ["(\"aikomus\")", "(\"intention\")", "（「意向」）"]),
+         ("nucleus",
+          ["Jos sana ei ole tasa-aksen&shy;tilli&shy;nen, se sisältää aksenttiytimen. \
+            Aksenttiydin on tavu, jota ennen äänenkorkeus nousee, ja jonka kohdalla se \
+            äkillisesti laskee.",
+           "If a word isn't flat, it contains an accent nucleus. The accent nucleus is the \
+            syllable before which the pitch rises, and at which it suddenly falls.",
+           "平板型でない単語には、アクセント核があります。アクセント核とは、\
+            その前で音が高くなり、そこで急に下がる拍のことです。"]),
+         ("nucleus_on_ko",
+          ["Ydin tavulla \"こ\":",
+           "Nucleus on the syllable \"こ\":",
+           "「こ」に核がある場合："]),
+         ("gloss_lets_go", ["(\"menkäämme\")", "(\"let's go\")", "（「行こう」）"]),
+         ("first_syllable",
+          ["Aksenttiydin voi olla myös sanan ensimmäisellä tavulla, jolloin äänenkorkeus laskee \
+            heti sanan alusta",
+           "The accent nucleus can also be on the first syllable of the word, in which case the \
+            pitch falls right from the start of the word",
+           "アクセント核が単語の最初の拍にあることもあります。その場合、\
+            音の高さは単語の初めからすぐに下がります"]),
+         ("nucleus_on_i",
+          ["Ydin tavulla \"い\":",
+           "Nucleus on the syllable \"い\":",
+           "「い」に核がある場合："]),
+         ("gloss_after_that", ["(\"sen jälkeen\")", "(\"after that\")", "（「以降」）"]),
+         ("last_syllable",
+          ["Aksenttiydin voi olla myös sanan viimeisellä tavulla, jolloin sana kuulostaa \
+            erehdyttävästi tasa-aksentilliselta, sillä aksentin äkillistä laskua ei tapahdu.",
+           "The accent nucleus can also be on the last syllable of the word, in which case the \
+            word sounds deceptively flat, because the sudden fall of the accent doesn't happen.",
+           "アクセント核が単語の最後の拍にあることもあります。その場合、\
+            急な下がり目が現れないので、単語は平板型とまぎらわしく聞こえます。"]),
+         ("nucleus_on_shi",
+          ["Ydin tavulla \"し\":",
+           "Nucleus on the syllable \"し\":",
+           "「し」に核がある場合："]),
+         ("gloss_bridge", ["(\"silta\")", "(\"bridge\")", "（「橋」）"]),
+         ("listen_two_words",
+          ["Kuuntele seuraavat kaksi sanaa. Toinen on tasa-aksentillinen ja toisessa on ydin \
+            viimeisellä tavulla:",
+           "Listen to the next two words. One of them is flat and the other has the nucleus on \
+            the last syllable:",
+           "次の二つの単語を聞いてください。一方は平板型で、もう一方は最後の拍に核があります："]),
+         ("gloss_edge", ["(\"reuna\")", "(\"edge\")", "（「端」）"]),
+         ("even_japanese",
+          ["Japanilainenkaan ei kuule niissä eroa!",
+           "Even a Japanese person can't hear the difference!",
+           "日本人にも違いは聞こえません！"]),
+         ("particle_difference",
+          ["Ero kuitenkin on olemassa; se kuuluu heti sanan jälkeen tulevissa sijapäätteissä. \
+            Vertaa sanoja に-sijapäätteen kanssa:",
+           "But there is a difference; it can be heard in the case particles right after the \
+            word. Compare the words with the に particle:",
+           "それでも違いはあります。単語のすぐ後に続く助詞で聞き分けられます。助詞「に」\
+            をつけて比べてみましょう："]),
+         ("nucleus_on_shi_ni",
+          ["Ydin tavulla \"し\":",
+           "Nucleus on the syllable \"し\":",
+           "「し」に核がある場合："]),
+         ("flat", ["Tasa-aksentti:", "Flat:", "平板型："]),
+         ("thats_all",
+          ["Siinä olikin kaikki tähän hätään. Sana-aksenteista voisi luennoida loputtomiin, \
+            mutta tärkeämpää on päästä harjoittelemaan niitä käytännössä. Tämä oli vain pieni \
+            esittely, missä käytiin läpi olennaisimmat seikat.",
+           "That's all for now. One could lecture about word accents endlessly, but it's more \
+            important to get to practise them. This was just a small introduction that went \
+            through the most essential points.",
+           "ひとまずこれで終わりです。アクセントについてはいくらでも話せますが、\
+            実際に練習するほうが大切です。これは最も重要な点をまとめた簡単な紹介でした。"]),
+         ("happy_learning", ["Oppimisen iloa!", "Happy learning!", "楽しく学んでください！"]),
+         ("continue", ["Eteenpäin", "Continue", "次へ"])]),
+      (&["main.html"],
+       &[("sessions_link", ["Kirjautumiset", "Sessions", "ログイン中の端末"]),
+         ("two_factor_link",
+          ["Kaksivaiheinen tunnistautuminen",
+           "Two-factor authentication",
+           "二段階認証"]),
+         ("your_answer", ["Oma vastaus", "Your answer", "自分の答え"]),
+         ("remember_word",
+          ["Ok, painan sanan mieleeni!",
+           "Ok, I'll remember the word!",
+           "OK、覚えました！"]),
+         ("check_answer",
+          ["Ok, tarkista vastaus!",
+           "Ok, check the answer!",
+           "OK、答えを確認する！"]),
+         ("exercise_success",
+          ["Muistin äänenpainon paikan<br>ja äänsin ongelmitta.",
+           "I remembered the accent<br>and pronounced it without problems.",
+           "アクセントの位置を覚えていて<br>問題なく発音できた。"]),
+         ("exercise_failure",
+          ["Suorituksessani on vielä<br>parantamisen varaa",
+           "There is still room<br>for improvement",
+           "まだ<br>改善の余地がある"])]),
+      (&["posttest_info.html"],
+       &[("intro",
+          ["Koejakson lopuksi testaamme lyhyesti, miten taitosi kuulla ja tuottaa aksentteja on \
+            muuttunut. Älä stressaa turhia, vaan vastaa parhaasi mukaan kysymyksiin.",
+           "At the end of the study period, we briefly test how your skill to hear and produce \
+            the accents has changed. Don't stress, just answer the questions as well as you \
+            can.",
+           "研究期間の終わりに、アクセントを聞き取り発音する力がどう変わったかを簡単にテストしま\
+            す。緊張しすぎず、できる範囲で質問に答えてください。"]),
+         ("no_breaks",
+          ["Normaalisti ei haittaa, vaikka pitäisit taukoja välissä harjoitellessasi \
+            aksentteja, mutta toivomme että tekisit tämän lopputestin pitämättä taukoja kesken \
+            kysymysten.",
+           "Normally it's fine to take breaks while practising the accents, but we hope that \
+            you take this posttest without breaks between the questions.",
+           "普段はアクセントの練習の合間に休憩をとっても構いませんが、\
+            この事後テストは質問の途中で休憩をとらずに受けてください。"]),
+         ("hard",
+          ["Testi voi tuntua turhauttavan vaikealta jopa harjoittelun jälkeen, mutta älä \
+            lannistu vaan vastaa parhaasi mukaan!",
+           "The test may feel frustratingly hard even after the training, but don't lose heart, \
+            just answer as well as you can!",
+           "練習の後でもテストはもどかしいほど難しく感じるかもしれませんが、\
+            あきらめずにできる範囲で答えてください！"])]),
+      (&["pretest_info.html"],
+       &[("intro",
+          ["Ennen harjoittelun aloittamista testaamme vielä lyhyesti, kuinka hyvä olet \
+            aksenteissa jo valmiiksi. Älä stressaa turhia, vaan vastaa parhaasi mukaan \
+            kysymyksiin.",
+           "Before the training starts, we briefly test how good you already are with the \
+            accents. Don't stress, just answer the questions as well as you can.",
+           "練習を始める前に、アクセントがすでにどのくらいできるかを簡単にテストします。\
+            緊張しすぎず、できる範囲で質問に答えてください。"]),
+         ("no_breaks",
+          ["Normaalisti ei haittaa, vaikka pitäisit taukoja välissä harjoitellessasi \
+            aksentteja, mutta toivomme että tekisit tämän alkutestin pitämättä taukoja kesken \
+            kysymysten.",
+           "Normally it's fine to take breaks while practising the accents, but we hope that \
+            you take this pretest without breaks between the questions.",
+           "普段はアクセントの練習の合間に休憩をとっても構いませんが、\
+            この事前テストは質問の途中で休憩をとらずに受けてください。"]),
+         ("hard",
+          ["Testi voi tuntua turhauttavan vaikealta, mutta älä lannistu!",
+           "The test may feel frustratingly hard, but don't lose heart!",
+           "テストはもどかしいほど難しく感じるかもしれませんが、あきらめないでください！"])]),
+      (&["retelling.html"],
+       &[("retold", ["Kerrottu!", "Done!", "話し終わった！"])]),
+      (&["survey.html"],
+       &[("survey_title", ["Kysely", "Survey", "アンケート"])]),
+      (&["my_data.html"],
+       &[("my_data_title", ["Omat tiedot", "Your data", "自分のデータ"]),
+         ("download_title",
+          ["Tietojen lataaminen",
+           "Downloading your data",
+           "データのダウンロード"]),
+         ("download_intro",
+          ["Voit ladata itsellesi kopion kaikista tiedoistasi: profiilisi, vastauksesi, \
+            tapahtumiin tallennetut tiedot ja äänitteesi.",
+           "You can download a copy of all your data: your profile, your answers, the data \
+            saved during the events and your recordings.",
+           "あなたのすべてのデータのコピーをダウンロードできます：プロフィール、回答、\
+            イベントで保存されたデータ、そして録音。"]),
+         ("download", ["Lataa tiedot", "Download", "ダウンロード"]),
+         ("language_title", ["Kieli", "Language", "言語"]),
+         ("change_language", ["Vaihda kieli", "Change the language", "言語を変更"]),
+         ("withdraw_title",
+          ["Tutkimuksesta vetäytyminen",
+           "Withdrawing from the study",
+           "研究への参加の取りやめ"]),
+         ("password_label", ["Salasana:", "Password:", "パスワード："]),
+         ("cancel_request", ["Peru pyyntö", "Cancel the request", "リクエストを取り消す"]),
+         ("anonymize", ["Anonymisoi tietoni", "Anonymize my data", "データを匿名化する"]),
+         ("delete_account", ["Poista tilini", "Delete my account", "アカウントを削除する"]),
+         ("deleted_on",
+          ["Tilisi ja kaikki tietosi poistetaan ",
+           "Your account and all your data will be deleted on ",
+           "あなたのアカウントとすべてのデータは"]),
+         ("deleted_on_end", [".", ".", "に削除されます。"]),
+         ("anonymized_on",
+          ["Tietosi anonymisoidaan ",
+           "Your data will be anonymized on ",
+           "あなたのデータは"]),
+         ("anonymized_on_end", [".", ".", "に匿名化されます。"]),
+         ("can_cancel",
+          ["Siihen asti voit vielä perua pyynnön.",
+           "Until then, you can still cancel the request.",
+           "それまではリクエストを取り消すことができます。"]),
+         ("really_delete",
+          ["Haluatko varmasti poistaa tilisi ja kaikki tietosi?",
+           "Do you really want to delete your account and all your data?",
+           "本当にアカウントとすべてのデータを削除しますか？"]),
+         ("really_anonymize",
+          ["Haluatko varmasti anonymisoida tietosi?",
+           "Do you really want to anonymize your data?",
+           "本当にデータを匿名化しますか？"]),
+         ("confirm_with_password",
+          ["Vahvista salasanallasi.",
+           "Confirm with your password.",
+           "パスワードで確認してください。"]),
+         ("withdraw_intro",
+          ["Voit poistaa tilisi ja kaikki tietosi, tai anonymisoida tietosi: silloin \
+            sähköpostiosoitteesi, salasanasi ja äänitteesi poistetaan, mutta vastauksesi \
+            jäävät tutkimusaineistoon ilman yhteyttä sinuun.",
+           "You can delete your account and all your data, or anonymize your data: then your \
+            email address, password and recordings are deleted, but your answers stay in the \
+            research data without a link to you.",
+           "アカウントとすべてのデータを削除するか、データを匿名化することができます。\
+            匿名化すると、メールアドレス、パスワード、録音は削除されますが、\
+            回答はあなたとのつながりのない形で研究データに残ります。"]),
+         ("carried_out",
+          ["Pyyntö toteutetaan ",
+           "The request is carried out ",
+           "リクエストは確認から"]),
+         ("carried_out_end",
+          [" päivän kuluttua vahvistamisesta.",
+           " days after you confirm it.",
+           "日後に実行されます。"])]),
+      (&["two_factor.html"],
+       &[("two_factor_title",
+          ["Kaksivaiheinen tunnistautuminen",
+           "Two-factor authentication",
+           "二段階認証"]),
+         ("intro",
+          ["Kun kaksivaiheinen tunnistautuminen on käytössä, kirjautumiseen tarvitaan salasanan \
+            lisäksi tunnistussovelluksen (esim. Google Authenticator) antama koodi.",
+           "When two-factor authentication is enabled, logging in needs a code from an \
+            authenticator app (e.g. Google Authenticator) in addition to the password.",
+           "二段階認証を有効にすると、ログインにはパスワードに加えて認証アプリ（例：\
+            Google Authenticator）のコードが必要になります。"]),
+         ("required",
+          ["Käyttäjäroolisi vaatii kaksivaiheisen tunnistautumisen. Ota se käyttöön päästäksesi \
+            ylläpitosivuille.",
+           "Your role requires two-factor authentication. Enable it to access the management \
+            pages.",
+           "あなたの役割では二段階認証が必要です。管理ページを使うには有効にしてください。"]),
+         ("enable", ["Ota käyttöön", "Enable", "有効にする"]),
+         ("add_account",
+          ["Lisää tunnistussovellukseen uusi tili <a id=\"otpauthLink\" href=\"\">tästä \
+            linkistä</a> tai syöttämällä avain:",
+           "Add a new account to the authenticator app <a id=\"otpauthLink\" href=\"\">with \
+            this link</a> or by entering the key:",
+           "<a id=\"otpauthLink\" href=\"\">このリンク</a>から、\
+            または次のキーを入力して認証アプリにアカウントを追加してください："]),
+         ("code_from_app",
+          ["Sovelluksen antama koodi",
+           "The code from the app",
+           "アプリのコード"]),
+         ("enabled_codes_left",
+          ["Kaksivaiheinen tunnistautuminen on käytössä. Varakoodeja jäljellä:",
+           "Two-factor authentication is enabled. Recovery codes left:",
+           "二段階認証は有効です。残りのリカバリーコード："]),
+         ("code_or_recovery_code",
+          ["Sovelluksen antama koodi tai varakoodi",
+           "The code from the app, or a recovery code",
+           "アプリのコードまたはリカバリーコード"]),
+         ("new_recovery_codes",
+          ["Uudet varakoodit",
+           "New recovery codes",
+           "新しいリカバリーコード"]),
+         ("disable", ["Poista käytöstä", "Disable", "無効にする"]),
+         ("save_recovery_codes",
+          ["Varakoodit näytetään vain nyt. Tallenna ne turvalliseen paikkaan: kukin koodi käy \
+            kerran kirjautumiseen, jos puhelimesi ei ole saatavilla.",
+           "The recovery codes are shown only now. Save them somewhere safe: each code works \
+            once for logging in if your phone isn't available.",
+           "リカバリーコードは今だけ表示されます。安全な場所に保存してください。\
+            電話が使えないとき、各コードで一度ログインできます。"])]),
+      (&["change_password.html"],
+       &[("old_password", ["Vanha salasana:", "Old password:", "古いパスワード："])]),
+      (&["sessions.html"],
+       &[("sessions_title", ["Kirjautumiset", "Sessions", "ログイン中の端末"]),
+         ("sessions_intro",
+          ["Laitteet, joilla olet kirjautuneena sisään. Jos et tunnista jotain niistä, kirjaa \
+            se ulos ja vaihda salasanasi.",
+           "The devices you are logged in on. If you don't recognize one of them, log it out \
+            and change your password.",
+           "ログインしている端末の一覧です。心当たりのない端末があれば、\
+            ログアウトさせてパスワードを変更してください。"]),
+         ("log_out_others",
+          ["Kirjaa ulos kaikki muut",
+           "Log out all the others",
+           "他の端末をすべてログアウト"])]),
+      (&["posttest_done.html"],
+       &[("thanks",
+          ["Kiitos että osallistuit kokeeseen!",
+           "Thank you for taking part in the study!",
+           "研究にご参加いただきありがとうございました！"]),
+         ("keep_using",
+          ["Nyt kokeen jälkeen ohjelman harjoitustoiminnot ovat edelleen vapaasti \
+            käytettävissäsi. Opiskelun iloa!",
+           "The exercises of the program stay freely available to you after the study. Happy \
+            studying!",
+           "研究の後も、プログラムの練習機能は引き続き自由に使えます。楽しく勉強してください！"])]),
+      (&["pretest_done.html"],
+       &[("start_training",
+          ["Nyt pääset varsinaisen harjoittelun pariin.",
+           "Now you can start the actual training.",
+           "いよいよ本格的な練習を始めます。"]),
+         ("own_pace",
+          ["Voit edetä harjoitusten kanssa omaan tahtiin päivän mittaan. Kerralla tehtävien \
+            harjoitusten määrä on kuitenkin rajoitettu; tämän on tarkoitus vähentää \
+            pänttäämistä ja hajauttaa harjoittelua moneen sessioon. Yritä harjoitella \
+            päivittäin, tai ainakin niin, ettei väliin jää monen päivän aukkoja.",
+           "You can go through the exercises at your own pace during the day. The number of \
+            exercises at a time is limited, though; this is meant to reduce cramming and \
+            spread the training over many sessions. Try to practise daily, or at least so that \
+            there are no gaps of many days.",
+           "一日の中で自分のペースで練習を進められます。ただし、\
+            一度にできる練習の数は限られています。これは詰め込みを減らし、\
+            練習を何回かに分けるためです。毎日、少なくとも何日も間が空かないように練習してくだ\
+            さい。"]),
+         ("reminders",
+          ["Lähetämme sinulle muistutusviestin sähköpostiin, jos et koske harjoituksiin pariin \
+            päivään.",
+           "We send you a reminder by email if you don't touch the exercises for a couple of \
+            days.",
+           "二、三日練習しないと、リマインダーのメールをお送りします。"])]),
+      (&["pw_reset_email.html"],
+       &[("hello", ["Hei,", "Hello,", "こんにちは、"]),
+         ("someone_asked",
+          ["Joku, toivottavasti sinä, pyysi salasanasi vaihtoa ",
+           "Someone, hopefully you, asked to reset your password at ",
+           "ご利用中の"]),
+         ("someone_asked_end",
+          ["-sivustolla.",
+           ".",
+           "でパスワードの再設定がリクエストされました。"]),
+         ("change_below",
+          ["Voit vaihtaa salasanasi alla olevassa osoitteessa:",
+           "You can change your password at the address below:",
+           "下記のアドレスでパスワードを変更できます："]),
+         ("ignore",
+          ["Jos epäilet tämän viestin olevan virhe tai et ole aikeissa vaihtaa salasanaasi, \
+            voit jättää tämän viestin huomiotta.",
+           "If you think this message was sent by mistake or you don't intend to change your \
+            password, you can ignore it.",
+           "心当たりがない場合や、パスワードを変更するつもりがない場合は、\
+            このメールを無視してください。"])]),
+      (&["email_confirm_email.html"],
+       &[("hello", ["Hei, tai こんにちは！", "Hello!", "こんにちは！"]),
+         ("invitation",
+          ["<p>Olen Pyry Kontio ja olen japanin pääaineopiskelija maailman kulttuurien \
+            laitoksella. Muistatko vielä minut? Kävin viime vuoden lopulla japanin tunneilla \
+            kertomassa tutkimuksestani, jossa yritän opettaa japanin kielen sana-aksentteja \
+            japanin opiskelijoille.</p><p>Aikataulut ovat – osin teknisistä ja osin muista \
+            syistä – venyneet kuukausikaupalla, mutta tässä on vihdoin kutsu aksentteja \
+            treenaavaan harjoitusohjelmaan! Kiinnostaako kokeeseen osallistuminen sinua vielä? \
+            (Voit jättää tämän viestin huomiotta, jos et voi tai halua enää osallistua.)</p>",
+           "<p>You have been invited to take part in a study on learning the word accents of \
+            Japanese.</p>",
+           "<p>日本語の単語アクセントの学習に関する研究へのご招待です。</p>"]),
+         ("study_period",
+          ["Koejakson on tarkoitus kestää kymmenen päivää alkaen siitä päivästä jona aloitat \
+            harjoittelun, ja toivoisin että kokeeseen osallistujat käyttäisivät \
+            harjoitusohjelmaa tänä aikana aktiivisesti. Toivoisin myös, että kiinnostuneet \
+            aloittaisivat harjoittelun heti lähipäivinä! (Ohjelma on käytettävissä myös \
+            koejakson jälkeen, jos haluat opiskella lisää aksentteja itsenäisesti.) Alla on \
+            kutsulinkki, jota klikkaamalla saa tehtyä itselleen käyttäjätunnuksen.",
+           "The study period lasts ten days, starting from the day you begin training. We hope \
+            that the participants use the training program actively during that time. (The \
+            program stays available after the study period, if you want to keep studying the \
+            accents on your own.) You can ignore this message if you don't want to take part.",
+           "実験期間は練習を始めた日から10日間です。この期間中、\
+            練習プログラムを積極的に使っていただければ幸いです。（実験期間が終わった後も、\
+            自分でアクセントの勉強を続けたい場合はプログラムを引き続き使えます。）\
+            参加を希望されない場合は、このメールを無視してください。"]),
+         ("welcome", ["Tervetuloa käyttämään ", "Welcome to ", "ようこそ"]),
+         ("welcome_end", ["-harjoitusohjelmaa.", "!", "へ！"]),
+         ("confirm_below",
+          ["Vahvista tunnuksesi alla olevassa osoitteessa:",
+           "Confirm your account at the address below:",
+           "下記のアドレスでアカウントを確認してください："])]),
+      (&["slacker_heatenings.html"],
+       &[("not_seen",
+          ["Et ole käynyt vähään aikaan ",
+           "You haven't practised Japanese pronunciation at ",
+           "しばらく"]),
+         ("not_seen_end",
+          ["-sivustolla harjoittelemassa japanin ääntämystä.",
+           " for a while.",
+           "で日本語の発音を練習していませんね。"]),
+         ("jump_back",
+          ["Hyppää suoraan treenaamaan tästä linkistä:",
+           "Jump right back to training:",
+           "こちらのリンクからすぐに練習を再開できます："]),
+         ("why_this_mail",
+          ["Sait tämän viestin automaattisesti, koska et ole muutamaan päivään käynyt sivulla. \
+            Näiden viestien tulo lakkaa automaattisesti kokeen loputtua. Jos et halua näitä \
+            viestejä, vastaa tähän mailiin niin asetan muistutukset pois.",
+           "You got this message automatically, because you haven't visited the site for a few \
+            days. These messages stop automatically when the study ends. If you don't want \
+            them, reply to this mail and I'll turn the reminders off.",
+           "数日間サイトを訪れていないため、このメールは自動的に送信されました。\
+            実験が終わるとこのメールは自動的に届かなくなります。リマインダーが不要な場合は、\
+            このメールに返信していただければ停止します。"])]),
+      (&["fresh_install.html"],
+       &[("installation", ["Asennus", "Installation", "インストール"]),
+         ("create_admin",
+          ["Luo ylläpitäjän tunnus",
+           "Create the admin account",
+           "管理者アカウントの作成"]),
+         ("email_address", ["Sähköpostiosoite:", "Email address:", "メールアドレス："]),
+         ("installed", ["Asennettu!", "Installed!", "インストールしました！"]),
+         ("to_front_page", ["Etusivulle", "To the front page", "トップページへ"])]),
+      (&["add_users.html"],
+       &[("add_users_title", ["Lisää käyttäjiä", "Add users", "ユーザーの追加"]),
+         ("add_users_help",
+          ["Listaa sähköpostiosoitteita, mihin lähetetään kutsut. Voit lisätä perään \
+            välilyönnillä erotettuna käyttäjäryhmät, mihin haluat liittää käyttäjän. Esim: \
+            subjects, show_accents. Roolit, kuten admin tai editor, annetaan käyttäjäsivulla, \
+            kun tili on luotu.",
+           "List the email addresses to send the invitations to. You can add the user groups \
+            the user should join after the address, separated by spaces, e.g. subjects, \
+            show_accents. Roles such as admin or editor are given on the users page after the \
+            account exists.",
+           "招待を送るメールアドレスを並べてください。アドレスの後に、\
+            ユーザーを加えるユーザーグループをスペースで区切って書けます。例：\
+            subjects, show_accents。adminやeditorなどの役割は、\
+            アカウントができた後にユーザーページで与えます。"]),
+         ("invitation_locale",
+          ["Kutsun ja käyttöliittymän kieli",
+           "Language of the invitation and the user interface",
+           "招待とユーザーインターフェースの言語"]),
+         ("send_invitations", ["Lähetä kutsut", "Send the invitations", "招待を送る"])]),
+      (&["send_mail.html"],
+       &[("sent", ["Lähetetty!", "Sent!", "送信しました！"])]),
+      (&["add_word.html"],
+       &[("word", ["Sana:", "Word:", "単語："]),
+         ("word_placeholder", ["Sana", "Word", "単語"]),
+         ("first_pronunciation", ["Ääntäminen 1", "Pronunciation 1", "発音 1"])]),
+      (&["add_quiz.html"],
+       &[("list_name",
+          ["Kysymyksen listanimi",
+           "Name of the question in lists",
+           "一覧での問題名"]),
+         ("question", ["Kysymys", "Question", "問題"]),
+         ("first_answer_choice", ["1. vastausvaihtoehto", "Answer choice 1", "選択肢 1"]),
+         ("first_question_audio", ["Kysymys (audio 1)", "Question (audio 1)", "問題（音声 1）"]),
+         ("answer_audio", ["Vastaus (audio)", "Answer (audio)", "答え（音声）"]),
+         ("answer_text", ["Vastaus (teksti)", "Answer (text)", "答え（テキスト）"])])];
+
+fn locale_index(locale: &str) -> Option<usize> {
+    LOCALES.iter().position(|&l| l == locale)
+}
+
+pub fn check_locale(locale: &str) -> Result<()> {
+    match locale_index(locale) {
+        Some(_) => Ok(()),
+        None => bail!(ErrorKind::InvalidInput),
+    }
+}
+
+/// Looks up a message from the catalog. Unknown locales fall back to the default locale,
+/// and unknown keys are returned as they are.
+pub fn message<'a>(locale: &str, key: &'a str) -> &'a str {
+    let index = locale_index(locale).unwrap_or(0);
+    match CATALOG.iter().find(|&&(k, _)| k == key) {
+        Some(&(_, ref translations)) => translations[index],
+        None => {
+            warn!("No message {:?} in the catalog!", key);
+            key
+        }
+    }
+}
+
+/// The messages of the browser scripts in the locale, as a JSON object that can be
+/// embedded in a `<script>` element.
+pub fn client_messages(locale: &str) -> String {
+    let index = locale_index(locale).unwrap_or(0);
+    let messages: BTreeMap<&str, &str> = CLIENT_CATALOG.iter()
+        .map(|&(key, ref translations)| (key, translations[index]))
+        .collect();
+    serde_json::to_string(&messages)
+        .expect("Serializing a map of strings doesn't fail.")
+        .replace("</", "<\\/")
+}
+
+/// Picks the best supported locale from the value of an `Accept-Language` header.
+pub fn negotiate(accept_language: &str) -> &'static str {
+    let mut candidates: Vec<(f32, &'static str)> = accept_language.split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let tag = parts.next().unwrap_or("").trim().to_lowercase();
+            let quality = parts.filter_map(|p| {
+                    let p = p.trim();
+                    if p.starts_with("q=") {
+                        p[2..].parse().ok()
+                    } else {
+                        None
+                    }
+                })
+                .next()
+                .unwrap_or(1.0);
+            let language = tag.split('-').next().unwrap_or("");
+            LOCALES.iter().find(|&&l| l == language).map(|&l| (quality, l))
+        })
+        .collect();
+    // The sort is stable, so with equal qualities, the order of the header is kept.
+    candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    candidates.first().map(|&(_, l)| l).unwrap_or(DEFAULT_LOCALE)
+}
+
+/// The texts of a template in the locale. The texts that are specific to the template
+/// override the common ones.
+pub fn template_messages(locale: &str, template: &str) -> BTreeMap<&'static str, &'static str> {
+    let index = locale_index(locale).unwrap_or(0);
+    TEMPLATE_CATALOG.iter()
+        .filter(|&&(templates, _)| {
+            templates.is_empty() || templates.iter().any(|&t| t == template)
+        })
+        .flat_map(|&(_, catalog)| catalog.iter())
+        .map(|&(key, ref translations)| (key, translations[index]))
+        .collect()
+}
+
+#[test]
+fn test_catalog_complete() {
+    let template_catalogs = TEMPLATE_CATALOG.iter().flat_map(|&(_, catalog)| catalog.iter());
+    for &(key, ref translations) in CATALOG.iter().chain(CLIENT_CATALOG).chain(template_catalogs) {
+        assert!(translations.iter().all(|t| !t.is_empty()), "{} is missing a translation", key);
+    }
+    assert_eq!(message("en", "email_confirm_subject"), "Welcome!");
+    assert_eq!(message("xx", "email_confirm_subject"), "Tervetuloa!");
+}
+
+#[test]
+fn test_negotiate() {
+    assert_eq!(negotiate("ja-JP,ja;q=0.9,en;q=0.8"), "ja");
+    assert_eq!(negotiate("de-DE, en;q=0.5, fi;q=0.7"), "fi");
+    assert_eq!(negotiate("de-DE"), DEFAULT_LOCALE);
+    assert_eq!(negotiate(""), DEFAULT_LOCALE);
+}
+
+#[test]
+fn test_client_messages() {
+    let messages: BTreeMap<String, String> =
+        serde_json::from_str(&client_messages("en")).unwrap();
+    assert_eq!(messages["time_up"], "Time's up!");
+    assert_eq!(messages.len(), CLIENT_CATALOG.len());
+    assert!(!client_messages("ja").contains("</"));
+}
+
+#[test]
+fn test_template_messages() {
+    let texts = template_messages("en", "main.html");
+    assert_eq!(texts["log_out"], "Log out");
+    assert!(!texts.contains_key("start_test"));
+    assert_eq!(template_messages("ja", "add_word.html")["explanation"], "説明");
+    assert_eq!(template_messages("xx", "pretest_info.html")["start_test"],
+               template_messages(DEFAULT_LOCALE, "posttest_info.html")["start_test"]);
+}
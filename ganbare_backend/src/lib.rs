@@ -23,6 +23,7 @@ extern crate try_map;
 extern crate tempdir;
 extern crate crypto;
 pub extern crate chrono;
+extern crate chrono_tz;
 extern crate rand;
 extern crate rustc_serialize;
 extern crate data_encoding;
@@ -33,6 +34,8 @@ extern crate dotenv;
 #[macro_use]
 extern crate binary_macros;
 extern crate byteorder;
+extern crate tar;
+extern crate argon2;
 
 pub use try_map::{FallibleMapExt, FlipResultExt};
 use std::sync::atomic::{Ordering, AtomicBool};
@@ -61,6 +64,12 @@ lazy_static! {
             .map(|s| s.parse().unwrap_or(false))
             .unwrap_or(false)
     };
+
+    pub static ref FFMPEG: String = {
+        dotenv::dotenv().ok();
+        std::env::var("GANBARE_FFMPEG")
+            .unwrap_or_else(|_| "ffmpeg".into())
+    };
 }
 
 #[macro_export]
@@ -94,13 +103,29 @@ pub mod schema;
 pub mod models;
 pub mod event;
 pub mod email;
+pub mod mailer;
+pub mod i18n;
 pub mod password;
 pub mod errors;
 pub mod user;
 pub mod session;
 pub mod audio;
 pub mod quiz;
+pub mod scheduler;
+pub mod randomize;
 pub mod manage;
+pub mod manifest;
+pub mod archive;
+pub mod research;
+pub mod pseudonym;
+pub mod account;
+pub mod consent;
+pub mod role;
+pub mod audit;
+pub mod trash;
+pub mod revision;
+pub mod api_token;
+pub mod totp;
 pub mod test;
 pub mod helpers;
 
@@ -286,11 +311,14 @@ pub mod skill {
             skill_nuggets::table.order(skill_nuggets::skill_summary.asc()).get_results(&**conn)?;
 
         let words = Word::belonging_to(&nuggets)
+            .filter(words::deleted.is_null())
             .order(words::id.asc())
             .load::<Word>(&**conn)?
             .grouped_by(&nuggets);
 
-        let questions = QuizQuestion::belonging_to(&nuggets).order(quiz_questions::id.asc())
+        let questions = QuizQuestion::belonging_to(&nuggets)
+            .filter(quiz_questions::deleted.is_null())
+            .order(quiz_questions::id.asc())
             .load::<QuizQuestion>(&**conn)?;
 
         let q_answers = Answer::belonging_to(&questions)
@@ -303,7 +331,9 @@ pub mod skill {
             .collect::<Vec<_>>()
             .grouped_by(&nuggets);
 
-        let exercises = Exercise::belonging_to(&nuggets).order(exercises::id.asc())
+        let exercises = Exercise::belonging_to(&nuggets)
+            .filter(exercises::deleted.is_null())
+            .order(exercises::id.asc())
             .load::<Exercise>(&**conn)?;
 
         let e_answers = ExerciseVariant::belonging_to(&exercises)
@@ -352,12 +382,15 @@ pub mod skill {
 
     }
 
-    pub fn remove(conn: &Connection, id: i32) -> Result<Option<SkillNugget>> {
+    pub fn remove(conn: &Connection, actor: i32, id: i32) -> Result<Option<SkillNugget>> {
         use schema::skill_nuggets;
 
         let skill: Option<SkillNugget> =
             diesel::delete(skill_nuggets::table.filter(skill_nuggets::id.eq(id))).get_result(&**conn)
                 .optional()?;
+        if let Some(ref skill) = skill {
+            audit::record(conn, actor, "delete", "skill", Some(id), Some(skill), None)?;
+        }
 
         Ok(skill)
     }
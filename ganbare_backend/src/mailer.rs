@@ -0,0 +1,171 @@
+extern crate lettre;
+
+use super::*;
+use std::fs;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use self::lettre::email::SimpleSendableEmail;
+use self::lettre::transport::EmailTransport;
+use self::lettre::transport::smtp::{SmtpTransport, SmtpTransportBuilder};
+
+/// A mail taken from the queue, ready to be handed over to a transport.
+#[derive(Debug, Clone)]
+pub struct OutgoingMail {
+    pub from_address: String,
+    pub to_addresses: Vec<String>,
+    /// The full message with the headers, as it goes on the wire.
+    pub message: String,
+}
+
+/// Delivers mails somewhere. Errors are returned as strings, because they are
+/// stored as the `last_error` of the queued mail.
+pub trait Mailer {
+    fn send(&mut self, mail: OutgoingMail) -> std::result::Result<(), String>;
+}
+
+pub struct SmtpMailer(SmtpTransport);
+
+impl SmtpMailer {
+    pub fn new(server: SocketAddr,
+               username: &str,
+               password: &str,
+               encrypt: bool)
+               -> Result<SmtpMailer> {
+        let mut builder = SmtpTransportBuilder::new(server)
+            .chain_err(|| "Couldn't setup the SMTP transport!")?
+            .credentials(username, password);
+        if encrypt {
+            builder = builder.encrypt();
+        }
+        Ok(SmtpMailer(builder.build()))
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&mut self, mail: OutgoingMail) -> std::result::Result<(), String> {
+        let email = SimpleSendableEmail::new(&mail.from_address, mail.to_addresses, mail.message);
+        self.0.send(email).map(|_| ()).map_err(|e| e.to_string())
+    }
+}
+
+/// Writes each mail as an .eml file to a directory. For development.
+pub struct FileMailer {
+    dir: PathBuf,
+    count: usize,
+}
+
+impl FileMailer {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Result<FileMailer> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).chain_err(|| format!("Couldn't create directory {:?}", dir))?;
+        Ok(FileMailer {
+               dir: dir,
+               count: 0,
+           })
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send(&mut self, mail: OutgoingMail) -> std::result::Result<(), String> {
+        self.count += 1;
+        let filename = format!("{}-{}.eml",
+                               chrono::UTC::now().format("%Y%m%dT%H%M%S%.f"),
+                               self.count);
+        let path = self.dir.join(filename);
+        let mut file = fs::File::create(&path).map_err(|e| e.to_string())?;
+        write!(file,
+               "X-Envelope-From: {}\r\nX-Envelope-To: {}\r\n{}",
+               mail.from_address,
+               mail.to_addresses.join(", "),
+               mail.message)
+                .map_err(|e| e.to_string())?;
+        info!("Wrote mail to {:?}", path);
+        Ok(())
+    }
+}
+
+lazy_static! {
+    /// The mails sent with the "memory" transport. Shared by all the mailers set up with
+    /// `from_config`, so the mails can be read wherever the mailer itself isn't at hand.
+    pub static ref MEMORY_MAILBOX: Arc<Mutex<Vec<OutgoingMail>>> = Arc::new(Mutex::new(vec![]));
+}
+
+/// Keeps the mails in memory. The mails can be inspected through a clone of `sent`.
+/// For tests.
+#[derive(Default, Clone)]
+pub struct MemoryMailer {
+    pub sent: Arc<Mutex<Vec<OutgoingMail>>>,
+}
+
+impl MemoryMailer {
+    pub fn new() -> MemoryMailer {
+        MemoryMailer::default()
+    }
+
+    /// A mailer that puts the mails to `MEMORY_MAILBOX`.
+    pub fn shared() -> MemoryMailer {
+        MemoryMailer { sent: MEMORY_MAILBOX.clone() }
+    }
+}
+
+impl Mailer for MemoryMailer {
+    fn send(&mut self, mail: OutgoingMail) -> std::result::Result<(), String> {
+        info!("Captured mail to {:?}", mail.to_addresses);
+        self.sent.lock().map_err(|e| e.to_string())?.push(mail);
+        Ok(())
+    }
+}
+
+pub struct SmtpConfig<'a> {
+    pub server: SocketAddr,
+    pub username: &'a str,
+    pub password: &'a str,
+    pub encrypt: bool,
+}
+
+/// Sets up the transport chosen by configuration: "smtp", "file" or "memory".
+/// `smtp` is only called when the SMTP transport is chosen,
+/// so that the SMTP settings aren't required otherwise.
+pub fn from_config<'a, F>(transport: &str, dir: &str, smtp: F) -> Result<Box<Mailer>>
+    where F: FnOnce() -> SmtpConfig<'a>
+{
+    let mailer: Box<Mailer> = match transport {
+        "smtp" => {
+            let c = smtp();
+            Box::new(SmtpMailer::new(c.server, c.username, c.password, c.encrypt)?)
+        }
+        "file" => Box::new(FileMailer::new(dir)?),
+        "memory" => Box::new(MemoryMailer::shared()),
+        _ => bail!(ErrorKind::InvalidInput),
+    };
+    Ok(mailer)
+}
+
+#[test]
+fn test_file_mailer() {
+    use tempdir;
+
+    let dir = tempdir::TempDir::new("ganbare_mails").unwrap();
+    let mut mailer = FileMailer::new(dir.path()).unwrap();
+    mailer.send(OutgoingMail {
+                      from_address: "support@example.com".into(),
+                      to_addresses: vec!["user@example.com".into()],
+                      message: "Subject: Hi\r\n\r\nHello!".into(),
+                  })
+        .unwrap();
+
+    let files: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+    assert_eq!(files.len(), 1);
+    let mut contents = String::new();
+    {
+        use std::io::Read;
+        fs::File::open(files[0].as_ref().unwrap().path())
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+    }
+    assert!(contents.contains("X-Envelope-To: user@example.com"));
+    assert!(contents.ends_with("Hello!"));
+}
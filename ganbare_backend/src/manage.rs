@@ -21,6 +21,7 @@ pub struct NewQuestion {
 }
 
 pub fn create_quiz(conn: &Connection,
+                   actor: i32,
                    new_q: NewQuestion,
                    mut answers: Vec<Fieldset>,
                    audio_dir: &Path)
@@ -41,59 +42,63 @@ pub fn create_quiz(conn: &Connection,
         }
     }
 
-    let nugget = skill::get_create_by_name(&*conn, &new_q.skill_nugget)?;
+    conn.transaction(|| -> Result<QuizQuestion> {
 
-    let new_quiz = NewQuizQuestion {
-        q_name: &new_q.q_name,
-        q_explanation: &new_q.q_explanation,
-        question_text: &new_q.question_text,
-        skill_id: nugget.id,
-        skill_level: 2, // FIXME
-    };
+        let nugget = skill::get_create_by_name(&*conn, &new_q.skill_nugget)?;
 
-    let quiz: QuizQuestion = diesel::insert(&new_quiz).into(quiz_questions::table)
-        .get_result(&**conn)
-        .chain_err(|| "Couldn't create a new question!")?;
+        let new_quiz = NewQuizQuestion {
+            q_name: &new_q.q_name,
+            q_explanation: &new_q.q_explanation,
+            question_text: &new_q.question_text,
+            skill_id: nugget.id,
+            skill_level: 2, // FIXME
+        };
 
-    info!("{:?}", &quiz);
+        let quiz: QuizQuestion = diesel::insert(&new_quiz).into(quiz_questions::table)
+            .get_result(&**conn)
+            .chain_err(|| "Couldn't create a new question!")?;
 
-    let mut narrator = None;
+        info!("{:?}", &quiz);
 
-    for fieldset in &mut answers {
-        let mut a_bundle = None;
-        let a_audio_id = match fieldset.answer_audio {
-            Some(ref mut a) => {
-                Some(audio::save(&*conn, &mut narrator, a, &mut a_bundle, audio_dir)?.id)
-            }
-            None => None,
-        };
+        let mut narrator = None;
 
-        let mut q_bundle = None;
-        for mut q_audio in &mut fieldset.q_variants {
-            audio::save(&*conn,
-                        &mut narrator,
-                        &mut q_audio,
-                        &mut q_bundle,
-                        audio_dir)?;
-        }
-        let q_bundle = q_bundle.expect("The audio bundle is initialized now.");
+        for fieldset in &mut answers {
+            let mut a_bundle = None;
+            let a_audio_id = match fieldset.answer_audio {
+                Some(ref mut a) => {
+                    Some(audio::save(&*conn, &mut narrator, a, &mut a_bundle, audio_dir)?.id)
+                }
+                None => None,
+            };
 
-        let new_answer = NewAnswer {
-            question_id: quiz.id,
-            answer_text: &fieldset.answer_text,
-            a_audio_bundle: a_audio_id,
-            q_audio_bundle: q_bundle.id,
-        };
+            let mut q_bundle = None;
+            for mut q_audio in &mut fieldset.q_variants {
+                audio::save(&*conn,
+                            &mut narrator,
+                            &mut q_audio,
+                            &mut q_bundle,
+                            audio_dir)?;
+            }
+            let q_bundle = q_bundle.expect("The audio bundle is initialized now.");
 
-        let answer: Answer = diesel::insert(&new_answer).into(question_answers::table)
-            .get_result(&**conn)
-            .chain_err(|| "Couldn't create a new answer!")?;
+            let new_answer = NewAnswer {
+                question_id: quiz.id,
+                answer_text: &fieldset.answer_text,
+                a_audio_bundle: a_audio_id,
+                q_audio_bundle: q_bundle.id,
+            };
 
-        info!("{:?}", &answer);
+            let answer: Answer = diesel::insert(&new_answer).into(question_answers::table)
+                .get_result(&**conn)
+                .chain_err(|| "Couldn't create a new answer!")?;
 
+            info!("{:?}", &answer);
 
-    }
-    Ok(quiz)
+
+        }
+        audit::record(conn, actor, "create", "question", Some(quiz.id), None, Some(&quiz))?;
+        Ok(quiz)
+    })
 }
 
 #[derive(Debug)]
@@ -130,7 +135,9 @@ pub fn add_audio(conn: &Connection, w: NewAudio, audio_dir: &Path) -> Result<Aud
     Ok(bundle)
 }
 
+/// The actor is `None` when the words are imported by the server.
 pub fn create_or_update_word(conn: &Connection,
+                             actor: Option<i32>,
                              mut w: NewWordFromStrings,
                              audio_dir: &Path)
                              -> Result<Word> {
@@ -163,7 +170,9 @@ pub fn create_or_update_word(conn: &Connection,
 
     let audio_file = audio_file.expect("If we are here, everything was successful.");
 
+    // A word in the trash isn't reused; the word is added anew.
     let word = words::table.filter(words::word.eq(&w.word))
+        .filter(words::deleted.is_null())
         .get_result(&**conn)
         .optional()?;
 
@@ -180,8 +189,14 @@ pub fn create_or_update_word(conn: &Connection,
             priority: w.priority,
         };
 
-        let word = diesel::insert(&new_word).into(words::table).get_result(&**conn)?;
-        return Ok(word);
+        return conn.transaction(|| -> Result<Word> {
+
+            let word: Word = diesel::insert(&new_word).into(words::table).get_result(&**conn)?;
+            if let Some(actor) = actor {
+                audit::record(conn, actor, "create", "word", Some(word.id), None, Some(&word))?;
+            }
+            Ok(word)
+        });
     }
 
 }
@@ -210,32 +225,49 @@ pub fn get_word(conn: &Connection, id: i32) -> Result<Option<Word>> {
            .optional()?)
 }
 
-pub fn publish_question(conn: &Connection, id: i32, published: bool) -> Result<()> {
+pub fn publish_question(conn: &Connection, actor: i32, id: i32, published: bool) -> Result<()> {
     use schema::quiz_questions;
-    diesel::update(quiz_questions::table
-        .filter(quiz_questions::id.eq(id)))
-        .set(quiz_questions::published.eq(published))
-        .execute(&**conn)?;
-    Ok(())
+    conn.transaction(|| -> Result<()> {
+
+        diesel::update(quiz_questions::table
+            .filter(quiz_questions::id.eq(id)))
+            .set(quiz_questions::published.eq(published))
+            .execute(&**conn)?;
+        audit::record_action(conn, actor, publish_action(published), "question", Some(id))?;
+        Ok(())
+    })
 }
 
-pub fn publish_exercise(conn: &Connection, id: i32, published: bool) -> Result<()> {
+pub fn publish_exercise(conn: &Connection, actor: i32, id: i32, published: bool) -> Result<()> {
     use schema::exercises;
-    diesel::update(exercises::table
-        .filter(exercises::id.eq(id)))
-        .set(exercises::published.eq(published))
-        .execute(&**conn)?;
-    Ok(())
+    conn.transaction(|| -> Result<()> {
+
+        diesel::update(exercises::table
+            .filter(exercises::id.eq(id)))
+            .set(exercises::published.eq(published))
+            .execute(&**conn)?;
+        audit::record_action(conn, actor, publish_action(published), "exercise", Some(id))?;
+        Ok(())
+    })
 }
 
-pub fn publish_word(conn: &Connection, id: i32, published: bool) -> Result<()> {
+pub fn publish_word(conn: &Connection, actor: i32, id: i32, published: bool) -> Result<()> {
     use schema::words;
-    diesel::update(words::table.filter(words::id.eq(id))).set(words::published.eq(published))
-        .execute(&**conn)?;
-    Ok(())
+    conn.transaction(|| -> Result<()> {
+
+        diesel::update(words::table.filter(words::id.eq(id))).set(words::published.eq(published))
+            .execute(&**conn)?;
+        audit::record_action(conn, actor, publish_action(published), "word", Some(id))?;
+        Ok(())
+    })
+}
+
+fn publish_action(published: bool) -> &'static str {
+    if published { "publish" } else { "unpublish" }
 }
 
 pub fn update_word(conn: &Connection,
+                   actor: i32,
                    id: i32,
                    mut item: UpdateWord,
                    image_dir: &Path)
@@ -244,37 +276,83 @@ pub fn update_word(conn: &Connection,
 
     item.explanation = item.explanation.try_map(|s| sanitize_links(&s, image_dir))?;
 
-    let item = diesel::update(words::table.filter(words::id.eq(id))).set(&item)
-        .get_result(&**conn)
-        .optional()?;
-    Ok(item)
+    conn.transaction(|| -> Result<Option<Word>> {
+
+        let before = try_or!(get_word(conn, id)?, else return Ok(None));
+        let item: Option<Word> = diesel::update(words::table.filter(words::id.eq(id))).set(&item)
+            .get_result(&**conn)
+            .optional()?;
+        if let Some(ref item) = item {
+            audit::record(conn, actor, "update", "word", Some(id), Some(&before), Some(item))?;
+            revision::record(conn,
+                             revision::WORD,
+                             id,
+                             &before.explanation,
+                             &item.explanation,
+                             Some(actor),
+                             revision::UI)?;
+        }
+        Ok(item)
+    })
 }
 
 pub fn update_exercise(conn: &Connection,
+                       actor: i32,
                        id: i32,
                        item: UpdateExercise)
                        -> Result<Option<Exercise>> {
     use schema::exercises;
-    let item = diesel::update(exercises::table.filter(exercises::id.eq(id))).set(&item)
-        .get_result(&**conn)
-        .optional()?;
-    Ok(item)
+    conn.transaction(|| -> Result<Option<Exercise>> {
+
+        let before: Exercise = try_or!(exercises::table.filter(exercises::id.eq(id))
+                                           .get_result(&**conn)
+                                           .optional()?,
+                                       else return Ok(None));
+        let item: Option<Exercise> =
+            diesel::update(exercises::table.filter(exercises::id.eq(id))).set(&item)
+                .get_result(&**conn)
+                .optional()?;
+        if let Some(ref item) = item {
+            audit::record(conn, actor, "update", "exercise", Some(id), Some(&before), Some(item))?;
+        }
+        Ok(item)
+    })
 }
 
 
 pub fn update_question(conn: &Connection,
+                       actor: i32,
                        id: i32,
                        item: UpdateQuestion)
                        -> Result<Option<QuizQuestion>> {
     use schema::quiz_questions;
-    let item = diesel::update(quiz_questions::table.filter(quiz_questions::id.eq(id))).set(&item)
-        .get_result(&**conn)
-        .optional()?;
-    Ok(item)
+    conn.transaction(|| -> Result<Option<QuizQuestion>> {
+
+        let before: QuizQuestion = try_or!(quiz_questions::table.filter(quiz_questions::id.eq(id))
+                                               .get_result(&**conn)
+                                               .optional()?,
+                                           else return Ok(None));
+        let item: Option<QuizQuestion> =
+            diesel::update(quiz_questions::table.filter(quiz_questions::id.eq(id))).set(&item)
+                .get_result(&**conn)
+                .optional()?;
+        if let Some(ref item) = item {
+            audit::record(conn, actor, "update", "question", Some(id), Some(&before), Some(item))?;
+            revision::record(conn,
+                             revision::QUESTION,
+                             id,
+                             &before.question_text,
+                             &item.question_text,
+                             Some(actor),
+                             revision::UI)?;
+        }
+        Ok(item)
+    })
 }
 
 
 pub fn update_answer(conn: &Connection,
+                     actor: i32,
                      id: i32,
                      mut item: UpdateAnswer,
                      image_dir: &Path)
@@ -283,76 +361,157 @@ pub fn update_answer(conn: &Connection,
 
     item.answer_text = item.answer_text.try_map(|s| sanitize_links(&s, image_dir))?;
 
-    let item = diesel::update(question_answers::table.filter(question_answers::id.eq(id))).set(&item)
-        .get_result(&**conn)
-        .optional()?;
-    Ok(item)
+    conn.transaction(|| -> Result<Option<Answer>> {
+
+        let before: Answer = try_or!(question_answers::table.filter(question_answers::id.eq(id))
+                                         .get_result(&**conn)
+                                         .optional()?,
+                                     else return Ok(None));
+        let item: Option<Answer> =
+            diesel::update(question_answers::table.filter(question_answers::id.eq(id))).set(&item)
+                .get_result(&**conn)
+                .optional()?;
+        if let Some(ref item) = item {
+            audit::record(conn, actor, "update", "answer", Some(id), Some(&before), Some(item))?;
+            revision::record(conn,
+                             revision::ANSWER,
+                             id,
+                             &before.answer_text,
+                             &item.answer_text,
+                             Some(actor),
+                             revision::UI)?;
+        }
+        Ok(item)
+    })
 }
 
 pub fn update_variant(conn: &Connection,
+                      actor: i32,
                       id: i32,
                       item: UpdateExerciseVariant)
                       -> Result<Option<ExerciseVariant>> {
 
     use schema::exercise_variants;
 
-    let item = diesel::update(exercise_variants::table.filter(exercise_variants::id.eq(id))).set(&item)
-        .get_result(&**conn)
-        .optional()?;
-    Ok(item)
+    conn.transaction(|| -> Result<Option<ExerciseVariant>> {
+
+        let before: ExerciseVariant =
+            try_or!(exercise_variants::table.filter(exercise_variants::id.eq(id))
+                        .get_result(&**conn)
+                        .optional()?,
+                    else return Ok(None));
+        let item: Option<ExerciseVariant> =
+            diesel::update(exercise_variants::table.filter(exercise_variants::id.eq(id))).set(&item)
+                .get_result(&**conn)
+                .optional()?;
+        if let Some(ref item) = item {
+            audit::record(conn, actor, "update", "variant", Some(id), Some(&before), Some(item))?;
+        }
+        Ok(item)
+    })
 }
 
-pub fn remove_word(conn: &Connection, id: i32) -> Result<Option<Word>> {
-    use schema::words;
+/// Moves the word to the trash. It's kept, with the answers to it, until it's purged.
+/// The exercises that have the word as a variant are moved to the trash with it, and
+/// restored with it.
+pub fn remove_word(conn: &Connection, actor: i32, id: i32) -> Result<Option<Word>> {
+    use schema::{words, exercises, exercise_variants};
+    use diesel::expression::dsl::any;
 
-    let word: Option<Word> = diesel::delete(words::table.filter(words::id.eq(id))).get_result(&**conn)
-        .optional()?;
+    conn.transaction(|| -> Result<Option<Word>> {
+
+        // The exercises get the same timestamp, so that restoring finds them.
+        let now = Some(chrono::UTC::now());
 
-    Ok(word)
+        let word: Option<Word> = diesel::update(words::table.filter(words::id.eq(id))
+                                                    .filter(words::deleted.is_null()))
+                .set(words::deleted.eq(now))
+                .get_result(&**conn)
+                .optional()?;
+        if let Some(ref word) = word {
+            let exercise_ids: Vec<i32> = exercise_variants::table
+                .filter(exercise_variants::id.eq(id))
+                .select(exercise_variants::exercise_id)
+                .get_results(&**conn)?;
+
+            let count = diesel::update(exercises::table
+                                           .filter(exercises::id.eq(any(exercise_ids)))
+                                           .filter(exercises::deleted.is_null()))
+                    .set(exercises::deleted.eq(now))
+                    .execute(&**conn)?;
+
+            info!("{} exercises with the word {:?} moved to the trash.", count, id);
+
+            audit::record(conn, actor, "delete", "word", Some(id), None, Some(word))?;
+        }
+
+        Ok(word)
+    })
 }
 
-pub fn remove_question(conn: &Connection, id: i32) -> Result<bool> {
-    use schema::{quiz_questions, question_answers};
+pub fn remove_question(conn: &Connection, actor: i32, id: i32) -> Result<bool> {
+    use schema::quiz_questions;
 
-    diesel::delete(question_answers::table.filter(question_answers::question_id.eq(id)))
-        .execute(&**conn)?;
+    conn.transaction(|| -> Result<bool> {
 
-    let count =
-        diesel::delete(quiz_questions::table.filter(quiz_questions::id.eq(id))).execute(&**conn)?;
+        let question: Option<QuizQuestion> =
+            diesel::update(quiz_questions::table.filter(quiz_questions::id.eq(id))
+                               .filter(quiz_questions::deleted.is_null()))
+                    .set(quiz_questions::deleted.eq(Some(chrono::UTC::now())))
+                    .get_result(&**conn)
+                    .optional()?;
+        if let Some(ref question) = question {
+            audit::record(conn, actor, "delete", "question", Some(id), None, Some(question))?;
+        }
 
-    Ok(count == 1)
+        Ok(question.is_some())
+    })
 }
 
-pub fn remove_exercise(conn: &Connection, id: i32) -> Result<bool> {
-    use schema::{exercises, exercise_variants};
+pub fn remove_exercise(conn: &Connection, actor: i32, id: i32) -> Result<bool> {
+    use schema::exercises;
 
-    diesel::delete(exercise_variants::table.filter(exercise_variants::exercise_id.eq(id)))
-        .execute(&**conn)?;
+    conn.transaction(|| -> Result<bool> {
 
-    let count = diesel::delete(exercises::table.filter(exercises::id.eq(id))).execute(&**conn)?;
+        let exercise: Option<Exercise> =
+            diesel::update(exercises::table.filter(exercises::id.eq(id))
+                               .filter(exercises::deleted.is_null()))
+                    .set(exercises::deleted.eq(Some(chrono::UTC::now())))
+                    .get_result(&**conn)
+                    .optional()?;
+        if let Some(ref exercise) = exercise {
+            audit::record(conn, actor, "delete", "exercise", Some(id), None, Some(exercise))?;
+        }
 
-    Ok(count == 1)
+        Ok(exercise.is_some())
+    })
 }
 
 pub fn post_question(conn: &Connection,
+                     actor: i32,
                      question: NewQuizQuestion,
                      mut answers: Vec<NewAnswer>)
                      -> Result<i32> {
     use schema::{question_answers, quiz_questions};
 
-    debug!("Post question: {:?} and answers: {:?}", question, answers);
+    conn.transaction(|| -> Result<i32> {
 
-    let q: QuizQuestion =
-        diesel::insert(&question).into(quiz_questions::table).get_result(&**conn)?;
+        debug!("Post question: {:?} and answers: {:?}", question, answers);
 
-    for aa in &mut answers {
-        aa.question_id = q.id;
-        diesel::insert(aa).into(question_answers::table).execute(&**conn)?;
-    }
-    Ok(q.id)
+        let q: QuizQuestion =
+            diesel::insert(&question).into(quiz_questions::table).get_result(&**conn)?;
+
+        for aa in &mut answers {
+            aa.question_id = q.id;
+            diesel::insert(aa).into(question_answers::table).execute(&**conn)?;
+        }
+        audit::record(conn, actor, "create", "question", Some(q.id), None, Some(&q))?;
+        Ok(q.id)
+    })
 }
 
 pub fn post_exercise(conn: &Connection,
+                     actor: i32,
                      exercise: NewExercise,
                      mut answers: Vec<ExerciseVariant>)
                      -> Result<i32> {
@@ -366,6 +525,7 @@ pub fn post_exercise(conn: &Connection,
                 aa.exercise_id = q.id;
                 diesel::insert(aa).into(exercise_variants::table).execute(&**conn)?;
             }
+            audit::record(conn, actor, "create", "exercise", Some(q.id), None, Some(&q))?;
             Ok(q.id)
 
         })
@@ -373,61 +533,65 @@ pub fn post_exercise(conn: &Connection,
 
 }
 
-pub fn del_due_and_pending_items(conn: &Connection, user_id: i32) -> Result<()> {
+pub fn del_due_and_pending_items(conn: &Connection, actor: i32, user_id: i32) -> Result<()> {
     use schema::{due_items, pending_items, question_data, exercise_data, e_asked_data, q_asked_data,
                  e_answered_data, q_answered_data};
     use diesel::expression::dsl::any;
 
-    let p = diesel::update(
-            pending_items::table
-                .filter(pending_items::user_id.eq(user_id).and(pending_items::pending.eq(true)))
-        )
-        .set(pending_items::pending.eq(false))
-        .execute(&**conn)?;
+    conn.transaction(|| -> Result<()> {
 
-    let pending: Vec<PendingItem> = pending_items::table.filter(pending_items::user_id.eq(user_id))
-        .get_results(&**conn)?;
+        let p = diesel::update(
+                pending_items::table
+                    .filter(pending_items::user_id.eq(user_id).and(pending_items::pending.eq(true)))
+            )
+            .set(pending_items::pending.eq(false))
+            .execute(&**conn)?;
 
-    let due_items = due_items::table.filter(due_items::user_id.eq(user_id)).select(due_items::id);
+        let pending: Vec<PendingItem> = pending_items::table.filter(pending_items::user_id.eq(user_id))
+            .get_results(&**conn)?;
 
-    let q = diesel::delete(question_data::table.filter(question_data::due.eq(any(due_items))))
-        .execute(&**conn)?;
+        let due_items = due_items::table.filter(due_items::user_id.eq(user_id)).select(due_items::id);
 
-    let e = diesel::delete(exercise_data::table.filter(exercise_data::due.eq(any(due_items))))
-        .execute(&**conn)?;
+        let q = diesel::delete(question_data::table.filter(question_data::due.eq(any(due_items))))
+            .execute(&**conn)?;
 
-    let d =
-        diesel::delete(due_items::table.filter(due_items::user_id.eq(user_id))).execute(&**conn)?;
+        let e = diesel::delete(exercise_data::table.filter(exercise_data::due.eq(any(due_items))))
+            .execute(&**conn)?;
 
-    let mut asks = 0;
-    let mut answers = 0;
+        let d =
+            diesel::delete(due_items::table.filter(due_items::user_id.eq(user_id))).execute(&**conn)?;
 
-    for p in &pending {
+        let mut asks = 0;
+        let mut answers = 0;
 
-        answers += diesel::delete(e_answered_data::table.filter(e_answered_data::id.eq(p.id)))
-            .execute(&**conn)?;
+        for p in &pending {
 
-        answers += diesel::delete(q_answered_data::table.filter(q_answered_data::id.eq(p.id)))
-            .execute(&**conn)?;
+            answers += diesel::delete(e_answered_data::table.filter(e_answered_data::id.eq(p.id)))
+                .execute(&**conn)?;
 
-        asks +=
-            diesel::delete(e_asked_data::table.filter(e_asked_data::id.eq(p.id))).execute(&**conn)?;
+            answers += diesel::delete(q_answered_data::table.filter(q_answered_data::id.eq(p.id)))
+                .execute(&**conn)?;
 
-        asks +=
-            diesel::delete(q_asked_data::table.filter(q_asked_data::id.eq(p.id))).execute(&**conn)?;
+            asks +=
+                diesel::delete(e_asked_data::table.filter(e_asked_data::id.eq(p.id))).execute(&**conn)?;
 
-    }
+            asks +=
+                diesel::delete(q_asked_data::table.filter(q_asked_data::id.eq(p.id))).execute(&**conn)?;
+
+        }
 
-    debug!("Deactivated {} pending items and deleted {} due items. ({} questions, {} exercises, \
-            {} asks, {} answers)",
-           p,
-           d,
-           q,
-           e,
-           asks,
-           answers);
+        debug!("Deactivated {} pending items and deleted {} due items. ({} questions, {} exercises, \
+                {} asks, {} answers)",
+               p,
+               d,
+               q,
+               e,
+               asks,
+               answers);
+        audit::record_action(conn, actor, "delete_items", "user", Some(user_id))?;
 
-    Ok(())
+        Ok(())
+    })
 }
 
 pub fn replace_audio_bundle(conn: &Connection, bundle_id: i32, new_bundle_id: i32) -> Result<()> {
@@ -0,0 +1,373 @@
+use super::*;
+use mime;
+use unicode_normalization::UnicodeNormalization;
+
+use std::fs;
+use std::mem;
+use std::path::PathBuf;
+use std::path::Path;
+
+/// The columns a manifest may have. The first line of the manifest names the columns used,
+/// in any order. Only `word` is required.
+pub const COLUMNS: &'static [&'static str] =
+    &["word", "explanation", "skill", "skill_level", "priority", "narrator", "audio"];
+
+/// Separates the audio files in the `audio` column.
+pub const AUDIO_SEPARATOR: char = '|';
+
+/// A row of the manifest. The empty cells are `None`: new words get the defaults,
+/// and the existing words keep what they have.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestRow {
+    /// The line number, for reporting.
+    pub line: usize,
+    pub word: String,
+    pub explanation: Option<String>,
+    /// The summary of the skill nugget. Defaults to the word without the accent marks.
+    pub skill: Option<String>,
+    pub skill_level: Option<i32>,
+    pub priority: Option<i32>,
+    pub narrator: Option<String>,
+    /// The audio files, relative to the directory of the manifest.
+    pub audio: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Create,
+    /// The fields that differ from the existing word; `audio` if there are new audio files.
+    Update(Vec<&'static str>),
+    Skip,
+}
+
+/// The delimiter to use for the manifest: tabs for `.tsv` files, commas otherwise.
+pub fn delimiter_of(path: &Path) -> char {
+    match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()) {
+        Some(ref e) if e == "tsv" || e == "tab" => '\t',
+        _ => ',',
+    }
+}
+
+fn invalid<T>(reason: String) -> Result<T> {
+    Err(ErrorKind::InvalidManifest(reason).into())
+}
+
+/// Splits a line into cells. A cell may be quoted with `"`, and a quote inside a quoted cell
+/// is written as `""`. Cells spanning multiple lines aren't supported.
+fn split_line(line: &str, delimiter: char) -> Result<Vec<String>> {
+    let mut cells = vec![];
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    cell.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if cell.trim().is_empty() => {
+                cell.clear();
+                quoted = true;
+            }
+            c if c == delimiter && !quoted => cells.push(mem::replace(&mut cell, String::new())),
+            c => cell.push(c),
+        }
+    }
+    if quoted {
+        return invalid("Unterminated quotes".into());
+    }
+    cells.push(cell);
+    Ok(cells.into_iter().map(|c| c.trim().nfc().collect()).collect())
+}
+
+fn parse_row(line: usize, header: &[&'static str], cells: Vec<String>) -> Result<ManifestRow> {
+    if cells.len() > header.len() {
+        return invalid(format!("{} cells, but only {} columns", cells.len(), header.len()));
+    }
+
+    let mut row = ManifestRow {
+        line: line,
+        word: String::new(),
+        explanation: None,
+        skill: None,
+        skill_level: None,
+        priority: None,
+        narrator: None,
+        audio: vec![],
+    };
+
+    for (&column, cell) in header.iter().zip(cells) {
+        if cell.is_empty() {
+            continue;
+        }
+        let number = |cell: &str| -> Result<i32> {
+            cell.parse()
+                .or_else(|_| invalid(format!("{} should be a number, not {:?}", column, cell)))
+        };
+        match column {
+            "word" => row.word = cell,
+            "explanation" => row.explanation = Some(cell),
+            "skill" => row.skill = Some(cell),
+            "skill_level" => row.skill_level = Some(number(&cell)?),
+            "priority" => row.priority = Some(number(&cell)?),
+            "narrator" => row.narrator = Some(cell),
+            "audio" => {
+                row.audio = cell.split(AUDIO_SEPARATOR)
+                    .map(str::trim)
+                    .filter(|p| !p.is_empty())
+                    .map(PathBuf::from)
+                    .collect()
+            }
+            _ => unreachable!("The header is checked when parsing it."),
+        }
+    }
+
+    if row.word.is_empty() {
+        return invalid("The word is missing".into());
+    }
+    Ok(row)
+}
+
+/// Parses a manifest. Fails as a whole only if the header is invalid; the errors of the rows
+/// are returned along with the line numbers. Empty lines and lines starting with `#`
+/// are ignored.
+pub fn parse(text: &str, delimiter: char) -> Result<Vec<(usize, Result<ManifestRow>)>> {
+    let mut lines = text.lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l.trim_right_matches('\r')))
+        .filter(|&(_, l)| !l.trim().is_empty() && !l.starts_with('#'));
+
+    let header = match lines.next() {
+        Some((_, line)) => split_line(line.trim_left_matches('\u{feff}'), delimiter)?,
+        None => return invalid("The manifest is empty".into()),
+    };
+    let mut columns = vec![];
+    for name in &header {
+        let name = name.to_lowercase();
+        match COLUMNS.iter().find(|&&c| c == name) {
+            Some(c) if columns.contains(c) => {
+                return invalid(format!("Duplicate column {:?}", name))
+            }
+            Some(&c) => columns.push(c),
+            None => return invalid(format!("Unknown column {:?}", name)),
+        }
+    }
+    if !columns.contains(&"word") {
+        return invalid("There's no word column".into());
+    }
+
+    Ok(lines.map(|(line, l)| {
+                     let row = split_line(l, delimiter)
+                         .and_then(|cells| parse_row(line, &columns, cells));
+                     (line, row)
+                 })
+           .collect())
+}
+
+fn default_skill(word: &str) -> String {
+    word.replace("*", "").replace("・", "")
+}
+
+/// Figures out what importing the row would do, without changing anything.
+pub fn plan(conn: &Connection, row: &ManifestRow, base_dir: &Path) -> Result<Action> {
+    use schema::{words, skill_nuggets};
+
+    let mut new_audio = false;
+    for path in &row.audio {
+        let full_path = base_dir.join(path);
+        if !full_path.is_file() {
+            return invalid(format!("No such audio file: {:?}", full_path));
+        }
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        if audio::mime_by_extension(extension).is_none() {
+            bail!(ErrorKind::UnsupportedAudioFormat(format!("{:?}", path)));
+        }
+        if !audio::exists(conn, &full_path)? {
+            new_audio = true;
+        }
+    }
+
+    let word: Word = match words::table.filter(words::word.eq(&row.word))
+              .filter(words::deleted.is_null())
+              .get_result(&**conn)
+              .optional()? {
+        Some(word) => word,
+        None if row.audio.is_empty() => {
+            return invalid(format!("The new word {:?} has no audio", row.word))
+        }
+        None => return Ok(Action::Create),
+    };
+
+    let mut changed = vec![];
+    if row.explanation.as_ref().map_or(false, |e| *e != word.explanation) {
+        changed.push("explanation");
+    }
+    if let Some(ref skill) = row.skill {
+        let summary: String = skill_nuggets::table.filter(skill_nuggets::id.eq(word.skill_nugget))
+            .select(skill_nuggets::skill_summary)
+            .get_result(&**conn)?;
+        if *skill != summary {
+            changed.push("skill");
+        }
+    }
+    if row.skill_level.map_or(false, |l| l != word.skill_level) {
+        changed.push("skill_level");
+    }
+    if row.priority.map_or(false, |p| p != word.priority) {
+        changed.push("priority");
+    }
+    if new_audio {
+        changed.push("audio");
+    }
+
+    Ok(if changed.is_empty() {
+           Action::Skip
+       } else {
+           Action::Update(changed)
+       })
+}
+
+/// Copies the audio files of the row to the temp dir, because saving them moves them
+/// to the audio directory.
+fn copy_audio(row: &ManifestRow,
+              base_dir: &Path,
+              temp_dir: &Path)
+              -> Result<Vec<(PathBuf, Option<String>, mime::Mime)>> {
+    let mut files = vec![];
+    for path in &row.audio {
+        let file_name = path.file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let temp_path = temp_dir.join(&file_name);
+        fs::copy(base_dir.join(path), &temp_path).chain_err(|| "Couldn't copy the audio file!")?;
+
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let mime = audio::mime_by_extension(extension)
+            .ok_or_else(|| ErrorKind::UnsupportedAudioFormat(format!("{:?}", path)))?;
+        files.push((temp_path,
+                    Some(file_name),
+                    mime.parse().expect("The canonical MIME types are valid.")));
+    }
+    Ok(files)
+}
+
+/// Imports the row: creates the word, or updates the fields that differ and adds
+/// the new audio files to the bundle of the word. `default_narrator` is used for the rows
+/// that don't name one.
+pub fn apply(conn: &Connection,
+             row: &ManifestRow,
+             base_dir: &Path,
+             audio_dir: &Path,
+             default_narrator: &str)
+             -> Result<Action> {
+    use schema::{words, audio_bundles};
+
+    let action = plan(conn, row, base_dir)?;
+    let narrator = row.narrator.as_ref().map(|n| n.as_str()).unwrap_or(default_narrator);
+    let temp_dir = tempdir::TempDir::new("ganbare_manifest_import")
+        .chain_err(|| "Couldn't create a temp dir!")?;
+
+    // The audio and the fields of the word are changed together, or not at all.
+    conn.transaction(|| -> Result<Action> {
+        let changed = match action {
+            Action::Skip => return Ok(Action::Skip),
+            Action::Create => {
+                let new_word = manage::NewWordFromStrings {
+                    word: row.word.clone(),
+                    explanation: row.explanation.clone().unwrap_or_default(),
+                    nugget: row.skill.clone().unwrap_or_else(|| default_skill(&row.word)),
+                    narrator: narrator,
+                    files: copy_audio(row, base_dir, temp_dir.path())?,
+                    skill_level: row.skill_level.unwrap_or(0),
+                    priority: row.priority.unwrap_or(0),
+                };
+                manage::create_or_update_word(conn, None, new_word, audio_dir)?;
+                return Ok(Action::Create);
+            }
+            Action::Update(changed) => changed,
+        };
+
+        let word: Word = words::table.filter(words::word.eq(&row.word))
+            .filter(words::deleted.is_null())
+            .get_result(&**conn)?;
+
+        if changed.contains(&"audio") {
+            let bundle: AudioBundle = audio_bundles::table.filter(audio_bundles::id
+                                                                       .eq(word.audio_bundle))
+                .get_result(&**conn)?;
+            let mut narrator = Some(audio::get_create_narrator(conn, narrator)?);
+            let mut bundle = Some(bundle);
+            for mut file in copy_audio(row, base_dir, temp_dir.path())? {
+                match audio::save(conn, &mut narrator, &mut file, &mut bundle, audio_dir) {
+                    Err(Error(ErrorKind::FileAlreadyExists(_), ..)) | Ok(_) => (),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        if changed.iter().any(|&c| c != "audio") {
+            let skill_nugget = match row.skill {
+                Some(ref skill) if changed.contains(&"skill") => {
+                    Some(skill::get_create_by_name(conn, skill)?.id)
+                }
+                _ => None,
+            };
+            let update = UpdateWord {
+                word: None,
+                explanation: row.explanation.clone(),
+                audio_bundle: None,
+                skill_nugget: skill_nugget,
+                published: None,
+                skill_level: row.skill_level,
+                priority: row.priority,
+            };
+            diesel::update(words::table.filter(words::id.eq(word.id))).set(&update)
+                .execute(&**conn)?;
+            if let Some(ref explanation) = row.explanation {
+                revision::record(conn,
+                                 revision::WORD,
+                                 word.id,
+                                 &word.explanation,
+                                 explanation,
+                                 None,
+                                 revision::IMPORT)?;
+            }
+        }
+
+        Ok(Action::Update(changed))
+    })
+}
+
+#[test]
+fn test_parse() {
+    let manifest = "word\texplanation\tskill_level\taudio\n\
+                    # A comment\n\
+                    \n\
+                    た・べる\t\"to \"\"eat\"\"\"\t2\ttaberu.mp3 | taberu2.wav\n\
+                    のむ\t\tlots\t\n\
+                    \tdrink\t\t\n";
+    let rows = parse(manifest, '\t').unwrap();
+    assert_eq!(rows.len(), 3);
+
+    let &(line, ref row) = &rows[0];
+    let row = row.as_ref().unwrap();
+    assert_eq!(line, 4);
+    assert_eq!(row.word, "た・べる");
+    assert_eq!(row.explanation, Some("to \"eat\"".to_string()));
+    assert_eq!(row.skill_level, Some(2));
+    assert_eq!(row.priority, None);
+    assert_eq!(row.audio, vec![PathBuf::from("taberu.mp3"), PathBuf::from("taberu2.wav")]);
+
+    assert_eq!(rows[1].0, 5);
+    assert!(rows[1].1.is_err());
+    assert!(rows[2].1.is_err());
+
+    assert!(parse("word,meaning\n", ',').is_err());
+    assert!(parse("explanation\n", ',').is_err());
+    assert_eq!(delimiter_of(Path::new("words.TSV")), '\t');
+    assert_eq!(default_skill("た*べる"), "たべる");
+}
@@ -57,24 +57,51 @@ pub struct NewUser<'a> {
 #[has_many(pending_items, foreign_key = "user_id")]
 #[has_many(due_items, foreign_key = "user_id")]
 #[has_many(reset_email_secrets, foreign_key = "user_id")]
+#[has_many(user_roles, foreign_key = "user_id")]
+#[has_many(api_tokens, foreign_key = "user_id")]
+// actually, the relationship is one-to-1..0
+#[has_many(totp_secrets, foreign_key = "user_id")]
+#[has_many(recovery_codes, foreign_key = "user_id")]
+#[has_many(pending_logins, foreign_key = "user_id")]
 #[derive(Identifiable, Clone, Queryable, Debug, Associations, AsChangeset, Serialize)]
 pub struct User {
     pub id: i32,
     pub email: Option<String>,
     pub joined: DateTime<UTC>,
     pub last_seen: DateTime<UTC>,
+    pub timezone: String,
+    pub day_rollover_hour: i32,
+    pub locale: String,
+}
+
+#[derive(AsChangeset, Deserialize, Debug)]
+#[table_name="users"]
+pub struct UpdateUserTimezone {
+    pub timezone: String,
+    pub day_rollover_hour: i32,
+}
+
+#[derive(AsChangeset, Deserialize, Debug)]
+#[table_name="users"]
+pub struct UpdateUserLocale {
+    pub locale: String,
 }
 
 
 #[derive(Identifiable, Queryable, Debug, Insertable, Associations, AsChangeset)]
 #[belongs_to(User, foreign_key = "id")]
 #[table_name="passwords"]
+#[changeset_options(treat_none_as_null = "true")]
 pub struct Password {
     pub id: i32,
     pub password_hash: Vec<u8>,
     pub salt: Vec<u8>,
     pub initial_rounds: i16,
     pub extra_rounds: i16,
+    pub hash_version: i16,
+    pub memory_kib: i32,
+    pub iterations: i32,
+    pub pepper_id: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Insertable)]
@@ -84,10 +111,12 @@ pub struct NewSession<'a> {
     pub started: DateTime<UTC>,
     pub last_seen: DateTime<UTC>,
     pub secret: &'a [u8],
+    pub user_agent: Option<&'a str>,
+    pub ip: Option<&'a str>,
 }
 
 
-#[derive(Identifiable, Queryable, Debug, Associations, AsChangeset)]
+#[derive(Identifiable, Queryable, Debug, Associations, AsChangeset, Serialize)]
 #[table_name="sessions"]
 #[belongs_to(User, foreign_key = "user_id")]
 #[changeset_options(treat_none_as_null = "true")]
@@ -96,8 +125,11 @@ pub struct Session {
     pub user_id: i32,
     pub started: DateTime<UTC>,
     pub last_seen: DateTime<UTC>,
+    #[serde(skip_serializing)]
     pub secret: Vec<u8>,
     pub refresh_count: i32,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
 }
 
 
@@ -107,6 +139,7 @@ pub struct PendingEmailConfirm {
     pub email: String,
     pub groups: Vec<i32>,
     pub added: DateTime<UTC>,
+    pub locale: String,
 }
 
 #[derive(Insertable)]
@@ -115,6 +148,7 @@ pub struct NewPendingEmailConfirm<'a> {
     pub secret: &'a str,
     pub email: &'a str,
     pub groups: &'a [i32],
+    pub locale: &'a str,
 }
 
 #[derive(Identifiable, Queryable, Debug, Insertable, Associations,
@@ -152,13 +186,22 @@ pub struct AnonAliases {
     pub group_id: Option<i32>,
 }
 
+#[derive(Insertable)]
+#[table_name="anon_aliases"]
+pub struct NewAnonAlias<'a> {
+    pub name: &'a str,
+    pub user_id: Option<i32>,
+    pub group_id: Option<i32>,
+}
+
 #[derive(Insertable)]
 #[table_name="skill_nuggets"]
 pub struct NewSkillNugget<'a> {
     pub skill_summary: &'a str,
 }
 
-#[derive(Insertable, Queryable, Associations, AsChangeset, Identifiable, Debug, Serialize)]
+#[derive(Insertable, Queryable, Associations, AsChangeset,
+    Identifiable, Debug, Serialize, Deserialize)]
 #[table_name="skill_nuggets"]
 #[has_many(quiz_questions, foreign_key = "skill_id")]
 #[has_many(exercises, foreign_key = "skill_id")]
@@ -201,6 +244,7 @@ Identifiable, Debug, Serialize, AsChangeset)]
 #[belongs_to(Narrator, foreign_key = "narrators_id")]
 #[belongs_to(AudioBundle, foreign_key = "bundle_id")]
 #[has_many(pending_items, foreign_key = "audio_file_id")]
+#[has_many(audio_renditions, foreign_key = "audio_file_id")]
 pub struct AudioFile {
     pub id: i32,
     pub narrators_id: i32,
@@ -208,6 +252,26 @@ pub struct AudioFile {
     pub file_path: String,
     pub mime: String,
     pub file_sha2: Option<Vec<u8>>,
+    pub processing: Option<String>,
+    pub processed: Option<DateTime<UTC>>,
+}
+
+#[derive(Queryable, Associations, Identifiable, Debug, Serialize)]
+#[table_name="audio_renditions"]
+#[belongs_to(AudioFile, foreign_key = "audio_file_id")]
+pub struct AudioRendition {
+    pub id: i32,
+    pub audio_file_id: i32,
+    pub file_path: String,
+    pub mime: String,
+}
+
+#[derive(Insertable)]
+#[table_name="audio_renditions"]
+pub struct NewAudioRendition<'a> {
+    pub audio_file_id: i32,
+    pub file_path: &'a str,
+    pub mime: &'a str,
 }
 
 #[derive(Queryable, AsChangeset, Debug, Serialize, Deserialize, Default)]
@@ -231,6 +295,8 @@ pub struct UpdateAudioFile {
 pub struct AudioBundle {
     pub id: i32,
     pub listname: String,
+    #[serde(default)]
+    pub deleted: Option<DateTime<UTC>>,
 }
 
 #[derive(Insertable)]
@@ -249,7 +315,8 @@ pub struct NewQuizQuestion<'a> {
     pub skill_level: i32,
 }
 
-#[derive(Insertable, Queryable, Associations, Identifiable, AsChangeset, Debug, Serialize)]
+#[derive(Insertable, Queryable, Associations, Identifiable,
+    AsChangeset, Debug, Serialize, Deserialize)]
 #[belongs_to(SkillNugget, foreign_key = "skill_id")]
 #[has_many(question_answers, foreign_key = "question_id")]
 #[has_many(question_data, foreign_key = "question_id")]
@@ -263,6 +330,8 @@ pub struct QuizQuestion {
     pub question_text: String,
     pub published: bool,
     pub skill_level: i32,
+    #[serde(default)]
+    pub deleted: Option<DateTime<UTC>>,
 }
 
 #[derive(Queryable, AsChangeset, Debug, Serialize, Deserialize, Default)]
@@ -286,7 +355,8 @@ pub struct NewAnswer<'a> {
     pub answer_text: &'a str,
 }
 
-#[derive(Insertable, Queryable, Associations, Identifiable, Debug, Serialize, AsChangeset)]
+#[derive(Insertable, Queryable, Associations, Identifiable,
+    Debug, Serialize, Deserialize, AsChangeset)]
 #[belongs_to(QuizQuestion, foreign_key = "question_id")]
 #[belongs_to(AudioBundle, foreign_key = "q_audio_bundle")]
 #[table_name="question_answers"]
@@ -322,6 +392,8 @@ pub struct Exercise {
     pub skill_id: i32,
     pub published: bool,
     pub skill_level: i32,
+    #[serde(default)]
+    pub deleted: Option<DateTime<UTC>>,
 }
 
 #[derive(Queryable, Debug, AsChangeset, Serialize, Deserialize, Default)]
@@ -368,7 +440,8 @@ pub struct NewWord<'a> {
     pub priority: i32,
 }
 
-#[derive(Insertable, Queryable, Associations, Identifiable, Debug, Serialize, AsChangeset)]
+#[derive(Insertable, Queryable, Associations, Identifiable,
+    Debug, Serialize, Deserialize, AsChangeset)]
 #[table_name="words"]
 #[belongs_to(SkillNugget, foreign_key = "skill_nugget")]
 #[belongs_to(AudioBundle, foreign_key = "audio_bundle")]
@@ -383,6 +456,8 @@ pub struct Word {
     pub published: bool,
     pub skill_level: i32,
     pub priority: i32,
+    #[serde(default)]
+    pub deleted: Option<DateTime<UTC>>,
 }
 
 #[derive(Queryable, AsChangeset, Debug, Serialize, Deserialize, Default)]
@@ -399,7 +474,7 @@ pub struct UpdateWord {
 }
 
 #[derive(Insertable, Identifiable, Queryable, Associations, Debug,
-AsChangeset, Serialize)]
+AsChangeset, Serialize, Clone)]
 #[table_name="due_items"]
 #[belongs_to(User, foreign_key = "user_id")]
 #[has_many(question_data, foreign_key = "due")]
@@ -413,6 +488,8 @@ pub struct DueItem {
     pub correct_streak_overall: i32,
     pub correct_streak_this_time: i32,
     pub item_type: String,
+    pub stability: f64,
+    pub difficulty: f64,
 }
 
 #[derive(Insertable)]
@@ -454,7 +531,8 @@ pub struct NewPendingItem<'a> {
     pub test_item: bool,
 }
 
-#[derive(Insertable, Queryable, Associations, Identifiable, Debug, Clone, AsChangeset)]
+#[derive(Insertable, Queryable, Associations, Identifiable, Debug, Clone, AsChangeset,
+    Serialize)]
 #[table_name="q_asked_data"]
 #[belongs_to(PendingItem, foreign_key = "id")]
 #[belongs_to(QuizQuestion, foreign_key = "question_id")]
@@ -478,7 +556,8 @@ pub struct QAnsweredData {
     pub full_spent_time_ms: i32,
 }
 
-#[derive(Insertable, Queryable, Associations, Identifiable, Debug, Clone, AsChangeset)]
+#[derive(Insertable, Queryable, Associations, Identifiable, Debug, Clone, AsChangeset,
+    Serialize)]
 #[table_name="e_asked_data"]
 #[belongs_to(PendingItem, foreign_key = "id")]
 #[belongs_to(Exercise, foreign_key = "exercise_id")]
@@ -590,6 +669,7 @@ pub struct UserMetrics {
     pub streak_limit: i32,
     pub cooldown_delay: i32,
     pub streak_skill_bump_criteria: i32,
+    pub memory_model: String,
 }
 
 #[derive(Debug, AsChangeset, Identifiable, Deserialize, Default)]
@@ -614,6 +694,7 @@ pub struct UpdateUserMetrics {
     pub streak_limit: Option<i32>,
     pub cooldown_delay: Option<i32>,
     pub streak_skill_bump_criteria: Option<i32>,
+    pub memory_model: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -645,6 +726,7 @@ pub struct UserStats {
 
 #[derive(Insertable, Queryable, Associations, Identifiable, Debug, Serialize, Deserialize)]
 #[has_many(event_experiences, foreign_key = "event_id")]
+#[has_many(test_items, foreign_key = "event_id")]
 #[belongs_to(UserGroup, foreign_key = "required_group")]
 #[table_name="events"]
 pub struct Event {
@@ -653,6 +735,9 @@ pub struct Event {
     pub published: bool,
     pub required_group: Option<i32>,
     pub priority: i32,
+    pub page_kind: String,
+    pub page: Option<String>,
+    pub required_event: Option<i32>,
 }
 
 #[derive(Queryable, Identifiable, Serialize, Debug, AsChangeset, Deserialize, Default)]
@@ -665,6 +750,11 @@ pub struct UpdateEvent {
     #[serde(deserialize_with = "double_option")]
     pub required_group: Option<Option<i32>>,
     pub priority: Option<i32>,
+    pub page_kind: Option<String>,
+    #[serde(deserialize_with = "double_option")]
+    pub page: Option<Option<String>>,
+    #[serde(deserialize_with = "double_option")]
+    pub required_event: Option<Option<i32>>,
 }
 
 #[derive(Insertable)]
@@ -692,6 +782,39 @@ pub struct NewEventExperience {
     pub event_id: i32,
 }
 
+#[derive(Queryable, Identifiable, Associations, Debug, Serialize)]
+#[belongs_to(Event, foreign_key = "event_id")]
+#[table_name="event_transitions"]
+pub struct EventTransition {
+    pub id: i32,
+    pub event_id: i32,
+    pub fires_on: String,
+    pub delay_hours: i32,
+    pub required_group: Option<i32>,
+    pub unless_finished: Option<i32>,
+    pub join_group: Option<i32>,
+    pub leave_group: Option<i32>,
+}
+
+#[derive(Insertable, Deserialize, Debug)]
+#[table_name="event_transitions"]
+pub struct NewEventTransition {
+    pub event_id: i32,
+    pub fires_on: String,
+    pub delay_hours: i32,
+    pub required_group: Option<i32>,
+    pub unless_finished: Option<i32>,
+    pub join_group: Option<i32>,
+    pub leave_group: Option<i32>,
+}
+
+#[derive(Insertable)]
+#[table_name="event_transitions_fired"]
+pub struct NewEventTransitionFired {
+    pub transition_id: i32,
+    pub user_id: i32,
+}
+
 #[derive(Insertable, Queryable, Identifiable, Associations, Debug, AsChangeset, Serialize)]
 #[belongs_to(User, foreign_key = "user_id")]
 #[belongs_to(Event, foreign_key = "event_id")]
@@ -721,6 +844,142 @@ pub struct UpdateEventUserdata<'a> {
     pub data: &'a str,
 }
 
+#[derive(Insertable, Queryable, Identifiable, Associations, Debug, AsChangeset, Serialize)]
+#[belongs_to(Event, foreign_key = "event_id")]
+#[table_name="test_items"]
+pub struct TestItem {
+    pub id: i32,
+    pub event_id: i32,
+    pub ordering: i32,
+    pub item_type: String,
+    pub item_text: String,
+    pub audio_id: Option<i32>,
+    pub audio_path: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name="test_items"]
+pub struct NewTestItem<'a> {
+    pub event_id: i32,
+    pub ordering: i32,
+    pub item_type: &'a str,
+    pub item_text: &'a str,
+    pub audio_id: Option<i32>,
+    pub audio_path: Option<&'a str>,
+}
+
+#[derive(AsChangeset, Deserialize, Debug, Default)]
+#[table_name="test_items"]
+#[serde(default)]
+pub struct UpdateTestItem {
+    pub event_id: Option<i32>,
+    pub ordering: Option<i32>,
+    pub item_type: Option<String>,
+    pub item_text: Option<String>,
+    #[serde(deserialize_with = "double_option")]
+    pub audio_id: Option<Option<i32>>,
+    #[serde(deserialize_with = "double_option")]
+    pub audio_path: Option<Option<String>>,
+}
+
+#[derive(Queryable, Identifiable, Associations, Debug, Serialize)]
+#[belongs_to(Event, foreign_key = "event_id")]
+#[table_name="randomizations"]
+pub struct Randomization {
+    pub id: i32,
+    pub event_id: i32,
+    pub block_size: i32,
+    pub seed: i64,
+    pub anonymous: bool,
+}
+
+#[derive(Insertable)]
+#[table_name="randomizations"]
+pub struct NewRandomization {
+    pub event_id: i32,
+    pub block_size: i32,
+    pub seed: i64,
+    pub anonymous: bool,
+}
+
+#[derive(Queryable, Identifiable, Associations, Debug, Serialize)]
+#[belongs_to(Randomization, foreign_key = "randomization_id")]
+#[table_name="randomization_arms"]
+pub struct RandomizationArm {
+    pub id: i32,
+    pub randomization_id: i32,
+    pub group_id: i32,
+    pub ratio: i32,
+}
+
+#[derive(Insertable)]
+#[table_name="randomization_arms"]
+pub struct NewRandomizationArm {
+    pub randomization_id: i32,
+    pub group_id: i32,
+    pub ratio: i32,
+}
+
+#[derive(Insertable, Queryable, Associations, Debug, Serialize)]
+#[belongs_to(Randomization, foreign_key = "randomization_id")]
+#[table_name="randomization_strata"]
+pub struct RandomizationStratum {
+    pub randomization_id: i32,
+    pub group_id: i32,
+}
+
+#[derive(Queryable, Identifiable, Associations, Debug, Serialize)]
+#[belongs_to(Randomization, foreign_key = "randomization_id")]
+#[table_name="randomization_log"]
+pub struct RandomizationLog {
+    pub id: i32,
+    pub randomization_id: i32,
+    pub user_id: Option<i32>,
+    pub stratum: String,
+    pub block_number: i32,
+    pub block_position: i32,
+    pub arm_group_id: i32,
+    pub assigned: DateTime<UTC>,
+}
+
+#[derive(Insertable)]
+#[table_name="randomization_log"]
+pub struct NewRandomizationLog<'a> {
+    pub randomization_id: i32,
+    pub user_id: Option<i32>,
+    pub stratum: &'a str,
+    pub block_number: i32,
+    pub block_position: i32,
+    pub arm_group_id: i32,
+}
+
+#[derive(Queryable, Identifiable, Debug, AsChangeset, Serialize)]
+#[changeset_options(treat_none_as_null = "true")]
+#[table_name="email_queue"]
+pub struct QueuedEmail {
+    pub id: i32,
+    pub from_address: String,
+    pub to_address: String,
+    pub subject: String,
+    #[serde(skip_serializing)]
+    pub message: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt: DateTime<UTC>,
+    pub last_error: Option<String>,
+    pub queued: DateTime<UTC>,
+    pub sent: Option<DateTime<UTC>>,
+}
+
+#[derive(Insertable)]
+#[table_name="email_queue"]
+pub struct NewQueuedEmail<'a> {
+    pub from_address: &'a str,
+    pub to_address: &'a str,
+    pub subject: &'a str,
+    pub message: &'a str,
+}
+
 #[derive(Queryable, Insertable, Debug, Associations)]
 #[belongs_to(User, foreign_key = "user_id")]
 #[table_name="reset_email_secrets"]
@@ -730,3 +989,235 @@ pub struct ResetEmailSecrets {
     pub secret: String,
     pub added: DateTime<UTC>,
 }
+
+#[derive(Queryable, Identifiable, Debug, AsChangeset, Serialize)]
+#[changeset_options(treat_none_as_null = "true")]
+#[table_name="account_requests"]
+pub struct AccountRequest {
+    pub id: i32,
+    pub user_id: i32,
+    pub action: String,
+    pub requested: DateTime<UTC>,
+    pub confirmed: Option<DateTime<UTC>>,
+    pub due: Option<DateTime<UTC>>,
+    pub cancelled: Option<DateTime<UTC>>,
+    pub executed: Option<DateTime<UTC>>,
+}
+
+#[derive(Insertable)]
+#[table_name="account_requests"]
+pub struct NewAccountRequest<'a> {
+    pub user_id: i32,
+    pub action: &'a str,
+    pub executed: Option<DateTime<UTC>>,
+}
+
+#[derive(Queryable, Identifiable, Associations, Debug, Serialize)]
+#[has_many(consents, foreign_key = "document_id")]
+#[table_name="consent_documents"]
+pub struct ConsentDocument {
+    pub id: i32,
+    pub version: i32,
+    pub locale: String,
+    pub content: String,
+    pub published: DateTime<UTC>,
+}
+
+#[derive(Insertable, Deserialize, Debug)]
+#[table_name="consent_documents"]
+pub struct NewConsentDocument {
+    pub version: i32,
+    pub locale: String,
+    pub content: String,
+}
+
+#[derive(Queryable, Identifiable, Associations, Debug, Serialize)]
+#[belongs_to(User, foreign_key = "user_id")]
+#[belongs_to(ConsentDocument, foreign_key = "document_id")]
+#[table_name="consents"]
+pub struct Consent {
+    pub id: i32,
+    pub user_id: i32,
+    pub document_id: i32,
+    pub agreed: bool,
+    pub decided: DateTime<UTC>,
+}
+
+#[derive(Insertable)]
+#[table_name="consents"]
+pub struct NewConsent {
+    pub user_id: i32,
+    pub document_id: i32,
+    pub agreed: bool,
+}
+
+#[derive(Queryable, Identifiable, Debug, Clone, Serialize)]
+#[table_name="content_revisions"]
+pub struct ContentRevision {
+    pub id: i32,
+    pub item_type: String,
+    pub item_id: i32,
+    pub content: String,
+    pub author: Option<i32>,
+    pub source: String,
+    pub created: DateTime<UTC>,
+}
+
+#[derive(Insertable)]
+#[table_name="content_revisions"]
+pub struct NewContentRevision<'a> {
+    pub item_type: &'a str,
+    pub item_id: i32,
+    pub content: &'a str,
+    pub author: Option<i32>,
+    pub source: &'a str,
+}
+
+#[derive(Identifiable, Queryable, Associations, Debug, Serialize)]
+#[has_many(role_permissions, foreign_key = "role_id")]
+#[has_many(user_roles, foreign_key = "role_id")]
+#[has_many(api_token_roles, foreign_key = "role_id")]
+#[table_name="roles"]
+pub struct Role {
+    pub id: i32,
+    pub role_name: String,
+}
+
+#[derive(Insertable)]
+#[table_name="roles"]
+pub struct NewRole<'a> {
+    pub role_name: &'a str,
+}
+
+#[derive(Identifiable, Queryable, Insertable, Associations, Debug, Serialize)]
+#[table_name="role_permissions"]
+#[primary_key(role_id, permission)]
+#[belongs_to(Role, foreign_key = "role_id")]
+pub struct RolePermission {
+    pub role_id: i32,
+    pub permission: String,
+}
+
+#[derive(Identifiable, Queryable, Insertable, Associations, Debug, Serialize)]
+#[table_name="user_roles"]
+#[primary_key(user_id, role_id)]
+#[belongs_to(User, foreign_key = "user_id")]
+#[belongs_to(Role, foreign_key = "role_id")]
+pub struct UserRole {
+    pub user_id: i32,
+    pub role_id: i32,
+}
+
+#[derive(Queryable, Identifiable, Debug, Serialize)]
+#[table_name="audit_log"]
+pub struct AuditEntry {
+    pub id: i32,
+    pub actor: Option<i32>,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: Option<i32>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub logged: DateTime<UTC>,
+}
+
+#[derive(Insertable)]
+#[table_name="audit_log"]
+pub struct NewAuditEntry<'a> {
+    pub actor: Option<i32>,
+    pub action: &'a str,
+    pub target_type: &'a str,
+    pub target_id: Option<i32>,
+    pub before: Option<&'a str>,
+    pub after: Option<&'a str>,
+}
+
+#[derive(Identifiable, Queryable, Associations, Debug, Clone, Serialize)]
+#[has_many(api_token_roles, foreign_key = "token_id")]
+#[table_name="api_tokens"]
+#[belongs_to(User, foreign_key = "user_id")]
+pub struct ApiToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub kind: String,
+    #[serde(skip_serializing)]
+    pub secret: Vec<u8>,
+    pub created: DateTime<UTC>,
+    pub expires: Option<DateTime<UTC>>,
+    pub last_used: Option<DateTime<UTC>>,
+}
+
+#[derive(Insertable)]
+#[table_name="api_tokens"]
+pub struct NewApiToken<'a> {
+    pub user_id: i32,
+    pub name: &'a str,
+    pub kind: &'a str,
+    pub secret: &'a [u8],
+    pub expires: Option<DateTime<UTC>>,
+}
+
+#[derive(Identifiable, Queryable, Insertable, Associations, Debug, Serialize)]
+#[table_name="api_token_roles"]
+#[primary_key(token_id, role_id)]
+#[belongs_to(ApiToken, foreign_key = "token_id")]
+#[belongs_to(Role, foreign_key = "role_id")]
+pub struct ApiTokenRole {
+    pub token_id: i32,
+    pub role_id: i32,
+}
+
+#[derive(Identifiable, Queryable, Associations, Debug)]
+#[table_name="totp_secrets"]
+#[primary_key(user_id)]
+#[belongs_to(User, foreign_key = "user_id")]
+pub struct TotpSecret {
+    pub user_id: i32,
+    pub secret: Vec<u8>,
+    pub confirmed: bool,
+    pub last_step: i64,
+    pub created: DateTime<UTC>,
+}
+
+#[derive(Insertable)]
+#[table_name="totp_secrets"]
+pub struct NewTotpSecret<'a> {
+    pub user_id: i32,
+    pub secret: &'a [u8],
+}
+
+#[derive(Identifiable, Queryable, Associations, Debug)]
+#[table_name="recovery_codes"]
+#[belongs_to(User, foreign_key = "user_id")]
+pub struct RecoveryCode {
+    pub id: i32,
+    pub user_id: i32,
+    pub code_hash: Vec<u8>,
+    pub used: Option<DateTime<UTC>>,
+}
+
+#[derive(Insertable)]
+#[table_name="recovery_codes"]
+pub struct NewRecoveryCode<'a> {
+    pub user_id: i32,
+    pub code_hash: &'a [u8],
+}
+
+#[derive(Identifiable, Queryable, Associations, Debug)]
+#[table_name="pending_logins"]
+#[primary_key(token)]
+#[belongs_to(User, foreign_key = "user_id")]
+pub struct PendingLogin {
+    pub token: String,
+    pub user_id: i32,
+    pub started: DateTime<UTC>,
+    pub attempts: i32,
+}
+
+#[derive(Insertable)]
+#[table_name="pending_logins"]
+pub struct NewPendingLogin<'a> {
+    pub token: &'a str,
+    pub user_id: i32,
+}
@@ -4,25 +4,66 @@ use super::errors::*;
 use super::models::Password;
 use std::time::{Instant, Duration};
 
-#[derive(Clone, Copy)]
+// The passwords are first hashed with SHA-512 together with two peppers: one that is
+// compiled in, and one that is given at runtime. The result is then hashed with a slow,
+// salted hash. The hashes are versioned: the old ones were made with bcrypt, and the new
+// ones with Argon2id. The old hashes are replaced when their users log in.
+//
+// The runtime pepper can be rotated. The hashes store an id of the pepper they were
+// made with, so the old peppers can still be checked while the passwords are rehashed
+// with the new one.
+
+/// SHA-512 and bcrypt, stretched later with extra bcrypt rounds.
+pub const BCRYPT: i16 = 1;
+/// SHA-512 and Argon2id.
+pub const ARGON2ID: i16 = 2;
+
+/// The memory cost of the new hashes. The time cost is set by the stretching time.
+pub const ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+const ARGON2_MIN_ITERATIONS: u32 = 2;
+const ARGON2_HASH_BYTES: u32 = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    Bcrypt {
+        initial_rounds: i16,
+        extra_rounds: i16,
+    },
+    Argon2id { memory_kib: u32, iterations: u32 },
+}
+
+#[derive(Clone)]
 pub struct HashedPassword {
-    hash: [u8; 24],
+    hash: Vec<u8>,
     salt: [u8; 16],
-    initial_rounds: i16,
-    extra_rounds: i16,
+    algorithm: Algorithm,
+    /// Not set for the hashes that were made before the pepper could be rotated.
+    pepper_id: Option<Vec<u8>>,
 }
 
 impl From<Password> for HashedPassword {
     fn from(db_password: Password) -> Self {
-        let mut hash = [0_u8; 24];
         let mut salt = [0_u8; 16];
-        hash[..].clone_from_slice(&db_password.password_hash[..]);
         salt[..].clone_from_slice(&db_password.salt[..]);
+        let algorithm = match db_password.hash_version {
+            ARGON2ID => {
+                Algorithm::Argon2id {
+                    memory_kib: db_password.memory_kib as u32,
+                    iterations: db_password.iterations as u32,
+                }
+            }
+            _ => {
+                Algorithm::Bcrypt {
+                    initial_rounds: db_password.initial_rounds,
+                    extra_rounds: db_password.extra_rounds,
+                }
+            }
+        };
         HashedPassword {
-            hash: hash,
+            hash: db_password.password_hash,
             salt: salt,
-            initial_rounds: db_password.initial_rounds,
-            extra_rounds: db_password.extra_rounds,
+            algorithm: algorithm,
+            pepper_id: db_password.pepper_id,
         }
     }
 }
@@ -30,22 +71,51 @@ impl From<Password> for HashedPassword {
 
 impl HashedPassword {
     pub fn into_db(self, user_id: i32) -> Password {
+        let (hash_version, initial_rounds, extra_rounds, memory_kib, iterations) =
+            match self.algorithm {
+                Algorithm::Bcrypt { initial_rounds, extra_rounds } => {
+                    (BCRYPT, initial_rounds, extra_rounds, 0, 0)
+                }
+                Algorithm::Argon2id { memory_kib, iterations } => {
+                    (ARGON2ID, 0, 0, memory_kib as i32, iterations as i32)
+                }
+            };
         Password {
             id: user_id,
-            password_hash: (&self.hash[..]).into(),
+            password_hash: self.hash,
             salt: (&self.salt[..]).into(),
-            initial_rounds: self.initial_rounds,
-            extra_rounds: self.extra_rounds,
+            initial_rounds: initial_rounds,
+            extra_rounds: extra_rounds,
+            hash_version: hash_version,
+            memory_kib: memory_kib,
+            iterations: iterations,
+            pepper_id: self.pepper_id,
+        }
+    }
+
+    /// Whether the hash should be replaced with one made with the current algorithm
+    /// and settings.
+    fn is_outdated(&self) -> bool {
+        match self.algorithm {
+            Algorithm::Bcrypt { .. } => true,
+            Algorithm::Argon2id { memory_kib, .. } => memory_kib < ARGON2_MEMORY_KIB,
         }
     }
 }
 
-fn pepper_salt_pw_hash(plaintext_pw: &str,
-                       salt: [u8; 16],
-                       initial_rounds: i16,
-                       runtime_pepper: &[u8])
-                       -> Result<HashedPassword> {
-    use crypto::bcrypt::bcrypt;
+/// Identifies the runtime pepper without revealing it.
+fn pepper_id(runtime_pepper: &[u8]) -> Vec<u8> {
+    use crypto::sha2;
+    use crypto::digest::Digest;
+
+    let mut hasher = sha2::Sha512::new();
+    hasher.input(runtime_pepper);
+    let mut hash = [0_u8; 64];
+    hasher.result(&mut hash);
+    hash[..8].to_vec()
+}
+
+fn pepper_pw(plaintext_pw: &str, runtime_pepper: &[u8]) -> [u8; 64] {
     use crypto::sha2;
     use crypto::digest::Digest;
 
@@ -57,18 +127,68 @@ fn pepper_salt_pw_hash(plaintext_pw: &str,
     hasher.input(runtime_pepper);
     let mut peppered_pw = [0_u8; 64];
     hasher.result(&mut peppered_pw);
-    let peppered_pw = peppered_pw;
+    peppered_pw
+}
+
+fn pepper_salt_pw_hash(plaintext_pw: &str,
+                       salt: [u8; 16],
+                       initial_rounds: i16,
+                       runtime_pepper: &[u8])
+                       -> Result<HashedPassword> {
+    use crypto::bcrypt::bcrypt;
+
+    let peppered_pw = pepper_pw(plaintext_pw, runtime_pepper);
 
     let mut output_hash = [0_u8; 24];
     bcrypt(initial_rounds as u32, &salt, &peppered_pw, &mut output_hash);
     Ok(HashedPassword {
-           hash: output_hash,
+           hash: output_hash.to_vec(),
            salt: salt,
-           initial_rounds: initial_rounds,
-           extra_rounds: 0,
+           algorithm: Algorithm::Bcrypt {
+               initial_rounds: initial_rounds,
+               extra_rounds: 0,
+           },
+           pepper_id: Some(pepper_id(runtime_pepper)),
        })
 }
 
+fn pepper_salt_pw_argon2(plaintext_pw: &str,
+                         salt: [u8; 16],
+                         memory_kib: u32,
+                         iterations: u32,
+                         runtime_pepper: &[u8])
+                         -> Result<HashedPassword> {
+    use argon2::{self, Config, ThreadMode, Variant, Version};
+
+    let peppered_pw = pepper_pw(plaintext_pw, runtime_pepper);
+
+    let config = Config {
+        variant: Variant::Argon2id,
+        version: Version::Version13,
+        mem_cost: memory_kib,
+        time_cost: iterations,
+        lanes: 1,
+        thread_mode: ThreadMode::Sequential,
+        secret: &[],
+        ad: &[],
+        hash_length: ARGON2_HASH_BYTES,
+    };
+
+    Ok(HashedPassword {
+           hash: argon2::hash_raw(&peppered_pw, &salt, &config)?,
+           salt: salt,
+           algorithm: Algorithm::Argon2id {
+               memory_kib: memory_kib,
+               iterations: iterations,
+           },
+           pepper_id: Some(pepper_id(runtime_pepper)),
+       })
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0
+}
+
 pub fn set_password(plaintext_pw: &str,
                     pepper: &[u8],
                     stretch_time: Duration)
@@ -85,70 +205,138 @@ pub fn set_password(plaintext_pw: &str,
     let mut salt = [0_u8; 16];
     OsRng::new()?.fill_bytes(&mut salt);
 
-    let mut rounds = 10;
     let start_time = Instant::now();
-    let mut hashed_pw = pepper_salt_pw_hash(plaintext_pw, salt, rounds, pepper)?;
-    let mut elapsed = Instant::now().duration_since(start_time);
-
-    while elapsed < stretch_time {
-
-        debug!("Not enough time elapsed ({:?}). Stretching more.", elapsed);
-        rounds += 1;
-        let start_time = Instant::now();
-        hashed_pw = pepper_salt_pw_hash(plaintext_pw, salt, rounds, pepper)?;
-        elapsed = Instant::now().duration_since(start_time);
-
+    let hashed_pw = pepper_salt_pw_argon2(plaintext_pw,
+                                          salt,
+                                          ARGON2_MEMORY_KIB,
+                                          ARGON2_MIN_ITERATIONS,
+                                          pepper)?;
+    let elapsed = Instant::now().duration_since(start_time);
+
+    // The time grows linearly with the iterations, so one measurement is enough.
+    let iterations = (ARGON2_MIN_ITERATIONS as f64 * seconds(stretch_time) /
+                      seconds(elapsed).max(0.001))
+            .ceil() as u32;
+
+    if iterations <= ARGON2_MIN_ITERATIONS {
+        return Ok(hashed_pw);
     }
 
-    Ok(hashed_pw)
+    debug!("Not enough time elapsed ({:?}). Stretching to {} iterations.",
+           elapsed,
+           iterations);
+    pepper_salt_pw_argon2(plaintext_pw, salt, ARGON2_MEMORY_KIB, iterations, pepper)
 }
 
+/// Adds bcrypt rounds to the old hashes. The Argon2id hashes are returned as they are.
 pub fn stretch_password(strength_goal: i16, hashed_pw: HashedPassword) -> HashedPassword {
     use crypto::bcrypt::bcrypt;
 
+    let (initial_rounds, mut extra_rounds) = match hashed_pw.algorithm {
+        Algorithm::Bcrypt { initial_rounds, extra_rounds } => (initial_rounds, extra_rounds),
+        Algorithm::Argon2id { .. } => return hashed_pw,
+    };
+
     // We can regard the password hash as the output of the original creation function.
-    let mut output_hash = hashed_pw.hash;
-    let mut extra_rounds = hashed_pw.extra_rounds;
+    let mut output_hash = [0_u8; 24];
+    output_hash[..].clone_from_slice(&hashed_pw.hash[..]);
 
-    while hashed_pw.initial_rounds + extra_rounds < strength_goal {
+    while initial_rounds + extra_rounds < strength_goal {
         let input = output_hash;
-        bcrypt((hashed_pw.initial_rounds + extra_rounds) as u32,
+        bcrypt((initial_rounds + extra_rounds) as u32,
                &hashed_pw.salt,
                &input,
                &mut output_hash);
         extra_rounds += 1;
     }
     HashedPassword {
-        hash: output_hash,
+        hash: output_hash.to_vec(),
         salt: hashed_pw.salt,
-        initial_rounds: hashed_pw.initial_rounds,
-        extra_rounds: extra_rounds,
+        algorithm: Algorithm::Bcrypt {
+            initial_rounds: initial_rounds,
+            extra_rounds: extra_rounds,
+        },
+        pepper_id: hashed_pw.pepper_id,
     }
 }
 
 pub fn check_password(plaintext_pw: &str, pw_from_db: HashedPassword, pepper: &[u8]) -> Result<()> {
     use crypto::util::fixed_time_eq;
-    let init_hash = pepper_salt_pw_hash(plaintext_pw,
-                                        pw_from_db.salt,
-                                        pw_from_db.initial_rounds,
-                                        pepper)?;
-    let strected_pw = stretch_password(pw_from_db.initial_rounds + pw_from_db.extra_rounds,
-                                       init_hash);
-
-    if fixed_time_eq(&strected_pw.hash, &pw_from_db.hash) {
+
+    let hashed_pw = match pw_from_db.algorithm {
+        Algorithm::Bcrypt { initial_rounds, extra_rounds } => {
+            let init_hash =
+                pepper_salt_pw_hash(plaintext_pw, pw_from_db.salt, initial_rounds, pepper)?;
+            stretch_password(initial_rounds + extra_rounds, init_hash)
+        }
+        Algorithm::Argon2id { memory_kib, iterations } => {
+            pepper_salt_pw_argon2(plaintext_pw, pw_from_db.salt, memory_kib, iterations, pepper)?
+        }
+    };
+
+    if hashed_pw.hash.len() == pw_from_db.hash.len() &&
+       fixed_time_eq(&hashed_pw.hash, &pw_from_db.hash) {
         Ok(())
     } else {
         Err(ErrorKind::PasswordDoesntMatch.into())
     }
 }
 
+/// Checks the password with the runtime pepper it was hashed with, which is either the
+/// current one or one of the old ones. Returns whether the hash is outdated and should
+/// be replaced: made with bcrypt, with lighter settings, or with an old pepper.
+pub fn verify(plaintext_pw: &str,
+              pw_from_db: HashedPassword,
+              pepper: &[u8],
+              old_peppers: &[Vec<u8>])
+              -> Result<bool> {
+    let peppers = Some(pepper).into_iter().chain(old_peppers.iter().map(|p| &p[..]));
+    let candidates: Vec<&[u8]> = match pw_from_db.pepper_id {
+        Some(ref id) => peppers.filter(|p| &pepper_id(p) == id).collect(),
+        // Hashed before the peppers had ids, so we don't know which one it is.
+        None => peppers.collect(),
+    };
+
+    if candidates.is_empty() {
+        warn!("The password was hashed with an unknown runtime pepper! Has an old pepper been \
+               removed from GANBARE_OLD_RUNTIME_PEPPERS?");
+    }
+
+    for candidate in candidates {
+        match check_password(plaintext_pw, pw_from_db.clone(), candidate) {
+            Ok(()) => {
+                return Ok(candidate != pepper || pw_from_db.pepper_id.is_none() ||
+                          pw_from_db.is_outdated())
+            }
+            Err(Error(ErrorKind::PasswordDoesntMatch, _)) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(ErrorKind::PasswordDoesntMatch.into())
+}
+
+/// Parses a comma-separated list of runtime peppers: 256-bit values encoded as base64.
+pub fn parse_peppers(peppers: &str) -> Result<Vec<Vec<u8>>> {
+    use data_encoding::base64::decode;
+
+    let mut parsed = vec![];
+    for pepper in peppers.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        let pepper = decode(pepper.as_bytes())?;
+        if pepper.len() != 32 {
+            bail!(ErrorKind::InvalidInput);
+        }
+        parsed.push(pepper);
+    }
+    Ok(parsed)
+}
+
 
 #[test]
 fn test_set_check_password1() {
     use rand::{StdRng, Rng};
     let mut pepper = [0_u8; 32];
     StdRng::new().unwrap().fill_bytes(&mut pepper);
-    let pw = set_password("password", &pepper).unwrap();
+    let pw = set_password("password", &pepper, Duration::from_millis(0)).unwrap();
     check_password("password", pw, &pepper).expect("Passwords should match!");
 }
 
@@ -157,7 +345,7 @@ fn test_set_check_password2() {
     use rand::{StdRng, Rng};
     let mut pepper = [0_u8; 32];
     StdRng::new().unwrap().fill_bytes(&mut pepper);
-    let pw = set_password("password1", &pepper).unwrap();
+    let pw = set_password("password1", &pepper, Duration::from_millis(0)).unwrap();
     if let Ok(()) = check_password("password2", pw, &pepper) {
         panic!("Passwords shouldn't match!");
     }
@@ -169,18 +357,18 @@ fn test_set_stretch_password1() {
     let mut pepper = [0_u8; 32];
     StdRng::new().unwrap().fill_bytes(&mut pepper);
 
-    let init_pw = set_password("daggerfish", &pepper).unwrap();
+    let init_pw = pepper_salt_pw_hash("daggerfish", [1; 16], 10, &pepper).unwrap();
     println!("hashed init_hash.");
     let stretched_pw_0 = stretch_password(11, init_pw);
     println!("stretched 10 → 11.");
     let stretched_pw_1 = stretch_password(12, stretched_pw_0);
     println!("stretched 11 → 12.");
 
-    let stretched_pw_2 = stretch_password(12, stretched_pw_1);
+    let stretched_pw_2 = stretch_password(12, stretched_pw_1.clone());
     println!("stretched 10 → 12.");
 
     assert_eq!(stretched_pw_1.hash, stretched_pw_2.hash);
-    assert_eq!(stretched_pw_1.extra_rounds, stretched_pw_2.extra_rounds);
+    assert_eq!(stretched_pw_1.algorithm, stretched_pw_2.algorithm);
 }
 
 
@@ -191,13 +379,17 @@ fn test_set_stretch_password2() {
     let mut pepper = [0_u8; 32];
     StdRng::new().unwrap().fill_bytes(&mut pepper);
 
-    let init_pw_1 = set_password("swordfish", &pepper).unwrap();
+    let init_pw_1 = pepper_salt_pw_hash("swordfish", [2; 16], 10, &pepper).unwrap();
     println!("hashed init_hash.");
-    let init_pw_2 = stretch_password(10, init_pw_1);
+    let init_pw_2 = stretch_password(10, init_pw_1.clone());
     println!("stretched 10 → 10.");
 
     assert_eq!(init_pw_1.hash, init_pw_2.hash);
-    assert_eq!(0, init_pw_2.extra_rounds);
+    assert_eq!(Algorithm::Bcrypt {
+                   initial_rounds: 10,
+                   extra_rounds: 0,
+               },
+               init_pw_2.algorithm);
 }
 
 
@@ -207,9 +399,9 @@ fn test_set_stretch_password3() {
     let mut pepper = [0_u8; 32];
     StdRng::new().unwrap().fill_bytes(&mut pepper);
 
-    let init_pw = set_password("schwertfisch", &pepper).unwrap();
+    let init_pw = pepper_salt_pw_hash("schwertfisch", [3; 16], 10, &pepper).unwrap();
     println!("hashed init_hash.");
-    let stretched_pw_0 = stretch_password(11, init_pw);
+    let stretched_pw_0 = stretch_password(11, init_pw.clone());
     println!("stretched 10 → 11.");
     let stretched_pw_1 = stretch_password(12, stretched_pw_0);
     println!("stretched 11 → 11.");
@@ -218,7 +410,7 @@ fn test_set_stretch_password3() {
     println!("stretched 10 → 11.");
 
     assert_eq!(stretched_pw_1.hash, stretched_pw_2.hash);
-    assert_eq!(stretched_pw_1.extra_rounds, stretched_pw_2.extra_rounds);
+    assert_eq!(stretched_pw_1.algorithm, stretched_pw_2.algorithm);
 }
 
 #[test]
@@ -227,7 +419,7 @@ fn test_set_stretch_check_password1() {
     let mut pepper = [0_u8; 32];
     StdRng::new().unwrap().fill_bytes(&mut pepper);
 
-    let init_pw = set_password("miekkakala", &pepper).unwrap();
+    let init_pw = pepper_salt_pw_hash("miekkakala", [4; 16], 10, &pepper).unwrap();
     println!("hashed init_hash.");
     let stretched_pw = stretch_password(11, init_pw);
     println!("stretched 10 → 11.");
@@ -241,7 +433,7 @@ fn test_set_stretch_check_password2() {
     let mut pepper = [0_u8; 32];
     StdRng::new().unwrap().fill_bytes(&mut pepper);
 
-    let init_pw = set_password("miekkakala", &pepper).unwrap();
+    let init_pw = pepper_salt_pw_hash("miekkakala", [5; 16], 10, &pepper).unwrap();
     println!("hashed init_hash.");
     let stretched_pw = stretch_password(11, init_pw);
     println!("stretched 10 → 11.");
@@ -250,3 +442,27 @@ fn test_set_stretch_check_password2() {
         panic!("Passwords shouldn't match!");
     }
 }
+
+#[test]
+fn test_verify_rotated_pepper() {
+    use rand::{StdRng, Rng};
+    let mut old_pepper = vec![0_u8; 32];
+    let mut pepper = [0_u8; 32];
+    StdRng::new().unwrap().fill_bytes(&mut old_pepper);
+    StdRng::new().unwrap().fill_bytes(&mut pepper);
+
+    let old_pw = set_password("kalamiekka", &old_pepper, Duration::from_millis(0)).unwrap();
+    let old_peppers = vec![old_pepper.clone()];
+    assert!(verify("kalamiekka", old_pw.clone(), &pepper, &old_peppers).unwrap(),
+            "Hashes with an old pepper should be outdated!");
+    assert!(verify("kalamiekka", old_pw.clone(), &pepper, &[]).is_err());
+    assert!(verify("kalatikari", old_pw, &pepper, &old_peppers).is_err());
+
+    let new_pw = set_password("kalamiekka", &pepper, Duration::from_millis(0)).unwrap();
+    assert!(!verify("kalamiekka", new_pw, &pepper, &old_peppers).unwrap());
+
+    let mut bcrypt_pw = pepper_salt_pw_hash("kalamiekka", [6; 16], 10, &pepper).unwrap();
+    bcrypt_pw.pepper_id = None;
+    assert!(verify("kalamiekka", bcrypt_pw, &pepper, &old_peppers).unwrap(),
+            "bcrypt hashes should be outdated!");
+}
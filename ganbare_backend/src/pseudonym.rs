@@ -0,0 +1,177 @@
+use super::*;
+use rand::{Rng, thread_rng};
+use std::collections::HashMap;
+
+/// A member of an anonymous group (or an anonymous member of a group), known only by an alias.
+#[derive(Debug, Serialize)]
+pub struct AnonymousMember {
+    pub group_id: i32,
+    pub alias: String,
+}
+
+fn new_alias_name() -> String {
+    thread_rng().gen_ascii_chars().take(10).collect()
+}
+
+/// The alias of the user in the group, created if the user doesn't have one yet.
+/// The aliases are random, and stay the same once created. A user has a different alias
+/// in each group, so the memberships can't be linked together by the aliases.
+/// The alias without a group is used for the research data.
+pub fn get_create_alias(conn: &Connection,
+                        user_id: i32,
+                        group_id: Option<i32>)
+                        -> Result<AnonAliases> {
+    use schema::anon_aliases;
+
+    let query = anon_aliases::table.filter(anon_aliases::user_id.eq(user_id)).into_boxed();
+    let query = match group_id {
+        Some(group_id) => query.filter(anon_aliases::group_id.eq(group_id)),
+        None => query.filter(anon_aliases::group_id.is_null()),
+    };
+    if let Some(alias) = query.get_result(&**conn).optional()? {
+        return Ok(alias);
+    }
+
+    let name = new_alias_name();
+    let alias = diesel::insert(&NewAnonAlias {
+                                    name: &name,
+                                    user_id: Some(user_id),
+                                    group_id: group_id,
+                                }).into(anon_aliases::table)
+            .get_result(&**conn)?;
+    Ok(alias)
+}
+
+/// The existing aliases in the group (or without a group), by user id.
+pub fn aliases(conn: &Connection, group_id: Option<i32>) -> Result<HashMap<i32, String>> {
+    use schema::anon_aliases;
+
+    let query = anon_aliases::table.filter(anon_aliases::user_id.is_not_null()).into_boxed();
+    let query = match group_id {
+        Some(group_id) => query.filter(anon_aliases::group_id.eq(group_id)),
+        None => query.filter(anon_aliases::group_id.is_null()),
+    };
+    let aliases: Vec<AnonAliases> = query.get_results(&**conn)?;
+
+    Ok(aliases.into_iter()
+           .filter_map(|a| a.user_id.map(|id| (id, a.name)))
+           .collect())
+}
+
+/// Finds the alias by its name.
+pub fn resolve(conn: &Connection, name: &str) -> Result<Option<AnonAliases>> {
+    use schema::anon_aliases;
+
+    Ok(anon_aliases::table.filter(anon_aliases::name.eq(name))
+           .get_result(&**conn)
+           .optional()?)
+}
+
+fn is_anonymous(membership: &GroupMembership, groups: &[UserGroup]) -> bool {
+    membership.anonymous ||
+    groups.iter().any(|g| g.id == membership.group_id && g.anonymous)
+}
+
+/// Removes the anonymous memberships: the memberships flagged anonymous,
+/// and all memberships of anonymous groups.
+pub fn hide_anonymous(memberships: &mut Vec<GroupMembership>, groups: &[UserGroup]) {
+    memberships.retain(|m| !is_anonymous(m, groups));
+}
+
+/// Orders the members by group, and by the alias within the group. The aliases are random,
+/// so unlike the order of the user ids, this order doesn't tell who joined first.
+fn sort_members(members: &mut Vec<AnonymousMember>) {
+    members.sort_by(|a, b| (a.group_id, &a.alias).cmp(&(b.group_id, &b.alias)));
+}
+
+/// The anonymous memberships of all users, with the users referred to by their aliases
+/// in the group.
+pub fn anonymous_members(conn: &Connection) -> Result<Vec<AnonymousMember>> {
+    use schema::group_memberships;
+
+    let groups = user::all_groups(conn)?;
+    let memberships: Vec<GroupMembership> = group_memberships::table.get_results(&**conn)?;
+
+    let mut members = vec![];
+    for m in memberships.into_iter().filter(|m| is_anonymous(m, &groups)) {
+        let alias = get_create_alias(conn, m.user_id, Some(m.group_id))?;
+        members.push(AnonymousMember {
+                         group_id: m.group_id,
+                         alias: alias.name,
+                     });
+    }
+    sort_members(&mut members);
+    Ok(members)
+}
+
+/// Replaces the user ids in the JSON with the alias, for showing the data of a user
+/// known only by an alias.
+pub fn replace_user_ids(value: &mut serde_json::Value, alias: &str) {
+    use serde_json::Value;
+
+    match *value {
+        Value::Object(ref mut map) => {
+            for (key, value) in map.iter_mut() {
+                if key == "user_id" {
+                    *value = Value::String(alias.to_string());
+                } else {
+                    replace_user_ids(value, alias);
+                }
+            }
+        }
+        Value::Array(ref mut values) => {
+            for value in values {
+                replace_user_ids(value, alias);
+            }
+        }
+        _ => (),
+    }
+}
+
+#[test]
+fn test_hide_anonymous() {
+    let groups = vec![UserGroup {
+                          id: 1,
+                          group_name: "subjects".into(),
+                          anonymous: false,
+                      },
+                      UserGroup {
+                          id: 2,
+                          group_name: "input_group".into(),
+                          anonymous: true,
+                      }];
+    let membership = |group_id, anonymous| {
+        GroupMembership {
+            user_id: 1,
+            group_id: group_id,
+            anonymous: anonymous,
+        }
+    };
+    let mut memberships = vec![membership(1, false), membership(2, false), membership(3, true)];
+    hide_anonymous(&mut memberships, &groups);
+    assert_eq!(memberships.len(), 1);
+    assert_eq!(memberships[0].group_id, 1);
+}
+
+#[test]
+fn test_replace_user_ids() {
+    let mut value: serde_json::Value =
+        serde_json::from_str(r#"[{"user_id": 5, "item": {"user_id": 5, "id": 7}}]"#).unwrap();
+    replace_user_ids(&mut value, "abc");
+    assert_eq!(value.to_string(), r#"[{"item":{"id":7,"user_id":"abc"},"user_id":"abc"}]"#);
+}
+
+#[test]
+fn test_sort_members() {
+    let member = |group_id, alias: &str| {
+        AnonymousMember {
+            group_id: group_id,
+            alias: alias.into(),
+        }
+    };
+    // In the order of the user ids.
+    let mut members = vec![member(2, "qXk"), member(1, "zTa"), member(1, "Bcd"), member(2, "aaa")];
+    sort_members(&mut members);
+    let order: Vec<(i32, &str)> = members.iter().map(|m| (m.group_id, m.alias.as_str())).collect();
+    assert_eq!(order, vec![(1, "Bcd"), (1, "zTa"), (2, "aaa"), (2, "qXk")]);
+}
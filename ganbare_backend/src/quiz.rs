@@ -1,4 +1,5 @@
 use super::*;
+use scheduler::{MemoryModel, Review};
 use rand::{Rng, thread_rng};
 use unicode_normalization::UnicodeNormalization;
 
@@ -43,7 +44,7 @@ pub struct QuestionJson {
 #[derive(Serialize, Debug, Clone)]
 pub struct ExerciseJson {
     pub quiz_type: &'static str,
-    pub event_name: &'static str,
+    pub event_name: String,
     pub asked_id: i32,
     pub word: String,
     pub explanation: String,
@@ -120,10 +121,13 @@ fn register_future_w_answer(conn: &Connection, data: &WAskedData) -> Result<()>
 fn log_answer_due_item(conn: &Connection,
                        mut due_item: DueItem,
                        skill_id: i32,
-                       correct: bool,
+                       review: &Review,
                        metrics: &UserMetrics)
                        -> Result<DueItem> {
-    use std::cmp::max;
+
+    let model = MemoryModel::from_name(&metrics.memory_model)
+        .chain_err(|| ErrorKind::DatabaseOdd("Unknown memory model in user_metrics!"))?;
+    let correct = review.correct;
 
     due_item.correct_streak_this_time = if correct {
         due_item.correct_streak_this_time + 1
@@ -133,19 +137,15 @@ fn log_answer_due_item(conn: &Connection,
     due_item.cooldown_delay = chrono::UTC::now() +
                               chrono::Duration::seconds(metrics.cooldown_delay as i64);
 
-    if due_item.correct_streak_this_time >= metrics.streak_limit {
+    if due_item.correct_streak_this_time >= metrics.streak_limit ||
+       !correct && model.reviews_misses() {
         due_item.correct_streak_this_time = 0;
         due_item.correct_streak_overall = if correct {
             due_item.correct_streak_overall + 1
         } else {
             0
         };
-        due_item.due_delay = if correct {
-            max(due_item.due_delay * metrics.delay_multiplier,
-                metrics.initial_delay)
-        } else {
-            0
-        };
+        model.schedule(&mut due_item, review, metrics);
         due_item.due_date = chrono::UTC::now() +
                             chrono::Duration::seconds(due_item.due_delay as i64);
         if due_item.correct_streak_overall >= metrics.streak_skill_bump_criteria {
@@ -167,7 +167,7 @@ fn log_answer_new_due_item(conn: &Connection,
                            user_id: i32,
                            item_type: &str,
                            skill_id: i32,
-                           correct: bool,
+                           review: &Review,
                            metrics: &UserMetrics)
                            -> Result<DueItem> {
     use schema::due_items;
@@ -194,7 +194,7 @@ fn log_answer_new_due_item(conn: &Connection,
     let due_item: DueItem = diesel::insert(&new_due_item).into(due_items::table)
         .get_result(&**conn)?;
 
-    Ok(log_answer_due_item(conn, due_item, skill_id, correct, metrics)?)
+    Ok(log_answer_due_item(conn, due_item, skill_id, review, metrics)?)
 }
 
 fn log_answer_word(conn: &Connection, user_id: i32, answered: &WAnsweredData) -> Result<()> {
@@ -259,6 +259,11 @@ fn log_answer_question(conn: &Connection,
     let _: PendingItem = pending_item.save_changes(&**conn)?;
 
     let correct = asked.correct_qa_id == answered.answered_qa_id.unwrap_or(-1);
+    let review = Review {
+        correct: correct,
+        answer_level: None,
+        active_answer_time_ms: answered.active_answer_time_ms,
+    };
 
     diesel::insert(answered).into(q_answered_data::table).execute(&**conn)?;
 
@@ -307,7 +312,7 @@ fn log_answer_question(conn: &Connection,
     // Update the data for this question (due date, statistics etc.)
     Ok(if let Some((_, due_item)) = questiondata {
 
-           log_answer_due_item(conn, due_item, question.skill_id, correct, metrics)?;
+           log_answer_due_item(conn, due_item, question.skill_id, &review, metrics)?;
 
        } else {
            // New!
@@ -316,7 +321,7 @@ fn log_answer_question(conn: &Connection,
                                                user_id,
                                                "question",
                                                question.skill_id,
-                                               correct,
+                                               &review,
                                                metrics)?;
 
            let questiondata = QuestionData {
@@ -336,7 +341,11 @@ fn log_answer_exercise(conn: &Connection,
     use schema::{user_stats, pending_items, e_asked_data, e_answered_data, due_items, exercise_data,
                  exercises};
 
-    let correct = answered.answer_level > 0;
+    let review = Review {
+        correct: answered.answer_level > 0,
+        answer_level: Some(answered.answer_level),
+        active_answer_time_ms: answered.active_answer_time_ms,
+    };
 
     let (mut pending_item, asked): (PendingItem, EAskedData) =
         pending_items::table.inner_join(e_asked_data::table)
@@ -396,7 +405,7 @@ fn log_answer_exercise(conn: &Connection,
     // Update the data for this word exercise (due date, statistics etc.)
     Ok(if let Some((_, due_item)) = exercisedata {
 
-           log_answer_due_item(conn, due_item, exercise.skill_id, correct, metrics)?;
+           log_answer_due_item(conn, due_item, exercise.skill_id, &review, metrics)?;
 
        } else {
            // New!
@@ -405,7 +414,7 @@ fn log_answer_exercise(conn: &Connection,
                                                user_id,
                                                "exercise",
                                                exercise.skill_id,
-                                               correct,
+                                               &review,
                                                metrics)?;
 
            let exercisedata = ExerciseData {
@@ -487,6 +496,7 @@ fn choose_next_due_item(conn: &Connection, user_id: i32) -> Result<Option<(DueIt
     let due_questions: Option<(DueItem, Option<QuestionData>)> =
         due_items::table.left_outer_join(question_data::table)
             .filter(due_items::user_id.eq(user_id))
+            .filter(not_in_trash())
             .order(due_items::due_date.asc())
             .first(&**conn)
             .optional()?;
@@ -494,6 +504,7 @@ fn choose_next_due_item(conn: &Connection, user_id: i32) -> Result<Option<(DueIt
     let due_exercises: Option<(DueItem, Option<ExerciseData>)> =
         due_items::table.left_outer_join(exercise_data::table)
             .filter(due_items::user_id.eq(user_id))
+            .filter(not_in_trash())
             .order(due_items::due_date.asc())
             .first(&**conn)
             .optional()?;
@@ -513,11 +524,27 @@ fn choose_next_due_item(conn: &Connection, user_id: i32) -> Result<Option<(DueIt
     Ok(due_item)
 }
 
+/// Leaves out the due items of the questions and exercises that are in the trash.
+const NOT_IN_TRASH: &'static str = r###"
+NOT EXISTS (
+    SELECT 1 FROM question_data JOIN quiz_questions ON quiz_questions.id=question_data.question_id
+    WHERE question_data.due=due_items.id AND quiz_questions.deleted IS NOT NULL
+) AND NOT EXISTS (
+    SELECT 1 FROM exercise_data JOIN exercises ON exercises.id=exercise_data.exercise_id
+    WHERE exercise_data.due=due_items.id AND exercises.deleted IS NOT NULL
+)
+"###;
+
+fn not_in_trash() -> diesel::expression::SqlLiteral<diesel::types::Bool> {
+    diesel::expression::dsl::sql(NOT_IN_TRASH)
+}
+
 pub fn count_overdue_items(conn: &Connection, user_id: i32) -> Result<i64> {
     use schema::due_items;
 
     let count: i64 = due_items::table.filter(due_items::user_id.eq(user_id))
         .filter(due_items::due_date.lt(chrono::UTC::now()))
+        .filter(not_in_trash())
         .count()
         .get_result(&**conn)?;
 
@@ -530,6 +557,7 @@ fn choose_random_overdue_item(conn: &Connection, user_id: i32) -> Result<Option<
     let due: Option<DueItem> = due_items::table.filter(due_items::user_id.eq(user_id))
         .filter(due_items::due_date.lt(chrono::UTC::now()))
         .filter(due_items::cooldown_delay.lt(chrono::UTC::now()))
+        .filter(not_in_trash())
         .order(sql::random)
         .first(&**conn)
         .optional()?;
@@ -561,6 +589,7 @@ fn choose_random_overdue_item_include_cooldown(conn: &Connection,
 
     let due: Option<DueItem> = due_items::table.filter(due_items::user_id.eq(user_id))
         .filter(due_items::due_date.lt(chrono::UTC::now()))
+        .filter(not_in_trash())
         .order(sql::random)
         .first(&**conn)
         .optional()?;
@@ -617,6 +646,7 @@ fn choose_new_question(conn: &Connection, user_id: i32) -> Result<Option<QuizQue
         diesel::types::Text,
         diesel::types::Bool,
         diesel::types::Integer,
+        diesel::types::Nullable<diesel::types::Timestamptz>,
         )>(&format!(r###"
 SELECT
     id,
@@ -625,7 +655,8 @@ SELECT
     q_explanation,
     question_text,
     published,
-    q.skill_level
+    q.skill_level,
+    q.deleted
 FROM
     quiz_questions AS q
     LEFT OUTER JOIN
@@ -640,6 +671,7 @@ FROM
 WHERE
     q.skill_level <= COALESCE(s.skill_level, 0) AND
     q.published = true AND
+    q.deleted IS NULL AND
     q.id NOT IN ( SELECT question_id FROM due_items JOIN question_data ON id=due WHERE user_id={} )
 ORDER BY RANDOM();
 "###, user_id, user_id)) // Injection isn't possible: user_id is numerical and non-tainted data.
@@ -678,12 +710,14 @@ fn choose_new_exercise(conn: &Connection, user_id: i32) -> Result<Option<Exercis
         diesel::types::Integer,
         diesel::types::Bool,
         diesel::types::Integer,
+        diesel::types::Nullable<diesel::types::Timestamptz>,
         )>(&format!(r###"
 SELECT
     id,
     skill_id,
     published,
-    e.skill_level
+    e.skill_level,
+    e.deleted
 FROM
     exercises AS e
     LEFT OUTER JOIN
@@ -698,6 +732,11 @@ FROM
 WHERE
     e.skill_level <= COALESCE(s.skill_level, 0) AND
     e.published = true AND
+    e.deleted IS NULL AND
+    NOT EXISTS (
+        SELECT 1 FROM exercise_variants JOIN words ON words.id=exercise_variants.id
+        WHERE exercise_variants.exercise_id=e.id AND words.deleted IS NOT NULL
+    ) AND
     e.id NOT IN ( SELECT exercise_id FROM due_items JOIN exercise_data ON id=due WHERE user_id={} )
 ORDER BY RANDOM();
 "###, user_id, user_id)) // Injection isn't possible: user_id is numerical and non-tainted data.
@@ -800,6 +839,7 @@ fn choose_new_random_word(conn: &Connection, user_id: i32) -> Result<Option<Word
         diesel::types::Bool,
         diesel::types::Integer,
         diesel::types::Integer,
+        diesel::types::Nullable<diesel::types::Timestamptz>,
         )>(&format!(r###"
 SELECT
     id,
@@ -809,7 +849,8 @@ SELECT
     words.skill_nugget,
     published,
     words.skill_level,
-    priority
+    priority,
+    words.deleted
 FROM
     words
     LEFT OUTER JOIN
@@ -824,6 +865,7 @@ FROM
 WHERE
     words.skill_level <= COALESCE(skill_data.skill_level, 0) AND
     words.published = true AND
+    words.deleted IS NULL AND
     words.id NOT IN (
         SELECT word_id
             FROM pending_items
@@ -879,6 +921,7 @@ fn choose_new_paired_word(conn: &Connection, user_id: i32) -> Result<Option<Word
         diesel::types::Bool,
         diesel::types::Integer,
         diesel::types::Integer,
+        diesel::types::Nullable<diesel::types::Timestamptz>,
         )>(&format!(r###"
 SELECT
     id,
@@ -888,7 +931,8 @@ SELECT
     words.skill_nugget,
     published,
     words.skill_level,
-    priority
+    priority,
+    words.deleted
 FROM
     words
     LEFT OUTER JOIN
@@ -904,6 +948,7 @@ WHERE
     COALESCE(skill_data.skill_level, 0) > 0 AND
     words.skill_level <= COALESCE(skill_data.skill_level, 0) AND
     words.published = true AND
+    words.deleted IS NULL AND
     words.id NOT IN (
         SELECT word_id
             FROM pending_items
@@ -958,9 +1003,10 @@ fn clear_limits(conn: &Connection, metrics: &mut UserMetrics) -> Result<Option<Q
 
     // This is important because we have to zero the counts
     // once every day even though we wouldn't break a single time!
-    // We are having 1 AM as the change point of the day
-    // (That's 3 AM in Finland, where the users of this app are likely to reside)
-    if chrono::UTC::now() > (metrics.today.date().and_hms(1, 0, 0) + chrono::Duration::hours(24)) {
+    // The change point of the day is the rollover hour in the user's own timezone.
+    let user = user::get_user(conn, metrics.id)?;
+    let today = user::day_start(&user, chrono::UTC::now())?;
+    if metrics.today < today {
 
         let mut stats: UserStats = user_stats::table.filter(user_stats::id.eq(metrics.id))
             .get_result(&**conn)?;
@@ -968,7 +1014,7 @@ fn clear_limits(conn: &Connection, metrics: &mut UserMetrics) -> Result<Option<Q
         stats.days_used += 1;
         let _: UserStats = stats.save_changes(&**conn)?;
 
-        metrics.today = chrono::UTC::today().and_hms(1, 0, 0);
+        metrics.today = today;
         metrics.new_words_since_break = 0;
         metrics.quizes_since_break = 0;
         metrics.new_words_today = 0;
@@ -1020,7 +1066,8 @@ fn check_break(conn: &Connection, user_id: i32, metrics: &mut UserMetrics) -> Re
         metrics.quizes_since_break = 0;
         metrics.new_words_today = 0;
         metrics.quizes_today = 0;
-        metrics.break_until = metrics.today + Duration::hours(24);
+        let user = user::get_user(conn, user_id)?;
+        metrics.break_until = user::day_end(&user, chrono::UTC::now())?;
 
         if no_new_words && no_new_quizes {
             // Nothing else left but due items – so no use breaking until there is some available
@@ -1152,7 +1199,7 @@ pub fn penditem_to_quiz(conn: &Connection, pi: &PendingItem) -> Result<Quiz> {
 
         Quiz::E(ExerciseJson {
                     quiz_type: "exercise",
-                    event_name: "training",
+                    event_name: "training".to_string(),
                     asked_id: pi.id,
                     word: word.word.nfc().collect::<String>(),
                     explanation: word.explanation,
@@ -1253,7 +1300,7 @@ pub fn return_q_or_e(conn: &Connection, user_id: i32, quiztype: QuizType) -> Res
 
             let quiz_json = ExerciseJson {
                 quiz_type: "exercise",
-                event_name: "training",
+                event_name: "training".to_string(),
                 asked_id: pending_item.id,
                 word: word.word.nfc().collect::<String>(),
                 explanation: word.explanation,
@@ -1324,10 +1371,11 @@ pub fn get_question(conn: &Connection, answer_text: &str) -> Result<(QuizQuestio
            .get_result(&**conn)?)
 }
 
+#[derive(Debug, Clone)]
 pub enum QuizSerialized {
-    Word(&'static str, i32),
-    Question(&'static str, i32),
-    Exercise(&'static str, i32),
+    Word(String, i32),
+    Question(String, i32),
+    Exercise(String, i32),
 }
 
 pub fn test_item(conn: &Connection,
@@ -1336,7 +1384,7 @@ pub fn test_item(conn: &Connection,
                  -> Result<(Quiz, i32)> {
     let pending_item;
     let test_item = match *quiz_str {
-        QuizSerialized::Word(s, audio_id) => {
+        QuizSerialized::Word(ref s, audio_id) => {
             let w = quiz::get_word_by_str(conn, s).chain_err(|| format!("Word {} not found", s))?;
             let a = audio::get_audio_file_by_id(conn, audio_id)?;
 
@@ -1369,7 +1417,7 @@ pub fn test_item(conn: &Connection,
                         show_accents: asked_data.show_accents,
                     })
         }
-        QuizSerialized::Question(s, audio_id) => {
+        QuizSerialized::Question(ref s, audio_id) => {
 
             let (q, ans) =
                 quiz::get_question(conn, s).chain_err(|| format!("Question {} not found", s))?;
@@ -1412,7 +1460,7 @@ pub fn test_item(conn: &Connection,
                     })
 
         }
-        QuizSerialized::Exercise(word, audio_id) => {
+        QuizSerialized::Exercise(ref word, audio_id) => {
 
             let (e, var) = quiz::get_exercise(conn, word)
                         .chain_err(|| format!("Exercise {} not found", word))?;
@@ -1443,7 +1491,7 @@ pub fn test_item(conn: &Connection,
 
             Quiz::E(ExerciseJson {
                         quiz_type: "exercise",
-                        event_name: "pretest or posttest",
+                        event_name: String::new(),
                         asked_id: pending_item.id,
                         word: word.word.nfc().collect::<String>(),
                         explanation: word.explanation,
@@ -0,0 +1,308 @@
+use super::*;
+use rand::{Rng, SeedableRng, IsaacRng, thread_rng};
+use std::collections::HashMap;
+
+#[derive(Deserialize, Debug)]
+pub struct ArmSpec {
+    pub group_id: i32,
+    #[serde(default = "default_ratio")]
+    pub ratio: i32,
+}
+
+fn default_ratio() -> i32 {
+    1
+}
+
+/// A randomization as posted to the API. If the seed is omitted, a random one is generated.
+#[derive(Deserialize, Debug)]
+pub struct RandomizationSpec {
+    pub event_id: i32,
+    pub block_size: i32,
+    pub seed: Option<i64>,
+    pub anonymous: Option<bool>,
+    pub arms: Vec<ArmSpec>,
+    #[serde(default)]
+    pub strata: Vec<i32>,
+}
+
+/// The stratum of a user is the set of the stratifying groups the user is a member of.
+/// It's stored as a comma-separated list of ascending group ids; users in none of the groups
+/// have an empty stratum.
+fn stratum_key(stratum: &[i32]) -> String {
+    stratum.iter().map(|g| g.to_string()).collect::<Vec<_>>().join(",")
+}
+
+/// Returns the arm group ids of a block in a random order. Each arm is included in proportion
+/// to its ratio. The order depends only on the seed, the stratum and the block number,
+/// so any allocation can be reproduced when auditing.
+pub fn block(seed: i64,
+             stratum: &[i32],
+             block_number: i32,
+             arms: &[RandomizationArm],
+             block_size: i32)
+             -> Vec<i32> {
+    let ratio_sum: i32 = arms.iter().map(|a| a.ratio).sum();
+    let repeats = block_size / ratio_sum;
+
+    let mut block = Vec::with_capacity(block_size as usize);
+    for arm in arms {
+        for _ in 0..arm.ratio * repeats {
+            block.push(arm.group_id);
+        }
+    }
+
+    let mut rng_seed = vec![seed as u32, (seed >> 32) as u32, block_number as u32];
+    rng_seed.extend(stratum.iter().map(|&g| g as u32));
+    let mut rng = IsaacRng::from_seed(&rng_seed);
+    rng.shuffle(&mut block);
+    block
+}
+
+pub fn create(conn: &Connection, spec: &RandomizationSpec) -> Result<Randomization> {
+    use schema::{randomizations, randomization_arms, randomization_strata};
+
+    let ratio_sum: i32 = spec.arms.iter().map(|a| a.ratio).sum();
+    if spec.arms.len() < 2 || spec.arms.iter().any(|a| a.ratio <= 0) || spec.block_size <= 0 ||
+       spec.block_size % ratio_sum != 0 {
+        bail!(ErrorKind::InvalidInput);
+    }
+    for (i, arm) in spec.arms.iter().enumerate() {
+        if spec.arms[..i].iter().any(|a| a.group_id == arm.group_id) ||
+           spec.strata.contains(&arm.group_id) {
+            bail!(ErrorKind::InvalidInput);
+        }
+    }
+
+    conn.transaction(|| -> Result<Randomization> {
+        let randomization: Randomization = diesel::insert(&NewRandomization {
+                                               event_id: spec.event_id,
+                                               block_size: spec.block_size,
+                                               seed: spec.seed
+                                                   .unwrap_or_else(|| thread_rng().gen()),
+                                               anonymous: spec.anonymous.unwrap_or(true),
+                                           }).into(randomizations::table)
+                .get_result(&**conn)?;
+
+        for arm in &spec.arms {
+            diesel::insert(&NewRandomizationArm {
+                                randomization_id: randomization.id,
+                                group_id: arm.group_id,
+                                ratio: arm.ratio,
+                            }).into(randomization_arms::table)
+                    .execute(&**conn)?;
+        }
+
+        for &group_id in &spec.strata {
+            diesel::insert(&RandomizationStratum {
+                                randomization_id: randomization.id,
+                                group_id: group_id,
+                            }).into(randomization_strata::table)
+                    .execute(&**conn)?;
+        }
+
+        Ok(randomization)
+    })
+}
+
+pub fn get_all(conn: &Connection)
+               -> Result<Vec<(Randomization, Vec<RandomizationArm>, Vec<i32>)>> {
+    use schema::randomizations;
+
+    let randomizations: Vec<Randomization> =
+        randomizations::table.order(randomizations::id.asc()).get_results(&**conn)?;
+
+    let mut result = Vec::with_capacity(randomizations.len());
+    for r in randomizations {
+        let arms = get_arms(conn, r.id)?;
+        let strata = get_strata(conn, r.id)?;
+        result.push((r, arms, strata));
+    }
+    Ok(result)
+}
+
+pub fn get_by_event(conn: &Connection, event_id: i32) -> Result<Option<Randomization>> {
+    use schema::randomizations;
+
+    let randomization = randomizations::table.filter(randomizations::event_id.eq(event_id))
+        .get_result(&**conn)
+        .optional()?;
+    Ok(randomization)
+}
+
+fn get_arms(conn: &Connection, randomization_id: i32) -> Result<Vec<RandomizationArm>> {
+    use schema::randomization_arms;
+
+    let arms = randomization_arms::table
+        .filter(randomization_arms::randomization_id.eq(randomization_id))
+        .order(randomization_arms::id.asc())
+        .get_results(&**conn)?;
+    Ok(arms)
+}
+
+fn get_strata(conn: &Connection, randomization_id: i32) -> Result<Vec<i32>> {
+    use schema::randomization_strata;
+
+    let strata = randomization_strata::table
+        .filter(randomization_strata::randomization_id.eq(randomization_id))
+        .select(randomization_strata::group_id)
+        .order(randomization_strata::group_id.asc())
+        .get_results(&**conn)?;
+    Ok(strata)
+}
+
+/// Assigns the user to an arm: the user is removed from the other arms and joined to the next
+/// arm of the current block of their stratum. The assignment is logged.
+/// If the user is already assigned, returns the earlier assignment.
+pub fn assign(conn: &Connection,
+              randomization: &Randomization,
+              user_id: i32)
+              -> Result<RandomizationLog> {
+    use schema::{randomization_log, group_memberships};
+    use diesel::expression::dsl::any;
+
+    conn.transaction(|| -> Result<RandomizationLog> {
+
+        // The position in the block depends on the earlier assignments,
+        // so concurrent assignments must wait for their turn.
+        (**conn).execute("LOCK TABLE randomization_log IN SHARE ROW EXCLUSIVE MODE")?;
+
+        let earlier: Option<RandomizationLog> =
+            randomization_log::table
+                .filter(randomization_log::randomization_id.eq(randomization.id))
+                .filter(randomization_log::user_id.eq(user_id))
+                .get_result(&**conn)
+                .optional()?;
+
+        if let Some(log) = earlier {
+            return Ok(log);
+        }
+
+        let arms = get_arms(conn, randomization.id)?;
+        if arms.is_empty() {
+            bail!(ErrorKind::DatabaseOdd("Randomization has no arms!"));
+        }
+        let strata = get_strata(conn, randomization.id)?;
+
+        let stratum: Vec<i32> = group_memberships::table
+            .filter(group_memberships::user_id.eq(user_id))
+            .filter(group_memberships::group_id.eq(any(strata)))
+            .select(group_memberships::group_id)
+            .order(group_memberships::group_id.asc())
+            .get_results(&**conn)?;
+        let key = stratum_key(&stratum);
+
+        let assigned: i64 = randomization_log::table
+            .filter(randomization_log::randomization_id.eq(randomization.id))
+            .filter(randomization_log::stratum.eq(&key))
+            .count()
+            .get_result(&**conn)?;
+
+        let block_number = (assigned / randomization.block_size as i64) as i32;
+        let block_position = (assigned % randomization.block_size as i64) as i32;
+
+        let arm_group_id = block(randomization.seed,
+                                 &stratum,
+                                 block_number,
+                                 &arms,
+                                 randomization.block_size)[block_position as usize];
+
+        for arm in &arms {
+            user::remove_user_group_by_id(conn, None, user_id, arm.group_id)?;
+        }
+
+        diesel::insert(&GroupMembership {
+                            user_id: user_id,
+                            group_id: arm_group_id,
+                            anonymous: randomization.anonymous,
+                        }).into(group_memberships::table)
+                .execute(&**conn)?;
+
+        let log = diesel::insert(&NewRandomizationLog {
+                                      randomization_id: randomization.id,
+                                      user_id: Some(user_id),
+                                      stratum: &key,
+                                      block_number: block_number,
+                                      block_position: block_position,
+                                      arm_group_id: arm_group_id,
+                                  }).into(randomization_log::table)
+                .get_result(&**conn)?;
+
+        info!("Randomized user {} to group {} (stratum: {:?}, block: {}, position: {})",
+              user_id,
+              arm_group_id,
+              key,
+              block_number,
+              block_position);
+
+        Ok(log)
+    })
+}
+
+/// The assignment log as CSV, with the groups referred to by name.
+pub fn export_log(conn: &Connection, randomization_id: i32) -> Result<Option<String>> {
+    use schema::{randomizations, randomization_log};
+
+    let randomization: Randomization =
+        try_or!(randomizations::table.filter(randomizations::id.eq(randomization_id))
+                    .get_result(&**conn)
+                    .optional()?,
+                else return Ok(None));
+
+    let logs: Vec<RandomizationLog> =
+        randomization_log::table
+            .filter(randomization_log::randomization_id.eq(randomization.id))
+            .order(randomization_log::id.asc())
+            .get_results(&**conn)?;
+
+    let group_names: HashMap<i32, String> = user::all_groups(conn)?
+        .into_iter()
+        .map(|g| (g.id, g.group_name))
+        .collect();
+    let group_name = |id: i32| group_names.get(&id).cloned().unwrap_or_else(|| id.to_string());
+
+    let mut csv = String::from("randomization_id,seed,block_size,user_id,stratum,block_number,\
+                                block_position,arm,assigned\n");
+    for log in logs {
+        let stratum = log.stratum
+            .split(',')
+            .filter(|g| !g.is_empty())
+            .map(|g| g.parse().map(&group_name).unwrap_or_else(|_| g.to_string()))
+            .collect::<Vec<_>>()
+            .join("+");
+        csv.push_str(&format!("{},{},{},{},{},{},{},{},{}\n",
+                              randomization.id,
+                              randomization.seed,
+                              randomization.block_size,
+                              log.user_id.map(|id| id.to_string()).unwrap_or_default(),
+                              stratum,
+                              log.block_number,
+                              log.block_position,
+                              group_name(log.arm_group_id),
+                              log.assigned.to_rfc3339()));
+    }
+    Ok(Some(csv))
+}
+
+#[test]
+fn test_block() {
+    let arms = [RandomizationArm {
+                    id: 1,
+                    randomization_id: 1,
+                    group_id: 10,
+                    ratio: 1,
+                },
+                RandomizationArm {
+                    id: 2,
+                    randomization_id: 1,
+                    group_id: 20,
+                    ratio: 2,
+                }];
+
+    let a = block(1234, &[3, 4], 0, &arms, 6);
+    assert_eq!(a.len(), 6);
+    assert_eq!(a.iter().filter(|&&g| g == 10).count(), 2);
+    assert_eq!(a.iter().filter(|&&g| g == 20).count(), 4);
+
+    // The same seed, stratum and block number give the same order.
+    assert_eq!(a, block(1234, &[3, 4], 0, &arms, 6));
+}
@@ -0,0 +1,348 @@
+use super::*;
+use std::collections::{HashMap, HashSet};
+use chrono::{DateTime, UTC};
+
+/// The tables of the research export. Each table is a CSV file with one row per observation;
+/// the participants are referred to by their aliases, never by their ids or email addresses.
+pub const TABLES: &'static [&'static str] =
+    &["participants", "answers", "events", "test_answers", "recordings"];
+
+/// The research aliases of the users, stored in `anon_aliases` without a group.
+/// A user gets an alias the first time they appear in an export, and keeps it.
+/// If the export is limited to the consenting users, the others are left out.
+struct Aliases {
+    aliases: HashMap<i32, String>,
+    included: Option<HashSet<i32>>,
+}
+
+impl Aliases {
+    fn load(conn: &Connection, consenting_only: bool) -> Result<Aliases> {
+        Ok(Aliases {
+               aliases: pseudonym::aliases(conn, None)?,
+               included: if consenting_only {
+                   Some(consent::consenting_users(conn)?)
+               } else {
+                   None
+               },
+           })
+    }
+
+    fn includes(&self, user_id: i32) -> bool {
+        match self.included {
+            Some(ref included) => included.contains(&user_id),
+            None => true,
+        }
+    }
+
+    fn get(&mut self, conn: &Connection, user_id: i32) -> Result<String> {
+        if let Some(alias) = self.aliases.get(&user_id) {
+            return Ok(alias.clone());
+        }
+        let alias = pseudonym::get_create_alias(conn, user_id, None)?;
+        self.aliases.insert(user_id, alias.name.clone());
+        Ok(alias.name)
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn push_row(csv: &mut String, fields: &[String]) {
+    let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+    csv.push_str(&fields.join(","));
+    csv.push('\n');
+}
+
+fn opt<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn date(date: &DateTime<UTC>) -> String {
+    date.to_rfc3339()
+}
+
+fn participants(conn: &Connection, aliases: &mut Aliases) -> Result<String> {
+    use schema::{users, group_memberships};
+
+    let group_names: HashMap<i32, String> = user::all_groups(conn)?
+        .into_iter()
+        .map(|g| (g.id, g.group_name))
+        .collect();
+    let memberships: Vec<GroupMembership> = group_memberships::table.get_results(&**conn)?;
+    let users: Vec<User> = users::table.order(users::id).get_results(&**conn)?;
+
+    let mut csv = String::from("participant,joined,last_seen,groups\n");
+    for u in users {
+        if !aliases.includes(u.id) {
+            continue;
+        }
+        let groups = memberships.iter()
+            .filter(|m| m.user_id == u.id)
+            .filter_map(|m| group_names.get(&m.group_id).cloned())
+            .collect::<Vec<_>>()
+            .join(";");
+        push_row(&mut csv,
+                 &[aliases.get(conn, u.id)?, date(&u.joined), date(&u.last_seen), groups]);
+    }
+    Ok(csv)
+}
+
+/// One row per asked item, answered or not.
+fn answers(conn: &Connection, aliases: &mut Aliases) -> Result<String> {
+    use schema::{pending_items, q_asked_data, q_answered_data, e_asked_data, e_answered_data,
+                 w_asked_data, w_answered_data};
+
+    fn by_id<T, F: Fn(&T) -> i32>(rows: Vec<T>, id: F) -> HashMap<i32, T> {
+        rows.into_iter().map(|r| (id(&r), r)).collect()
+    }
+
+    let items: Vec<PendingItem> = pending_items::table.order(pending_items::id)
+        .get_results(&**conn)?;
+    let q_asked = by_id(q_asked_data::table.get_results::<QAskedData>(&**conn)?, |r| r.id);
+    let q_answered = by_id(q_answered_data::table.get_results::<QAnsweredData>(&**conn)?,
+                           |r| r.id);
+    let e_asked = by_id(e_asked_data::table.get_results::<EAskedData>(&**conn)?, |r| r.id);
+    let e_answered = by_id(e_answered_data::table.get_results::<EAnsweredData>(&**conn)?,
+                           |r| r.id);
+    let w_asked = by_id(w_asked_data::table.get_results::<WAskedData>(&**conn)?, |r| r.id);
+    let w_answered = by_id(w_answered_data::table.get_results::<WAnsweredData>(&**conn)?,
+                           |r| r.id);
+
+    let mut csv = String::from("participant,item_id,item_type,test_item,question_id,\
+                                exercise_id,word_id,audio_file_id,asked,answered,correct,\
+                                answer_level,active_answer_time_ms,full_answer_time_ms,\
+                                full_spent_time_ms,audio_times,reflected_time_ms\n");
+
+    for i in items {
+        if !aliases.includes(i.user_id) {
+            continue;
+        }
+        let participant = aliases.get(conn, i.user_id)?;
+        let mut row = vec![participant,
+                           i.id.to_string(),
+                           i.item_type.clone(),
+                           i.test_item.to_string()];
+
+        if let Some(asked) = q_asked.get(&i.id) {
+            let a = q_answered.get(&i.id);
+            row.extend(vec![asked.question_id.to_string(),
+                            String::new(),
+                            String::new(),
+                            i.audio_file_id.to_string(),
+                            date(&i.asked_date),
+                            opt(a.map(|a| date(&a.answered_date))),
+                            opt(a.map(|a| a.answered_qa_id == Some(asked.correct_qa_id))),
+                            String::new(),
+                            opt(a.map(|a| a.active_answer_time_ms)),
+                            opt(a.map(|a| a.full_answer_time_ms)),
+                            opt(a.map(|a| a.full_spent_time_ms)),
+                            String::new(),
+                            String::new()]);
+        } else if let Some(asked) = e_asked.get(&i.id) {
+            let a = e_answered.get(&i.id);
+            row.extend(vec![String::new(),
+                            asked.exercise_id.to_string(),
+                            asked.word_id.to_string(),
+                            i.audio_file_id.to_string(),
+                            date(&i.asked_date),
+                            opt(a.map(|a| date(&a.answered_date))),
+                            opt(a.map(|a| a.answer_level > 0)),
+                            opt(a.map(|a| a.answer_level)),
+                            opt(a.map(|a| a.active_answer_time_ms)),
+                            opt(a.map(|a| a.full_answer_time_ms)),
+                            opt(a.map(|a| a.full_spent_time_ms)),
+                            opt(a.map(|a| a.audio_times)),
+                            opt(a.map(|a| a.reflected_time_ms))]);
+        } else if let Some(asked) = w_asked.get(&i.id) {
+            let a = w_answered.get(&i.id);
+            row.extend(vec![String::new(),
+                            String::new(),
+                            asked.word_id.to_string(),
+                            i.audio_file_id.to_string(),
+                            date(&i.asked_date),
+                            opt(a.map(|a| date(&a.checked_date))),
+                            String::new(),
+                            String::new(),
+                            opt(a.map(|a| a.active_answer_time_ms)),
+                            String::new(),
+                            opt(a.map(|a| a.full_spent_time_ms)),
+                            opt(a.map(|a| a.audio_times)),
+                            String::new()]);
+        } else {
+            continue;
+        }
+        push_row(&mut csv, &row);
+    }
+    Ok(csv)
+}
+
+fn event_names(conn: &Connection) -> Result<HashMap<i32, String>> {
+    use schema::events;
+
+    let events: Vec<Event> = events::table.get_results(&**conn)?;
+    Ok(events.into_iter().map(|e| (e.id, e.name)).collect())
+}
+
+fn events(conn: &Connection, aliases: &mut Aliases) -> Result<String> {
+    use schema::event_experiences;
+
+    let names = event_names(conn)?;
+    let experiences: Vec<EventExperience> =
+        event_experiences::table.order((event_experiences::user_id,
+                                        event_experiences::event_init))
+            .get_results(&**conn)?;
+
+    let mut csv = String::from("participant,event,started,finished,duration_s\n");
+    for e in experiences {
+        if !aliases.includes(e.user_id) {
+            continue;
+        }
+        let duration = e.event_finish
+            .map(|f| f.signed_duration_since(e.event_init).num_seconds());
+        push_row(&mut csv,
+                 &[aliases.get(conn, e.user_id)?,
+                   opt(names.get(&e.event_id)),
+                   date(&e.event_init),
+                   opt(e.event_finish.as_ref().map(date)),
+                   opt(duration)]);
+    }
+    Ok(csv)
+}
+
+/// Parses the numbers out of a userdata key like `quiz_3_rec_2`.
+fn key_numbers(key: &str, parts: &[&str]) -> Option<Vec<u32>> {
+    let mut split = key.split('_');
+    let mut numbers = vec![];
+    for &part in parts {
+        if split.next() != Some(part) {
+            return None;
+        }
+        match split.next().and_then(|n| n.parse().ok()) {
+            Some(number) => numbers.push(number),
+            None => return None,
+        }
+    }
+    if split.next().is_some() {
+        return None;
+    }
+    Some(numbers)
+}
+
+fn userdata(conn: &Connection, pattern: &str) -> Result<Vec<EventUserdata>> {
+    use schema::event_userdata;
+
+    Ok(event_userdata::table.filter(event_userdata::key.like(pattern))
+           .order((event_userdata::user_id, event_userdata::id))
+           .get_results(&**conn)?)
+}
+
+/// The answers to the test items, saved in the event userdata under `answer_N`.
+fn test_answers(conn: &Connection, aliases: &mut Aliases) -> Result<String> {
+    let names = event_names(conn)?;
+
+    let mut csv = String::from("participant,event,quiz_number,saved,answer_type,item_id,\
+                                answered_qa_id,answer_level,active_answer_time_ms,\
+                                full_answer_time_ms,full_spent_time_ms\n");
+    for d in userdata(conn, "answer_%")? {
+        if !aliases.includes(d.user_id) {
+            continue;
+        }
+        let number = match d.key.as_ref().and_then(|k| key_numbers(k, &["answer"])) {
+            Some(numbers) => numbers[0],
+            None => continue,
+        };
+        let answer: serde_json::Value = serde_json::from_str(&d.data)
+            .chain_err(|| format!("Couldn't parse the test answer {}!", d.id))?;
+        let (answer_type, fields) = match answer.as_object().and_then(|o| o.iter().next()) {
+            Some((answer_type, fields)) => (answer_type.clone(), fields.clone()),
+            None => continue,
+        };
+        let field = |name: &str| match fields.get(name) {
+            Some(&serde_json::Value::Null) | None => String::new(),
+            Some(value) => value.to_string(),
+        };
+        push_row(&mut csv,
+                 &[aliases.get(conn, d.user_id)?,
+                   opt(names.get(&d.event_id)),
+                   number.to_string(),
+                   date(&d.created),
+                   answer_type,
+                   field("id"),
+                   field("answered_qa_id"),
+                   field("answer_level"),
+                   field("active_answer_time_ms"),
+                   field("full_answer_time_ms"),
+                   field("full_spent_time_ms")]);
+    }
+    Ok(csv)
+}
+
+/// The audio recordings made during the tests, saved in the event userdata under
+/// `quiz_N_rec_M`. The files are in the user audio directory.
+fn recordings(conn: &Connection, aliases: &mut Aliases) -> Result<String> {
+    let names = event_names(conn)?;
+
+    let mut csv = String::from("participant,event,quiz_number,rec_number,saved,file\n");
+    for d in userdata(conn, "quiz_%_rec_%")? {
+        if !aliases.includes(d.user_id) {
+            continue;
+        }
+        let numbers = match d.key.as_ref().and_then(|k| key_numbers(k, &["quiz", "rec"])) {
+            Some(numbers) => numbers,
+            None => continue,
+        };
+        push_row(&mut csv,
+                 &[aliases.get(conn, d.user_id)?,
+                   opt(names.get(&d.event_id)),
+                   numbers[0].to_string(),
+                   numbers[1].to_string(),
+                   date(&d.created),
+                   d.data]);
+    }
+    Ok(csv)
+}
+
+/// Exports a table of `TABLES` as CSV. Returns `None` if there's no such table.
+/// With `consenting_only`, the users whose latest decision on the consent document
+/// isn't agreeing are left out.
+pub fn export_table(conn: &Connection,
+                    table: &str,
+                    consenting_only: bool)
+                    -> Result<Option<String>> {
+    let mut aliases = Aliases::load(conn, consenting_only)?;
+    let csv = match table {
+        "participants" => participants(conn, &mut aliases)?,
+        "answers" => answers(conn, &mut aliases)?,
+        "events" => events(conn, &mut aliases)?,
+        "test_answers" => test_answers(conn, &mut aliases)?,
+        "recordings" => recordings(conn, &mut aliases)?,
+        _ => return Ok(None),
+    };
+    Ok(Some(csv))
+}
+
+/// Whether the file in the user audio directory is a recording made during a test.
+pub fn is_recording(conn: &Connection, file_name: &str) -> Result<bool> {
+    Ok(userdata(conn, "quiz_%_rec_%")?.iter().any(|d| d.data == file_name))
+}
+
+#[test]
+fn test_key_numbers() {
+    assert_eq!(key_numbers("quiz_3_rec_12", &["quiz", "rec"]), Some(vec![3, 12]));
+    assert_eq!(key_numbers("answer_0", &["answer"]), Some(vec![0]));
+    assert_eq!(key_numbers("answer_x", &["answer"]), None);
+    assert_eq!(key_numbers("quiz_number", &["quiz", "rec"]), None);
+    assert_eq!(key_numbers("quiz_3_rec_12_x", &["quiz", "rec"]), None);
+}
+
+#[test]
+fn test_csv_field() {
+    assert_eq!(csv_field("plain"), "plain");
+    assert_eq!(csv_field("a,b"), "\"a,b\"");
+    assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+}
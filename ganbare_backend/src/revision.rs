@@ -0,0 +1,307 @@
+use super::*;
+
+// The revisions of the texts of the content: the explanations of the words, the question
+// texts and the answer texts. The first revision of an item is its text before the first
+// recorded edit, with the source `ORIGINAL`.
+
+/// The explanation of a word.
+pub const WORD: &'static str = "word";
+/// The question text of a question.
+pub const QUESTION: &'static str = "question";
+/// The answer text of an answer.
+pub const ANSWER: &'static str = "answer";
+
+pub const ORIGINAL: &'static str = "original";
+/// Edited in the manager.
+pub const UI: &'static str = "ui";
+/// Rewritten by the `clean` tool.
+pub const CLEANUP: &'static str = "cleanup";
+/// Overwritten by a content archive or a manifest import.
+pub const IMPORT: &'static str = "import";
+pub const REVERT: &'static str = "revert";
+
+/// Records the change of the text of an item as a new revision. Nothing is recorded if the
+/// text didn't change.
+pub fn record(conn: &Connection,
+              item_type: &str,
+              item_id: i32,
+              before: &str,
+              after: &str,
+              author: Option<i32>,
+              source: &str)
+              -> Result<Option<ContentRevision>> {
+    use schema::content_revisions;
+
+    if before == after {
+        return Ok(None);
+    }
+
+    let count: i64 = content_revisions::table.filter(content_revisions::item_type.eq(item_type))
+        .filter(content_revisions::item_id.eq(item_id))
+        .count()
+        .get_result(&**conn)?;
+
+    if count == 0 {
+        diesel::insert(&NewContentRevision {
+                            item_type: item_type,
+                            item_id: item_id,
+                            content: before,
+                            author: None,
+                            source: ORIGINAL,
+                        }).into(content_revisions::table)
+                .execute(&**conn)?;
+    }
+
+    let revision = diesel::insert(&NewContentRevision {
+                                      item_type: item_type,
+                                      item_id: item_id,
+                                      content: after,
+                                      author: author,
+                                      source: source,
+                                  }).into(content_revisions::table)
+            .get_result(&**conn)?;
+
+    Ok(Some(revision))
+}
+
+/// The revisions of the item, newest first.
+pub fn get_revisions(conn: &Connection,
+                     item_type: &str,
+                     item_id: i32)
+                     -> Result<Vec<ContentRevision>> {
+    use schema::content_revisions;
+
+    Ok(content_revisions::table.filter(content_revisions::item_type.eq(item_type))
+           .filter(content_revisions::item_id.eq(item_id))
+           .order(content_revisions::id.desc())
+           .get_results(&**conn)?)
+}
+
+pub fn get_revision(conn: &Connection, id: i32) -> Result<Option<ContentRevision>> {
+    use schema::content_revisions;
+
+    Ok(content_revisions::table.filter(content_revisions::id.eq(id))
+           .get_result(&**conn)
+           .optional()?)
+}
+
+/// The revision of the same item before this one.
+pub fn get_previous(conn: &Connection,
+                    revision: &ContentRevision)
+                    -> Result<Option<ContentRevision>> {
+    use schema::content_revisions;
+
+    Ok(content_revisions::table.filter(content_revisions::item_type.eq(&revision.item_type))
+           .filter(content_revisions::item_id.eq(revision.item_id))
+           .filter(content_revisions::id.lt(revision.id))
+           .order(content_revisions::id.desc())
+           .first(&**conn)
+           .optional()?)
+}
+
+fn get_content(conn: &Connection, item_type: &str, item_id: i32) -> Result<Option<String>> {
+    use schema::{words, quiz_questions, question_answers};
+
+    Ok(match item_type {
+           WORD => {
+               words::table.filter(words::id.eq(item_id))
+                   .select(words::explanation)
+                   .get_result(&**conn)
+                   .optional()?
+           }
+           QUESTION => {
+               quiz_questions::table.filter(quiz_questions::id.eq(item_id))
+                   .select(quiz_questions::question_text)
+                   .get_result(&**conn)
+                   .optional()?
+           }
+           ANSWER => {
+               question_answers::table.filter(question_answers::id.eq(item_id))
+                   .select(question_answers::answer_text)
+                   .get_result(&**conn)
+                   .optional()?
+           }
+           _ => bail!(ErrorKind::InvalidInput),
+       })
+}
+
+fn set_content(conn: &Connection, item_type: &str, item_id: i32, content: &str) -> Result<()> {
+    use schema::{words, quiz_questions, question_answers};
+
+    match item_type {
+        WORD => {
+            diesel::update(words::table.filter(words::id.eq(item_id)))
+                .set(words::explanation.eq(content))
+                .execute(&**conn)?
+        }
+        QUESTION => {
+            diesel::update(quiz_questions::table.filter(quiz_questions::id.eq(item_id)))
+                .set(quiz_questions::question_text.eq(content))
+                .execute(&**conn)?
+        }
+        ANSWER => {
+            diesel::update(question_answers::table.filter(question_answers::id.eq(item_id)))
+                .set(question_answers::answer_text.eq(content))
+                .execute(&**conn)?
+        }
+        _ => bail!(ErrorKind::InvalidInput),
+    };
+    Ok(())
+}
+
+/// Sets the text of the item back to the revision. The revert is recorded as a new revision.
+/// Returns `None` if the revision or the item doesn't exist.
+pub fn revert(conn: &Connection, actor: i32, id: i32) -> Result<Option<ContentRevision>> {
+
+    conn.transaction(|| {
+        let revision = try_or!(get_revision(conn, id)?, else return Ok(None));
+        let before = try_or!(get_content(conn, &revision.item_type, revision.item_id)?,
+                             else return Ok(None));
+
+        set_content(conn, &revision.item_type, revision.item_id, &revision.content)?;
+        audit::record(conn,
+                      actor,
+                      "revert",
+                      &revision.item_type,
+                      Some(revision.item_id),
+                      Some(&before),
+                      Some(&revision.content))?;
+        record(conn,
+               &revision.item_type,
+               revision.item_id,
+               &before,
+               &revision.content,
+               Some(actor),
+               REVERT)?;
+
+        Ok(Some(revision))
+    })
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub enum Change {
+    #[serde(rename = "same")]
+    Same,
+    #[serde(rename = "removed")]
+    Removed,
+    #[serde(rename = "added")]
+    Added,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DiffChunk {
+    pub change: Change,
+    pub text: String,
+}
+
+fn is_ascii_alphanumeric(c: char) -> bool {
+    c < '\u{80}' && c.is_alphanumeric()
+}
+
+/// Splits the text into HTML tags, runs of whitespace, runs of ASCII letters and digits,
+/// and single other characters, so that Japanese is diffed by the character.
+fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let len = if c == '<' {
+            rest.find('>').map(|i| i + 1).unwrap_or_else(|| rest.len())
+        } else if c.is_whitespace() {
+            rest.find(|c: char| !c.is_whitespace()).unwrap_or_else(|| rest.len())
+        } else if is_ascii_alphanumeric(c) {
+            rest.find(|c: char| !is_ascii_alphanumeric(c)).unwrap_or_else(|| rest.len())
+        } else {
+            c.len_utf8()
+        };
+        let (token, tail) = rest.split_at(len);
+        tokens.push(token);
+        rest = tail;
+    }
+    tokens
+}
+
+/// Above this, the texts are shown as removed and added as a whole.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// The difference between the texts, as runs of unchanged, removed and added text.
+pub fn diff(old: &str, new: &str) -> Vec<DiffChunk> {
+    let a = tokenize(old);
+    let b = tokenize(new);
+
+    let mut chunks: Vec<DiffChunk> = vec![];
+    {
+        let mut push = |change: Change, text: &str| {
+            if let Some(last) = chunks.last_mut() {
+                if last.change == change {
+                    last.text.push_str(text);
+                    return;
+                }
+            }
+            chunks.push(DiffChunk {
+                            change: change,
+                            text: text.to_string(),
+                        });
+        };
+
+        if (a.len() + 1) * (b.len() + 1) > MAX_DIFF_CELLS {
+            push(Change::Removed, old);
+            push(Change::Added, new);
+        } else {
+            // The lengths of the longest common subsequences of the suffixes.
+            let width = b.len() + 1;
+            let mut lcs = vec![0u32; (a.len() + 1) * width];
+            for i in (0..a.len()).rev() {
+                for j in (0..b.len()).rev() {
+                    lcs[i * width + j] = if a[i] == b[j] {
+                        lcs[(i + 1) * width + j + 1] + 1
+                    } else {
+                        ::std::cmp::max(lcs[(i + 1) * width + j], lcs[i * width + j + 1])
+                    };
+                }
+            }
+
+            let (mut i, mut j) = (0, 0);
+            while i < a.len() && j < b.len() {
+                if a[i] == b[j] {
+                    push(Change::Same, a[i]);
+                    i += 1;
+                    j += 1;
+                } else if lcs[(i + 1) * width + j] >= lcs[i * width + j + 1] {
+                    push(Change::Removed, a[i]);
+                    i += 1;
+                } else {
+                    push(Change::Added, b[j]);
+                    j += 1;
+                }
+            }
+            for token in &a[i..] {
+                push(Change::Removed, token);
+            }
+            for token in &b[j..] {
+                push(Change::Added, token);
+            }
+        }
+    }
+
+    chunks.retain(|c| !c.text.is_empty());
+    chunks
+}
+
+#[test]
+fn test_diff() {
+    let chunks = diff("<b>食べる</b> to eat", "<b>食べた</b> ate");
+    assert_eq!(chunks,
+               vec![DiffChunk { change: Change::Same, text: "<b>食べ".to_string() },
+                    DiffChunk { change: Change::Removed, text: "る".to_string() },
+                    DiffChunk { change: Change::Added, text: "た".to_string() },
+                    DiffChunk { change: Change::Same, text: "</b> ".to_string() },
+                    DiffChunk { change: Change::Removed, text: "to eat".to_string() },
+                    DiffChunk { change: Change::Added, text: "ate".to_string() }]);
+}
+
+#[test]
+fn test_diff_unchanged() {
+    assert_eq!(diff("", ""), vec![]);
+    assert_eq!(diff("same<br>text", "same<br>text"),
+               vec![DiffChunk { change: Change::Same, text: "same<br>text".to_string() }]);
+}
@@ -0,0 +1,282 @@
+use super::*;
+use std::collections::HashSet;
+
+// The permissions are fixed, and checked by the handlers. Which roles have which
+// permissions, and who has which roles, is stored in the database.
+
+/// Adding, editing, publishing and removing words, quizes, skills, audio and narrators.
+pub const EDIT_CONTENT: &'static str = "edit_content";
+/// Editing events, their transitions and the tests.
+pub const MANAGE_EVENTS: &'static str = "manage_events";
+/// Setting up randomizations and publishing consent documents.
+pub const MANAGE_EXPERIMENTS: &'static str = "manage_experiments";
+/// Seeing the users, their groups and their progress.
+pub const VIEW_USERS: &'static str = "view_users";
+/// Adding and removing users, and changing their groups, roles and settings.
+pub const MANAGE_USERS: &'static str = "manage_users";
+pub const SEND_MAIL: &'static str = "send_mail";
+pub const EXPORT_RESEARCH: &'static str = "export_research";
+/// Seeing the anonymous group memberships by aliases.
+pub const VIEW_ANONYMOUS: &'static str = "view_anonymous";
+pub const VIEW_AUDIT_LOG: &'static str = "view_audit_log";
+
+pub const PERMISSIONS: [&'static str; 9] = [EDIT_CONTENT,
+                                            MANAGE_EVENTS,
+                                            MANAGE_EXPERIMENTS,
+                                            VIEW_USERS,
+                                            MANAGE_USERS,
+                                            SEND_MAIL,
+                                            EXPORT_RESEARCH,
+                                            VIEW_ANONYMOUS,
+                                            VIEW_AUDIT_LOG];
+
+/// The role that is given to the first user at install.
+pub const ADMIN: &'static str = "admin";
+
+pub fn check_permission(permission: &str) -> Result<()> {
+    if PERMISSIONS.contains(&permission) {
+        Ok(())
+    } else {
+        bail!(ErrorKind::InvalidInput)
+    }
+}
+
+fn role_ids(conn: &Connection, user_id: i32) -> Result<Vec<i32>> {
+    use schema::user_roles;
+
+    Ok(user_roles::table.filter(user_roles::user_id.eq(user_id))
+           .select(user_roles::role_id)
+           .get_results(&**conn)?)
+}
+
+/// All the permissions the user has through their roles.
+pub fn get_permissions(conn: &Connection, user_id: i32) -> Result<HashSet<String>> {
+    use schema::role_permissions;
+    use diesel::expression::dsl::any;
+
+    let role_ids = role_ids(conn, user_id)?;
+    let permissions: Vec<String> =
+        role_permissions::table.filter(role_permissions::role_id.eq(any(role_ids)))
+            .select(role_permissions::permission)
+            .get_results(&**conn)?;
+
+    Ok(permissions.into_iter().collect())
+}
+
+fn roles_have_permission(conn: &Connection, role_ids: Vec<i32>, permission: &str) -> Result<bool> {
+    use schema::role_permissions;
+    use diesel::expression::dsl::any;
+
+    check_permission(permission)?;
+
+    let count: i64 = role_permissions::table.filter(role_permissions::role_id.eq(any(role_ids)))
+        .filter(role_permissions::permission.eq(permission))
+        .count()
+        .get_result(&**conn)?;
+
+    Ok(count > 0)
+}
+
+pub fn has_permission(conn: &Connection, user_id: i32, permission: &str) -> Result<bool> {
+    let role_ids = role_ids(conn, user_id)?;
+    roles_have_permission(conn, role_ids, permission)
+}
+
+/// Like `has_permission`, but only the roles that the API token is scoped to count.
+/// A role that has been taken from the user doesn't count even if the token has it.
+pub fn token_has_permission(conn: &Connection,
+                            user_id: i32,
+                            token_id: i32,
+                            permission: &str)
+                            -> Result<bool> {
+    use schema::api_token_roles;
+    use diesel::expression::dsl::any;
+
+    let role_ids = role_ids(conn, user_id)?;
+    let token_role_ids = api_token_roles::table.filter(api_token_roles::token_id.eq(token_id))
+        .filter(api_token_roles::role_id.eq(any(role_ids)))
+        .select(api_token_roles::role_id)
+        .get_results(&**conn)?;
+    roles_have_permission(conn, token_role_ids, permission)
+}
+
+/// The names of the roles the user has.
+pub fn get_role_names(conn: &Connection, user_id: i32) -> Result<Vec<String>> {
+    use schema::{roles, user_roles};
+
+    Ok(user_roles::table.inner_join(roles::table)
+           .filter(user_roles::user_id.eq(user_id))
+           .select(roles::role_name)
+           .order(roles::role_name)
+           .get_results(&**conn)?)
+}
+
+/// The roles with their permissions, and the users that have them.
+pub fn get_all(conn: &Connection) -> Result<(Vec<(Role, Vec<String>)>, Vec<UserRole>)> {
+    use schema::{roles, role_permissions, user_roles};
+
+    let roles: Vec<Role> = roles::table.order(roles::id).get_results(&**conn)?;
+    let permissions: Vec<Vec<RolePermission>> =
+        role_permissions::table.get_results(&**conn)?.grouped_by(&roles);
+    let user_roles: Vec<UserRole> = user_roles::table.order((user_roles::user_id,
+                                                            user_roles::role_id))
+        .get_results(&**conn)?;
+
+    let roles = roles.into_iter()
+        .zip(permissions.into_iter())
+        .map(|(r, p)| (r, p.into_iter().map(|p| p.permission).collect()))
+        .collect();
+
+    Ok((roles, user_roles))
+}
+
+pub fn get_by_name(conn: &Connection, role_name: &str) -> Result<Option<Role>> {
+    use schema::roles;
+
+    Ok(roles::table.filter(roles::role_name.eq(role_name))
+           .get_result(&**conn)
+           .optional()?)
+}
+
+/// Creates a role, or replaces the permissions of an existing role with the same name.
+pub fn set_role(conn: &Connection,
+                actor: i32,
+                role_name: &str,
+                permissions: &[String])
+                -> Result<Role> {
+    use schema::{roles, role_permissions};
+
+    for p in permissions {
+        check_permission(p)?;
+    }
+
+    conn.transaction(|| {
+        let before = get_by_name(conn, role_name)?;
+        let before_permissions: Option<Vec<String>> = match before {
+            Some(ref role) => {
+                Some(role_permissions::table.filter(role_permissions::role_id.eq(role.id))
+                         .select(role_permissions::permission)
+                         .get_results(&**conn)?)
+            }
+            None => None,
+        };
+        let role = match before {
+            Some(role) => role,
+            None => {
+                diesel::insert(&NewRole { role_name: role_name }).into(roles::table)
+                    .get_result(&**conn)?
+            }
+        };
+
+        diesel::delete(role_permissions::table.filter(role_permissions::role_id.eq(role.id)))
+            .execute(&**conn)?;
+        for p in permissions {
+            diesel::insert(&RolePermission {
+                                role_id: role.id,
+                                permission: p.clone(),
+                            }).into(role_permissions::table)
+                    .execute(&**conn)?;
+        }
+        info!("Set the permissions of the role {}: {:?}", role_name, permissions);
+        audit::record(conn,
+                      actor,
+                      "set_permissions",
+                      "role",
+                      Some(role.id),
+                      before_permissions.as_ref().map(|p| &**p),
+                      Some(permissions))?;
+
+        Ok(role)
+    })
+}
+
+pub fn remove_role(conn: &Connection, actor: i32, role_id: i32) -> Result<Option<Role>> {
+    use schema::roles;
+
+    conn.transaction(|| -> Result<Option<Role>> {
+
+        let role: Option<Role> = diesel::delete(roles::table.filter(roles::id.eq(role_id)))
+            .get_result(&**conn)
+            .optional()?;
+        if let Some(ref role) = role {
+            audit::record(conn, actor, "delete", "role", Some(role_id), Some(role), None)?;
+        }
+        Ok(role)
+    })
+}
+
+/// The actor is `None` when the role is given at install or from the command line.
+pub fn assign(conn: &Connection, actor: Option<i32>, user_id: i32, role_id: i32) -> Result<()> {
+    use schema::user_roles;
+
+    conn.transaction(|| -> Result<()> {
+
+        let exists: Option<UserRole> = user_roles::table.filter(user_roles::user_id.eq(user_id))
+            .filter(user_roles::role_id.eq(role_id))
+            .get_result(&**conn)
+            .optional()?;
+
+        if exists.is_none() {
+            diesel::insert(&UserRole {
+                                user_id: user_id,
+                                role_id: role_id,
+                            }).into(user_roles::table)
+                    .execute(&**conn)?;
+            info!("Assigned the role {} to the user {}.", role_id, user_id);
+            if let Some(actor) = actor {
+                audit::record(conn,
+                              actor,
+                              "assign_role",
+                              "user",
+                              Some(user_id),
+                              None,
+                              Some(&role_id))?;
+            }
+        }
+        Ok(())
+    })
+}
+
+pub fn assign_by_name(conn: &Connection,
+                      actor: Option<i32>,
+                      user_id: i32,
+                      role_name: &str)
+                      -> Result<()> {
+    let role = try_or!(get_by_name(conn, role_name)?, else bail!(ErrorKind::InvalidInput));
+    assign(conn, actor, user_id, role.id)
+}
+
+pub fn unassign(conn: &Connection,
+                actor: Option<i32>,
+                user_id: i32,
+                role_id: i32)
+                -> Result<bool> {
+    use schema::user_roles;
+
+    conn.transaction(|| -> Result<bool> {
+
+        let count = diesel::delete(user_roles::table.filter(user_roles::user_id.eq(user_id))
+                .filter(user_roles::role_id.eq(role_id))).execute(&**conn)?;
+        if count == 1 {
+            info!("Removed the role {} from the user {}.", role_id, user_id);
+            if let Some(actor) = actor {
+                audit::record(conn,
+                              actor,
+                              "unassign_role",
+                              "user",
+                              Some(user_id),
+                              Some(&role_id),
+                              None)?;
+            }
+        }
+
+        Ok(count == 1)
+    })
+}
+
+#[test]
+fn test_check_permission() {
+    assert!(check_permission(EDIT_CONTENT).is_ok());
+    assert!(check_permission("editors").is_err());
+    assert!(check_permission("").is_err());
+}
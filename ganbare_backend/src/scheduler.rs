@@ -0,0 +1,347 @@
+use super::*;
+use std::cmp::{max, min};
+
+/// Answering faster than this counts as an easy recall.
+const FAST_ANSWER_MS: i32 = 3_000;
+/// Answering slower than this counts as a hard recall.
+const SLOW_ANSWER_MS: i32 = 10_000;
+
+const SECONDS_IN_DAY: f64 = 86_400.0;
+
+/// The default FSRS v4 weights.
+const FSRS_W: [f64; 17] = [0.4, 0.6, 2.4, 5.8, 4.93, 0.94, 0.86, 0.01, 1.49, 0.14, 0.94, 2.18,
+                           0.05, 0.34, 1.26, 0.29, 2.61];
+
+/// The model that decides when a due item is going to be asked next.
+/// Stored in `user_metrics.memory_model` by its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryModel {
+    /// The original scheduler: multiply the delay by `delay_multiplier` on each
+    /// successful streak, and reset it on a miss.
+    Multiplicative,
+    /// SuperMemo 2. `stability` stores the interval in seconds
+    /// and `difficulty` stores the ease factor.
+    Sm2,
+    /// Free Spaced Repetition Scheduler. `stability` stores the memory stability in days
+    /// and `difficulty` stores the FSRS difficulty (1.0 – 10.0).
+    Fsrs,
+}
+
+impl MemoryModel {
+    pub fn from_name(name: &str) -> Result<MemoryModel> {
+        match name {
+            "multiplicative" => Ok(MemoryModel::Multiplicative),
+            "sm2" => Ok(MemoryModel::Sm2),
+            "fsrs" => Ok(MemoryModel::Fsrs),
+            _ => bail!(ErrorKind::InvalidInput),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            MemoryModel::Multiplicative => "multiplicative",
+            MemoryModel::Sm2 => "sm2",
+            MemoryModel::Fsrs => "fsrs",
+        }
+    }
+
+    /// Whether a miss should be fed to the model right away, and not just
+    /// after the streak limit is reached.
+    pub fn reviews_misses(&self) -> bool {
+        *self != MemoryModel::Multiplicative
+    }
+
+    /// Takes over an item that was scheduled with another model. `stability` and
+    /// `difficulty` mean different things in each model, so they are derived anew
+    /// from the current delay, which is in seconds in all of them.
+    pub fn adopt(&self, due_item: &mut DueItem) {
+        match *self {
+            MemoryModel::Multiplicative => (),
+            MemoryModel::Sm2 => {
+                due_item.stability = due_item.due_delay as f64;
+                due_item.difficulty = 0.0;
+            }
+            MemoryModel::Fsrs => {
+                due_item.stability = due_item.due_delay as f64 / SECONDS_IN_DAY;
+                due_item.difficulty = if due_item.due_delay > 0 { FSRS_W[4] } else { 0.0 };
+            }
+        }
+    }
+
+    /// Updates `due_delay`, `stability` and `difficulty` of the item.
+    /// Expects `correct_streak_overall` to be already updated.
+    pub fn schedule(&self, due_item: &mut DueItem, review: &Review, metrics: &UserMetrics) {
+        match *self {
+            MemoryModel::Multiplicative => schedule_multiplicative(due_item, review, metrics),
+            MemoryModel::Sm2 => schedule_sm2(due_item, review, metrics),
+            MemoryModel::Fsrs => schedule_fsrs(due_item, review, metrics),
+        }
+    }
+}
+
+/// A single answer to a due item.
+#[derive(Debug, Clone, Copy)]
+pub struct Review {
+    pub correct: bool,
+    /// Only exercises have an answer level; questions are either right or wrong.
+    pub answer_level: Option<i32>,
+    pub active_answer_time_ms: i32,
+}
+
+impl Review {
+    /// The quality of the answer in the SM-2 scale of 0 – 5.
+    /// Wrong answers are 1, right answers are 3 – 5 depending on the latency
+    /// and the answer level.
+    pub fn grade(&self) -> i32 {
+        if !self.correct {
+            return 1;
+        }
+        let mut grade = 4;
+        if self.active_answer_time_ms < FAST_ANSWER_MS {
+            grade += 1;
+        } else if self.active_answer_time_ms > SLOW_ANSWER_MS {
+            grade -= 1;
+        }
+        if self.answer_level.unwrap_or(1) > 1 {
+            grade += 1;
+        }
+        min(max(grade, 3), 5)
+    }
+}
+
+fn seconds_to_delay(seconds: f64) -> i32 {
+    if seconds >= i32::max_value() as f64 {
+        i32::max_value()
+    } else {
+        max(seconds.round() as i32, 0)
+    }
+}
+
+fn schedule_multiplicative(due_item: &mut DueItem, review: &Review, metrics: &UserMetrics) {
+    due_item.due_delay = if review.correct {
+        max(due_item.due_delay.saturating_mul(metrics.delay_multiplier),
+            metrics.initial_delay)
+    } else {
+        0
+    };
+}
+
+fn schedule_sm2(due_item: &mut DueItem, review: &Review, metrics: &UserMetrics) {
+    let grade = review.grade();
+
+    let ease = if due_item.difficulty > 0.0 {
+        due_item.difficulty
+    } else {
+        2.5
+    };
+    let miss = (5 - grade) as f64;
+    due_item.difficulty = (ease + 0.1 - miss * (0.08 + miss * 0.02)).max(1.3);
+
+    due_item.stability = if grade < 3 {
+        metrics.initial_delay as f64
+    } else {
+        match due_item.correct_streak_overall {
+            0 | 1 => metrics.initial_delay as f64,
+            2 => metrics.initial_delay as f64 * metrics.delay_multiplier as f64,
+            _ => due_item.stability.max(metrics.initial_delay as f64) * ease,
+        }
+    };
+    due_item.due_delay = seconds_to_delay(due_item.stability);
+}
+
+fn fsrs_rating(grade: i32) -> usize {
+    match grade {
+        0...2 => 1, // Again
+        3 => 2, // Hard
+        4 => 3, // Good
+        _ => 4, // Easy
+    }
+}
+
+fn schedule_fsrs(due_item: &mut DueItem, review: &Review, metrics: &UserMetrics) {
+    let w = &FSRS_W;
+    let rating = fsrs_rating(review.grade());
+    let g = rating as f64;
+
+    if due_item.stability <= 0.0 {
+        due_item.stability = w[rating - 1];
+        due_item.difficulty = (w[4] - (g - 3.0) * w[5]).max(1.0).min(10.0);
+    } else {
+        let last_review = due_item.due_date -
+                          chrono::Duration::seconds(due_item.due_delay as i64);
+        let elapsed_days = (chrono::UTC::now() - last_review).num_seconds().max(0) as f64 /
+                           SECONDS_IN_DAY;
+        let s = due_item.stability;
+        let d = due_item.difficulty;
+        let retrievability = 1.0 / (1.0 + elapsed_days / (9.0 * s));
+
+        due_item.stability = if rating == 1 {
+            w[11] * d.powf(-w[12]) * ((s + 1.0).powf(w[13]) - 1.0) *
+            (w[14] * (1.0 - retrievability)).exp()
+        } else {
+            let hard_penalty = if rating == 2 { w[15] } else { 1.0 };
+            let easy_bonus = if rating == 4 { w[16] } else { 1.0 };
+            s *
+            (w[8].exp() * (11.0 - d) * s.powf(-w[9]) *
+             ((w[10] * (1.0 - retrievability)).exp() - 1.0) * hard_penalty *
+             easy_bonus + 1.0)
+        };
+
+        let next_d = d - w[6] * (g - 3.0);
+        due_item.difficulty = (w[7] * w[4] + (1.0 - w[7]) * next_d).max(1.0).min(10.0);
+    }
+
+    // With the target retention of 90%, the interval equals to the stability.
+    due_item.due_delay = max(seconds_to_delay(due_item.stability * SECONDS_IN_DAY),
+                             if rating == 1 { 0 } else { metrics.initial_delay });
+}
+
+#[test]
+fn test_memory_model_names() {
+    for model in &[MemoryModel::Multiplicative, MemoryModel::Sm2, MemoryModel::Fsrs] {
+        assert_eq!(MemoryModel::from_name(model.name()).unwrap(), *model);
+    }
+    assert!(MemoryModel::from_name("leitner").is_err());
+}
+
+#[test]
+fn test_review_grade() {
+    let fast = Review {
+        correct: true,
+        answer_level: None,
+        active_answer_time_ms: 1_000,
+    };
+    let slow = Review { active_answer_time_ms: 20_000, ..fast };
+    let wrong = Review { correct: false, ..fast };
+    let good_exercise = Review { answer_level: Some(2), ..slow };
+    assert_eq!(fast.grade(), 5);
+    assert_eq!(slow.grade(), 3);
+    assert_eq!(wrong.grade(), 1);
+    assert_eq!(good_exercise.grade(), 4);
+}
+
+#[test]
+fn test_schedule_intervals() {
+    let now = chrono::UTC::now();
+    let metrics = UserMetrics {
+        id: 1,
+        new_words_since_break: 0,
+        new_words_today: 0,
+        quizes_since_break: 0,
+        quizes_today: 0,
+        break_until: now,
+        today: now,
+        max_words_since_break: 5,
+        max_words_today: 10,
+        max_quizes_since_break: 10,
+        max_quizes_today: 20,
+        break_length: 3600,
+        delay_multiplier: 2,
+        initial_delay: 3600,
+        streak_limit: 2,
+        cooldown_delay: 600,
+        streak_skill_bump_criteria: 2,
+        memory_model: "multiplicative".into(),
+    };
+    let new_item = DueItem {
+        id: 1,
+        user_id: 1,
+        due_date: now,
+        due_delay: 0,
+        cooldown_delay: now,
+        correct_streak_overall: 0,
+        correct_streak_this_time: 0,
+        item_type: "question".into(),
+        stability: 0.0,
+        difficulty: 0.0,
+    };
+    let good = Review {
+        correct: true,
+        answer_level: None,
+        active_answer_time_ms: 5_000,
+    };
+    let wrong = Review { correct: false, ..good };
+
+    // Each review is done right when the item is due.
+    let answer = |model: MemoryModel, item: &mut DueItem, review: &Review| {
+        item.correct_streak_overall = if review.correct {
+            item.correct_streak_overall + 1
+        } else {
+            0
+        };
+        item.due_date = chrono::UTC::now();
+        model.schedule(item, review, &metrics);
+    };
+
+    let model = MemoryModel::Multiplicative;
+    let mut item = new_item.clone();
+    answer(model, &mut item, &good);
+    assert_eq!(item.due_delay, 3600);
+    answer(model, &mut item, &good);
+    assert_eq!(item.due_delay, 7200);
+    answer(model, &mut item, &wrong);
+    assert_eq!(item.due_delay, 0);
+
+    // SM-2 keeps the interval in seconds.
+    let model = MemoryModel::Sm2;
+    let mut item = new_item.clone();
+    answer(model, &mut item, &good);
+    assert_eq!(item.due_delay, 3600);
+    assert!((item.difficulty - 2.5).abs() < 1e-9);
+    answer(model, &mut item, &good);
+    assert_eq!(item.due_delay, 7200);
+    answer(model, &mut item, &good);
+    assert_eq!(item.due_delay, 18000);
+    assert!((item.stability - 18000.0).abs() < 1e-6);
+    answer(model, &mut item, &wrong);
+    assert_eq!(item.due_delay, 3600);
+    assert!(item.difficulty < 2.5);
+
+    // FSRS keeps the stability in days.
+    let model = MemoryModel::Fsrs;
+    let mut item = new_item.clone();
+    answer(model, &mut item, &good);
+    assert_eq!(item.stability, FSRS_W[2]);
+    assert_eq!(item.due_delay, seconds_to_delay(FSRS_W[2] * SECONDS_IN_DAY));
+    assert_eq!(item.difficulty, FSRS_W[4]);
+    let first_stability = item.stability;
+    answer(model, &mut item, &good);
+    assert!(item.stability > first_stability);
+    assert!(item.stability < 365.0);
+    assert_eq!(item.due_delay, seconds_to_delay(item.stability * SECONDS_IN_DAY));
+    let second_stability = item.stability;
+    answer(model, &mut item, &wrong);
+    assert!(item.stability < second_stability);
+    assert_eq!(item.due_delay, seconds_to_delay(item.stability * SECONDS_IN_DAY));
+}
+
+#[test]
+fn test_adopt_memory_model() {
+    let now = chrono::UTC::now();
+    let mut item = DueItem {
+        id: 1,
+        user_id: 1,
+        due_date: now,
+        due_delay: 2 * 86_400,
+        cooldown_delay: now,
+        correct_streak_overall: 3,
+        correct_streak_this_time: 0,
+        item_type: "question".into(),
+        stability: 172_800.0,
+        difficulty: 2.5,
+    };
+
+    // From SM-2 seconds to FSRS days and back.
+    MemoryModel::Fsrs.adopt(&mut item);
+    assert_eq!(item.stability, 2.0);
+    assert_eq!(item.difficulty, FSRS_W[4]);
+    MemoryModel::Sm2.adopt(&mut item);
+    assert_eq!(item.stability, 172_800.0);
+    assert_eq!(item.difficulty, 0.0);
+
+    // An item that was never got right starts from scratch.
+    item.due_delay = 0;
+    MemoryModel::Fsrs.adopt(&mut item);
+    assert_eq!(item.stability, 0.0);
+    assert_eq!(item.difficulty, 0.0);
+}
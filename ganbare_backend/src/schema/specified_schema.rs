@@ -1,3 +1,16 @@
+table! {
+    account_requests (id) {
+        id -> Int4,
+        user_id -> Int4,
+        action -> Varchar,
+        requested -> Timestamptz,
+        confirmed -> Nullable<Timestamptz>,
+        due -> Nullable<Timestamptz>,
+        cancelled -> Nullable<Timestamptz>,
+        executed -> Nullable<Timestamptz>,
+    }
+}
+
 table! {
     anon_aliases (id) {
         id -> Int4,
@@ -7,10 +20,32 @@ table! {
     }
 }
 
+table! {
+    api_token_roles (token_id,
+    role_id) {
+        token_id -> Int4,
+        role_id -> Int4,
+    }
+}
+
+table! {
+    api_tokens (id) {
+        id -> Int4,
+        user_id -> Int4,
+        name -> Varchar,
+        kind -> Varchar,
+        secret -> Bytea,
+        created -> Timestamptz,
+        expires -> Nullable<Timestamptz>,
+        last_used -> Nullable<Timestamptz>,
+    }
+}
+
 table! {
     audio_bundles (id) {
         id -> Int4,
         listname -> Varchar,
+        deleted -> Nullable<Timestamptz>,
     }
 }
 
@@ -22,6 +57,62 @@ table! {
         file_path -> Varchar,
         mime -> Varchar,
         file_sha2 -> Nullable<Bytea>,
+        processing -> Nullable<Varchar>,
+        processed -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    audio_renditions (id) {
+        id -> Int4,
+        audio_file_id -> Int4,
+        file_path -> Varchar,
+        mime -> Varchar,
+    }
+}
+
+table! {
+    audit_log (id) {
+        id -> Int4,
+        actor -> Nullable<Int4>,
+        action -> Varchar,
+        target_type -> Varchar,
+        target_id -> Nullable<Int4>,
+        before -> Nullable<Text>,
+        after -> Nullable<Text>,
+        logged -> Timestamptz,
+    }
+}
+
+table! {
+    consent_documents (id) {
+        id -> Int4,
+        version -> Int4,
+        locale -> Varchar,
+        content -> Text,
+        published -> Timestamptz,
+    }
+}
+
+table! {
+    consents (id) {
+        id -> Int4,
+        user_id -> Int4,
+        document_id -> Int4,
+        agreed -> Bool,
+        decided -> Timestamptz,
+    }
+}
+
+table! {
+    content_revisions (id) {
+        id -> Int4,
+        item_type -> Varchar,
+        item_id -> Int4,
+        content -> Text,
+        author -> Nullable<Int4>,
+        source -> Varchar,
+        created -> Timestamptz,
     }
 }
 
@@ -35,6 +126,8 @@ table! {
         correct_streak_overall -> Int4,
         correct_streak_this_time -> Int4,
         item_type -> Varchar,
+        stability -> Float8,
+        difficulty -> Float8,
     }
 }
 
@@ -59,6 +152,22 @@ table! {
     }
 }
 
+table! {
+    email_queue (id) {
+        id -> Int4,
+        from_address -> Varchar,
+        to_address -> Varchar,
+        subject -> Varchar,
+        message -> Text,
+        status -> Varchar,
+        attempts -> Int4,
+        next_attempt -> Timestamptz,
+        last_error -> Nullable<Varchar>,
+        queued -> Timestamptz,
+        sent -> Nullable<Timestamptz>,
+    }
+}
+
 table! {
     event_experiences (user_id,
     event_id) {
@@ -69,6 +178,28 @@ table! {
     }
 }
 
+table! {
+    event_transitions (id) {
+        id -> Int4,
+        event_id -> Int4,
+        fires_on -> Varchar,
+        delay_hours -> Int4,
+        required_group -> Nullable<Int4>,
+        unless_finished -> Nullable<Int4>,
+        join_group -> Nullable<Int4>,
+        leave_group -> Nullable<Int4>,
+    }
+}
+
+table! {
+    event_transitions_fired (transition_id,
+    user_id) {
+        transition_id -> Int4,
+        user_id -> Int4,
+        fired -> Timestamptz,
+    }
+}
+
 table! {
     event_userdata (id) {
         id -> Int4,
@@ -87,6 +218,9 @@ table! {
         published -> Bool,
         required_group -> Nullable<Int4>,
         priority -> Int4,
+        page_kind -> Varchar,
+        page -> Nullable<Varchar>,
+        required_event -> Nullable<Int4>,
     }
 }
 
@@ -111,6 +245,7 @@ table! {
         skill_id -> Int4,
         published -> Bool,
         skill_level -> Int4,
+        deleted -> Nullable<Timestamptz>,
     }
 }
 
@@ -138,6 +273,10 @@ table! {
         salt -> Bytea,
         initial_rounds -> Int2,
         extra_rounds -> Int2,
+        hash_version -> Int2,
+        memory_kib -> Int4,
+        iterations -> Int4,
+        pepper_id -> Nullable<Bytea>,
     }
 }
 
@@ -147,6 +286,16 @@ table! {
         email -> Varchar,
         groups -> Array<Int4>,
         added -> Timestamptz,
+        locale -> Varchar,
+    }
+}
+
+table! {
+    pending_logins (token) {
+        token -> Varchar,
+        user_id -> Int4,
+        started -> Timestamptz,
+        attempts -> Int4,
     }
 }
 
@@ -208,6 +357,56 @@ table! {
         question_text -> Varchar,
         published -> Bool,
         skill_level -> Int4,
+        deleted -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    randomization_arms (id) {
+        id -> Int4,
+        randomization_id -> Int4,
+        group_id -> Int4,
+        ratio -> Int4,
+    }
+}
+
+table! {
+    randomization_log (id) {
+        id -> Int4,
+        randomization_id -> Int4,
+        user_id -> Nullable<Int4>,
+        stratum -> Varchar,
+        block_number -> Int4,
+        block_position -> Int4,
+        arm_group_id -> Int4,
+        assigned -> Timestamptz,
+    }
+}
+
+table! {
+    randomization_strata (randomization_id,
+    group_id) {
+        randomization_id -> Int4,
+        group_id -> Int4,
+    }
+}
+
+table! {
+    randomizations (id) {
+        id -> Int4,
+        event_id -> Int4,
+        block_size -> Int4,
+        seed -> Int8,
+        anonymous -> Bool,
+    }
+}
+
+table! {
+    recovery_codes (id) {
+        id -> Int4,
+        user_id -> Int4,
+        code_hash -> Bytea,
+        used -> Nullable<Timestamptz>,
     }
 }
 
@@ -220,6 +419,21 @@ table! {
     }
 }
 
+table! {
+    role_permissions (role_id,
+    permission) {
+        role_id -> Int4,
+        permission -> Varchar,
+    }
+}
+
+table! {
+    roles (id) {
+        id -> Int4,
+        role_name -> Varchar,
+    }
+}
+
 table! {
     sessions (id) {
         id -> Int4,
@@ -228,6 +442,8 @@ table! {
         last_seen -> Timestamptz,
         secret -> Bytea,
         refresh_count -> Int4,
+        user_agent -> Nullable<Varchar>,
+        ip -> Nullable<Varchar>,
     }
 }
 
@@ -247,6 +463,28 @@ table! {
     }
 }
 
+table! {
+    test_items (id) {
+        id -> Int4,
+        event_id -> Int4,
+        ordering -> Int4,
+        item_type -> Varchar,
+        item_text -> Varchar,
+        audio_id -> Nullable<Int4>,
+        audio_path -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    totp_secrets (user_id) {
+        user_id -> Int4,
+        secret -> Bytea,
+        confirmed -> Bool,
+        last_step -> Int8,
+        created -> Timestamptz,
+    }
+}
+
 table! {
     user_groups (id) {
         id -> Int4,
@@ -274,6 +512,15 @@ table! {
         streak_limit -> Int4,
         cooldown_delay -> Int4,
         streak_skill_bump_criteria -> Int4,
+        memory_model -> Varchar,
+    }
+}
+
+table! {
+    user_roles (user_id,
+    role_id) {
+        user_id -> Int4,
+        role_id -> Int4,
     }
 }
 
@@ -296,6 +543,9 @@ table! {
         email -> Nullable<Varchar>,
         joined -> Timestamptz,
         last_seen -> Timestamptz,
+        timezone -> Varchar,
+        day_rollover_hour -> Int4,
+        locale -> Varchar,
     }
 }
 
@@ -327,5 +577,6 @@ table! {
         published -> Bool,
         skill_level -> Int4,
         priority -> Int4,
+        deleted -> Nullable<Timestamptz>,
     }
 }
@@ -19,6 +19,30 @@ pub struct UserSession {
     pub refresh_now: bool,
     pub token: Vec<u8>,
     pub refresh_count: i32,
+    /// Set when the request is authenticated with an API token instead of the session cookies.
+    /// There's no session then, and `sess_id` is 0.
+    pub api_token: Option<i32>,
+}
+
+impl<'a> From<&'a Session> for UserSession {
+    fn from(db_sess: &'a Session) -> Self {
+        UserSession {
+            sess_id: db_sess.id,
+            user_id: db_sess.user_id,
+            refreshed: db_sess.last_seen,
+            refresh_now: false,
+            token: get_hmac_for_sess_secret(db_sess.secret.as_slice(), db_sess.refresh_count),
+            refresh_count: db_sess.refresh_count,
+            api_token: None,
+        }
+    }
+}
+
+/// The browser or app that started a session, so that the user can tell their sessions apart.
+#[derive(Debug, Clone, Default)]
+pub struct Client {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
 }
 
 pub fn new_token_and_hmac(hmac_key: &[u8]) -> Result<(String, String)> {
@@ -144,6 +168,7 @@ pub fn check_integrity(sess_id_str: &str,
                refresh_now: false,
                token: token,
                refresh_count: refresh_count,
+               api_token: None,
            })
     } else {
         warn!("The HMAC doesn't agree with the cookie!");
@@ -216,6 +241,7 @@ pub fn db_check(conn: &Connection,
                         refresh_now: true,
                         token: get_hmac_for_sess_secret(db_sess.secret.as_slice(), db_sess.refresh_count),
                         refresh_count: db_sess.refresh_count,
+                        api_token: None,
                     }))
                 } else {
                     Ok(None) // The token didn't match
@@ -244,6 +270,7 @@ pub fn db_check(conn: &Connection,
                             refresh_now: true,
                             token: get_hmac_for_sess_secret(db_sess.secret.as_slice(), db_sess.refresh_count),
                             refresh_count: db_sess.refresh_count,
+                            api_token: None,
                         }))
                     } else {
                         Ok(None) // The token didn't match
@@ -275,7 +302,7 @@ pub fn end(conn: &Connection, sess_id: i32) -> Result<Option<()>> {
        })
 }
 
-pub fn start(conn: &Connection, user: &User) -> Result<UserSession> {
+pub fn start(conn: &Connection, user: &User, client: &Client) -> Result<UserSession> {
     use schema::sessions;
 
     let sess_secret = fresh_token()?;
@@ -287,6 +314,8 @@ pub fn start(conn: &Connection, user: &User) -> Result<UserSession> {
         started: session_started,
         last_seen: session_started,
         secret: &sess_secret[..],
+        user_agent: client.user_agent.as_ref().map(|s| s.as_str()),
+        ip: client.ip.as_ref().map(|s| s.as_str()),
     };
 
     update_user_last_seen(conn, user.id, session_started)?;
@@ -302,5 +331,73 @@ pub fn start(conn: &Connection, user: &User) -> Result<UserSession> {
            refresh_now: true,
            token: get_hmac_for_sess_secret(&sess_secret[..], 0),
            refresh_count: 0,
+           api_token: None,
        })
 }
+
+/// The user's sessions that haven't expired yet, the most recently used first.
+pub fn get_sessions(conn: &Connection,
+                    user_id: i32,
+                    sess_expire: chrono::Duration)
+                    -> Result<Vec<Session>> {
+    use schema::sessions;
+
+    let sessions: Vec<Session> = sessions::table.filter(sessions::user_id.eq(user_id))
+        .filter(sessions::last_seen.gt(chrono::UTC::now() - sess_expire))
+        .order(sessions::last_seen.desc())
+        .get_results(&**conn)?;
+
+    Ok(sessions)
+}
+
+/// Ends one of the user's sessions on behalf of the actor. The ended session is returned
+/// so that the caller can put it in the logout cache; otherwise a cookie that has been
+/// refreshed in the last five minutes would still be trusted.
+pub fn revoke(conn: &Connection,
+              actor: i32,
+              user_id: i32,
+              sess_id: i32)
+              -> Result<Option<Session>> {
+    use schema::sessions;
+
+    let db_sess: Option<Session> =
+        diesel::delete(sessions::table.filter(sessions::id.eq(sess_id))
+                .filter(sessions::user_id.eq(user_id))).get_result(&**conn)
+            .optional()?;
+
+    if let Some(ref db_sess) = db_sess {
+        audit::record(conn, actor, "revoke_session", "user", Some(user_id), Some(db_sess), None)?;
+    }
+    Ok(db_sess)
+}
+
+/// Ends all the user's sessions except `keep`, and revokes their API session tokens.
+/// The personal access tokens are left alone; they are revoked one by one.
+pub fn revoke_all(conn: &Connection,
+                  actor: i32,
+                  user_id: i32,
+                  keep: Option<i32>)
+                  -> Result<Vec<Session>> {
+    use schema::{sessions, api_tokens};
+
+    conn.transaction(|| {
+        let ended: Vec<Session> =
+            diesel::delete(sessions::table.filter(sessions::user_id.eq(user_id))
+                    .filter(sessions::id.ne(keep.unwrap_or(0)))).get_results(&**conn)?;
+
+        let tokens: Vec<ApiToken> =
+            diesel::delete(api_tokens::table.filter(api_tokens::user_id.eq(user_id))
+                    .filter(api_tokens::kind.eq(api_token::SESSION))).get_results(&**conn)?;
+
+        if !ended.is_empty() || !tokens.is_empty() {
+            audit::record(conn,
+                          actor,
+                          "revoke_sessions",
+                          "user",
+                          Some(user_id),
+                          Some(&(&ended, &tokens)),
+                          None)?;
+        }
+        Ok(ended)
+    })
+}
@@ -1,6 +1,210 @@
 use super::*;
 use quiz::{Answered, Quiz, QuizSerialized};
 
+/* TEST DEFINITIONS */
+
+fn check_item(item_type: &str, audio_id: Option<i32>, audio_path: Option<&str>) -> Result<()> {
+    match (item_type, audio_id, audio_path) {
+        ("retelling", _, Some(_)) => Ok(()),
+        ("word", Some(_), _) |
+        ("question", Some(_), _) |
+        ("exercise", Some(_), _) => Ok(()),
+        _ => bail!(ErrorKind::InvalidInput),
+    }
+}
+
+pub fn get_all_items(conn: &Connection) -> Result<Vec<TestItem>> {
+    use schema::test_items;
+
+    let items = test_items::table.order((test_items::event_id,
+                                         test_items::ordering,
+                                         test_items::id))
+        .get_results(&**conn)?;
+    Ok(items)
+}
+
+pub fn get_items(conn: &Connection, event_id: i32) -> Result<Vec<TestItem>> {
+    use schema::test_items;
+
+    let items = test_items::table.filter(test_items::event_id.eq(event_id))
+        .order((test_items::ordering, test_items::id))
+        .get_results(&**conn)?;
+    Ok(items)
+}
+
+pub fn get_item(conn: &Connection, id: i32) -> Result<Option<TestItem>> {
+    use schema::test_items;
+
+    let item = test_items::table.filter(test_items::id.eq(id))
+        .get_result(&**conn)
+        .optional()?;
+    Ok(item)
+}
+
+pub fn add_item(conn: &Connection, actor: i32, item: &NewTestItem) -> Result<TestItem> {
+    use schema::test_items;
+
+    conn.transaction(|| -> Result<TestItem> {
+
+        check_item(item.item_type, item.audio_id, item.audio_path)?;
+
+        let item: TestItem = diesel::insert(item).into(test_items::table).get_result(&**conn)?;
+        audit::record(conn, actor, "create", "test_item", Some(item.id), None, Some(&item))?;
+        Ok(item)
+    })
+}
+
+pub fn update_item(conn: &Connection,
+                   actor: i32,
+                   id: i32,
+                   item: &UpdateTestItem)
+                   -> Result<Option<TestItem>> {
+    use schema::test_items;
+
+    conn.transaction(|| -> Result<Option<TestItem>> {
+
+        let before = try_or!(get_item(conn, id)?, else return Ok(None));
+
+        let item: Option<TestItem> =
+            diesel::update(test_items::table.filter(test_items::id.eq(id))).set(item)
+                .get_result(&**conn)
+                .optional()?;
+
+        if let Some(ref item) = item {
+            check_item(&item.item_type,
+                       item.audio_id,
+                       item.audio_path.as_ref().map(|s| &**s))?;
+            audit::record(conn,
+                          actor,
+                          "update",
+                          "test_item",
+                          Some(id),
+                          Some(&before),
+                          Some(item))?;
+        }
+        Ok(item)
+    })
+}
+
+pub fn remove_item(conn: &Connection, actor: i32, id: i32) -> Result<bool> {
+    use schema::test_items;
+
+    conn.transaction(|| -> Result<bool> {
+
+        let item: Option<TestItem> = diesel::delete(test_items::table.filter(test_items::id.eq(id)))
+            .get_result(&**conn)
+            .optional()?;
+        if let Some(ref item) = item {
+            audit::record(conn, actor, "delete", "test_item", Some(id), Some(item), None)?;
+        }
+        Ok(item.is_some())
+    })
+}
+
+fn to_quiz_serialized(item: TestItem) -> Result<QuizSerialized> {
+    let audio_id = try_or!(item.audio_id,
+        else bail!(ErrorKind::DatabaseOdd("Test items that aren't retellings must have audio_id!")));
+    Ok(match &*item.item_type {
+           "word" => QuizSerialized::Word(item.item_text, audio_id),
+           "question" => QuizSerialized::Question(item.item_text, audio_id),
+           "exercise" => QuizSerialized::Exercise(item.item_text, audio_id),
+           _ => bail!(ErrorKind::DatabaseOdd("Unknown test item type!")),
+       })
+}
+
+/// The quiz items of the test event in the order they are asked.
+pub fn get_quizes(conn: &Connection, event: &Event) -> Result<Vec<QuizSerialized>> {
+    get_items(conn, event.id)?
+        .into_iter()
+        .filter(|i| i.item_type != "retelling")
+        .map(to_quiz_serialized)
+        .collect()
+}
+
+/// The retellings of the test event as (image, audio) pairs in the order they are shown.
+pub fn get_retellings(conn: &Connection, event: &Event) -> Result<Vec<(String, String)>> {
+    let retellings = get_items(conn, event.id)?
+        .into_iter()
+        .filter(|i| i.item_type == "retelling")
+        .filter_map(|i| i.audio_path.map(|audio| (i.item_text, audio)))
+        .collect();
+    Ok(retellings)
+}
+
+/// Parses the item lists of the `pretest.rs` and `posttest.rs` files that used to be
+/// compiled in: `QuizSerialized::Word("text", audio_id)` etc., in the order they are asked.
+pub fn parse_item_list(source: &str) -> Result<Vec<QuizSerialized>> {
+    use regex::Regex;
+
+    lazy_static! {
+        static ref ITEM_REGEX: Regex = Regex::new(
+            r#"QuizSerialized::(Word|Question|Exercise)\(\s*"((?:[^"\\]|\\.)*)"\s*,\s*(\d+)\s*\)"#
+        ).expect("<- that is a valid regex there");
+    }
+
+    let mut items = vec![];
+    for cap in ITEM_REGEX.captures_iter(source) {
+        let text = cap[2].replace(r#"\""#, "\"").replace(r"\\", "\\");
+        let audio_id = cap[3].parse()?;
+        items.push(match &cap[1] {
+                       "Word" => QuizSerialized::Word(text, audio_id),
+                       "Question" => QuizSerialized::Question(text, audio_id),
+                       _ => QuizSerialized::Exercise(text, audio_id),
+                   });
+    }
+    if items.is_empty() {
+        bail!(ErrorKind::InvalidInput);
+    }
+    Ok(items)
+}
+
+/// Replaces the quiz items of the test event with `items`. The retellings are kept.
+pub fn replace_quiz_items(conn: &Connection,
+                          actor: Option<i32>,
+                          event_id: i32,
+                          items: &[QuizSerialized])
+                          -> Result<Vec<TestItem>> {
+    use schema::test_items;
+
+    conn.transaction(|| {
+        let removed: Vec<TestItem> =
+            diesel::delete(test_items::table.filter(test_items::event_id.eq(event_id))
+                               .filter(test_items::item_type.ne("retelling")))
+                .get_results(&**conn)?;
+
+        let mut added = vec![];
+        for (ordering, item) in items.iter().enumerate() {
+            let (item_type, text, audio_id) = match *item {
+                QuizSerialized::Word(ref text, audio_id) => ("word", text, audio_id),
+                QuizSerialized::Question(ref text, audio_id) => ("question", text, audio_id),
+                QuizSerialized::Exercise(ref text, audio_id) => ("exercise", text, audio_id),
+            };
+            let item: TestItem = diesel::insert(&NewTestItem {
+                                                     event_id: event_id,
+                                                     ordering: ordering as i32,
+                                                     item_type: item_type,
+                                                     item_text: text,
+                                                     audio_id: Some(audio_id),
+                                                     audio_path: None,
+                                                 }).into(test_items::table)
+                    .get_result(&**conn)?;
+            added.push(item);
+        }
+
+        if let Some(actor) = actor {
+            for item in &removed {
+                audit::record(conn, actor, "delete", "test_item", Some(item.id), Some(item), None)?;
+            }
+            for item in &added {
+                audit::record(conn, actor, "create", "test_item", Some(item.id), None, Some(item))?;
+            }
+        }
+        Ok(added)
+    })
+}
+
+/* TAKING TESTS */
+
 pub fn save_answer_test_item(conn: &Connection,
                              user_id: i32,
                              event: &Event,
@@ -51,11 +255,7 @@ pub fn save_answer_test_item(conn: &Connection,
     Ok(())
 }
 
-pub fn get_new_quiz_test(conn: &Connection,
-                         user_id: i32,
-                         event: &Event,
-                         quizes: &[QuizSerialized])
-                         -> Result<Option<Quiz>> {
+pub fn get_new_quiz_test(conn: &Connection, user_id: i32, event: &Event) -> Result<Option<Quiz>> {
 
     let number = event::get_userdata(conn, event, user_id, "quiz_number")
         ?
@@ -69,7 +269,13 @@ pub fn get_new_quiz_test(conn: &Connection,
     let quiz = if pending_test_item == 0 {
         // We can show a new item, since to old one was answered to
 
-        if number == quizes.len() {
+        let quizes = get_quizes(conn, event)?;
+
+        if quizes.is_empty() {
+            bail!(ErrorKind::DatabaseOdd("The test event has no items! \
+                                          Load them with `content import_tests`."));
+        }
+        if number >= quizes.len() {
             event::set_done(conn, &event.name, user_id)?;
             return Ok(None);
         }
@@ -120,8 +326,7 @@ pub fn save_retelling(conn: &Connection, user_id: i32, event: &Event) -> Result<
 
 pub fn get_new_retelling(conn: &Connection,
                          user_id: i32,
-                         event: &Event,
-                         retellings: &[(&'static str, &'static str)])
+                         event: &Event)
                          -> Result<Option<RetellingJson>> {
 
     let number = event::get_userdata(conn, event, user_id, "retelling_number")
@@ -129,15 +334,40 @@ pub fn get_new_retelling(conn: &Connection,
         .and_then(|d| d.data.parse::<usize>().ok())
         .unwrap_or(0);
 
-    if number == retellings.len() {
+    let mut retellings = get_retellings(conn, event)?;
+
+    if retellings.is_empty() {
+        bail!(ErrorKind::DatabaseOdd("The retelling event has no items!"));
+    }
+    if number >= retellings.len() {
         event::set_done(conn, &event.name, user_id)?;
         return Ok(None);
     }
 
-    let retelling = retellings[number];
+    let (img_src, audio_src) = retellings.swap_remove(number);
 
     Ok(Some(RetellingJson {
-                img_src: retelling.0.into(),
-                audio_src: retelling.1.into(),
+                img_src: img_src,
+                audio_src: audio_src,
             }))
 }
+
+#[test]
+fn test_parse_item_list() {
+    let source = r#"vec![
+        QuizSerialized::Word("かさ", 101),
+        QuizSerialized::Question("Mitä \"kasa\" on?", 102),
+        QuizSerialized::Exercise( "あめ" , 103 ),
+    ]"#;
+    let items = parse_item_list(source).unwrap();
+    assert_eq!(items.len(), 3);
+    match items[1] {
+        QuizSerialized::Question(ref text, 102) => assert_eq!(text, "Mitä \"kasa\" on?"),
+        ref other => panic!("Unexpected item: {:?}", other),
+    }
+    match items[2] {
+        QuizSerialized::Exercise(ref text, 103) => assert_eq!(text, "あめ"),
+        ref other => panic!("Unexpected item: {:?}", other),
+    }
+    assert!(parse_item_list("vec![]").is_err());
+}
@@ -0,0 +1,363 @@
+use super::*;
+use chrono::{self, DateTime, UTC};
+use crypto::digest::Digest;
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha1::Sha1;
+use crypto::sha2::Sha512;
+use rand::{Rng, OsRng};
+use data_encoding::base32;
+use data_encoding::base64url::encode_nopad;
+
+// Time-based one-time passwords (RFC 6238) as the second step of the login, for the
+// users that enable them. The codes are the ones that Google Authenticator and the
+// like generate: six digits, a new one every 30 seconds, HMAC-SHA1.
+//
+// Users that lose their phone log in with one of the recovery codes they got when
+// enabling the codes. Each recovery code works once.
+
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+/// How many steps the clock of the authenticator app can be off.
+const ALLOWED_DRIFT: i64 = 1;
+const SECRET_BYTES: usize = 20;
+pub const RECOVERY_CODE_COUNT: usize = 10;
+/// Wrong codes allowed before the password has to be given again.
+const MAX_ATTEMPTS: i32 = 5;
+/// Logins allowed to wait for the code at the same time. Failed logins count until
+/// they expire, so that the codes can't be guessed by giving the password again.
+const MAX_PENDING_LOGINS: i64 = 5;
+const PENDING_LOGIN_MINUTES: i64 = 10;
+
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    use byteorder::{WriteBytesExt, BigEndian};
+
+    let mut counter_bytes = [0_u8; 8];
+    (&mut counter_bytes[..]).write_u64::<BigEndian>(counter)
+        .expect("We should be able to write to the memory we just allocated!");
+
+    let mut hmac_maker = Hmac::new(Sha1::new(), secret);
+    hmac_maker.input(&counter_bytes[..]);
+    let result = hmac_maker.result();
+    let hash = result.code();
+
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let truncated = (hash[offset] as u32 & 0x7f) << 24 | (hash[offset + 1] as u32) << 16 |
+                    (hash[offset + 2] as u32) << 8 | hash[offset + 3] as u32;
+
+    truncated % 10_u32.pow(DIGITS)
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_uppercase()).collect()
+}
+
+fn hash_recovery_code(code: &str) -> Vec<u8> {
+    let mut hasher = Sha512::new();
+    hasher.input_str(&normalize_recovery_code(code));
+    let mut hash = vec![0; hasher.output_bytes()];
+    hasher.result(&mut hash);
+    hash
+}
+
+fn random_bytes(count: usize) -> Result<Vec<u8>> {
+    let mut bytes = vec![0_u8; count];
+    OsRng::new()
+        .chain_err(|| "Unable to connect to the system random number generator!")?
+        .fill_bytes(&mut bytes);
+    Ok(bytes)
+}
+
+fn get_secret(conn: &Connection, user_id: i32) -> Result<Option<TotpSecret>> {
+    use schema::totp_secrets;
+
+    Ok(totp_secrets::table.filter(totp_secrets::user_id.eq(user_id))
+           .get_result(&**conn)
+           .optional()?)
+}
+
+/// Checks a six-digit code against the secret. A code that has been used once
+/// doesn't work again, and neither do the codes older than it.
+fn check_totp(conn: &Connection,
+              secret: &TotpSecret,
+              code: u32,
+              now: DateTime<UTC>)
+              -> Result<bool> {
+    use schema::totp_secrets;
+
+    let current_step = now.timestamp() / STEP_SECONDS;
+    for step in (current_step - ALLOWED_DRIFT)..(current_step + ALLOWED_DRIFT + 1) {
+        if step > secret.last_step && hotp(&secret.secret, step as u64) == code {
+            let updated = diesel::update(totp_secrets::table
+                        .filter(totp_secrets::user_id.eq(secret.user_id))
+                        .filter(totp_secrets::last_step.lt(step)))
+                .set(totp_secrets::last_step.eq(step))
+                .execute(&**conn)?;
+            return Ok(updated == 1);
+        }
+    }
+    Ok(false)
+}
+
+fn use_recovery_code(conn: &Connection, user_id: i32, code: &str) -> Result<bool> {
+    use schema::recovery_codes;
+
+    let used = diesel::update(recovery_codes::table.filter(recovery_codes::user_id.eq(user_id))
+            .filter(recovery_codes::code_hash.eq(hash_recovery_code(code)))
+            .filter(recovery_codes::used.is_null())).set(recovery_codes::used.eq(Some(UTC::now())))
+        .execute(&**conn)?;
+
+    Ok(used == 1)
+}
+
+fn replace_recovery_codes(conn: &Connection, user_id: i32) -> Result<Vec<String>> {
+    use schema::recovery_codes;
+
+    diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user_id)))
+        .execute(&**conn)?;
+
+    let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+    for _ in 0..RECOVERY_CODE_COUNT {
+        let code = base32::encode(&random_bytes(5)?);
+        diesel::insert(&NewRecoveryCode {
+                            user_id: user_id,
+                            code_hash: &hash_recovery_code(&code),
+                        }).into(recovery_codes::table)
+                .execute(&**conn)?;
+        codes.push(format!("{}-{}", &code[..4], &code[4..]));
+    }
+    Ok(codes)
+}
+
+#[derive(Debug, Serialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub recovery_codes_left: i64,
+}
+
+pub fn status(conn: &Connection, user_id: i32) -> Result<TwoFactorStatus> {
+    use schema::recovery_codes;
+
+    let recovery_codes_left = recovery_codes::table.filter(recovery_codes::user_id.eq(user_id))
+        .filter(recovery_codes::used.is_null())
+        .count()
+        .get_result(&**conn)?;
+
+    Ok(TwoFactorStatus {
+           enabled: is_enabled(conn, user_id)?,
+           recovery_codes_left: recovery_codes_left,
+       })
+}
+
+pub fn is_enabled(conn: &Connection, user_id: i32) -> Result<bool> {
+    Ok(get_secret(conn, user_id)?.map(|s| s.confirmed).unwrap_or(false))
+}
+
+/// Whether the user has one of the roles that require the codes, but hasn't enabled them.
+pub fn is_missing(conn: &Connection, user_id: i32, required_roles: &[String]) -> Result<bool> {
+    if required_roles.is_empty() {
+        return Ok(false);
+    }
+    let roles = role::get_role_names(conn, user_id)?;
+    if !roles.iter().any(|r| required_roles.contains(r)) {
+        return Ok(false);
+    }
+    Ok(!is_enabled(conn, user_id)?)
+}
+
+/// Creates a new secret for the user. The codes aren't asked at login until the user
+/// has confirmed that their app works with `confirm_enrollment`. Returns the secret in
+/// base32, the way the apps want it.
+pub fn start_enrollment(conn: &Connection, user_id: i32) -> Result<String> {
+    use schema::totp_secrets;
+
+    if is_enabled(conn, user_id)? {
+        bail!(ErrorKind::InvalidInput);
+    }
+
+    let secret = random_bytes(SECRET_BYTES)?;
+
+    conn.transaction(|| {
+        diesel::delete(totp_secrets::table.filter(totp_secrets::user_id.eq(user_id)))
+            .execute(&**conn)?;
+        diesel::insert(&NewTotpSecret {
+                            user_id: user_id,
+                            secret: &secret,
+                        }).into(totp_secrets::table)
+                .execute(&**conn)?;
+        Ok(base32::encode(&secret))
+    })
+}
+
+/// The `otpauth://` URI that the authenticator apps read from a QR code.
+pub fn otpauth_uri(secret: &str, account: &str, issuer: &str) -> String {
+    fn escape(text: &str) -> String {
+        let mut escaped = String::new();
+        for b in text.bytes() {
+            match b {
+                b'a'...b'z' | b'A'...b'Z' | b'0'...b'9' | b'@' | b'.' | b'-' | b'_' => {
+                    escaped.push(b as char)
+                }
+                _ => escaped.push_str(&format!("%{:02X}", b)),
+            }
+        }
+        escaped
+    }
+
+    format!("otpauth://totp/{}:{}?secret={}&issuer={}&digits={}&period={}",
+            escape(issuer),
+            escape(account),
+            secret.trim_right_matches('='),
+            escape(issuer),
+            DIGITS,
+            STEP_SECONDS)
+}
+
+/// Enables the codes if the code from the app is right. Returns the recovery codes,
+/// which aren't stored in plaintext, so they can be shown only once.
+pub fn confirm_enrollment(conn: &Connection,
+                          user_id: i32,
+                          code: &str)
+                          -> Result<Option<Vec<String>>> {
+    use schema::totp_secrets;
+
+    let secret = try_or!(get_secret(conn, user_id)?, else return Ok(None));
+    let code = try_or!(code.trim().parse::<u32>().ok(), else return Ok(None));
+
+    if secret.confirmed || !check_totp(conn, &secret, code, UTC::now())? {
+        return Ok(None);
+    }
+
+    conn.transaction(|| {
+        diesel::update(totp_secrets::table.filter(totp_secrets::user_id.eq(user_id)))
+            .set(totp_secrets::confirmed.eq(true))
+            .execute(&**conn)?;
+        let codes = replace_recovery_codes(conn, user_id)?;
+        audit::record_action(conn, user_id, "enable_two_factor", "user", Some(user_id))?;
+        Ok(Some(codes))
+    })
+}
+
+/// Checks a code from the app or a recovery code.
+pub fn check_code(conn: &Connection, user_id: i32, code: &str) -> Result<bool> {
+    let secret = try_or!(get_secret(conn, user_id)?, else return Ok(false));
+    if !secret.confirmed {
+        return Ok(false);
+    }
+
+    let code = code.trim();
+    if code.len() == DIGITS as usize && code.chars().all(|c| c.is_digit(10)) {
+        check_totp(conn, &secret, code.parse()?, UTC::now())
+    } else {
+        use_recovery_code(conn, user_id, code)
+    }
+}
+
+/// Replaces the recovery codes with new ones if the code is right.
+pub fn regenerate_recovery_codes(conn: &Connection,
+                                 user_id: i32,
+                                 code: &str)
+                                 -> Result<Option<Vec<String>>> {
+    if !check_code(conn, user_id, code)? {
+        return Ok(None);
+    }
+    conn.transaction(|| Ok(Some(replace_recovery_codes(conn, user_id)?)))
+}
+
+/// Turns the codes off. The users need a code to do that themselves; the admins can
+/// do it for users that have lost both their phone and their recovery codes.
+pub fn disable(conn: &Connection, actor: i32, user_id: i32) -> Result<bool> {
+    use schema::{totp_secrets, recovery_codes};
+
+    conn.transaction(|| {
+        let deleted = diesel::delete(totp_secrets::table.filter(totp_secrets::user_id.eq(user_id)))
+            .execute(&**conn)?;
+        diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user_id)))
+            .execute(&**conn)?;
+        if deleted > 0 {
+            audit::record_action(conn, actor, "disable_two_factor", "user", Some(user_id))?;
+        }
+        Ok(deleted > 0)
+    })
+}
+
+/// Starts the second step of a login for a user whose password was right. The returned
+/// token identifies the login when the code is checked with `finish_login`.
+pub fn start_login(conn: &Connection, user_id: i32) -> Result<String> {
+    use schema::pending_logins;
+
+    let oldest_viable = UTC::now() - chrono::Duration::minutes(PENDING_LOGIN_MINUTES);
+    let pending: i64 = pending_logins::table.filter(pending_logins::user_id.eq(user_id))
+        .filter(pending_logins::started.gt(oldest_viable))
+        .count()
+        .get_result(&**conn)?;
+    if pending >= MAX_PENDING_LOGINS {
+        bail!(ErrorKind::RateLimitExceeded);
+    }
+
+    let token = encode_nopad(&session::fresh_token()?[..]);
+    diesel::insert(&NewPendingLogin {
+                        token: &token,
+                        user_id: user_id,
+                    }).into(pending_logins::table)
+            .execute(&**conn)?;
+    Ok(token)
+}
+
+/// Returns the user if the code is right for the pending login. After too many wrong
+/// codes, or when the login has expired, the password has to be given again.
+pub fn finish_login(conn: &Connection, token: &str, code: &str) -> Result<Option<User>> {
+    use schema::pending_logins;
+
+    let oldest_viable = UTC::now() - chrono::Duration::minutes(PENDING_LOGIN_MINUTES);
+    let pending: PendingLogin =
+        try_or!(diesel::update(pending_logins::table.filter(pending_logins::token.eq(token))
+                    .filter(pending_logins::started.gt(oldest_viable)))
+                    .set(pending_logins::attempts.eq(pending_logins::attempts + 1))
+                    .get_result(&**conn)
+                    .optional()?,
+                else return Ok(None));
+
+    if pending.attempts > MAX_ATTEMPTS || !check_code(conn, pending.user_id, code)? {
+        return Ok(None);
+    }
+
+    diesel::delete(pending_logins::table.filter(pending_logins::token.eq(token)))
+        .execute(&**conn)?;
+    Ok(Some(user::get_user(conn, pending.user_id)?))
+}
+
+/// Forgets the pending login, e.g. when the code wasn't given at all.
+pub fn cancel_login(conn: &Connection, token: &str) -> Result<()> {
+    use schema::pending_logins;
+
+    diesel::delete(pending_logins::table.filter(pending_logins::token.eq(token)))
+        .execute(&**conn)?;
+    Ok(())
+}
+
+pub fn clean_pending_logins(conn: &Connection) -> Result<usize> {
+    use schema::pending_logins;
+
+    let oldest_viable = UTC::now() - chrono::Duration::minutes(PENDING_LOGIN_MINUTES);
+    Ok(diesel::delete(pending_logins::table.filter(pending_logins::started.lt(oldest_viable)))
+           .execute(&**conn)?)
+}
+
+#[test]
+fn test_hotp() {
+    // The test values from the appendix D of RFC 4226
+    let secret = b"12345678901234567890";
+    let expected = [755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871,
+                    520489];
+    for (counter, &code) in expected.iter().enumerate() {
+        assert_eq!(hotp(secret, counter as u64), code);
+    }
+}
+
+#[test]
+fn test_recovery_code_normalization() {
+    assert_eq!(hash_recovery_code("abcd-efgh"), hash_recovery_code("ABCDEFGH"));
+    assert_eq!(hash_recovery_code(" ABCD EFGH "), hash_recovery_code("ABCD-EFGH"));
+    assert!(hash_recovery_code("ABCD-EFGH") != hash_recovery_code("ABCD-EFGI"));
+}
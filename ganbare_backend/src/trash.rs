@@ -0,0 +1,452 @@
+use super::*;
+use chrono::{DateTime, Duration, UTC};
+use diesel::expression::dsl::{any, sql};
+use diesel::types::Bool;
+
+// Words, questions, exercises and audio bundles aren't deleted right away, but moved to the
+// trash by setting `deleted`. The items in the trash aren't shown to the learners or in the
+// manager, but the answers and the due items pointing at them are kept, so they can be
+// restored. After the retention period, the background thread purges the items that no
+// learner has seen; the rest stay in the trash for the research data.
+
+#[derive(Debug, Serialize)]
+pub struct Trash {
+    pub words: Vec<Word>,
+    pub questions: Vec<QuizQuestion>,
+    pub exercises: Vec<Exercise>,
+    pub bundles: Vec<AudioBundle>,
+}
+
+/// The items in the trash, the most recently deleted first.
+pub fn get_all(conn: &Connection) -> Result<Trash> {
+    use schema::{words, quiz_questions, exercises, audio_bundles};
+
+    Ok(Trash {
+           words: words::table.filter(words::deleted.is_not_null())
+               .order(words::deleted.desc())
+               .get_results(&**conn)?,
+           questions: quiz_questions::table.filter(quiz_questions::deleted.is_not_null())
+               .order(quiz_questions::deleted.desc())
+               .get_results(&**conn)?,
+           exercises: exercises::table.filter(exercises::deleted.is_not_null())
+               .order(exercises::deleted.desc())
+               .get_results(&**conn)?,
+           bundles: audio_bundles::table.filter(audio_bundles::deleted.is_not_null())
+               .order(audio_bundles::deleted.desc())
+               .get_results(&**conn)?,
+       })
+}
+
+/// Restores the word, and the exercises that were moved to the trash with it. Fails with
+/// `InvalidInput` if its audio bundle is still in the trash; restoring the bundle restores
+/// the words that were deleted with it.
+pub fn restore_word(conn: &Connection, actor: i32, id: i32) -> Result<Option<Word>> {
+    use schema::{words, audio_bundles, exercises, exercise_variants};
+
+    conn.transaction(|| -> Result<Option<Word>> {
+
+        let word: Word = try_or!(words::table.filter(words::id.eq(id))
+                                     .filter(words::deleted.is_not_null())
+                                     .get_result(&**conn)
+                                     .optional()?,
+                                 else return Ok(None));
+
+        let bundle_deleted: Option<DateTime<UTC>> =
+            audio_bundles::table.filter(audio_bundles::id.eq(word.audio_bundle))
+                .select(audio_bundles::deleted)
+                .get_result(&**conn)?;
+        if bundle_deleted.is_some() {
+            bail!(ErrorKind::InvalidInput);
+        }
+
+        let restored: Word = diesel::update(words::table.filter(words::id.eq(id)))
+            .set(words::deleted.eq(None::<DateTime<UTC>>))
+            .get_result(&**conn)?;
+
+        let exercise_ids: Vec<i32> = exercise_variants::table
+            .filter(exercise_variants::id.eq(id))
+            .select(exercise_variants::exercise_id)
+            .get_results(&**conn)?;
+        diesel::update(exercises::table.filter(exercises::id.eq(any(exercise_ids)))
+                           .filter(exercises::deleted.eq(word.deleted)))
+                .set(exercises::deleted.eq(None::<DateTime<UTC>>))
+                .execute(&**conn)?;
+
+        audit::record(conn, actor, "restore", "word", Some(id), Some(&word), Some(&restored))?;
+
+        Ok(Some(restored))
+    })
+}
+
+/// Restores the question from the trash. Fails with `InvalidInput` if the audio bundle of
+/// any of its answers is still in the trash.
+pub fn restore_question(conn: &Connection, actor: i32, id: i32) -> Result<Option<QuizQuestion>> {
+    use schema::{quiz_questions, question_answers, audio_bundles};
+
+    conn.transaction(|| -> Result<Option<QuizQuestion>> {
+
+        let question: QuizQuestion =
+            try_or!(quiz_questions::table.filter(quiz_questions::id.eq(id))
+                        .filter(quiz_questions::deleted.is_not_null())
+                        .get_result(&**conn)
+                        .optional()?,
+                    else return Ok(None));
+
+        let answers: Vec<Answer> = question_answers::table
+            .filter(question_answers::question_id.eq(id))
+            .get_results(&**conn)?;
+        let mut bundle_ids: Vec<i32> = answers.iter().map(|a| a.q_audio_bundle).collect();
+        bundle_ids.extend(answers.iter().filter_map(|a| a.a_audio_bundle));
+
+        let deleted_bundles: i64 = audio_bundles::table.filter(audio_bundles::id.eq(any(bundle_ids)))
+            .filter(audio_bundles::deleted.is_not_null())
+            .count()
+            .get_result(&**conn)?;
+        if deleted_bundles > 0 {
+            bail!(ErrorKind::InvalidInput);
+        }
+
+        let restored: QuizQuestion =
+            diesel::update(quiz_questions::table.filter(quiz_questions::id.eq(id)))
+                .set(quiz_questions::deleted.eq(None::<DateTime<UTC>>))
+                .get_result(&**conn)?;
+        audit::record(conn,
+                      actor,
+                      "restore",
+                      "question",
+                      Some(id),
+                      Some(&question),
+                      Some(&restored))?;
+
+        Ok(Some(restored))
+    })
+}
+
+/// Restores the exercise from the trash. Fails with `InvalidInput` if any of its words is
+/// still in the trash.
+pub fn restore_exercise(conn: &Connection, actor: i32, id: i32) -> Result<Option<Exercise>> {
+    use schema::{exercises, exercise_variants, words};
+
+    conn.transaction(|| -> Result<Option<Exercise>> {
+
+        let exercise: Exercise = try_or!(exercises::table.filter(exercises::id.eq(id))
+                                             .filter(exercises::deleted.is_not_null())
+                                             .get_result(&**conn)
+                                             .optional()?,
+                                         else return Ok(None));
+
+        let word_ids: Vec<i32> = exercise_variants::table
+            .filter(exercise_variants::exercise_id.eq(id))
+            .select(exercise_variants::id)
+            .get_results(&**conn)?;
+
+        let deleted_words: i64 = words::table.filter(words::id.eq(any(word_ids)))
+            .filter(words::deleted.is_not_null())
+            .count()
+            .get_result(&**conn)?;
+        if deleted_words > 0 {
+            bail!(ErrorKind::InvalidInput);
+        }
+
+        let restored: Exercise = diesel::update(exercises::table.filter(exercises::id.eq(id)))
+            .set(exercises::deleted.eq(None::<DateTime<UTC>>))
+            .get_result(&**conn)?;
+        audit::record(conn,
+                      actor,
+                      "restore",
+                      "exercise",
+                      Some(id),
+                      Some(&exercise),
+                      Some(&restored))?;
+
+        Ok(Some(restored))
+    })
+}
+
+/// Restores the bundle, and the words, questions and exercises that were moved to the trash
+/// with it.
+pub fn restore_bundle(conn: &Connection, actor: i32, id: i32) -> Result<Option<AudioBundle>> {
+    use schema::{audio_bundles, words, quiz_questions, question_answers, exercises,
+                 exercise_variants};
+
+    conn.transaction(|| {
+        let bundle: AudioBundle = try_or!(audio_bundles::table.filter(audio_bundles::id.eq(id))
+                                              .filter(audio_bundles::deleted.is_not_null())
+                                              .get_result(&**conn)
+                                              .optional()?,
+                                          else return Ok(None));
+        let deleted = bundle.deleted;
+
+        let word_ids: Vec<i32> = diesel::update(words::table
+                                                    .filter(words::audio_bundle.eq(id))
+                                                    .filter(words::deleted.eq(deleted)))
+                .set(words::deleted.eq(None::<DateTime<UTC>>))
+                .get_results::<Word>(&**conn)?
+                .into_iter()
+                .map(|w| w.id)
+                .collect();
+
+        let exercise_ids: Vec<i32> = exercise_variants::table
+            .filter(exercise_variants::id.eq(any(word_ids)))
+            .select(exercise_variants::exercise_id)
+            .get_results(&**conn)?;
+        diesel::update(exercises::table.filter(exercises::id.eq(any(exercise_ids)))
+                           .filter(exercises::deleted.eq(deleted)))
+                .set(exercises::deleted.eq(None::<DateTime<UTC>>))
+                .execute(&**conn)?;
+
+        let question_ids: Vec<i32> = question_answers::table
+            .filter(question_answers::a_audio_bundle.eq(id)
+                        .or(question_answers::q_audio_bundle.eq(id)))
+            .select(question_answers::question_id)
+            .get_results(&**conn)?;
+        diesel::update(quiz_questions::table.filter(quiz_questions::id.eq(any(question_ids)))
+                           .filter(quiz_questions::deleted.eq(deleted)))
+                .set(quiz_questions::deleted.eq(None::<DateTime<UTC>>))
+                .execute(&**conn)?;
+
+        let restored: AudioBundle = diesel::update(audio_bundles::table
+                                                       .filter(audio_bundles::id.eq(id)))
+                .set(audio_bundles::deleted.eq(None::<DateTime<UTC>>))
+                .get_result(&**conn)?;
+        audit::record(conn, actor, "restore", "bundle", Some(id), Some(&bundle), Some(&restored))?;
+
+        Ok(Some(restored))
+    })
+}
+
+// The items that aren't pointed at by the learners' answers, due items or test items,
+// and can be deleted for good.
+
+const EXERCISE_UNUSED: &'static str = r###"
+NOT EXISTS (SELECT 1 FROM exercise_data WHERE exercise_data.exercise_id = exercises.id) AND
+NOT EXISTS (SELECT 1 FROM e_asked_data WHERE e_asked_data.exercise_id = exercises.id)
+"###;
+
+const QUESTION_UNUSED: &'static str = r###"
+NOT EXISTS (SELECT 1 FROM question_data WHERE question_data.question_id = quiz_questions.id) AND
+NOT EXISTS (SELECT 1 FROM q_asked_data WHERE q_asked_data.question_id = quiz_questions.id)
+"###;
+
+const WORD_UNUSED: &'static str = r###"
+NOT EXISTS (SELECT 1 FROM w_asked_data WHERE w_asked_data.word_id = words.id) AND
+NOT EXISTS (SELECT 1 FROM exercise_variants WHERE exercise_variants.id = words.id)
+"###;
+
+const BUNDLE_UNUSED: &'static str = r###"
+NOT EXISTS (SELECT 1 FROM words WHERE words.audio_bundle = audio_bundles.id) AND
+NOT EXISTS (
+    SELECT 1 FROM question_answers
+    WHERE question_answers.q_audio_bundle = audio_bundles.id
+        OR question_answers.a_audio_bundle = audio_bundles.id
+) AND
+NOT EXISTS (
+    SELECT 1 FROM audio_files JOIN pending_items ON pending_items.audio_file_id = audio_files.id
+    WHERE audio_files.bundle_id = audio_bundles.id
+) AND
+NOT EXISTS (
+    SELECT 1 FROM audio_files JOIN test_items ON test_items.audio_id = audio_files.id
+    WHERE audio_files.bundle_id = audio_bundles.id
+)
+"###;
+
+/// Deletes for good the items that have been in the trash longer than `retention`, unless
+/// learners have seen them. The audio files of the purged bundles are left on the disk for
+/// the cleaning tool to move to the audio trash directory. Returns the number of items purged.
+pub fn purge(conn: &Connection, retention: Duration) -> Result<usize> {
+    use schema::{exercises, exercise_variants, quiz_questions, question_answers, words,
+                 audio_bundles, audio_files};
+
+    let limit = UTC::now() - retention;
+
+    conn.transaction(|| {
+        let exercise_ids: Vec<i32> = exercises::table.filter(exercises::deleted.lt(limit))
+            .filter(sql::<Bool>(EXERCISE_UNUSED))
+            .select(exercises::id)
+            .get_results(&**conn)?;
+        diesel::delete(exercise_variants::table
+                           .filter(exercise_variants::exercise_id.eq(any(&exercise_ids))))
+                .execute(&**conn)?;
+        diesel::delete(exercises::table.filter(exercises::id.eq(any(&exercise_ids))))
+            .execute(&**conn)?;
+
+        let question_ids: Vec<i32> =
+            quiz_questions::table.filter(quiz_questions::deleted.lt(limit))
+                .filter(sql::<Bool>(QUESTION_UNUSED))
+                .select(quiz_questions::id)
+                .get_results(&**conn)?;
+        diesel::delete(question_answers::table
+                           .filter(question_answers::question_id.eq(any(&question_ids))))
+                .execute(&**conn)?;
+        diesel::delete(quiz_questions::table.filter(quiz_questions::id.eq(any(&question_ids))))
+            .execute(&**conn)?;
+
+        let word_ids: Vec<i32> = words::table.filter(words::deleted.lt(limit))
+            .filter(sql::<Bool>(WORD_UNUSED))
+            .select(words::id)
+            .get_results(&**conn)?;
+        diesel::delete(words::table.filter(words::id.eq(any(&word_ids)))).execute(&**conn)?;
+
+        let bundle_ids: Vec<i32> = audio_bundles::table.filter(audio_bundles::deleted.lt(limit))
+            .filter(sql::<Bool>(BUNDLE_UNUSED))
+            .select(audio_bundles::id)
+            .get_results(&**conn)?;
+        diesel::delete(audio_files::table.filter(audio_files::bundle_id.eq(any(&bundle_ids))))
+            .execute(&**conn)?;
+        diesel::delete(audio_bundles::table.filter(audio_bundles::id.eq(any(&bundle_ids))))
+            .execute(&**conn)?;
+
+        let purged = exercise_ids.len() + question_ids.len() + word_ids.len() + bundle_ids.len();
+        if purged > 0 {
+            info!("Purged from the trash: exercises {:?}, questions {:?}, words {:?}, \
+                   bundles {:?}",
+                  exercise_ids,
+                  question_ids,
+                  word_ids,
+                  bundle_ids);
+        }
+
+        Ok(purged)
+    })
+}
+
+/// The trash tests need a database. They are skipped unless `GANBARE_TEST_DATABASE_URL`
+/// is set; the database is migrated, and the changes of the test are rolled back.
+fn with_test_database<F: FnOnce(&Connection) -> Result<()>>(test: F) {
+    let url = match std::env::var("GANBARE_TEST_DATABASE_URL") {
+        Ok(url) => url,
+        Err(_) => return,
+    };
+    let pool = r2d2::Pool::new(r2d2::Config::default(), ConnManager::new(url.as_str()))
+        .expect("Failed to create pool.");
+    let conn = pool.get().expect("Couldn't connect to the test database.");
+    db::check(&conn).expect("Couldn't migrate the test database.");
+
+    conn.test_transaction(|| test(&conn));
+}
+
+/// A word with its own bundle and an exercise that has the word as its only variant.
+fn add_test_exercise(conn: &Connection, name: &str) -> Result<(Word, i32)> {
+    use schema::words;
+
+    let bundle = audio::new_bundle(conn, name)?;
+    let nugget = skill::get_create_by_name(conn, name)?;
+    let word: Word = diesel::insert(&NewWord {
+                                        word: name,
+                                        explanation: "",
+                                        audio_bundle: bundle.id,
+                                        skill_nugget: nugget.id,
+                                        skill_level: 0,
+                                        priority: 0,
+                                    }).into(words::table)
+            .get_result(&**conn)?;
+    let exercise_id = manage::post_exercise(conn,
+                                            1,
+                                            NewExercise {
+                                                skill_id: nugget.id,
+                                                skill_level: 0,
+                                            },
+                                            vec![ExerciseVariant {
+                                                     id: word.id,
+                                                     exercise_id: 0,
+                                                 }])?;
+    Ok((word, exercise_id))
+}
+
+fn is_in_trash(conn: &Connection, exercise_id: i32) -> Result<bool> {
+    use schema::exercises;
+
+    let deleted: Option<DateTime<UTC>> = exercises::table.filter(exercises::id.eq(exercise_id))
+        .select(exercises::deleted)
+        .get_result(&**conn)?;
+    Ok(deleted.is_some())
+}
+
+#[test]
+fn test_restore_word() {
+    with_test_database(|conn| {
+        let (word, exercise_id) = add_test_exercise(conn, "trash_test_restore")?;
+
+        assert!(manage::remove_word(conn, 1, word.id)?.is_some());
+        assert!(is_in_trash(conn, exercise_id)?);
+        assert!(get_all(conn)?.words.iter().any(|w| w.id == word.id));
+        match restore_exercise(conn, 1, exercise_id) {
+            Err(Error(ErrorKind::InvalidInput, ..)) => (),
+            r => panic!("The exercise was restored without its word: {:?}", r),
+        }
+
+        assert!(restore_word(conn, 1, word.id)?.is_some());
+        assert!(!is_in_trash(conn, exercise_id)?);
+        assert!(restore_word(conn, 1, word.id)?.is_none());
+        Ok(())
+    });
+}
+
+#[test]
+fn test_readd_trashed_word() {
+    use schema::audio_files;
+    use std::io::Write;
+
+    with_test_database(|conn| {
+        let (word, _) = add_test_exercise(conn, "trash_test_readd")?;
+
+        // The audio file is saved already, so adding the word again doesn't process it.
+        let dir = tempdir::TempDir::new("ganbare_trash_readd").unwrap();
+        let path = dir.path().join("trash_test_readd.mp3");
+        std::fs::File::create(&path).unwrap().write_all(b"trash_test_readd").unwrap();
+        let hash = audio::audio_file_hash("", &path)?;
+        let narrator = audio::get_create_narrator(conn, "trash_test")?;
+        diesel::insert(&NewAudioFile {
+                            narrators_id: narrator.id,
+                            bundle_id: word.audio_bundle,
+                            file_path: "trash_test_readd.mp3",
+                            mime: "audio/mpeg",
+                            file_sha2: &hash[..],
+                        }).into(audio_files::table)
+                .execute(&**conn)?;
+
+        manage::remove_word(conn, 1, word.id)?;
+        let new_word = manage::NewWordFromStrings {
+            word: "trash_test_readd".to_string(),
+            explanation: String::new(),
+            nugget: "trash_test_readd".to_string(),
+            narrator: "trash_test",
+            files: vec![(path, Some("trash_test_readd.mp3".to_string()),
+                         "audio/mpeg".parse().unwrap())],
+            skill_level: 0,
+            priority: 0,
+        };
+        let readded = manage::create_or_update_word(conn, Some(1), new_word, dir.path())?;
+        assert!(readded.id != word.id);
+        assert!(readded.deleted.is_none());
+        assert!(get_all(conn)?.words.iter().any(|w| w.id == word.id));
+        Ok(())
+    });
+}
+
+#[test]
+fn test_purge() {
+    use schema::{words, exercises};
+
+    fn count(conn: &Connection, word_ids: Vec<i32>, exercise_ids: Vec<i32>) -> Result<(i64, i64)> {
+        Ok((words::table.filter(words::id.eq(any(word_ids))).count().get_result(&**conn)?,
+            exercises::table.filter(exercises::id.eq(any(exercise_ids)))
+                .count()
+                .get_result(&**conn)?))
+    }
+
+    with_test_database(|conn| {
+        let (word, exercise_id) = add_test_exercise(conn, "trash_test_purge")?;
+        let (kept, kept_exercise_id) = add_test_exercise(conn, "trash_test_keep")?;
+        let word_ids = vec![word.id, kept.id];
+        let exercise_ids = vec![exercise_id, kept_exercise_id];
+
+        manage::remove_word(conn, 1, word.id)?;
+        purge(conn, Duration::days(1))?;
+        assert_eq!(count(conn, word_ids.clone(), exercise_ids.clone())?, (2, 2));
+
+        assert!(purge(conn, Duration::seconds(-1))? >= 2);
+        assert_eq!(count(conn, word_ids, exercise_ids)?, (1, 1));
+        Ok(())
+    });
+}
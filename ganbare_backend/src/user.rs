@@ -1,6 +1,7 @@
 use super::*;
 use std::time::Instant;
-use chrono::Duration;
+use chrono::{Duration, DateTime, UTC, NaiveDate, TimeZone, Timelike};
+use chrono_tz::Tz;
 
 /* TODO FIXME this can be a full-blown typed group system some day
 enum Group {
@@ -43,10 +44,14 @@ fn get_user_pass_by_email(conn: &Connection, user_email: &str) -> Result<(User,
 }
 
 
+/// Checks the password, and replaces its hash if it was made with an old algorithm,
+/// old settings or an old runtime pepper.
 pub fn auth_user(conn: &Connection,
                  email: &str,
                  plaintext_pw: &str,
-                 pepper: &[u8])
+                 pepper: &[u8],
+                 old_peppers: &[Vec<u8>],
+                 stretching_time: std::time::Duration)
                  -> Result<Option<User>> {
     let (user, hashed_pw_from_db) = match get_user_pass_by_email(conn, email) {
         Err(err) => {
@@ -59,7 +64,10 @@ pub fn auth_user(conn: &Connection,
     }?;
 
     let time_before = Instant::now();
-    match password::check_password(plaintext_pw, hashed_pw_from_db.into(), pepper) {
+    let outdated = match password::verify(plaintext_pw,
+                                          hashed_pw_from_db.into(),
+                                          pepper,
+                                          old_peppers) {
         Err(err) => {
             match *err.kind() {
                 ErrorKind::PasswordDoesntMatch => return Ok(None),
@@ -73,6 +81,14 @@ pub fn auth_user(conn: &Connection,
           (time_after - time_before).as_secs() * 1000 +
           (time_after - time_before).subsec_nanos() as u64 / 1_000_000);
 
+    if outdated {
+        // The user is let in even if this fails; the hash is replaced at the next login then.
+        match change_password(conn, user.id, plaintext_pw, pepper, stretching_time) {
+            Ok(()) => info!("Rehashed the password of user {}.", user.id),
+            Err(e) => warn!("Couldn't rehash the password of user {}: {}", user.id, e),
+        }
+    }
+
     Ok(Some(user))
 }
 
@@ -234,7 +250,9 @@ pub fn remove_user_by_email(conn: &Connection, rm_email: &str) -> Result<User> {
                  })
 }
 
-pub fn deactivate_user(conn: &Connection, id: i32) -> Result<Option<User>> {
+/// Deletes the user's email address and password. The actor is `None` when the server
+/// does it on the user's own request.
+pub fn deactivate_user(conn: &Connection, actor: Option<i32>, id: i32) -> Result<Option<User>> {
     use schema::users;
 
     let user = match users::table.filter(users::id.eq(id))
@@ -250,11 +268,16 @@ pub fn deactivate_user(conn: &Connection, id: i32) -> Result<Option<User>> {
     diesel::update(users::table.filter(users::id.eq(id))).set(users::email.eq(no_email))
         .execute(&**conn)?;
 
+    if let Some(actor) = actor {
+        audit::record_action(conn, actor, "deactivate", "user", Some(id))?;
+    }
+
     Ok(Some(user))
 }
 
 pub fn remove_user_completely(conn: &Connection, id: i32) -> Result<Option<User>> {
     use schema::users;
+    use diesel::expression::dsl::any;
 
     let user = match users::table.filter(users::id.eq(id))
               .get_result::<User>(&**conn)
@@ -271,12 +294,34 @@ pub fn remove_user_completely(conn: &Connection, id: i32) -> Result<Option<User>
         }
     }
 
+    let pending_ids: Vec<i32> = schema::pending_items::table
+        .filter(schema::pending_items::user_id.eq(id))
+        .select(schema::pending_items::id)
+        .get_results(&**conn)?;
+
+    macro_rules! delete_answers {
+        ($table:ident) => {
+            diesel::delete(
+                schema::$table::table.filter(schema::$table::id.eq(any(&pending_ids)))
+            ).execute(&**conn)?;
+        }
+    }
+
+    delete_answers!(q_answered_data);
+    delete_answers!(q_asked_data);
+    delete_answers!(e_answered_data);
+    delete_answers!(e_asked_data);
+    delete_answers!(w_answered_data);
+    delete_answers!(w_asked_data);
+
     delete!(passwords, id);
     delete!(user_metrics, id);
     delete!(user_stats, id);
     delete!(sessions, user_id);
     delete!(skill_data, user_id);
     delete!(event_experiences, user_id);
+    delete!(event_userdata, user_id);
+    delete!(reset_email_secrets, user_id);
     delete!(group_memberships, user_id);
     delete!(anon_aliases, user_id);
     delete!(pending_items, user_id);
@@ -302,7 +347,9 @@ pub fn change_password(conn: &Connection,
 }
 
 
+/// The actor is `None` when the server joins the user, like in an event transition.
 pub fn join_user_group_by_id(conn: &Connection,
+                             actor: Option<i32>,
                              user_id: i32,
                              group_id: i32)
                              -> Result<GroupMembership> {
@@ -314,18 +361,42 @@ pub fn join_user_group_by_id(conn: &Connection,
                                                           anonymous: false,
                                                       }).into(group_memberships::table)
             .get_result(&**conn)?;
+    if let Some(actor) = actor {
+        audit::record(conn,
+                      actor,
+                      "join_group",
+                      "user",
+                      Some(user_id),
+                      None,
+                      Some(&membership))?;
+    }
     Ok(membership)
 }
 
 
-pub fn remove_user_group_by_id(conn: &Connection, user_id: i32, group_id: i32) -> Result<bool> {
+pub fn remove_user_group_by_id(conn: &Connection,
+                               actor: Option<i32>,
+                               user_id: i32,
+                               group_id: i32)
+                               -> Result<bool> {
     use schema::group_memberships;
 
-    let count =
+    let membership: Option<GroupMembership> =
         diesel::delete(group_memberships::table.filter(group_memberships::user_id.eq(user_id)
-                .and(group_memberships::group_id.eq(group_id)))).execute(&**conn)?;
+                .and(group_memberships::group_id.eq(group_id)))).get_result(&**conn)
+            .optional()?;
 
-    Ok(count == 1)
+    if let (Some(actor), Some(membership)) = (actor, membership.as_ref()) {
+        audit::record(conn,
+                      actor,
+                      "leave_group",
+                      "user",
+                      Some(user_id),
+                      Some(membership),
+                      None)?;
+    }
+
+    Ok(membership.is_some())
 }
 
 pub fn group_size(conn: &Connection, group_name: &str) -> Result<i64> {
@@ -534,37 +605,265 @@ pub fn get_all(conn: &Connection)
     Ok((user_data, groups, confirms))
 }
 
-pub fn set_metrics(conn: &Connection, metrics: &UpdateUserMetrics) -> Result<Option<UserMetrics>> {
+pub fn set_metrics(conn: &Connection,
+                   actor: i32,
+                   metrics: &UpdateUserMetrics)
+                   -> Result<Option<UserMetrics>> {
     use schema::user_metrics;
 
-    let item = diesel::update(user_metrics::table.filter(user_metrics::id.eq(metrics.id))).set(metrics)
+    let new_model = match metrics.memory_model {
+        Some(ref model) => Some(scheduler::MemoryModel::from_name(model)?),
+        None => None,
+    };
+
+    conn.transaction(|| {
+        let before: UserMetrics =
+            try_or!(user_metrics::table.filter(user_metrics::id.eq(metrics.id))
+                        .get_result(&**conn)
+                        .optional()?,
+                    else return Ok(None));
+
+        if let Some(model) = new_model {
+            if model.name() != before.memory_model {
+                adopt_due_items(conn, before.id, model)?;
+            }
+        }
+
+        let item: Option<UserMetrics> =
+            diesel::update(user_metrics::table.filter(user_metrics::id.eq(metrics.id)))
+                .set(metrics)
+                .get_result(&**conn)
+                .optional()?;
+
+        if let Some(ref item) = item {
+            audit::record(conn,
+                          actor,
+                          "update",
+                          "user_metrics",
+                          Some(metrics.id),
+                          Some(&before),
+                          Some(item))?;
+        }
+
+        Ok(item)
+    })
+}
+
+/// Converts the scheduling state of the user's due items for the memory model `model`.
+fn adopt_due_items(conn: &Connection, user_id: i32, model: scheduler::MemoryModel) -> Result<()> {
+    use schema::due_items;
+
+    let items: Vec<DueItem> = due_items::table.filter(due_items::user_id.eq(user_id))
+        .get_results(&**conn)?;
+    for mut item in items {
+        model.adopt(&mut item);
+        let _: DueItem = item.save_changes(&**conn)?;
+    }
+    Ok(())
+}
+
+/// Switches every member of the group over to the memory model `model`.
+/// Returns the number of users affected.
+pub fn set_group_memory_model(conn: &Connection,
+                              actor: i32,
+                              group_id: i32,
+                              model: &str)
+                              -> Result<usize> {
+    use schema::{user_metrics, group_memberships};
+
+    let model = scheduler::MemoryModel::from_name(model)?;
+
+    conn.transaction(|| {
+        let members: Vec<i32> = group_memberships::table
+            .filter(group_memberships::group_id.eq(group_id))
+            .select(group_memberships::user_id)
+            .get_results(&**conn)?;
+
+        let switching: Vec<i32> = user_metrics::table
+            .filter(user_metrics::id.eq_any(members))
+            .filter(user_metrics::memory_model.ne(model.name()))
+            .select(user_metrics::id)
+            .get_results(&**conn)?;
+
+        for &user_id in &switching {
+            adopt_due_items(conn, user_id, model)?;
+        }
+
+        let count = diesel::update(user_metrics::table.filter(user_metrics::id
+                .eq_any(switching)))
+            .set(user_metrics::memory_model.eq(model.name()))
+            .execute(&**conn)?;
+
+        audit::record(conn,
+                      actor,
+                      "set_memory_model",
+                      "group",
+                      Some(group_id),
+                      None,
+                      Some(&model.name()))?;
+
+        Ok(count)
+    })
+}
+
+pub fn set_timezone(conn: &Connection,
+                    actor: i32,
+                    user_id: i32,
+                    settings: &UpdateUserTimezone)
+                    -> Result<Option<User>> {
+    use schema::users;
+
+    parse_timezone(&settings.timezone)?;
+    if settings.day_rollover_hour < 0 || settings.day_rollover_hour > 23 {
+        bail!(ErrorKind::InvalidInput);
+    }
+
+    let before: User = try_or!(users::table.filter(users::id.eq(user_id))
+                                   .get_result(&**conn)
+                                   .optional()?,
+                               else return Ok(None));
+
+    let user: Option<User> = diesel::update(users::table.filter(users::id.eq(user_id)))
+        .set(settings)
+        .get_result(&**conn)
+        .optional()?;
+
+    if let Some(ref user) = user {
+        audit::record(conn,
+                      actor,
+                      "set_timezone",
+                      "user",
+                      Some(user_id),
+                      Some(&(&before.timezone, before.day_rollover_hour)),
+                      Some(&(&user.timezone, user.day_rollover_hour)))?;
+    }
+
+    Ok(user)
+}
+
+/// The actor is `None` when the user sets the locale themselves.
+pub fn set_locale(conn: &Connection,
+                  actor: Option<i32>,
+                  user_id: i32,
+                  settings: &UpdateUserLocale)
+                  -> Result<Option<User>> {
+    use schema::users;
+
+    i18n::check_locale(&settings.locale)?;
+
+    let before: User = try_or!(users::table.filter(users::id.eq(user_id))
+                                   .get_result(&**conn)
+                                   .optional()?,
+                               else return Ok(None));
+
+    let user: Option<User> = diesel::update(users::table.filter(users::id.eq(user_id)))
+        .set(settings)
         .get_result(&**conn)
         .optional()?;
 
-    Ok(item)
+    if let (Some(actor), Some(user)) = (actor, user.as_ref()) {
+        audit::record(conn,
+                      actor,
+                      "set_locale",
+                      "user",
+                      Some(user_id),
+                      Some(&before.locale),
+                      Some(&user.locale))?;
+    }
+
+    Ok(user)
+}
+
+fn parse_timezone(timezone: &str) -> Result<Tz> {
+    timezone.parse::<Tz>().map_err(|_| ErrorKind::InvalidInput.into())
+}
+
+/// The moment the user's day changes on the local `date`.
+fn rollover_on(tz: &Tz, date: NaiveDate, hour: u32) -> Result<DateTime<UTC>> {
+    let local = date.and_hms(hour, 0, 0);
+    // If the rollover hour happens to be skipped because of a DST change,
+    // the next hour will do.
+    let rollover = tz.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(local + Duration::hours(1))).earliest())
+        .ok_or_else(|| ErrorKind::DatabaseOdd("Can't find a local time for day rollover."))?;
+    Ok(rollover.with_timezone(&UTC))
 }
 
-pub fn get_slackers(conn: &Connection, inactive: Duration) -> Result<Vec<(i32, String)>> {
+/// The moment the current day of the user started at `at`, according to the user's timezone
+/// and the day rollover hour.
+pub fn day_start(user: &User, at: DateTime<UTC>) -> Result<DateTime<UTC>> {
+    let tz = parse_timezone(&user.timezone)?;
+    let hour = user.day_rollover_hour as u32;
+    let local = at.with_timezone(&tz).naive_local();
+    let date = if local.hour() < hour {
+        local.date().pred()
+    } else {
+        local.date()
+    };
+    rollover_on(&tz, date, hour)
+}
+
+/// The moment the day of the user that is ongoing at `at` ends.
+pub fn day_end(user: &User, at: DateTime<UTC>) -> Result<DateTime<UTC>> {
+    let tz = parse_timezone(&user.timezone)?;
+    let hour = user.day_rollover_hour as u32;
+    let start = day_start(user, at)?.with_timezone(&tz).naive_local();
+    rollover_on(&tz, start.date().succ(), hour)
+}
+
+/// Users that have stuff to study but haven't been seen for a while.
+/// The absence is counted from the end of the day the user was last seen,
+/// in the user's own timezone.
+/// Returns the id, the email address and the locale of the slackers.
+pub fn get_slackers(conn: &Connection,
+                    inactive: Duration)
+                    -> Result<Vec<(i32, String, String)>> {
     use schema::{users};
 
-    let slackers: Vec<(i32, Option<String>)> = users::table
+    let slackers: Vec<User> = users::table
         .filter(users::email.is_not_null())
         .filter(users::last_seen.lt(chrono::UTC::now() - inactive))
-        .select((users::id, users::email))
         .get_results(&**conn)?;
 
     let mut true_slackers = vec![];
-    for (user_id, email) in slackers {
+    for user in slackers {
+
+        if day_end(&user, user.last_seen)? > chrono::UTC::now() - inactive {
+            continue; // The absence isn't long enough when counted in whole days
+        }
 
         let (next_existing_due, no_new_words, no_new_quizes) = quiz::things_left_to_do(conn,
-                                                                                       user_id)?;
+                                                                                       user.id)?;
 
         if no_new_words && no_new_quizes && next_existing_due.is_none() {
             continue; // Nothing left to study , so he isn't a slacker
         }
 
-        true_slackers.push((user_id, email.expect("We filtered NULL emails earlier")));
+        true_slackers.push((user.id,
+                            user.email.expect("We filtered NULL emails earlier"),
+                            user.locale));
 
     }
     Ok(true_slackers)
 }
+
+#[test]
+fn test_day_start() {
+    let user = User {
+        id: 1,
+        email: None,
+        joined: UTC.ymd(2017, 1, 1).and_hms(0, 0, 0),
+        last_seen: UTC.ymd(2017, 1, 1).and_hms(0, 0, 0),
+        timezone: "Asia/Tokyo".into(),
+        day_rollover_hour: 4,
+        locale: "fi".into(),
+    };
+    // 03:00 in Tokyo still belongs to the previous day that started 04:00 (= 19:00 UTC)
+    assert_eq!(day_start(&user, UTC.ymd(2017, 3, 14).and_hms(18, 0, 0)).unwrap(),
+               UTC.ymd(2017, 3, 13).and_hms(19, 0, 0));
+    assert_eq!(day_start(&user, UTC.ymd(2017, 3, 14).and_hms(20, 0, 0)).unwrap(),
+               UTC.ymd(2017, 3, 14).and_hms(19, 0, 0));
+    assert_eq!(day_end(&user, UTC.ymd(2017, 3, 14).and_hms(18, 0, 0)).unwrap(),
+               UTC.ymd(2017, 3, 14).and_hms(19, 0, 0));
+}
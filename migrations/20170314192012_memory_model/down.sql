@@ -0,0 +1,3 @@
+ALTER TABLE user_metrics DROP COLUMN memory_model;
+ALTER TABLE due_items DROP COLUMN difficulty;
+ALTER TABLE due_items DROP COLUMN stability;
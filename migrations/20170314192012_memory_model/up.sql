@@ -0,0 +1,3 @@
+ALTER TABLE due_items ADD COLUMN stability DOUBLE PRECISION NOT NULL DEFAULT 0;
+ALTER TABLE due_items ADD COLUMN difficulty DOUBLE PRECISION NOT NULL DEFAULT 0;
+ALTER TABLE user_metrics ADD COLUMN memory_model VARCHAR NOT NULL DEFAULT 'multiplicative';
@@ -0,0 +1,2 @@
+ALTER TABLE users DROP COLUMN day_rollover_hour;
+ALTER TABLE users DROP COLUMN timezone;
@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN timezone VARCHAR NOT NULL DEFAULT 'Europe/Helsinki';
+ALTER TABLE users ADD COLUMN day_rollover_hour INTEGER NOT NULL DEFAULT 3 CHECK (day_rollover_hour >= 0 AND day_rollover_hour < 24);
@@ -0,0 +1,23 @@
+CREATE TABLE test_items (
+	id SERIAL PRIMARY KEY,
+	event_id INTEGER REFERENCES events ON DELETE CASCADE NOT NULL,
+	ordering INTEGER NOT NULL DEFAULT 0,
+	item_type VARCHAR NOT NULL CHECK (item_type IN ('word', 'question', 'exercise', 'retelling')),
+	item_text VARCHAR NOT NULL,
+	audio_id INTEGER REFERENCES audio_files ON DELETE RESTRICT,
+	audio_path VARCHAR
+);
+
+CREATE INDEX test_items_event_id_ordering_idx ON test_items (event_id, ordering);
+
+INSERT INTO test_items (event_id, ordering, item_type, item_text, audio_path)
+	SELECT events.id, retellings.ordering, 'retelling', retellings.img, retellings.audio
+	FROM events, (VALUES
+		(0, 'static/content_images/retelling/yamada.png', 'static/content_audio/retelling/yamada.mp3'),
+		(1, 'static/content_images/retelling/nishida.png', 'static/content_audio/retelling/nishida.mp3'),
+		(2, 'static/content_images/retelling/mari_a.png', 'static/content_audio/retelling/mari_a.mp3'),
+		(3, 'static/content_images/retelling/mari_b.png', 'static/content_audio/retelling/mari_b.mp3'),
+		(4, 'static/content_images/retelling/mari_c.png', 'static/content_audio/retelling/mari_c.mp3'),
+		(5, 'static/content_images/retelling/mari_d.png', 'static/content_audio/retelling/mari_d.mp3')
+	) AS retellings (ordering, img, audio)
+	WHERE events.name IN ('pretest_retelling', 'posttest_retelling');
@@ -0,0 +1,5 @@
+DROP TABLE event_transitions_fired;
+DROP TABLE event_transitions;
+ALTER TABLE events DROP COLUMN required_event;
+ALTER TABLE events DROP COLUMN page;
+ALTER TABLE events DROP COLUMN page_kind;
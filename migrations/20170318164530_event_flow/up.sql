@@ -0,0 +1,52 @@
+ALTER TABLE events ADD COLUMN page_kind VARCHAR NOT NULL DEFAULT 'text' CHECK (page_kind IN ('text', 'survey', 'test', 'retelling', 'sorting', 'training'));
+ALTER TABLE events ADD COLUMN page VARCHAR;
+ALTER TABLE events ADD COLUMN required_event INTEGER REFERENCES events ON DELETE SET NULL;
+
+UPDATE events SET page='/welcome' WHERE name='welcome';
+UPDATE events SET page='/agreement' WHERE name='agreement';
+UPDATE events SET page='/info' WHERE name='info';
+UPDATE events SET page='/survey', page_kind='survey' WHERE name='survey';
+UPDATE events SET page='/pretest_info' WHERE name='pretest_info';
+UPDATE events SET page='/pretest', page_kind='test' WHERE name='pretest';
+UPDATE events SET page='/pretest_retelling', page_kind='retelling' WHERE name='pretest_retelling';
+UPDATE events SET page='/pretest_done' WHERE name='pretest_done';
+UPDATE events SET page='/sorting', page_kind='sorting' WHERE name='sorting_ceremony';
+UPDATE events SET page='/posttest_info' WHERE name='posttest_info';
+UPDATE events SET page='/posttest', page_kind='test' WHERE name='posttest';
+UPDATE events SET page='/posttest_retelling', page_kind='retelling' WHERE name='posttest_retelling';
+UPDATE events SET page='/posttest_done' WHERE name='posttest_done';
+UPDATE events SET page_kind='training' WHERE name='training';
+
+CREATE TABLE event_transitions (
+	id SERIAL PRIMARY KEY,
+	event_id INTEGER REFERENCES events ON DELETE CASCADE NOT NULL,
+	fires_on VARCHAR NOT NULL DEFAULT 'started' CHECK (fires_on IN ('started', 'finished')),
+	delay_hours INTEGER NOT NULL DEFAULT 0 CHECK (delay_hours >= 0),
+	required_group INTEGER REFERENCES user_groups ON DELETE CASCADE,
+	unless_finished INTEGER REFERENCES events ON DELETE CASCADE,
+	join_group INTEGER REFERENCES user_groups ON DELETE CASCADE,
+	leave_group INTEGER REFERENCES user_groups ON DELETE CASCADE
+);
+
+CREATE TABLE event_transitions_fired (
+	transition_id INTEGER REFERENCES event_transitions ON DELETE CASCADE NOT NULL,
+	user_id INTEGER REFERENCES users ON DELETE CASCADE NOT NULL,
+	fired TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+	PRIMARY KEY (transition_id, user_id)
+);
+
+INSERT INTO event_transitions (event_id, fires_on, join_group)
+	SELECT events.id, 'started', user_groups.id FROM events, user_groups
+	WHERE events.name='survey' AND user_groups.group_name='nag_emails';
+
+INSERT INTO event_transitions (event_id, fires_on, delay_hours, required_group, unless_finished, join_group)
+	SELECT training.id, 'started', 240, subjects.id, posttest.id, posttest_group.id
+	FROM events AS training, events AS posttest, user_groups AS subjects, user_groups AS posttest_group
+	WHERE training.name='training' AND posttest.name='posttest'
+		AND subjects.group_name='subjects' AND posttest_group.group_name='posttest';
+
+INSERT INTO event_transitions_fired (transition_id, user_id)
+	SELECT event_transitions.id, event_experiences.user_id
+	FROM event_transitions, event_experiences, events
+	WHERE event_transitions.event_id=events.id AND event_experiences.event_id=events.id
+		AND events.name='survey';
@@ -0,0 +1,4 @@
+DROP TABLE randomization_log;
+DROP TABLE randomization_strata;
+DROP TABLE randomization_arms;
+DROP TABLE randomizations;
@@ -0,0 +1,50 @@
+CREATE TABLE randomizations (
+	id SERIAL PRIMARY KEY,
+	event_id INTEGER UNIQUE REFERENCES events ON DELETE CASCADE NOT NULL,
+	block_size INTEGER NOT NULL CHECK (block_size > 0),
+	seed BIGINT NOT NULL,
+	anonymous BOOLEAN NOT NULL DEFAULT true
+);
+
+CREATE TABLE randomization_arms (
+	id SERIAL PRIMARY KEY,
+	randomization_id INTEGER REFERENCES randomizations ON DELETE CASCADE NOT NULL,
+	group_id INTEGER REFERENCES user_groups ON DELETE RESTRICT NOT NULL,
+	ratio INTEGER NOT NULL DEFAULT 1 CHECK (ratio > 0),
+	UNIQUE (randomization_id, group_id)
+);
+
+CREATE TABLE randomization_strata (
+	randomization_id INTEGER REFERENCES randomizations ON DELETE CASCADE NOT NULL,
+	group_id INTEGER REFERENCES user_groups ON DELETE RESTRICT NOT NULL,
+	PRIMARY KEY (randomization_id, group_id)
+);
+
+CREATE TABLE randomization_log (
+	id SERIAL PRIMARY KEY,
+	randomization_id INTEGER REFERENCES randomizations ON DELETE CASCADE NOT NULL,
+	user_id INTEGER REFERENCES users ON DELETE SET NULL,
+	stratum VARCHAR NOT NULL,
+	block_number INTEGER NOT NULL,
+	block_position INTEGER NOT NULL,
+	arm_group_id INTEGER REFERENCES user_groups ON DELETE RESTRICT NOT NULL,
+	assigned TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+	UNIQUE (randomization_id, user_id)
+);
+
+CREATE INDEX randomization_log_stratum_idx ON randomization_log (randomization_id, stratum);
+
+INSERT INTO randomizations (event_id, block_size, seed)
+	SELECT id, 2, floor(random() * 2147483647)::BIGINT FROM events WHERE name='sorting_ceremony';
+
+INSERT INTO randomization_arms (randomization_id, group_id, ratio)
+	SELECT randomizations.id, user_groups.id, 1
+	FROM randomizations, events, user_groups
+	WHERE randomizations.event_id=events.id AND events.name='sorting_ceremony'
+		AND user_groups.group_name IN ('questions', 'exercises');
+
+INSERT INTO randomization_strata (randomization_id, group_id)
+	SELECT randomizations.id, user_groups.id
+	FROM randomizations, events, user_groups
+	WHERE randomizations.event_id=events.id AND events.name='sorting_ceremony'
+		AND user_groups.group_name IN ('japani1', 'japani2', 'japani3', 'japani4');
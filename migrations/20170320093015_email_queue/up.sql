@@ -0,0 +1,15 @@
+CREATE TABLE email_queue (
+	id SERIAL PRIMARY KEY,
+	from_address VARCHAR NOT NULL,
+	to_address VARCHAR NOT NULL,
+	subject VARCHAR NOT NULL,
+	message TEXT NOT NULL,
+	status VARCHAR NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'sent', 'failed')),
+	attempts INTEGER NOT NULL DEFAULT 0,
+	next_attempt TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+	last_error VARCHAR,
+	queued TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+	sent TIMESTAMPTZ
+);
+
+CREATE INDEX email_queue_status_next_attempt_idx ON email_queue (status, next_attempt);
@@ -0,0 +1,2 @@
+ALTER TABLE pending_email_confirms DROP COLUMN locale;
+ALTER TABLE users DROP COLUMN locale;
@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN locale VARCHAR NOT NULL DEFAULT 'fi';
+ALTER TABLE pending_email_confirms ADD COLUMN locale VARCHAR NOT NULL DEFAULT 'fi';
@@ -0,0 +1,7 @@
+CREATE TABLE audio_renditions (
+	id SERIAL PRIMARY KEY,
+	audio_file_id INTEGER NOT NULL REFERENCES audio_files(id) ON DELETE CASCADE,
+	file_path VARCHAR NOT NULL,
+	mime VARCHAR NOT NULL,
+	UNIQUE(audio_file_id, mime)
+);
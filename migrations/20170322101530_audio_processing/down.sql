@@ -0,0 +1,2 @@
+ALTER TABLE audio_files DROP COLUMN processing;
+ALTER TABLE audio_files DROP COLUMN processed;
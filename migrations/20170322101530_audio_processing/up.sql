@@ -0,0 +1,2 @@
+ALTER TABLE audio_files ADD COLUMN processing VARCHAR;
+ALTER TABLE audio_files ADD COLUMN processed TIMESTAMPTZ;
@@ -0,0 +1,4 @@
+DROP INDEX anon_aliases_user_group_key;
+DROP INDEX anon_aliases_name_key;
+DELETE FROM group_memberships WHERE group_id=(SELECT id FROM user_groups WHERE group_name='researchers');
+DELETE FROM user_groups WHERE group_name='researchers';
@@ -0,0 +1,3 @@
+INSERT INTO user_groups (group_name) VALUES ('researchers');
+CREATE UNIQUE INDEX anon_aliases_name_key ON anon_aliases (name);
+CREATE UNIQUE INDEX anon_aliases_user_group_key ON anon_aliases (user_id, COALESCE(group_id, 0));
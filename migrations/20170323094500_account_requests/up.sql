@@ -0,0 +1,13 @@
+CREATE TABLE account_requests (
+	id SERIAL PRIMARY KEY,
+	user_id INTEGER NOT NULL,
+	action VARCHAR NOT NULL CHECK (action IN ('export', 'delete', 'anonymize')),
+	requested TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+	confirmed TIMESTAMPTZ,
+	due TIMESTAMPTZ,
+	cancelled TIMESTAMPTZ,
+	executed TIMESTAMPTZ
+);
+
+CREATE INDEX account_requests_user_id_idx ON account_requests (user_id);
+CREATE INDEX account_requests_due_idx ON account_requests (due) WHERE executed IS NULL AND cancelled IS NULL;
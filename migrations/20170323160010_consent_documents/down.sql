@@ -0,0 +1,7 @@
+UPDATE events SET page_kind='text' WHERE name='agreement';
+
+DROP TABLE consents;
+DROP TABLE consent_documents;
+
+ALTER TABLE events DROP CONSTRAINT events_page_kind_check;
+ALTER TABLE events ADD CONSTRAINT events_page_kind_check CHECK (page_kind IN ('text', 'survey', 'test', 'retelling', 'sorting', 'training'));
@@ -0,0 +1,49 @@
+ALTER TABLE events DROP CONSTRAINT events_page_kind_check;
+ALTER TABLE events ADD CONSTRAINT events_page_kind_check CHECK (page_kind IN ('text', 'survey', 'test', 'retelling', 'sorting', 'training', 'consent'));
+
+CREATE TABLE consent_documents (
+	id SERIAL PRIMARY KEY,
+	version INTEGER NOT NULL,
+	locale VARCHAR NOT NULL,
+	content TEXT NOT NULL,
+	published TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+	UNIQUE (version, locale)
+);
+
+CREATE TABLE consents (
+	id SERIAL PRIMARY KEY,
+	user_id INTEGER REFERENCES users ON DELETE CASCADE NOT NULL,
+	document_id INTEGER REFERENCES consent_documents ON DELETE RESTRICT NOT NULL,
+	agreed BOOLEAN NOT NULL,
+	decided TIMESTAMPTZ NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX consents_user_id_idx ON consents (user_id, decided);
+
+INSERT INTO consent_documents (version, locale, content) VALUES (1, 'fi', '<h2>Suostumus tietojen keräämiseen</h2>
+<p class="wordyExplanation">
+Tämä ohjelma kerää tietoja käyttäjästään tutkimusta varten. Tallennetut tiedot ovat:
+</p>
+<ul class="wordyExplanation">
+<li>Käyttäjän sähköpostiosoite</li>
+<li>Käyttäjän vastaukset alkuhaastatteluun</li>
+<li>Käyttäjän vastaukset harjoituskysymyksiin</li>
+<li>Käyttäjän mikrofonilla nauhoittamat ääninäytteet</li>
+</ul>
+<p class="wordyExplanation">
+Sitoudumme käsittelemään tietoja huolellisesti:
+</p>
+<ul class="wordyExplanation">
+<li>Tietoja ei luovuteta kolmansille osapuolille.</li>
+<li>Kerätyt tiedot anonymisoidaan niin, että kokeeseen osallistujia ei voi niistä tunnistaa.</li>
+<li>Saatuja tutkimustuloksia – muttei kerättyjä tietoja itsessään – saatetaan julkaista.</li>
+<li>Tietoja säilytetään maksimissaan kolme vuotta niiden keräämisestä.</li>
+</ul>');
+
+INSERT INTO consents (user_id, document_id, agreed, decided)
+	SELECT event_experiences.user_id, consent_documents.id, true, event_experiences.event_finish
+	FROM event_experiences, events, consent_documents
+	WHERE event_experiences.event_id=events.id AND events.name='agreement'
+		AND event_experiences.event_finish IS NOT NULL AND consent_documents.version=1;
+
+UPDATE events SET page_kind='consent' WHERE name='agreement';
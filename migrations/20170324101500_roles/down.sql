@@ -0,0 +1,10 @@
+INSERT INTO user_groups (group_name) VALUES ('admins'), ('editors'), ('researchers');
+
+INSERT INTO group_memberships (user_id, group_id)
+	SELECT user_roles.user_id, user_groups.id FROM user_roles
+	JOIN roles ON roles.id = user_roles.role_id
+	JOIN user_groups ON user_groups.group_name = CASE roles.role_name WHEN 'admin' THEN 'admins' WHEN 'editor' THEN 'editors' WHEN 'researcher' THEN 'researchers' END;
+
+DROP TABLE user_roles;
+DROP TABLE role_permissions;
+DROP TABLE roles;
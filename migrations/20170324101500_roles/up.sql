@@ -0,0 +1,39 @@
+CREATE TABLE roles (
+	id SERIAL PRIMARY KEY,
+	role_name VARCHAR NOT NULL UNIQUE
+);
+
+CREATE TABLE role_permissions (
+	role_id INTEGER REFERENCES roles ON DELETE CASCADE NOT NULL,
+	permission VARCHAR NOT NULL CHECK (permission IN ('edit_content', 'manage_events', 'manage_experiments', 'view_users', 'manage_users', 'send_mail', 'export_research', 'view_anonymous')),
+	PRIMARY KEY (role_id, permission)
+);
+
+CREATE TABLE user_roles (
+	user_id INTEGER REFERENCES users ON DELETE CASCADE NOT NULL,
+	role_id INTEGER REFERENCES roles ON DELETE CASCADE NOT NULL,
+	PRIMARY KEY (user_id, role_id)
+);
+
+INSERT INTO roles (role_name) VALUES ('admin'), ('editor'), ('researcher');
+
+INSERT INTO role_permissions (role_id, permission)
+	SELECT roles.id, permissions.permission FROM roles, (VALUES ('edit_content'), ('manage_events'), ('manage_experiments'), ('view_users'), ('manage_users'), ('send_mail'), ('export_research'), ('view_anonymous')) AS permissions (permission)
+	WHERE roles.role_name = 'admin';
+INSERT INTO role_permissions (role_id, permission)
+	SELECT roles.id, permissions.permission FROM roles, (VALUES ('edit_content'), ('manage_events'), ('view_users'), ('send_mail')) AS permissions (permission)
+	WHERE roles.role_name = 'editor';
+INSERT INTO role_permissions (role_id, permission)
+	SELECT roles.id, permissions.permission FROM roles, (VALUES ('view_users'), ('export_research'), ('view_anonymous')) AS permissions (permission)
+	WHERE roles.role_name = 'researcher';
+
+INSERT INTO user_roles (user_id, role_id)
+	SELECT group_memberships.user_id, roles.id FROM group_memberships
+	JOIN user_groups ON user_groups.id = group_memberships.group_id
+	JOIN roles ON roles.role_name = CASE user_groups.group_name WHEN 'admins' THEN 'admin' WHEN 'editors' THEN 'editor' WHEN 'researchers' THEN 'researcher' END;
+
+DELETE FROM group_memberships WHERE group_id IN (SELECT id FROM user_groups WHERE group_name IN ('admins', 'editors', 'researchers'));
+DELETE FROM anon_aliases WHERE group_id IN (SELECT id FROM user_groups WHERE group_name IN ('admins', 'editors', 'researchers'));
+UPDATE events SET required_group = NULL WHERE required_group IN (SELECT id FROM user_groups WHERE group_name IN ('admins', 'editors', 'researchers'));
+
+DELETE FROM user_groups WHERE group_name IN ('admins', 'editors', 'researchers');
@@ -0,0 +1,6 @@
+DELETE FROM role_permissions WHERE permission = 'view_audit_log';
+ALTER TABLE role_permissions DROP CONSTRAINT role_permissions_permission_check;
+ALTER TABLE role_permissions ADD CONSTRAINT role_permissions_permission_check CHECK (permission IN ('edit_content', 'manage_events', 'manage_experiments', 'view_users', 'manage_users', 'send_mail', 'export_research', 'view_anonymous'));
+
+DROP TABLE audit_log;
+DROP FUNCTION audit_log_append_only();
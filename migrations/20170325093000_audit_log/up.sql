@@ -0,0 +1,30 @@
+CREATE TABLE audit_log (
+	id SERIAL PRIMARY KEY,
+	actor INTEGER, -- Not a foreign key: the log is kept when the user is deleted.
+	action VARCHAR NOT NULL,
+	target_type VARCHAR NOT NULL,
+	target_id INTEGER,
+	before TEXT,
+	after TEXT,
+	logged TIMESTAMPTZ NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX audit_log_actor_idx ON audit_log (actor);
+CREATE INDEX audit_log_target_idx ON audit_log (target_type, target_id);
+
+CREATE FUNCTION audit_log_append_only() RETURNS trigger AS $$
+BEGIN
+	RAISE EXCEPTION 'The audit log is append-only.';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_no_update_or_delete BEFORE UPDATE OR DELETE ON audit_log
+	FOR EACH ROW EXECUTE PROCEDURE audit_log_append_only();
+CREATE TRIGGER audit_log_no_truncate BEFORE TRUNCATE ON audit_log
+	FOR EACH STATEMENT EXECUTE PROCEDURE audit_log_append_only();
+
+ALTER TABLE role_permissions DROP CONSTRAINT role_permissions_permission_check;
+ALTER TABLE role_permissions ADD CONSTRAINT role_permissions_permission_check CHECK (permission IN ('edit_content', 'manage_events', 'manage_experiments', 'view_users', 'manage_users', 'send_mail', 'export_research', 'view_anonymous', 'view_audit_log'));
+
+INSERT INTO role_permissions (role_id, permission)
+	SELECT roles.id, 'view_audit_log' FROM roles WHERE roles.role_name = 'admin';
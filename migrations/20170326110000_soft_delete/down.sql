@@ -0,0 +1,4 @@
+ALTER TABLE audio_bundles DROP COLUMN deleted;
+ALTER TABLE exercises DROP COLUMN deleted;
+ALTER TABLE quiz_questions DROP COLUMN deleted;
+ALTER TABLE words DROP COLUMN deleted;
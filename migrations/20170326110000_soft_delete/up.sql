@@ -0,0 +1,4 @@
+ALTER TABLE words ADD COLUMN deleted TIMESTAMPTZ;
+ALTER TABLE quiz_questions ADD COLUMN deleted TIMESTAMPTZ;
+ALTER TABLE exercises ADD COLUMN deleted TIMESTAMPTZ;
+ALTER TABLE audio_bundles ADD COLUMN deleted TIMESTAMPTZ;
@@ -0,0 +1,11 @@
+CREATE TABLE content_revisions (
+	id SERIAL PRIMARY KEY,
+	item_type VARCHAR NOT NULL CHECK (item_type IN ('word', 'question', 'answer')),
+	item_id INTEGER NOT NULL,
+	content TEXT NOT NULL,
+	author INTEGER REFERENCES users ON DELETE SET NULL,
+	source VARCHAR NOT NULL CHECK (source IN ('original', 'ui', 'cleanup', 'import', 'revert')),
+	created TIMESTAMPTZ NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX content_revisions_item_idx ON content_revisions (item_type, item_id, id);
@@ -0,0 +1,2 @@
+DROP TABLE api_token_roles;
+DROP TABLE api_tokens;
@@ -0,0 +1,18 @@
+CREATE TABLE api_tokens (
+	id SERIAL PRIMARY KEY,
+	user_id INTEGER REFERENCES users ON DELETE CASCADE NOT NULL,
+	name VARCHAR NOT NULL,
+	kind VARCHAR NOT NULL CHECK (kind IN ('personal', 'session')),
+	secret BYTEA NOT NULL,
+	created TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+	expires TIMESTAMPTZ,
+	last_used TIMESTAMPTZ
+);
+
+CREATE INDEX api_tokens_user_id_idx ON api_tokens (user_id);
+
+CREATE TABLE api_token_roles (
+	token_id INTEGER REFERENCES api_tokens ON DELETE CASCADE NOT NULL,
+	role_id INTEGER REFERENCES roles ON DELETE CASCADE NOT NULL,
+	PRIMARY KEY (token_id, role_id)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE sessions ADD COLUMN user_agent VARCHAR;
+ALTER TABLE sessions ADD COLUMN ip VARCHAR;
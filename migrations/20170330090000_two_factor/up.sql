@@ -0,0 +1,23 @@
+CREATE TABLE totp_secrets (
+	user_id INTEGER PRIMARY KEY REFERENCES users ON DELETE CASCADE,
+	secret BYTEA NOT NULL,
+	confirmed BOOLEAN NOT NULL DEFAULT false,
+	last_step BIGINT NOT NULL DEFAULT 0,
+	created TIMESTAMPTZ NOT NULL DEFAULT current_timestamp
+);
+
+CREATE TABLE recovery_codes (
+	id SERIAL PRIMARY KEY,
+	user_id INTEGER REFERENCES users ON DELETE CASCADE NOT NULL,
+	code_hash BYTEA NOT NULL,
+	used TIMESTAMPTZ
+);
+
+CREATE INDEX recovery_codes_user_id_idx ON recovery_codes (user_id);
+
+CREATE TABLE pending_logins (
+	token VARCHAR PRIMARY KEY,
+	user_id INTEGER REFERENCES users ON DELETE CASCADE NOT NULL,
+	started TIMESTAMPTZ NOT NULL DEFAULT current_timestamp,
+	attempts INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,6 @@
+-- The Argon2id hashes can't be turned back into bcrypt ones; their users have to reset their passwords.
+DELETE FROM passwords WHERE hash_version <> 1;
+ALTER TABLE passwords DROP COLUMN pepper_id;
+ALTER TABLE passwords DROP COLUMN iterations;
+ALTER TABLE passwords DROP COLUMN memory_kib;
+ALTER TABLE passwords DROP COLUMN hash_version;
@@ -0,0 +1,4 @@
+ALTER TABLE passwords ADD COLUMN hash_version SMALLINT NOT NULL DEFAULT 1;
+ALTER TABLE passwords ADD COLUMN memory_kib INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE passwords ADD COLUMN iterations INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE passwords ADD COLUMN pepper_id BYTEA;
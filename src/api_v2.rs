@@ -0,0 +1,1137 @@
+
+use super::*;
+use pencil::{jsonify, Response};
+use pencil::http_errors::HTTPError;
+use serde::{Serialize, Deserialize};
+use serde_json::{self, Value, Map};
+use std::io::Read;
+
+use ganbare::quiz;
+use ganbare::models;
+use ganbare::user;
+use ganbare::api_token;
+use ganbare::totp;
+use ganbare::manage;
+use ganbare::event;
+use ganbare::role;
+use http_api;
+use chrono::{DateTime, UTC};
+
+// The versioned JSON API at /api/v2. Successful responses are wrapped in
+// `{"data": ...}`, and the actions that have nothing to return respond with
+// 204 No Content. Errors are `{"error": {"code": ..., "message": ...}}`, where
+// the code is one of `ERROR_CODES`. The routes are listed in `ROUTES`, from which
+// both the router and the OpenAPI document at /api/v2/openapi.json are set up.
+
+/// The error codes of the API and their HTTP statuses.
+pub const ERROR_CODES: [(&'static str, u16); 25] = [("InvalidInput", 400),
+                                                    ("FormParseError", 400),
+                                                    ("EmailAddressTooLong", 400),
+                                                    ("EmailAddressNotValid", 400),
+                                                    ("PasswordTooShort", 400),
+                                                    ("PasswordTooLong", 400),
+                                                    ("UnsupportedAudioFormat", 400),
+                                                    ("InvalidManifest", 400),
+                                                    ("InvalidArchive", 400),
+                                                    ("AuthError", 401),
+                                                    ("TwoFactorRequired", 401),
+                                                    ("PasswordDoesntMatch", 401),
+                                                    ("BadSessId", 401),
+                                                    ("NoSuchSess", 401),
+                                                    ("Unauthorized", 401),
+                                                    ("AccessDenied", 403),
+                                                    ("NoSuchUser", 404),
+                                                    ("FileNotFound", 404),
+                                                    ("NotFound", 404),
+                                                    ("MethodNotAllowed", 405),
+                                                    ("FileAlreadyExists", 409),
+                                                    ("RateLimitExceeded", 429),
+                                                    ("TranscodingFailed", 500),
+                                                    ("InternalError", 500),
+                                                    ("ServiceUnavailable", 503)];
+
+fn status_of(code: &str) -> u16 {
+    ERROR_CODES.iter().find(|&&(c, _)| c == code).map(|&(_, status)| status).unwrap_or(500)
+}
+
+#[derive(Debug)]
+pub struct ApiError {
+    code: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    pub fn new<M: ToString>(code: &'static str, message: M) -> ApiError {
+        ApiError {
+            code: code,
+            message: message.to_string(),
+        }
+    }
+
+    fn into_response(self) -> PencilResult {
+        #[derive(Serialize)]
+        struct ErrorBody<'a> {
+            code: &'a str,
+            message: &'a str,
+        }
+
+        #[derive(Serialize)]
+        struct Envelope<'a> {
+            error: ErrorBody<'a>,
+        }
+
+        let status = status_of(self.code);
+        if status == 500 {
+            error!("API error: {:?}", self);
+        } else {
+            warn!("Error {}: {:?}", status, self);
+        }
+
+        jsonify(&Envelope {
+                     error: ErrorBody {
+                         code: self.code,
+                         message: &self.message,
+                     },
+                 })
+                .map(|mut resp| {
+                         resp.status_code = status;
+                         resp
+                     })
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(err: Error) -> ApiError {
+        let code = err.kind().code();
+        if status_of(code) == 500 {
+            // The details of the server errors are only logged.
+            error!("{:?}", err);
+            ApiError::new(code, "Internal server error.")
+        } else {
+            ApiError::new(code, err)
+        }
+    }
+}
+
+impl From<ErrorKind> for ApiError {
+    fn from(kind: ErrorKind) -> ApiError {
+        Error::from_kind(kind).into()
+    }
+}
+
+impl From<PencilError> for ApiError {
+    fn from(err: PencilError) -> ApiError {
+        match err {
+            PencilError::PenHTTPError(e) => {
+                let code = match e {
+                    HTTPError::BadRequest => "InvalidInput",
+                    HTTPError::Unauthorized => "Unauthorized",
+                    HTTPError::Forbidden => "AccessDenied",
+                    HTTPError::NotFound => "NotFound",
+                    HTTPError::MethodNotAllowed => "MethodNotAllowed",
+                    HTTPError::ServiceUnavailable => "ServiceUnavailable",
+                    _ => "InternalError",
+                };
+                ApiError::new(code, e.name())
+            }
+            PencilError::PenUserError(e) => {
+                error!("{:?}", e);
+                ApiError::new("InternalError", "Internal server error.")
+            }
+        }
+    }
+}
+
+/// Runs the handler, turning its errors into error responses.
+fn respond<F>(req: &mut Request, handler: F) -> PencilResult
+    where F: FnOnce(&mut Request) -> StdResult<Response, ApiError>
+{
+    match handler(req) {
+        Ok(resp) => Ok(resp),
+        Err(e) => e.into_response(),
+    }
+}
+
+fn data<T: Serialize>(data: &T) -> PencilResult {
+    #[derive(Serialize)]
+    struct Envelope<'a, T: 'a> {
+        data: &'a T,
+    }
+
+    jsonify(&Envelope { data: data })
+}
+
+fn no_content() -> Response {
+    let mut resp = Response::new_empty();
+    resp.status_code = 204;
+    resp
+}
+
+fn read_json<T: Deserialize>(req: &mut Request) -> StdResult<T, ApiError> {
+    let mut text = String::new();
+    req.read_to_string(&mut text)
+        .map_err(|e| ApiError::new("InvalidInput", e))?;
+    serde_json::from_str(&text).map_err(|e| ApiError::new("InvalidInput", e))
+}
+
+#[derive(Deserialize)]
+struct Login {
+    email: String,
+    password: String,
+    /// Required from the users that have two-factor authentication enabled.
+    code: Option<String>,
+}
+
+fn check_lengths(email: &str, password: &str) -> StdResult<(), ApiError> {
+    if email.len() > 254 {
+        return Err(ErrorKind::EmailAddressTooLong.into());
+    }
+    if password.len() > 1024 {
+        return Err(ErrorKind::PasswordTooLong.into());
+    }
+    Ok(())
+}
+
+pub fn login(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let login: Login = read_json(req)?;
+        check_lengths(&login.email, &login.password)?;
+
+        let conn = db_connect()?;
+
+        if let Some(old_sess) = get_sess(&conn, &*req)? {
+            do_logout(&conn, &old_sess)?;
+        }
+
+        let client = get_client(&*req);
+        match do_login(&conn, &login.email, &login.password, &client)? {
+            Some(LoginStep::Done(user, sess)) => Ok(data(&user).refresh_cookie(&sess)?),
+            Some(LoginStep::NeedsCode(token)) => {
+                // The API takes the code with the password, so without the code, the login
+                // can't continue and there's no point in keeping it pending.
+                let code = try_or!(login.code, else {
+                    totp::cancel_login(&conn, &token)?;
+                    return Err(ErrorKind::TwoFactorRequired.into());
+                });
+                match do_second_step(&conn, &token, &code, &client)? {
+                    Some((user, sess)) => Ok(data(&user).refresh_cookie(&sess)?),
+                    None => Err(ErrorKind::AuthError.into()),
+                }
+            }
+            None => {
+                warn!("Failed login: {}", &login.email);
+                Err(ErrorKind::AuthError.into())
+            }
+        }
+    })
+}
+
+#[derive(Deserialize)]
+struct TokenLogin {
+    email: String,
+    password: String,
+    #[serde(default)]
+    roles: Vec<String>,
+    code: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SessionToken {
+    bearer: String,
+    expires: Option<DateTime<UTC>>,
+}
+
+pub fn create_token(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let login: TokenLogin = read_json(req)?;
+        check_lengths(&login.email, &login.password)?;
+
+        let conn = db_connect()?;
+
+        let user = try_or!(user::auth_user(&conn,
+                                           &login.email,
+                                           &login.password,
+                                           &*RUNTIME_PEPPER,
+                                           &*OLD_RUNTIME_PEPPERS,
+                                           *PASSWORD_STRETCHING_TIME)?,
+                           else return Err(ErrorKind::AuthError.into()));
+        if totp::is_enabled(&conn, user.id)? {
+            let code = try_or!(login.code.as_ref(),
+                               else return Err(ErrorKind::TwoFactorRequired.into()));
+            let pending = totp::start_login(&conn, user.id)?;
+            if totp::finish_login(&conn, &pending, code)?.is_none() {
+                return Err(ErrorKind::AuthError.into());
+            }
+        }
+        let (token, bearer) = api_token::create(&conn,
+                                                user.id,
+                                                "API login",
+                                                api_token::SESSION,
+                                                &login.roles,
+                                                Some(UTC::now() + *API_TOKEN_EXPIRE),
+                                                COOKIE_HMAC_KEY.as_slice())?;
+
+        Ok(data(&SessionToken {
+                     bearer: bearer,
+                     expires: token.expires,
+                 })?)
+    })
+}
+
+pub fn logout(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        if let Some((conn, old_sess)) = try_auth_user(req)? {
+            do_logout(&conn, &old_sess)?;
+        }
+        Ok(no_content().expire_cookie())
+    })
+}
+
+pub fn me(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_user(req, "")?;
+        let user = user::get_user(&conn, sess.user_id)?;
+        Ok(data(&user).refresh_cookie(&sess)?)
+    })
+}
+
+#[derive(Deserialize)]
+struct PasswordChange {
+    old_password: String,
+    new_password: String,
+}
+
+pub fn change_password(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_account(req)?;
+        let change: PasswordChange = read_json(req)?;
+
+        let user = user::get_user(&conn, sess.user_id)?;
+        let email = try_or!(user.email, else return Err(ErrorKind::AccessDenied.into()));
+
+        if user::auth_user(&conn,
+                           &email,
+                           &change.old_password,
+                           &*RUNTIME_PEPPER,
+                           &*OLD_RUNTIME_PEPPERS,
+                           *PASSWORD_STRETCHING_TIME)?
+            .is_none() {
+            return Err(ErrorKind::AuthError.into());
+        }
+        user::change_password(&conn,
+                              sess.user_id,
+                              &change.new_password,
+                              &*RUNTIME_PEPPER,
+                              *PASSWORD_STRETCHING_TIME)?;
+
+        Ok(no_content().refresh_cookie(&sess)?)
+    })
+}
+
+fn quiz_data(quiz: Option<quiz::Quiz>) -> PencilResult {
+    use ganbare::quiz::Quiz::*;
+    match quiz {
+        Some(Q(q_json)) => data(&q_json),
+        Some(E(e_json)) => data(&e_json),
+        Some(W(w_json)) => data(&w_json),
+        Some(F(future)) => data(&future),
+        None => data(&()),
+    }
+}
+
+/// An answer to a quiz. The fields needed depend on the type: words need
+/// `times_audio_played`, exercises `times_audio_played`, `reflected_time`,
+/// `full_answer_time` and `answer_level`, and questions `answered_qa_id` and
+/// `full_answer_time`.
+#[derive(Deserialize)]
+struct QuizAnswer {
+    #[serde(rename = "type")]
+    answer_type: String,
+    asked_id: i32,
+    active_answer_time: i32,
+    full_spent_time: i32,
+    times_audio_played: Option<i32>,
+    reflected_time: Option<i32>,
+    full_answer_time: Option<i32>,
+    answer_level: Option<i32>,
+    answered_qa_id: Option<i32>,
+}
+
+fn required(field: Option<i32>) -> Result<i32> {
+    Ok(try_or!(field, else bail!(ErrorKind::InvalidInput)))
+}
+
+impl QuizAnswer {
+    fn into_answered(self) -> Result<quiz::Answered> {
+        Ok(match self.answer_type.as_str() {
+               "word" => {
+                   quiz::Answered::W(models::WAnsweredData {
+                                         id: self.asked_id,
+                                         audio_times: required(self.times_audio_played)?,
+                                         checked_date: UTC::now(),
+                                         active_answer_time_ms: self.active_answer_time,
+                                         full_spent_time_ms: self.full_spent_time,
+                                     })
+               }
+               "exercise" => {
+                   quiz::Answered::E(models::EAnsweredData {
+                                         id: self.asked_id,
+                                         audio_times: required(self.times_audio_played)?,
+                                         active_answer_time_ms: self.active_answer_time,
+                                         answered_date: UTC::now(),
+                                         reflected_time_ms: required(self.reflected_time)?,
+                                         full_answer_time_ms: required(self.full_answer_time)?,
+                                         answer_level: required(self.answer_level)?,
+                                         full_spent_time_ms: self.full_spent_time,
+                                     })
+               }
+               "question" => {
+                   // Unanswered questions (due to the time limit) have no answer.
+                   quiz::Answered::Q(models::QAnsweredData {
+                                         id: self.asked_id,
+                                         answered_qa_id: self.answered_qa_id,
+                                         answered_date: UTC::now(),
+                                         active_answer_time_ms: self.active_answer_time,
+                                         full_answer_time_ms: required(self.full_answer_time)?,
+                                         full_spent_time_ms: self.full_spent_time,
+                                     })
+               }
+               _ => bail!(ErrorKind::InvalidInput),
+           })
+    }
+}
+
+pub fn get_quiz(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_user(req, "")?;
+        let quiz = quiz::get_new_quiz(&conn, sess.user_id)?;
+        Ok(quiz_data(quiz).refresh_cookie(&sess)?)
+    })
+}
+
+pub fn answer_quiz(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_user(req, "")?;
+        let answer: QuizAnswer = read_json(req)?;
+        let quiz = quiz::get_next_quiz(&conn, sess.user_id, answer.into_answered()?)?;
+        Ok(quiz_data(quiz).refresh_cookie(&sess)?)
+    })
+}
+
+fn id_arg(req: &Request) -> i32 {
+    req.view_args
+        .get("id")
+        .expect("Pencil guarantees that Line ID should exist as an arg.")
+        .parse::<i32>()
+        .expect("Pencil guarantees that Line ID should be an integer.")
+}
+
+fn found<T>(item: Option<T>) -> StdResult<T, ApiError> {
+    item.ok_or_else(|| ApiError::new("NotFound", "No such item."))
+}
+
+/// Like `read_json`, but for the content items that may contain images.
+fn read_content_json<T: Deserialize>(req: &mut Request) -> StdResult<T, ApiError> {
+    let mut text = String::new();
+    req.read_to_string(&mut text)
+        .map_err(|e| ApiError::new("InvalidInput", e))?;
+    serde_json::from_str(&http_api::strip_img_attributes(&text))
+        .map_err(|e| ApiError::new("InvalidInput", e))
+}
+
+pub fn get_word(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
+        let word = found(manage::get_word(&conn, id_arg(req))?)?;
+        Ok(data(&word).refresh_cookie(&sess)?)
+    })
+}
+
+pub fn update_word(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
+        let id = id_arg(req);
+        let item: models::UpdateWord = read_content_json(req)?;
+        let word = found(manage::update_word(&conn, sess.user_id, id, item, &*IMAGES_DIR)?)?;
+        Ok(data(&word).refresh_cookie(&sess)?)
+    })
+}
+
+pub fn delete_word(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
+        found(manage::remove_word(&conn, sess.user_id, id_arg(req))?)?;
+        Ok(no_content().refresh_cookie(&sess)?)
+    })
+}
+
+#[derive(Serialize)]
+struct QuestionWithAnswers {
+    question: models::QuizQuestion,
+    answers: Vec<models::Answer>,
+}
+
+pub fn get_question(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
+        let (question, answers) = found(manage::get_question(&conn, id_arg(req))?)?;
+        Ok(data(&QuestionWithAnswers {
+                     question: question,
+                     answers: answers,
+                 }).refresh_cookie(&sess)?)
+    })
+}
+
+pub fn update_question(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
+        let id = id_arg(req);
+        let item: models::UpdateQuestion = read_content_json(req)?;
+        let question = found(manage::update_question(&conn, sess.user_id, id, item)?)?;
+        Ok(data(&question).refresh_cookie(&sess)?)
+    })
+}
+
+pub fn delete_question(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
+        if !manage::remove_question(&conn, sess.user_id, id_arg(req))? {
+            return Err(ApiError::new("NotFound", "No such item."));
+        }
+        Ok(no_content().refresh_cookie(&sess)?)
+    })
+}
+
+#[derive(Serialize)]
+struct ExerciseWithVariants {
+    exercise: models::Exercise,
+    variants: Vec<models::ExerciseVariant>,
+}
+
+pub fn get_exercise(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
+        let (exercise, variants) = found(manage::get_exercise(&conn, id_arg(req))?)?;
+        Ok(data(&ExerciseWithVariants {
+                     exercise: exercise,
+                     variants: variants,
+                 }).refresh_cookie(&sess)?)
+    })
+}
+
+pub fn update_exercise(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
+        let id = id_arg(req);
+        let item: models::UpdateExercise = read_json(req)?;
+        let exercise = found(manage::update_exercise(&conn, sess.user_id, id, item)?)?;
+        Ok(data(&exercise).refresh_cookie(&sess)?)
+    })
+}
+
+pub fn delete_exercise(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
+        if !manage::remove_exercise(&conn, sess.user_id, id_arg(req))? {
+            return Err(ApiError::new("NotFound", "No such item."));
+        }
+        Ok(no_content().refresh_cookie(&sess)?)
+    })
+}
+
+/// The users without their group memberships, so that the anonymous memberships
+/// don't need to be hidden.
+pub fn get_users(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_permission(req, role::VIEW_USERS)?;
+        let (users, _, _) = user::get_all(&conn)?;
+        let users: Vec<User> = users.into_iter().map(|u| u.0).collect();
+        Ok(data(&users).refresh_cookie(&sess)?)
+    })
+}
+
+pub fn get_user(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_permission(req, role::VIEW_USERS)?;
+        let id = id_arg(req);
+        let user = match user::get_user(&conn, id) {
+            Err(Error(ErrorKind::DieselError(ganbare::diesel::result::Error::NotFound), ..)) => {
+                return Err(ErrorKind::NoSuchUser(id.to_string()).into())
+            }
+            r => r?,
+        };
+        Ok(data(&user).refresh_cookie(&sess)?)
+    })
+}
+
+pub fn delete_user(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_permission(req, role::MANAGE_USERS)?;
+        found(user::deactivate_user(&conn, Some(sess.user_id), id_arg(req))?)?;
+        Ok(no_content().refresh_cookie(&sess)?)
+    })
+}
+
+/// The events without the users' experiences of them.
+pub fn get_events(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_permission(req, role::MANAGE_EVENTS)?;
+        let events: Vec<models::Event> = event::get_all(&conn)?
+            .into_iter()
+            .map(|(event, _)| event)
+            .collect();
+        Ok(data(&events).refresh_cookie(&sess)?)
+    })
+}
+
+pub fn update_event(req: &mut Request) -> PencilResult {
+    respond(req, |req| {
+        let (conn, sess) = auth_permission(req, role::MANAGE_EVENTS)?;
+        let id = id_arg(req);
+        let item: models::UpdateEvent = read_json(req)?;
+        if item.id != id {
+            return Err(ApiError::new("InvalidInput", "The id doesn't match the path."));
+        }
+        let event = found(event::update_event(&conn, sess.user_id, &item)?)?;
+        Ok(data(&event).refresh_cookie(&sess)?)
+    })
+}
+
+/// Serves the OpenAPI document. It's not wrapped in an envelope, so that the tools can read it.
+pub fn openapi(req: &mut Request) -> PencilResult {
+    respond(req, |_| Ok(jsonify(&openapi_document())?))
+}
+
+pub struct Route {
+    pub method: &'static str,
+    pub path: &'static str,
+    pub endpoint: &'static str,
+    pub handler: fn(&mut Request) -> PencilResult,
+    pub summary: &'static str,
+    /// The schema of the JSON body of the request.
+    pub request: Option<&'static str>,
+    /// The schema of the data of the response, or `None` for 204 No Content.
+    pub response: Option<&'static str>,
+    pub authenticated: bool,
+}
+
+pub static ROUTES: [Route; 21] = [Route {
+                                     method: "POST",
+                                     path: "/api/v2/session",
+                                     endpoint: "v2_login",
+                                     handler: login,
+                                     summary: "Logs in, setting the session cookie.",
+                                     request: Some("Login"),
+                                     response: Some("User"),
+                                     authenticated: false,
+                                 },
+                                 Route {
+                                     method: "POST",
+                                     path: "/api/v2/tokens",
+                                     endpoint: "v2_create_token",
+                                     handler: create_token,
+                                     summary: "Logs in, returning a short-lived session \
+                                               token for the Authorization: Bearer header.",
+                                     request: Some("TokenLogin"),
+                                     response: Some("SessionToken"),
+                                     authenticated: false,
+                                 },
+                                 Route {
+                                     method: "DELETE",
+                                     path: "/api/v2/session",
+                                     endpoint: "v2_logout",
+                                     handler: logout,
+                                     summary: "Logs out. With a session token, revokes the \
+                                               token.",
+                                     request: None,
+                                     response: None,
+                                     authenticated: false,
+                                 },
+                                 Route {
+                                     method: "GET",
+                                     path: "/api/v2/me",
+                                     endpoint: "v2_me",
+                                     handler: me,
+                                     summary: "The logged-in user.",
+                                     request: None,
+                                     response: Some("User"),
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "PUT",
+                                     path: "/api/v2/me/password",
+                                     endpoint: "v2_change_password",
+                                     handler: change_password,
+                                     summary: "Changes the password of the logged-in user.",
+                                     request: Some("PasswordChange"),
+                                     response: None,
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "GET",
+                                     path: "/api/v2/quiz",
+                                     endpoint: "v2_get_quiz",
+                                     handler: get_quiz,
+                                     summary: "The next quiz to study, or null if there's \
+                                               nothing to study.",
+                                     request: None,
+                                     response: Some("Quiz"),
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "POST",
+                                     path: "/api/v2/quiz",
+                                     endpoint: "v2_answer_quiz",
+                                     handler: answer_quiz,
+                                     summary: "Saves the answer to the current quiz and \
+                                               returns the next one, or null.",
+                                     request: Some("QuizAnswer"),
+                                     response: Some("Quiz"),
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "GET",
+                                     path: "/api/v2/words/<id:int>",
+                                     endpoint: "v2_get_word",
+                                     handler: get_word,
+                                     summary: "A word.",
+                                     request: None,
+                                     response: Some("Word"),
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "PUT",
+                                     path: "/api/v2/words/<id:int>",
+                                     endpoint: "v2_update_word",
+                                     handler: update_word,
+                                     summary: "Updates the fields of the word that are given.",
+                                     request: Some("UpdateWord"),
+                                     response: Some("Word"),
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "DELETE",
+                                     path: "/api/v2/words/<id:int>",
+                                     endpoint: "v2_delete_word",
+                                     handler: delete_word,
+                                     summary: "Moves the word, and the exercises that have it, to \
+                                               the trash.",
+                                     request: None,
+                                     response: None,
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "GET",
+                                     path: "/api/v2/questions/<id:int>",
+                                     endpoint: "v2_get_question",
+                                     handler: get_question,
+                                     summary: "A question with its answers.",
+                                     request: None,
+                                     response: Some("QuestionWithAnswers"),
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "PUT",
+                                     path: "/api/v2/questions/<id:int>",
+                                     endpoint: "v2_update_question",
+                                     handler: update_question,
+                                     summary: "Updates the fields of the question that are given.",
+                                     request: Some("UpdateQuestion"),
+                                     response: Some("Question"),
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "DELETE",
+                                     path: "/api/v2/questions/<id:int>",
+                                     endpoint: "v2_delete_question",
+                                     handler: delete_question,
+                                     summary: "Moves the question to the trash.",
+                                     request: None,
+                                     response: None,
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "GET",
+                                     path: "/api/v2/exercises/<id:int>",
+                                     endpoint: "v2_get_exercise",
+                                     handler: get_exercise,
+                                     summary: "An exercise with its variants.",
+                                     request: None,
+                                     response: Some("ExerciseWithVariants"),
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "PUT",
+                                     path: "/api/v2/exercises/<id:int>",
+                                     endpoint: "v2_update_exercise",
+                                     handler: update_exercise,
+                                     summary: "Updates the fields of the exercise that are given.",
+                                     request: Some("UpdateExercise"),
+                                     response: Some("Exercise"),
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "DELETE",
+                                     path: "/api/v2/exercises/<id:int>",
+                                     endpoint: "v2_delete_exercise",
+                                     handler: delete_exercise,
+                                     summary: "Moves the exercise to the trash.",
+                                     request: None,
+                                     response: None,
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "GET",
+                                     path: "/api/v2/users",
+                                     endpoint: "v2_get_users",
+                                     handler: get_users,
+                                     summary: "The users.",
+                                     request: None,
+                                     response: Some("User[]"),
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "GET",
+                                     path: "/api/v2/users/<id:int>",
+                                     endpoint: "v2_get_user",
+                                     handler: get_user,
+                                     summary: "A user.",
+                                     request: None,
+                                     response: Some("User"),
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "DELETE",
+                                     path: "/api/v2/users/<id:int>",
+                                     endpoint: "v2_delete_user",
+                                     handler: delete_user,
+                                     summary: "Deactivates the user.",
+                                     request: None,
+                                     response: None,
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "GET",
+                                     path: "/api/v2/events",
+                                     endpoint: "v2_get_events",
+                                     handler: get_events,
+                                     summary: "The events, in the order of priority.",
+                                     request: None,
+                                     response: Some("Event[]"),
+                                     authenticated: true,
+                                 },
+                                 Route {
+                                     method: "PUT",
+                                     path: "/api/v2/events/<id:int>",
+                                     endpoint: "v2_update_event",
+                                     handler: update_event,
+                                     summary: "Updates the fields of the event that are given.",
+                                     request: Some("UpdateEvent"),
+                                     response: Some("Event"),
+                                     authenticated: true,
+                                 }];
+
+fn object(fields: Vec<(&str, Value)>) -> Value {
+    let mut map = Map::new();
+    for (key, value) in fields {
+        map.insert(key.to_string(), value);
+    }
+    Value::Object(map)
+}
+
+fn string(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+fn schema_ref(name: &str) -> Value {
+    object(vec![("$ref", string(&format!("#/components/schemas/{}", name)))])
+}
+
+/// A schema of an object with the listed properties. The types with `?` at the
+/// end are optional.
+fn object_schema(properties: &[(&str, &str)]) -> Value {
+    let required: Vec<Value> = properties.iter()
+        .filter(|&&(_, t)| !t.ends_with('?'))
+        .map(|&(name, _)| string(name))
+        .collect();
+    let properties: Vec<(&str, Value)> = properties.iter()
+        .map(|&(name, t)| {
+            let t = t.trim_right_matches('?');
+            let schema = match t {
+                "date-time" => {
+                    object(vec![("type", string("string")), ("format", string("date-time"))])
+                }
+                t => object(vec![("type", string(t))]),
+            };
+            (name, schema)
+        })
+        .collect();
+    object(vec![("type", string("object")),
+                ("required", Value::Array(required)),
+                ("properties", object(properties))])
+}
+
+fn schemas() -> Value {
+    let codes: Vec<Value> = ERROR_CODES.iter().map(|&(code, _)| string(code)).collect();
+    let error = object(vec![("type", string("object")),
+                            ("required", Value::Array(vec![string("code"), string("message")])),
+                            ("properties",
+                             object(vec![("code",
+                                          object(vec![("type", string("string")),
+                                                      ("enum", Value::Array(codes))])),
+                                         ("message", object(vec![("type", string("string"))]))]))]);
+
+    object(vec![("Error",
+                 object(vec![("type", string("object")),
+                             ("required", Value::Array(vec![string("error")])),
+                             ("properties", object(vec![("error", error)]))])),
+                ("Login",
+                 object_schema(&[("email", "string"),
+                                 ("password", "string"),
+                                 ("code", "string?")])),
+                ("TokenLogin",
+                 object_schema(&[("email", "string"),
+                                 ("password", "string"),
+                                 ("roles", "array?"),
+                                 ("code", "string?")])),
+                ("SessionToken",
+                 object_schema(&[("bearer", "string"), ("expires", "date-time")])),
+                ("PasswordChange",
+                 object_schema(&[("old_password", "string"), ("new_password", "string")])),
+                ("User",
+                 object_schema(&[("id", "integer"),
+                                 ("email", "string?"),
+                                 ("joined", "date-time"),
+                                 ("last_seen", "date-time"),
+                                 ("timezone", "string"),
+                                 ("day_rollover_hour", "integer"),
+                                 ("locale", "string")])),
+                ("Quiz",
+                 object_schema(&[("quiz_type", "string"),
+                                 ("asked_id", "integer?"),
+                                 ("explanation", "string?"),
+                                 ("question", "string?"),
+                                 ("right_a", "integer?"),
+                                 ("answers", "array?"),
+                                 ("event_name", "string?"),
+                                 ("word", "string?"),
+                                 ("must_record", "boolean?"),
+                                 ("show_accents", "boolean?"),
+                                 ("due_date", "string?")])),
+                ("QuizAnswer",
+                 object_schema(&[("type", "string"),
+                                 ("asked_id", "integer"),
+                                 ("active_answer_time", "integer"),
+                                 ("full_spent_time", "integer"),
+                                 ("times_audio_played", "integer?"),
+                                 ("reflected_time", "integer?"),
+                                 ("full_answer_time", "integer?"),
+                                 ("answer_level", "integer?"),
+                                 ("answered_qa_id", "integer?")])),
+                ("Word",
+                 object_schema(&[("id", "integer"),
+                                 ("word", "string"),
+                                 ("explanation", "string"),
+                                 ("audio_bundle", "integer"),
+                                 ("skill_nugget", "integer"),
+                                 ("published", "boolean"),
+                                 ("skill_level", "integer"),
+                                 ("priority", "integer"),
+                                 ("deleted", "date-time?")])),
+                ("UpdateWord",
+                 object_schema(&[("word", "string?"),
+                                 ("explanation", "string?"),
+                                 ("audio_bundle", "integer?"),
+                                 ("skill_nugget", "integer?"),
+                                 ("published", "boolean?"),
+                                 ("skill_level", "integer?"),
+                                 ("priority", "integer?")])),
+                ("Question",
+                 object_schema(&[("id", "integer"),
+                                 ("skill_id", "integer"),
+                                 ("q_name", "string"),
+                                 ("q_explanation", "string"),
+                                 ("question_text", "string"),
+                                 ("published", "boolean"),
+                                 ("skill_level", "integer"),
+                                 ("deleted", "date-time?")])),
+                ("UpdateQuestion",
+                 object_schema(&[("skill_id", "integer?"),
+                                 ("q_name", "string?"),
+                                 ("q_explanation", "string?"),
+                                 ("question_text", "string?"),
+                                 ("published", "boolean?"),
+                                 ("skill_level", "integer?")])),
+                ("QuestionWithAnswers",
+                 object_schema(&[("question", "object"), ("answers", "array")])),
+                ("Exercise",
+                 object_schema(&[("id", "integer"),
+                                 ("skill_id", "integer"),
+                                 ("published", "boolean"),
+                                 ("skill_level", "integer"),
+                                 ("deleted", "date-time?")])),
+                ("UpdateExercise",
+                 object_schema(&[("skill_id", "integer?"),
+                                 ("published", "boolean?"),
+                                 ("skill_level", "integer?")])),
+                ("ExerciseWithVariants",
+                 object_schema(&[("exercise", "object"), ("variants", "array")])),
+                ("Event",
+                 object_schema(&[("id", "integer"),
+                                 ("name", "string"),
+                                 ("published", "boolean"),
+                                 ("required_group", "integer?"),
+                                 ("priority", "integer"),
+                                 ("page_kind", "string"),
+                                 ("page", "string?"),
+                                 ("required_event", "integer?")])),
+                ("UpdateEvent",
+                 object_schema(&[("id", "integer"),
+                                 ("name", "string?"),
+                                 ("published", "boolean?"),
+                                 ("required_group", "integer?"),
+                                 ("priority", "integer?"),
+                                 ("page_kind", "string?"),
+                                 ("page", "string?"),
+                                 ("required_event", "integer?")]))])
+}
+
+fn json_content(schema: Value) -> Value {
+    object(vec![("application/json", object(vec![("schema", schema)]))])
+}
+
+/// The OpenAPI form of a route path, and the names of its parameters:
+/// `/api/v2/words/<id:int>` is `/api/v2/words/{id}`.
+fn openapi_path(path: &str) -> (String, Vec<&str>) {
+    let mut params = vec![];
+    let segments: Vec<String> = path.split('/')
+        .map(|segment| if segment.starts_with('<') && segment.ends_with('>') {
+                 let name = segment[1..segment.len() - 1].split(':').next().unwrap_or("");
+                 params.push(name);
+                 format!("{{{}}}", name)
+             } else {
+                 segment.to_string()
+             })
+        .collect();
+    (segments.join("/"), params)
+}
+
+/// A reference to the schema; a name ending with `[]` is an array of them.
+fn response_schema(name: &str) -> Value {
+    if name.ends_with("[]") {
+        object(vec![("type", string("array")),
+                    ("items", schema_ref(name.trim_right_matches("[]")))])
+    } else {
+        schema_ref(name)
+    }
+}
+
+fn operation(route: &Route) -> Value {
+    let mut responses = vec![];
+    match route.response {
+        Some(schema) => {
+            let envelope = object(vec![("type", string("object")),
+                                       ("required", Value::Array(vec![string("data")])),
+                                       ("properties",
+                                        object(vec![("data", response_schema(schema))]))]);
+            responses.push(("200",
+                            object(vec![("description", string("OK")),
+                                        ("content", json_content(envelope))])));
+        }
+        None => responses.push(("204", object(vec![("description", string("Done"))]))),
+    }
+    responses.push(("default",
+                    object(vec![("description", string("Error")),
+                                ("content", json_content(schema_ref("Error")))])));
+
+    let mut fields = vec![("summary", string(route.summary)),
+                          ("operationId", string(route.endpoint)),
+                          ("responses", object(responses))];
+    let (_, params) = openapi_path(route.path);
+    if !params.is_empty() {
+        let params = params.into_iter()
+            .map(|name| {
+                     object(vec![("name", string(name)),
+                                 ("in", string("path")),
+                                 ("required", Value::Bool(true)),
+                                 ("schema", object(vec![("type", string("integer"))]))])
+                 })
+            .collect();
+        fields.push(("parameters", Value::Array(params)));
+    }
+    if let Some(schema) = route.request {
+        fields.push(("requestBody",
+                     object(vec![("required", Value::Bool(true)),
+                                 ("content", json_content(schema_ref(schema)))])));
+    }
+    if route.authenticated {
+        fields.push(("security",
+                     Value::Array(vec![object(vec![("session", Value::Array(vec![]))]),
+                                       object(vec![("bearer", Value::Array(vec![]))])])));
+    }
+    object(fields)
+}
+
+/// The OpenAPI 3 description of the API, generated from `ROUTES`.
+pub fn openapi_document() -> Value {
+    let mut paths: Vec<(String, Vec<(&str, Value)>)> = vec![];
+    for route in ROUTES.iter() {
+        let (path, _) = openapi_path(route.path);
+        let method = match route.method {
+            "GET" => "get",
+            "POST" => "post",
+            "PUT" => "put",
+            "DELETE" => "delete",
+            _ => unreachable!(),
+        };
+        match paths.iter().position(|&(ref p, _)| *p == path) {
+            Some(i) => paths[i].1.push((method, operation(route))),
+            None => paths.push((path, vec![(method, operation(route))])),
+        }
+    }
+    let paths: Vec<(&str, Value)> =
+        paths.iter().map(|&(ref path, ref ops)| (path.as_str(), object(ops.clone()))).collect();
+
+    let session = object(vec![("type", string("apiKey")),
+                              ("in", string("cookie")),
+                              ("name", string("session_id"))]);
+    let bearer = object(vec![("type", string("http")), ("scheme", string("bearer"))]);
+
+    object(vec![("openapi", string("3.0.0")),
+                ("info",
+                 object(vec![("title", string("Ganbare")),
+                             ("version", string(env!("CARGO_PKG_VERSION")))])),
+                ("servers", Value::Array(vec![object(vec![("url", string(&*SITE_LINK))])])),
+                ("paths", object(paths)),
+                ("components",
+                 object(vec![("schemas", schemas()),
+                             ("securitySchemes",
+                              object(vec![("session", session), ("bearer", bearer)]))]))])
+}
+
+#[test]
+fn test_error_codes() {
+    for kind in vec![ErrorKind::NoSuchUser("x".into()),
+                     ErrorKind::PasswordTooShort,
+                     ErrorKind::FileNotFound,
+                     ErrorKind::InvalidInput,
+                     ErrorKind::Msg("x".into())] {
+        assert!(ERROR_CODES.iter().any(|&(code, _)| code == kind.code()),
+                "{} is missing from ERROR_CODES",
+                kind.code());
+    }
+    assert_eq!(status_of(ErrorKind::NoSuchUser("x".into()).code()), 404);
+    assert_eq!(status_of(ErrorKind::PasswordTooShort.code()), 400);
+    assert_eq!(status_of(ErrorKind::Msg("x".into()).code()), 500);
+}
+
+#[test]
+fn test_openapi_path() {
+    assert_eq!(openapi_path("/api/v2/me"), ("/api/v2/me".to_string(), vec![]));
+    assert_eq!(openapi_path("/api/v2/words/<id:int>"),
+               ("/api/v2/words/{id}".to_string(), vec!["id"]));
+    for route in ROUTES.iter() {
+        let (path, _) = openapi_path(route.path);
+        assert!(!path.contains('<') && !path.contains('>'), "{}", path);
+    }
+}
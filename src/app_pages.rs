@@ -6,67 +6,45 @@ use ganbare::event;
 use ganbare::user;
 use ganbare::email;
 use ganbare::session;
+use ganbare::randomize;
+use ganbare::i18n;
+use ganbare::account;
+use ganbare::consent;
 
 fn dispatch_events(conn: &Connection,
                    user_id: i32)
                    -> StdResult<Option<PencilResult>, PencilError> {
 
+    if consent::needs_consent(conn, user_id).err_500()? {
+        return Ok(Some(redirect("/consent", 303)));
+    }
+
     let event = match event::dispatch_event(conn, user_id).err_500()? {
         Some(e) => e,
         None => return Ok(None), // FIXME this actually never happens any more, since
         // the event "training" is the default mode
     };
 
-    let ev_exp = event::initiate(conn, &*event.name, user_id).err_500()?;
-
-    use ganbare_backend::Event;
-    use ganbare_backend::EventExperience;
-
-    if let Some((Event { name, .. }, EventExperience { event_init, .. })) = ev_exp {
-        if name == "training" && event_init < chrono::UTC::now() - *TRAINING_PERIOD {
+    event::initiate(conn, &*event.name, user_id).err_500()?;
 
-            // User has trained for the whole training period; has he done posttest yet?
-            if !event::is_done(conn, "posttest", user_id).err_500()?
-                && user::check_user_group(conn, user_id, "subjects").err_500()? // user must be a member of "subjects" to be able to take tests
-            {
-                user::join_user_group_by_name(&conn, user_id, "posttest").err_500()?;
-                return dispatch_events(conn, user_id);
-            }
-        }
+    // Firing transitions may change the user's groups, and with them, the dispatched event.
+    if event::run_transitions(conn, user_id).err_500()? {
+        return dispatch_events(conn, user_id);
     }
 
-    let redirect = match &*event.name {
-        "welcome" => redirect("/welcome", 303),
-        "agreement" => redirect("/agreement", 303),
-        "info" => redirect("/info", 303),
-        "survey" => redirect("/survey", 303),
-        "pretest_info" => redirect("/pretest_info", 303),
-        "pretest" => redirect("/pretest", 303),
-        "pretest_retelling" => redirect("/pretest_retelling", 303),
-        "pretest_done" => redirect("/pretest_done", 303),
-        "sorting_ceremony" => redirect("/sorting", 303),
-        "posttest_info" => redirect("/posttest_info", 303),
-        "posttest" => redirect("/posttest", 303),
-        "posttest_retelling" => redirect("/posttest_retelling", 303),
-        "posttest_done" => redirect("/posttest_done", 303),
-        "training" => return Ok(None),
-        event_name => {
-            return Err(internal_error(&format!("I don't know how to handle event {}!", event_name)))
-        }
-    };
-
-    Ok(Some(redirect))
+    Ok(event::page_url(&event).map(|url| redirect(&url, 303)))
 }
 
 fn main_quiz(req: &mut Request, conn: &Connection, user_id: i32) -> PencilResult {
-    let mut context = new_template_context();
+    let locale = user_locale(conn, user_id)?;
+    let mut context = new_template_context(&locale);
 
     if !user::check_user_group(conn, user_id, "questions").err_500()? &&
        !user::check_user_group(conn, user_id, "exercises").err_500()? {
-        context.insert("alert_msg", "Et kuulu mihinkään harjoitusryhmään!");
+        context.insert("alert_msg", i18n::message(&locale, "no_training_group"));
     }
 
-    req.app.render_template("main.html", &context)
+    render_template(req.app, "main.html", &context)
 }
 
 pub fn hello(req: &mut Request) -> PencilResult {
@@ -98,143 +76,227 @@ pub fn ok(req: &mut Request) -> PencilResult {
     redirect("/", 303).refresh_cookie(&sess)
 }
 
-pub fn survey(req: &mut Request) -> PencilResult {
-    let (conn, sess) = auth_user(req, "")?;
-    let (event, _) = event::require_ongoing(&conn, "survey", sess.user_id).err_401()?;
+fn survey_page(req: &mut Request,
+               conn: &Connection,
+               user_id: i32,
+               event_name: &str)
+               -> PencilResult {
+    let (event, _) = event::require_ongoing(conn, event_name, user_id).err_401()?;
 
-    user::join_user_group_by_name(&conn, sess.user_id, "nag_emails").err_500()?;
-
-    let mut context = new_template_context();
-    context.insert("event_name", "survey");
-    let answered_questions = event::get_userdata(&conn, &event, sess.user_id, "answered_questions")
+    let mut context = new_template_context(&user_locale(conn, user_id)?);
+    context.insert("event_name", event_name);
+    let answered_questions = event::get_userdata(conn, &event, user_id, "answered_questions")
         .err_500()?
         .map(|d| d.data)
         .unwrap_or_else(|| "".to_string());
 
     context.insert("answered_questions", answered_questions);
-    req.app.render_template("survey.html", &context).refresh_cookie(&sess)
+    render_template(req.app, "survey.html", &context)
 }
 
-pub fn text_pages(req: &mut Request) -> PencilResult {
-    let (conn, sess) = auth_user(req, "")?;
+fn text_page(req: &mut Request, conn: &Connection, user_id: i32, event_name: &str) -> PencilResult {
+    event::require_started(conn, event_name, user_id).err_401()?;
+    let mut context = new_template_context(&user_locale(conn, user_id)?);
+    context.insert("event_name", event_name);
 
-    let endpoint_string = req.endpoint().expect("Pencil guarantees that this is always set.");
-    let endpoint = endpoint_string.as_ref();
+    let mut template = event_name.to_owned();
+    template.push_str(".html");
+    render_template(req.app, &template, &context)
+}
 
-    event::require_started(&conn, endpoint, sess.user_id).err_401()?;
-    let mut context = new_template_context();
-    context.insert("event_name", endpoint);
+fn test_page(req: &mut Request, conn: &Connection, user_id: i32, event_name: &str) -> PencilResult {
+    let mut context = new_template_context(&user_locale(conn, user_id)?);
 
-    let mut template = endpoint.to_owned();
-    template.push_str(".html");
-    req.app.render_template(&template, &context).refresh_cookie(&sess)
+    if event::is_ongoing(conn, event_name, user_id).err_500()?.is_some() {
+        context.insert("testing", "true");
+    } else {
+        return redirect("/", 303);
+    }
+
+    render_template(req.app, "main.html", &context)
 }
 
-pub fn pre_post_test(req: &mut Request) -> PencilResult {
-    let (conn, sess) = auth_user(req, "subjects")?; // FIXME should user be allowed in with group posttest only
+fn sorting_page(conn: &Connection, user_id: i32, event_name: &str) -> PencilResult {
 
-    let mut context = new_template_context();
+    let (event, _) = event::require_ongoing(conn, event_name, user_id).err_401()?;
 
-    match req.endpoint().as_ref().map(|s| &**s) {
-        Some("pretest") => {
-            if event::is_ongoing(&conn, "pretest", sess.user_id).err_500()?.is_some() {
-                context.insert("testing", "true");
-            } else {
-                return redirect("/", 303).refresh_cookie(&sess);
-            }
-        }
-        Some("posttest") => {
-            if event::is_ongoing(&conn, "posttest", sess.user_id).err_500()?.is_some() {
-                context.insert("testing", "true");
-            } else {
-                return redirect("/", 303).refresh_cookie(&sess);
-            }
+    let randomization = match randomize::get_by_event(conn, event.id).err_500()? {
+        Some(r) => r,
+        None => {
+            return Err(internal_error(&format!("No randomization is set up for event {}!",
+                                               event_name)))
         }
-        _ => unreachable!(),
-    }
+    };
+
+    randomize::assign(conn, &randomization, user_id).err_500()?;
+
+    event::set_done(conn, event_name, user_id).err_500()?;
 
-    req.app.render_template("main.html", &context).refresh_cookie(&sess)
+    redirect("/", 303)
 }
 
-pub fn sorting_ceremony(req: &mut Request) -> PencilResult {
-    use rand::{Rng, thread_rng};
+fn retelling_page(req: &mut Request,
+                  conn: &Connection,
+                  user_id: i32,
+                  event_name: &str)
+                  -> PencilResult {
 
-    let (conn, sess) = auth_user(req, "sort")?;
+    let (_, _) = event::require_ongoing(conn, event_name, user_id).err_401()?;
 
-    let (_, _) = event::require_ongoing(&conn, "sorting_ceremony", sess.user_id).err_401()?;
+    let mut context = new_template_context(&user_locale(conn, user_id)?);
+    context.insert("testing", "true");
+    context.insert("event_name", event_name);
 
-    let group_name = if user::check_user_group(&conn, sess.user_id, "japani1").err_500()? {
-        "japani1"
-    } else if user::check_user_group(&conn, sess.user_id, "japani2").err_500()? {
-        "japani2"
-    } else if user::check_user_group(&conn, sess.user_id, "japani3").err_500()? {
-        "japani3"
-    } else if user::check_user_group(&conn, sess.user_id, "japani4").err_500()? {
-        "japani4"
-    } else {
-        "sort"
-    };
+    render_template(req.app, "retelling.html", &context)
+}
 
-    if user::remove_user_group_by_name(&conn, sess.user_id, "questions").err_500()? {
-        debug!("Removed old questions group");
-    };
-    if user::remove_user_group_by_name(&conn, sess.user_id, "exercises").err_500()? {
-        debug!("Removed old exercises group");
-    };
+/// Shows the newest consent document. With an event, agreeing to it finishes the event;
+/// without one, the user is asked to consent again to a new version.
+fn consent_page(req: &mut Request,
+                conn: &Connection,
+                user_id: i32,
+                event_name: Option<&str>)
+                -> PencilResult {
 
-    let mut membership = {
-        let subjects_size = user::group_size(&conn, group_name).err_500()?;
-        let quota = subjects_size / 2 + subjects_size % 2;
-        let s_input_size = user::group_intersection_size(&conn, group_name, "questions").err_500()?;
-        let s_output_size =
-            user::group_intersection_size(&conn, group_name, "exercises").err_500()?;
-        let sort_to_input: bool = if s_input_size < quota && s_output_size < quota {
-            thread_rng().gen::<bool>()
-        } else if s_input_size >= quota {
-            false
-        } else if s_output_size >= quota {
-            true
-        } else {
-            unreachable!()
-        };
+    if let Some(event_name) = event_name {
+        event::require_started(conn, event_name, user_id).err_401()?;
+    }
 
-        if sort_to_input {
-            user::join_user_group_by_name(&conn, sess.user_id, "questions").err_500()?
-        } else {
-            user::join_user_group_by_name(&conn, sess.user_id, "exercises").err_500()?
-        }
+    let locale = user_locale(conn, user_id)?;
+    let document = match consent::latest_document(conn, &locale).err_500()? {
+        Some(d) => d,
+        None => return Err(internal_error("No consent document is published!")),
     };
-    use ganbare::SaveChangesDsl;
-    membership.anonymous = true;
-    let _: ganbare::models::GroupMembership = membership.save_changes(&*conn).err_500()?;
 
-    event::set_done(&conn, "sorting_ceremony", sess.user_id).err_500()?;
+    let mut context = new_template_context(&locale);
+    context.insert("consent_text", document.content);
+    context.insert("document_id", document.id.to_string());
+    match event_name {
+        Some(event_name) => {
+            context.insert("event_name", event_name.to_string());
+        }
+        None => {
+            context.insert("reconsent", "true");
+        }
+    }
+    if req.args().get("declined").is_some() {
+        context.insert("declined", "true");
+    }
 
-    redirect("/", 303).refresh_cookie(&sess)
+    render_template(req.app, "consent.html", &context)
 }
 
-pub fn retelling(req: &mut Request) -> PencilResult {
+/// Serves any event by its page kind. Events without a page of their own
+/// are dispatched here, so new events don't need new routes.
+pub fn event_page(req: &mut Request) -> PencilResult {
     let (conn, sess) = auth_user(req, "")?;
 
-    let event_name;
+    let event_name = req.view_args
+        .remove("event_name")
+        .expect("Pencil guarantees that event name should exist as an arg.");
 
-    match req.endpoint().as_ref().map(|s| &**s) {
-        Some("pretest_retelling") => {
-            event_name = "pretest_retelling";
-        }
-        Some("posttest_retelling") => {
-            event_name = "posttest_retelling";
+    let event = try_or!(event::get_by_name(&conn, &event_name).err_500()?,
+                        else return pencil::abort(404));
+
+    if !event::is_allowed(&conn, &event, sess.user_id).err_500()? {
+        return pencil::abort(401);
+    }
+
+    match event.page_kind.as_str() {
+            "text" => text_page(req, &conn, sess.user_id, &event.name),
+            "survey" => survey_page(req, &conn, sess.user_id, &event.name),
+            "test" => test_page(req, &conn, sess.user_id, &event.name),
+            "retelling" => retelling_page(req, &conn, sess.user_id, &event.name),
+            "sorting" => sorting_page(&conn, sess.user_id, &event.name),
+            "consent" => consent_page(req, &conn, sess.user_id, Some(&event.name)),
+            "training" => redirect("/", 303),
+            kind => {
+                return Err(internal_error(&format!("I don't know how to show pages of kind {}!",
+                                                   kind)))
+            }
         }
-        _ => unreachable!(),
+        .refresh_cookie(&sess)
+}
+
+pub fn survey(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_user(req, "")?;
+    survey_page(req, &conn, sess.user_id, "survey").refresh_cookie(&sess)
+}
+
+pub fn text_pages(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_user(req, "")?;
+
+    let endpoint = req.endpoint().expect("Pencil guarantees that this is always set.");
+
+    text_page(req, &conn, sess.user_id, &endpoint).refresh_cookie(&sess)
+}
+
+pub fn consent_pages(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_user(req, "")?;
+
+    let endpoint = req.endpoint().expect("Pencil guarantees that this is always set.");
+
+    consent_page(req, &conn, sess.user_id, Some(&endpoint)).refresh_cookie(&sess)
+}
+
+pub fn consent_form(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_user(req, "")?;
+    consent_page(req, &conn, sess.user_id, None).refresh_cookie(&sess)
+}
+
+pub fn consent_post(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_user(req, "")?;
+
+    let document_id = err_400!(req.form().get("document_id"), "document_id missing");
+    let document_id = err_400!(document_id.parse::<i32>(), "document_id must be an integer");
+    let agreed = match err_400!(req.form().get("decision"), "decision missing").as_str() {
+        "agree" => true,
+        "decline" => false,
+        _ => return Ok(bad_request("The decision must be agree or decline!")),
+    };
+    if !consent::is_current(&conn, document_id).err_500()? {
+        return Ok(bad_request("The document isn't the current consent document!"));
     }
+    let event_name = req.form().get("event_ok").cloned();
 
-    let (_, _) = event::require_ongoing(&conn, event_name, sess.user_id).err_401()?;
+    consent::decide(&conn, sess.user_id, document_id, agreed).err_500()?;
 
-    let mut context = new_template_context();
-    context.insert("testing", "true");
-    context.insert("event_name", event_name);
+    if !agreed {
+        let page = match event_name {
+            Some(event_name) => format!("/event/{}?declined=true", event_name),
+            None => "/consent?declined=true".to_string(),
+        };
+        return redirect(&page, 303).refresh_cookie(&sess);
+    }
+
+    if let Some(event_name) = event_name {
+        let _ = err_400!(event::set_done(&conn, &event_name, sess.user_id).err_500()?,
+                         "Event \"{}\" doesn't exist!",
+                         &event_name);
+    }
+
+    redirect("/", 303).refresh_cookie(&sess)
+}
+
+pub fn pre_post_test(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_user(req, "subjects")?; // FIXME should user be allowed in with group posttest only
+
+    let endpoint = req.endpoint().expect("Pencil guarantees that this is always set.");
+
+    test_page(req, &conn, sess.user_id, &endpoint).refresh_cookie(&sess)
+}
+
+pub fn sorting_ceremony(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_user(req, "sort")?;
+    sorting_page(&conn, sess.user_id, "sorting_ceremony").refresh_cookie(&sess)
+}
+
+pub fn retelling(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_user(req, "")?;
+
+    let endpoint = req.endpoint().expect("Pencil guarantees that this is always set.");
 
-    req.app.render_template("retelling.html", &context).refresh_cookie(&sess)
+    retelling_page(req, &conn, sess.user_id, &endpoint).refresh_cookie(&sess)
 }
 
 pub fn login_form(req: &mut Request) -> PencilResult {
@@ -247,16 +309,56 @@ pub fn login_form(req: &mut Request) -> PencilResult {
         return redirect("/", 303).refresh_cookie(&sess);
     }
 
+    let locale = request_locale(req);
     let email = req.args().get("email").unwrap_or("");
-    let mut context = new_template_context();
+    let mut context = new_template_context(locale);
     context.insert("email", email);
 
-    req.app.render_template("hello.html", &context)
+    render_template(req.app, "hello.html", &context)
+}
+
+/// The second step of the login for the users that have two-factor authentication enabled.
+fn ask_code(app: &pencil::Pencil, locale: &str, token: &str, auth_error: bool) -> PencilResult {
+    let mut context = new_template_context(locale);
+    context.insert("token", token);
+    if auth_error {
+        context.insert("authError", "true");
+    }
+    let result = render_template(app, "login_code.html", &context);
+    result.map(|mut resp| {
+                   if auth_error {
+                       resp.status_code = 401;
+                   }
+                   resp
+               })
+}
+
+pub fn login_code_post(req: &mut Request) -> PencilResult {
+
+    let app = req.app;
+    let locale = request_locale(req);
+    let token = req.form().get("token").unwrap_or("");
+    let code = req.form().get("code").unwrap_or("");
+
+    if token.len() > 100 || code.len() > 100 {
+        return Ok(bad_request("Too long token/code."));
+    }
+
+    let conn = db_connect().err_500()?;
+
+    match do_second_step(&conn, token, code, &get_client(&*req)).err_500()? {
+        Some((_, sess)) => redirect("/", 303).refresh_cookie(&sess),
+        None => {
+            warn!("Failed two-factor login.");
+            ask_code(app, locale, token, true)
+        }
+    }
 }
 
 pub fn login_post(req: &mut Request) -> PencilResult {
 
     let app = req.app;
+    let locale = request_locale(req);
     let email = req.form().get("email").unwrap_or("");
     let plaintext_pw = req.form().get("password").unwrap_or("");
 
@@ -270,14 +372,15 @@ pub fn login_post(req: &mut Request) -> PencilResult {
         do_logout(&conn, &old_sess).err_500()?;
     }
 
-    match do_login(&conn, email, plaintext_pw).err_500()? {
-        Some((_, sess)) => redirect("/", 303).refresh_cookie(&sess),
+    match do_login(&conn, email, plaintext_pw, &get_client(&*req)).err_500()? {
+        Some(LoginStep::Done(_, sess)) => redirect("/", 303).refresh_cookie(&sess),
+        Some(LoginStep::NeedsCode(token)) => ask_code(app, locale, &token, false),
         None => {
             warn!("Failed login: {}", &email);
-            let mut context = new_template_context();
+            let mut context = new_template_context(locale);
             context.insert("email", email);
             context.insert("authError", "true");
-            let result = app.render_template("hello.html", &context);
+            let result = render_template(app, "hello.html", &context);
             result.map(|mut resp| {
                            resp.status_code = 401;
                            resp
@@ -306,17 +409,17 @@ pub fn confirm_form(req: &mut Request) -> PencilResult {
     }
 
     let conn = db_connect().err_500()?;
-    let email = match email::check_pending_email_confirm(&conn, secret).err_500()? {
-        Some((email, _)) => email,
+    let confirm = match email::get_pending_email_confirm(&conn, secret).err_500()? {
+        Some(confirm) => confirm,
         None => return redirect("/", 303),
     };
 
-    let mut context = new_template_context();
-    context.insert("email".to_owned(), email);
+    let mut context = new_template_context(&confirm.locale);
+    context.insert("email".to_owned(), confirm.email);
     context.insert("secret".to_owned(), secret.to_owned());
     context.insert("hmac".to_owned(), hmac.to_owned());
 
-    req.app.render_template("confirm.html", &context)
+    render_template(req.app, "confirm.html", &context)
 }
 
 pub fn confirm_post(req: &mut Request) -> PencilResult {
@@ -360,9 +463,10 @@ pub fn confirm_post(req: &mut Request) -> PencilResult {
 
     match do_login(&conn,
                    &user.email.expect("The email address was just proven to exits."),
-                   password).err_500()? {
-        Some((_, sess)) => redirect("/", 303).refresh_cookie(&sess),
-        None => {
+                   password,
+                   &get_client(&*req)).err_500()? {
+        Some(LoginStep::Done(_, sess)) => redirect("/", 303).refresh_cookie(&sess),
+        _ => {
             Err(internal_error(Error::from(ErrMsg("We just added the user, yet we can't login \
                                                    them in. A bug?"
                                                           .to_string()))))
@@ -373,9 +477,9 @@ pub fn confirm_post(req: &mut Request) -> PencilResult {
 
 pub fn change_password_form(req: &mut Request) -> PencilResult {
 
-    let (_, sess) = auth_user(req, "")?;
+    let (conn, sess) = auth_account(req)?;
 
-    let mut context = new_template_context();
+    let mut context = new_template_context(&user_locale(&conn, sess.user_id)?);
 
     let password_changed = req.args()
         .get("password_changed")
@@ -384,16 +488,34 @@ pub fn change_password_form(req: &mut Request) -> PencilResult {
 
     context.insert("password_changed".to_string(), password_changed);
 
-    req.app.render_template("change_password.html", &context).refresh_cookie(&sess)
+    render_template(req.app, "change_password.html", &context).refresh_cookie(&sess)
+}
+
+pub fn sessions(req: &mut Request) -> PencilResult {
+
+    let (conn, sess) = auth_account(req)?;
+
+    let context = new_template_context(&user_locale(&conn, sess.user_id)?);
+
+    render_template(req.app, "sessions.html", &context).refresh_cookie(&sess)
+}
+
+pub fn two_factor(req: &mut Request) -> PencilResult {
+
+    let (conn, sess) = auth_account(req)?;
+
+    let context = new_template_context(&user_locale(&conn, sess.user_id)?);
+
+    render_template(req.app, "two_factor.html", &context).refresh_cookie(&sess)
 }
 
 pub fn password_reset_success(req: &mut Request) -> PencilResult {
 
-    let (_, sess) = auth_user(req, "")?;
+    let (conn, sess) = auth_user(req, "")?;
 
-    let mut context = new_template_context();
+    let mut context = new_template_context(&user_locale(&conn, sess.user_id)?);
     context.insert("changed", "changed");
-    req.app.render_template("reset_password.html", &context).refresh_cookie(&sess)
+    render_template(req.app, "reset_password.html", &context).refresh_cookie(&sess)
 }
 
 pub fn confirm_password_reset_form(req: &mut Request) -> PencilResult {
@@ -409,12 +531,12 @@ pub fn confirm_password_reset_form(req: &mut Request) -> PencilResult {
     let changed = req.args().get("changed");
     let conn = db_connect().err_500()?;
 
-    let email = match user::check_password_reset(&conn, secret).err_500()? {
-        Some((secret, _)) => secret.email,
+    let (email, user) = match user::check_password_reset(&conn, secret).err_500()? {
+        Some((secret, user)) => (secret.email, user),
         None => return redirect("/", 303),
     };
 
-    let mut context = new_template_context();
+    let mut context = new_template_context(&user.locale);
     context.insert("email", email);
     context.insert("secret", secret);
     context.insert("hmac", hmac);
@@ -422,7 +544,7 @@ pub fn confirm_password_reset_form(req: &mut Request) -> PencilResult {
         context.insert("changed", changed);
     }
 
-    req.app.render_template("reset_password.html", &context)
+    render_template(req.app, "reset_password.html", &context)
 
 }
 
@@ -468,8 +590,11 @@ pub fn confirm_password_reset_post(req: &mut Request) -> PencilResult {
         do_logout(&conn, &old_sess).err_500()?;
     }
 
-    match do_login(&conn, &secret.email, password).err_500()? {
-        Some((_, sess)) => redirect("/reset_password?changed=true", 303).refresh_cookie(&sess),
+    match do_login(&conn, &secret.email, password, &get_client(&*req)).err_500()? {
+        Some(LoginStep::Done(_, sess)) => {
+            redirect("/reset_password?changed=true", 303).refresh_cookie(&sess)
+        }
+        Some(LoginStep::NeedsCode(token)) => ask_code(req.app, request_locale(req), &token, false),
         None => {
             Err(internal_error(Error::from(ErrMsg("We just successfully changed password, yet \
                                                    we can't login them in. A bug?"
@@ -479,16 +604,17 @@ pub fn confirm_password_reset_post(req: &mut Request) -> PencilResult {
 }
 
 pub fn pw_reset_email_form(req: &mut Request) -> PencilResult {
+    let locale = request_locale(req);
     let email = req.args().get("email").unwrap_or_else(|| "");
     let sent = req.args().get("sent");
-    let mut context = new_template_context();
+    let mut context = new_template_context(locale);
     if sent.is_none() {
         context.insert("show_form".to_string(), "show_form");
     }
     context.insert("email".to_string(), email);
     context.insert("sent".to_string(), sent.unwrap_or_else(|| ""));
 
-    req.app.render_template("send_pw_reset_email.html", &context)
+    render_template(req.app, "send_pw_reset_email.html", &context)
 }
 
 
@@ -498,13 +624,14 @@ pub fn send_pw_reset_email(req: &mut Request) -> PencilResult {
         Ok(parse!(req.form().get("email")))
     }
 
+    let locale = request_locale(req);
     let user_email = err_400!(parse_form(req), "invalid form data");
 
     let conn = db_connect().err_500()?;
 
     match user::send_pw_change_email(&conn, user_email, COOKIE_HMAC_KEY.as_slice()) {
         Ok((secret, hmac)) => {
-            email::send_pw_reset_email(&*MAIL_QUEUE,
+            email::send_pw_reset_email(&conn,
                                        &secret,
                                        &hmac,
                                        &*SITE_DOMAIN,
@@ -520,10 +647,10 @@ pub fn send_pw_reset_email(req: &mut Request) -> PencilResult {
         Err(Error(ErrorKind::NoSuchUser(user), _)) => {
             warn!("Trying to reset the password of non-existent address: {}",
                   user);
-            let mut context = new_template_context();
-            context.insert("error".to_string(), "No such e-mail address :(");
+            let mut context = new_template_context(locale);
+            context.insert("error".to_string(), i18n::message(locale, "no_such_email"));
             context.insert("show_form".to_string(), "show_form");
-            req.app.render_template("send_pw_reset_email.html", &context).map(|mut r| {
+            render_template(req.app, "send_pw_reset_email.html", &context).map(|mut r| {
                                                                                   r.status_code =
                                                                                       400;
                                                                                   r
@@ -531,9 +658,9 @@ pub fn send_pw_reset_email(req: &mut Request) -> PencilResult {
         }
         Err(Error(ErrorKind::RateLimitExceeded, _)) => {
             warn!("Someone is sending multiple password request requests per day!");
-            let mut context = new_template_context();
-            context.insert("error".to_string(), "Rate limit exceeded :(");
-            req.app.render_template("send_pw_reset_email.html", &context).map(|mut r| {
+            let mut context = new_template_context(locale);
+            context.insert("error".to_string(), i18n::message(locale, "rate_limit_exceeded"));
+            render_template(req.app, "send_pw_reset_email.html", &context).map(|mut r| {
                                                                                   r.status_code =
                                                                                       429;
                                                                                   r
@@ -546,7 +673,7 @@ pub fn send_pw_reset_email(req: &mut Request) -> PencilResult {
 
 pub fn change_password(req: &mut Request) -> PencilResult {
 
-    let (conn, sess) = auth_user(req, "")?;
+    let (conn, sess) = auth_account(req)?;
 
     fn parse_form<'a>(req: &'a mut Request) -> Result<(&'a str, &'a str)> {
 
@@ -569,57 +696,193 @@ pub fn change_password(req: &mut Request) -> PencilResult {
         None => return Ok(bad_request("User account is deactivated!? Cannot change password.")),
     };
 
-    match user::auth_user(&conn, &user_email, old_password, &*RUNTIME_PEPPER) {
-        Err(e) => {
-            return match *e.kind() {
-                       ErrorKind::AuthError => {
-                let mut context = new_template_context();
-                context.insert("authError".to_string(), "true".to_string());
-
-                req.app
-                        .render_template("change_password.html", &context)
-                        .map(|mut resp| {
+    let auth = user::auth_user(&conn,
+                               &user_email,
+                               old_password,
+                               &*RUNTIME_PEPPER,
+                               &*OLD_RUNTIME_PEPPERS,
+                               *PASSWORD_STRETCHING_TIME);
+
+    if !password_ok(auth).err_500()? {
+        let mut context = new_template_context(&user.locale);
+        context.insert("authError".to_string(), "true".to_string());
+
+        return render_template(req.app, "change_password.html", &context)
+                   .map(|mut resp| {
                             resp.status_code = 401;
                             resp
-                        })
-            }
-                       _ => Err(internal_error(e)),
-                   }
+                        });
+    }
+
+    if let Err(e) = user::change_password(&conn,
+                                          sess.user_id,
+                                          new_password,
+                                          &*RUNTIME_PEPPER,
+                                          *PASSWORD_STRETCHING_TIME) {
+        match *e.kind() {
+            errors::ErrorKind::PasswordTooShort => return Ok(bad_request("Password too short")),
+            errors::ErrorKind::PasswordTooLong => return Ok(bad_request("Password too long")),
+            _ => return Err(internal_error(e)),
         }
-        Ok(_) => {
-            if let Err(e) = user::change_password(&conn,
-                                                  sess.user_id,
-                                                  new_password,
-                                                  &*RUNTIME_PEPPER,
-                                                  *PASSWORD_STRETCHING_TIME) {
-                match *e.kind() {
-                    errors::ErrorKind::PasswordTooShort => return Ok(bad_request("Password too short")),
-                    errors::ErrorKind::PasswordTooLong => return Ok(bad_request("Password too long")),
-                    _ => return Err(internal_error(e)),
-                }
-            }
+    }
+
+    redirect("/change_password?password_changed=true", 303).refresh_cookie(&sess)
+}
+
+pub fn my_data_form(req: &mut Request) -> PencilResult {
+
+    let (conn, sess) = auth_account(req)?;
+
+    let locale = user_locale(&conn, sess.user_id)?;
+    let mut context = new_template_context(&locale);
+    context.insert("grace_period_days", ACCOUNT_GRACE_PERIOD.num_days().to_string());
+    context.insert(format!("locale_{}", locale), "selected");
+
+    if let Some(request) = account::open_request(&conn, sess.user_id).err_500()? {
+        if request.action == "delete" {
+            context.insert("request_delete", "true");
         }
+        context.insert("request_action", request.action);
+        if let Some(due) = request.due {
+            context.insert("request_due", due.format("%Y-%m-%d %H:%M UTC").to_string());
+        }
+    }
+
+    if req.args().get("auth_error").is_some() {
+        context.insert("authError", "true");
+    }
+
+    render_template(req.app, "my_data.html", &context).refresh_cookie(&sess)
+}
+
+pub fn my_data_archive(req: &mut Request) -> PencilResult {
+
+    let (conn, sess) = auth_account(req)?;
+    use std::str::FromStr;
+
+    let archive = account::export(&conn, sess.user_id, &*USER_AUDIO_DIR).err_500()?;
+
+    let mut resp = pencil::Response::from(archive);
+    let mime = mime::Mime::from_str("application/x-tar").unwrap();
+    resp.headers.set::<hyper::header::ContentType>(hyper::header::ContentType(mime));
+    resp.headers.set_raw("Content-Disposition",
+                         vec![b"attachment; filename=\"my_data.tar\"".to_vec()]);
+    resp.refresh_cookie(&sess)
+}
+
+pub fn my_data_request(req: &mut Request) -> PencilResult {
+
+    let (conn, sess) = auth_account(req)?;
+
+    let action = err_400!(req.form().get("action"), "action missing").to_string();
+    if !account::WITHDRAWALS.contains(&action.as_str()) {
+        return Ok(bad_request("No such action!"));
+    }
+
+    account::request(&conn, sess.user_id, &action).err_500()?;
+
+    redirect("/my_data", 303).refresh_cookie(&sess)
+}
+
+pub fn my_data_confirm(req: &mut Request) -> PencilResult {
+
+    let (conn, sess) = auth_account(req)?;
+
+    let password = err_400!(req.form().get("password"), "password missing").to_string();
+
+    let user = user::get_user(&conn, sess.user_id).err_500()?;
+    let user_email = match user.email {
+        Some(email) => email,
+        None => return Ok(bad_request("User account is deactivated!")),
     };
 
-    redirect("/change_password?password_changed=true", 303).refresh_cookie(&sess)
+    let auth = user::auth_user(&conn,
+                               &user_email,
+                               &password,
+                               &*RUNTIME_PEPPER,
+                               &*OLD_RUNTIME_PEPPERS,
+                               *PASSWORD_STRETCHING_TIME);
+
+    if !password_ok(auth).err_500()? {
+        return redirect("/my_data?auth_error=true", 303).refresh_cookie(&sess);
+    }
+
+    account::confirm(&conn, sess.user_id, *ACCOUNT_GRACE_PERIOD).err_500()?;
+
+    redirect("/my_data", 303).refresh_cookie(&sess)
+}
+
+/// Whether the password given to confirm an action was right. `auth_user` tells about
+/// a wrong password with `Ok(None)`, so that must be refused just like an `AuthError`.
+fn password_ok(auth: Result<Option<User>>) -> Result<bool> {
+    match auth {
+        Ok(Some(_)) => Ok(true),
+        Ok(None) |
+        Err(Error(ErrorKind::AuthError, _)) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn my_data_locale(req: &mut Request) -> PencilResult {
+
+    let (conn, sess) = auth_account(req)?;
+
+    let locale = err_400!(req.form().get("locale"), "locale missing").to_string();
+    let settings = ganbare::models::UpdateUserLocale { locale: locale };
+
+    match user::set_locale(&conn, None, sess.user_id, &settings) {
+        Ok(Some(_)) => (),
+        Ok(None) => return abort(404),
+        Err(Error(ErrorKind::InvalidInput, _)) => return Ok(bad_request("Unsupported locale!")),
+        Err(e) => return Err(internal_error(e)),
+    }
+
+    redirect("/my_data", 303).refresh_cookie(&sess)
+}
+
+pub fn my_data_cancel(req: &mut Request) -> PencilResult {
+
+    let (conn, sess) = auth_account(req)?;
+
+    account::cancel(&conn, sess.user_id).err_500()?;
+
+    redirect("/my_data", 303).refresh_cookie(&sess)
 }
 
 /*
 // Not in use currently
 pub fn join_form(req: &mut Request) -> PencilResult {
 
-    let context = new_template_context();
+    let context = new_template_context(request_locale(req));
 
-    req.app
-        .render_template("join.html", &context)
+    render_template(req.app, "join.html", &context)
 }
 
 // Not in use currently
 pub fn join_post(req: &mut Request) -> PencilResult {
 
-    let context = new_template_context();
+    let context = new_template_context(request_locale(req));
 
-    req.app
-        .render_template("join.html", &context)
+    render_template(req.app, "join.html", &context)
 }
 */
+
+#[test]
+fn test_password_ok() {
+    use chrono::UTC;
+
+    let user = User {
+        id: 1,
+        email: Some("a@example.com".into()),
+        joined: UTC::now(),
+        last_seen: UTC::now(),
+        timezone: "UTC".into(),
+        day_rollover_hour: 3,
+        locale: "fi".into(),
+    };
+    assert!(password_ok(Ok(Some(user))).unwrap());
+    // A wrong password.
+    assert!(!password_ok(Ok(None)).unwrap());
+    assert!(!password_ok(Err(ErrorKind::AuthError.into())).unwrap());
+    assert!(password_ok(Err(ErrorKind::DatabaseOdd("x").into())).is_err());
+}
@@ -5,12 +5,17 @@ use std::net::{SocketAddr, ToSocketAddrs};
 use std::collections::BTreeMap;
 use cookie::Cookie as CookiePair;
 use pencil::{self, Request, Response, abort, PencilError, PencilResult, SetCookie, Cookie};
-use ganbare::models::User;
+use ganbare::models::{User, Session};
 use std::net::IpAddr;
 use std::result::Result as StdResult;
 use ganbare::errors::Result;
 use ganbare::user;
+use ganbare::password;
+use ganbare::role;
+use ganbare::i18n;
 use ganbare::session;
+use ganbare::api_token;
+use ganbare::totp;
 use ganbare::errors;
 use std::path::PathBuf;
 pub use try_map::{FallibleMapExt, FlipResultExt};
@@ -29,13 +34,6 @@ pub use ganbare_backend::PERF_TRACE;
 
 lazy_static! {
 
-    pub static ref TRAINING_PERIOD: TimeDuration = {
-        dotenv::dotenv().ok();
-        TimeDuration::days(env::var("GANBARE_TRAINING_PERIOD_DAYS")
-            .map(|s| s.parse().unwrap_or(10))
-            .unwrap_or(10))
-    };
-
     pub static ref NAG_EMAIL_GRACE_PERIOD: TimeDuration = {
         dotenv::dotenv().ok();
         TimeDuration::hours(env::var("GANBARE_NAG_EMAIL_GRACE_PERIOD_HOURS")
@@ -57,6 +55,37 @@ lazy_static! {
             .unwrap_or(14))
     };
 
+    pub static ref ACCOUNT_GRACE_PERIOD: TimeDuration = {
+        dotenv::dotenv().ok();
+        TimeDuration::days(env::var("GANBARE_ACCOUNT_GRACE_PERIOD_DAYS")
+            .map(|s| s.parse().unwrap_or(14))
+            .unwrap_or(14))
+    };
+
+    pub static ref TRASH_RETENTION: TimeDuration = {
+        dotenv::dotenv().ok();
+        TimeDuration::days(env::var("GANBARE_TRASH_RETENTION_DAYS")
+            .map(|s| s.parse().unwrap_or(30))
+            .unwrap_or(30))
+    };
+
+    pub static ref API_TOKEN_EXPIRE: TimeDuration = {
+        dotenv::dotenv().ok();
+        TimeDuration::minutes(env::var("GANBARE_API_TOKEN_EXPIRE_MINUTES")
+            .map(|s| s.parse().unwrap_or(60))
+            .unwrap_or(60))
+    };
+
+    pub static ref TWO_FACTOR_REQUIRED_ROLES: Vec<String> = {
+        dotenv::dotenv().ok();
+        env::var("GANBARE_TWO_FACTOR_REQUIRED_ROLES")
+            .map(|s| s.split(',')
+                .map(|r| r.trim().to_string())
+                .filter(|r| !r.is_empty())
+                .collect())
+            .unwrap_or_default()
+    };
+
     pub static ref EMAIL_EXPIRE: TimeDuration = {
         dotenv::dotenv().ok();
         TimeDuration::days(env::var("GANBARE_EMAIL_EXPIRE_DAYS")
@@ -64,6 +93,13 @@ lazy_static! {
             .unwrap_or(14))
     };
 
+    pub static ref EMAIL_MAX_ATTEMPTS: i32 = {
+        dotenv::dotenv().ok();
+        env::var("GANBARE_EMAIL_MAX_ATTEMPTS")
+            .map(|s| s.parse().unwrap_or(10))
+            .unwrap_or(10)
+    };
+
     pub static ref PASSWORD_STRETCHING_TIME: Duration = {
         dotenv::dotenv().ok();
         Duration::from_millis(env::var("GANBARE_PASSWORD_STRETCHING_MS")
@@ -155,6 +191,25 @@ lazy_static! {
         .unwrap_or_else(|_| "".into())
     };
 
+    pub static ref EMAIL_SMTP_ENCRYPT : bool = {
+        dotenv::dotenv().ok();
+        env::var("GANBARE_EMAIL_SMTP_ENCRYPT")
+            .map(|s| s.parse().unwrap_or(true))
+            .unwrap_or(true)
+    };
+
+    pub static ref EMAIL_TRANSPORT : String = {
+        dotenv::dotenv().ok();
+        env::var("GANBARE_EMAIL_TRANSPORT")
+            .unwrap_or_else(|_| "smtp".into())
+    };
+
+    pub static ref EMAIL_DIR : String = {
+        dotenv::dotenv().ok();
+        env::var("GANBARE_EMAIL_DIR")
+            .unwrap_or_else(|_| "emails".into())
+    };
+
     pub static ref EMAIL_DOMAIN : String = {
         dotenv::dotenv().ok();
         env::var("GANBARE_EMAIL_DOMAIN")
@@ -239,6 +294,16 @@ lazy_static! {
         pepper
     };
 
+    pub static ref OLD_RUNTIME_PEPPERS : Vec<Vec<u8>> = {
+        dotenv::dotenv().ok();
+        env::var("GANBARE_OLD_RUNTIME_PEPPERS")
+            .map(|s| password::parse_peppers(&s).expect(
+                "Environmental variable GANBARE_OLD_RUNTIME_PEPPERS isn't valid! \
+                (format: comma-separated list of 256-bit values encoded as base64)"
+            ))
+            .unwrap_or_else(|_| vec![])
+    };
+
     pub static ref BUILD_NUMBER : String = {
         dotenv::dotenv().ok();
         env::var("GANBARE_BUILD_NUMBER")
@@ -342,7 +407,7 @@ fn get_session_cookie(cookies: &Cookie) -> Result<Option<session::UserSession>>
 
 
 use std::borrow::Cow;
-use serde::ser::{Serialize, Serializer};
+use serde::ser::{Serialize, Serializer, SerializeMap};
 
 pub struct TemplateContext<'a> {
     map: BTreeMap<Cow<'a, str>, Cow<'a, str>>,
@@ -368,16 +433,98 @@ impl<'a> Serialize for TemplateContext<'a> {
     }
 }
 
-pub fn new_template_context<'a>() -> TemplateContext<'a> {
+pub fn new_template_context<'a>(locale: &str) -> TemplateContext<'a> {
     let mut ctx = TemplateContext { map: BTreeMap::new() };
     ctx.insert("title", "akusento.ganba.re");
     ctx.insert("jquery_url", JQUERY_URL.as_ref());
     ctx.insert("font_stylesheet", FONT_URL.as_ref());
+    ctx.insert("locale", locale.to_string());
+    ctx.insert("client_messages", i18n::client_messages(locale));
     ctx
 }
 
+/// The template context together with the texts of the template, which the templates
+/// refer to as `t.key`.
+struct LocalizedContext<'a, 'b: 'a> {
+    context: &'a TemplateContext<'b>,
+    t: BTreeMap<&'static str, &'static str>,
+}
+
+impl<'a, 'b> Serialize for LocalizedContext<'a, 'b> {
+    fn serialize<S>(&self, serializer: S) -> StdResult<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut map = serializer.serialize_map(Some(self.context.map.len() + 1))?;
+        for (key, value) in &self.context.map {
+            map.serialize_key(key)?;
+            map.serialize_value(value)?;
+        }
+        map.serialize_key("t")?;
+        map.serialize_value(&self.t)?;
+        map.end()
+    }
+}
+
+/// Renders the template with its texts in the locale of the context.
+pub fn render_template(app: &pencil::Pencil,
+                       template: &str,
+                       context: &TemplateContext)
+                       -> PencilResult {
+    let locale = context.map.get("locale").map(|l| l.as_ref()).unwrap_or(i18n::DEFAULT_LOCALE);
+    let context = LocalizedContext {
+        context: context,
+        t: i18n::template_messages(locale, template),
+    };
+    app.render_template(template, &context)
+}
+
+/// The locale for visitors that aren't logged in, negotiated from the Accept-Language header.
+pub fn request_locale(req: &Request) -> &'static str {
+    req.headers()
+        .get_raw("Accept-Language")
+        .and_then(|values| values.first())
+        .and_then(|value| std::str::from_utf8(value).ok())
+        .map(i18n::negotiate)
+        .unwrap_or(i18n::DEFAULT_LOCALE)
+}
+
+/// The raw value of the Accept header, for picking an audio rendition.
+pub fn accept_header(req: &Request) -> Option<String> {
+    req.headers()
+        .get_raw("Accept")
+        .map(|values| {
+                 values.iter()
+                     .filter_map(|value| std::str::from_utf8(value).ok())
+                     .collect::<Vec<_>>()
+                     .join(",")
+             })
+}
+
+pub fn user_locale(conn: &Connection, user_id: i32) -> StdResult<String, PencilError> {
+    Ok(user::get_user(conn, user_id).err_500()?.locale)
+}
+
 
 
+/// The token of the `Authorization: Bearer` header.
+pub fn get_bearer_token(req: &Request) -> Option<String> {
+    use hyper::header::{Authorization, Bearer};
+
+    req.headers().get::<Authorization<Bearer>>().map(|auth| auth.0.token.clone())
+}
+
+/// The user agent and the address of the client, recorded when a session is started.
+pub fn get_client(req: &Request) -> session::Client {
+    use hyper::header::UserAgent;
+
+    session::Client {
+        user_agent: req.headers()
+            .get::<UserAgent>()
+            .map(|ua| ua.0.chars().take(512).collect()),
+        ip: Some(req.remote_addr.ip().to_string()),
+    }
+}
+
 pub fn get_sess(conn: &Connection, req: &Request) -> Result<Option<UserSession>> {
     if let Ok(Some(Some(sess))) = req.cookies().try_map(get_session_cookie) {
         if session::check(&sess, &*LOGGED_OUT_CACHE)? {
@@ -411,11 +558,61 @@ pub fn auth_user(req: &mut Request,
     }
 }
 
+/// Like `auth_user`, but for the actions on the account itself, like changing the password,
+/// ending sessions, managing tokens or deleting the user's data. Bearer tokens are refused
+/// whatever their roles, so a leaked token can't be used to take over the account.
+pub fn auth_account(req: &mut Request) -> StdResult<(Connection, UserSession), PencilError> {
+    let (conn, sess) = auth_user(req, "")?;
+    if sess.api_token.is_some() {
+        return Err(abort(403).unwrap_err());
+    }
+    Ok((conn, sess))
+}
+
+/// Whether the session has the permission. Sessions that are authenticated with an API token
+/// only have the permissions within the scope of the token.
+pub fn sess_has_permission(conn: &Connection,
+                           sess: &UserSession,
+                           permission: &str)
+                           -> Result<bool> {
+    match sess.api_token {
+        Some(token_id) => role::token_has_permission(conn, sess.user_id, token_id, permission),
+        None => role::has_permission(conn, sess.user_id, permission),
+    }
+}
+
+/// Like `auth_user`, but instead of a group, the user must have the permission
+/// through one of their roles.
+pub fn auth_permission(req: &mut Request,
+                       permission: &str)
+                       -> StdResult<(Connection, UserSession), PencilError> {
+
+    match try_auth_user(req)? {
+        Some((conn, sess)) => {
+            if !sess_has_permission(&conn, &sess, permission).err_500()? {
+                Err(abort(401).unwrap_err()) // User doesn't have the required permission
+            } else if totp::is_missing(&conn, sess.user_id, &TWO_FACTOR_REQUIRED_ROLES)
+                          .err_500()? {
+                Err(abort(403).unwrap_err()) // User has to enable two-factor authentication first
+            } else {
+                Ok((conn, sess))
+            }
+        }
+        None => {
+            Err(abort(401).unwrap_err()) // User isn't logged in
+        }
+    }
+}
+
 pub fn try_auth_user(req: &mut Request)
                      -> StdResult<Option<(Connection, UserSession)>, PencilError> {
 
     time_it!{"try_auth_user",
-        if let Ok(Some(Some(sess))) = req.cookies().try_map(get_session_cookie) {
+        if let Some(bearer) = get_bearer_token(req) {
+            let conn = db_connect().err_500()?;
+            let sess = api_token::check(&conn, &bearer, COOKIE_HMAC_KEY.as_slice()).err_500()?;
+            Ok(sess.map(|sess| (conn, sess)))
+        } else if let Ok(Some(Some(sess))) = req.cookies().try_map(get_session_cookie) {
             let conn = db_connect().err_500()?;
             if session::check(&sess, &*LOGGED_OUT_CACHE).err_500()? {
                 Ok(Some((conn, sess)))
@@ -686,33 +883,83 @@ macro_rules! include_templates(
 /// to run and check if the values are present.
 pub fn check_env_vars() {
     lazy_static::initialize(&DATABASE_URL);
-    lazy_static::initialize(&EMAIL_SERVER);
+    if *EMAIL_TRANSPORT == "smtp" {
+        lazy_static::initialize(&EMAIL_SERVER);
+    }
     lazy_static::initialize(&SITE_DOMAIN);
     lazy_static::initialize(&SITE_LINK);
     lazy_static::initialize(&TIME_AT_SERVER_START);
     lazy_static::initialize(&COOKIE_HMAC_KEY);
 }
 
+pub enum LoginStep {
+    Done(User, UserSession),
+    /// The password was right, but the user has two-factor authentication enabled.
+    /// The token identifies the login when the code is given to `do_second_step`.
+    NeedsCode(String),
+}
+
 pub fn do_login(conn: &Connection,
                 email: &str,
-                plaintext_pw: &str)
-                -> StdResult<Option<(User, UserSession)>, PencilError> {
+                plaintext_pw: &str,
+                client: &session::Client)
+                -> StdResult<Option<LoginStep>, PencilError> {
     debug!("Logging in user: {:?}", email);
-    let user = try_or!(user::auth_user(&conn, email, plaintext_pw, &*RUNTIME_PEPPER).err_500()?,
+    let user = try_or!(user::auth_user(&conn,
+                                       email,
+                                       plaintext_pw,
+                                       &*RUNTIME_PEPPER,
+                                       &*OLD_RUNTIME_PEPPERS,
+                                       *PASSWORD_STRETCHING_TIME).err_500()?,
             else return Ok(None));
 
-    let sess = session::start(conn, &user).err_500()?;
+    if totp::is_enabled(conn, user.id).err_500()? {
+        let token = match totp::start_login(conn, user.id) {
+            Ok(token) => token,
+            Err(errors::Error(errors::ErrorKind::RateLimitExceeded, _)) => {
+                warn!("Too many logins waiting for the code: {}", email);
+                return Err(abort(403).unwrap_err());
+            }
+            Err(e) => return Err(internal_error(e)),
+        };
+        return Ok(Some(LoginStep::NeedsCode(token)));
+    }
+
+    let sess = session::start(conn, &user, client).err_500()?;
+
+    Ok(Some(LoginStep::Done(user, sess)))
+}
+
+pub fn do_second_step(conn: &Connection,
+                      token: &str,
+                      code: &str,
+                      client: &session::Client)
+                      -> StdResult<Option<(User, UserSession)>, PencilError> {
+    let user = try_or!(totp::finish_login(conn, token, code).err_500()?, else return Ok(None));
+
+    let sess = session::start(conn, &user, client).err_500()?;
 
     Ok(Some((user, sess)))
 }
 
 pub fn do_logout(conn: &Connection, sess: &UserSession) -> StdResult<(), PencilError> {
     debug!("Logging out session: {:?}", sess);
+    if let Some(token_id) = sess.api_token {
+        api_token::revoke(conn, None, sess.user_id, token_id).err_500()?;
+        return Ok(());
+    }
     session::end(conn, sess.sess_id).err_500()?;
     LOGGED_OUT_CACHE.insert(sess.sess_id, sess.clone()).err_500()?;
     Ok(())
 }
 
+/// Puts a session that was ended from elsewhere in the logout cache, so that
+/// its cookie isn't trusted without checking the database.
+pub fn forget_session(db_sess: &Session) -> StdResult<(), PencilError> {
+    LOGGED_OUT_CACHE.insert(db_sess.id, UserSession::from(db_sess)).err_500()?;
+    Ok(())
+}
+
 macro_rules! parse {
     ($expression:expr) => {
         $expression
@@ -15,30 +15,40 @@ use ganbare::skill;
 use ganbare::manage;
 use ganbare::event;
 use ganbare::user;
+use ganbare::role;
+use ganbare::session;
+use ganbare::totp;
 use test;
 
+/// The audio rendition depends on the Accept header, so caches must take it into account.
+fn vary_by_accept(mut resp: Response) -> Response {
+    resp.headers.set_raw("Vary", vec![b"Accept".to_vec()]);
+    resp
+}
+
 pub fn get_audio(req: &mut Request) -> PencilResult {
 
-    let (conn, sess) = auth_user(req, "editors")?;
+    let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
 
     let mut audio_name = req.view_args
         .get("audio_name")
         .expect("Pencil guarantees that Line ID should exist as an arg.")
         .split('.');
     let audio_id = try_or!(audio_name.next(), else return abort(404));
-    let audio_extension = try_or!(audio_name.next(), else return abort(404));
-    if audio_extension != "mp3" {
-        return abort(404);
-    }
+    let audio_extension = audio_name.next();
     if audio_name.next().is_some() {
         return abort(404);
     }
-    let audio_id =
-        audio_id.parse::<i32>().expect("Pencil guarantees that Line ID should be an integer.");
-    let (file_name, mime_type) = audio::get_file_path(&conn, audio_id).map_err(|e| match e.kind() {
-                     &ErrorKind::FileNotFound => abort(404).unwrap_err(),
-                     e => internal_error(e),
-                 })?;
+    let audio_id = try_or!(audio_id.parse::<i32>().ok(), else return abort(404));
+    let accept = accept_header(req);
+    let (file_name, mime_type) = audio::get_file_path(&conn,
+                                                      audio_id,
+                                                      audio_extension,
+                                                      accept.as_ref().map(|a| a.as_str()))
+            .map_err(|e| match e.kind() {
+                         &ErrorKind::FileNotFound => abort(404).unwrap_err(),
+                         e => internal_error(e),
+                     })?;
 
     use pencil::{PencilError, HTTPError};
 
@@ -51,6 +61,7 @@ pub fn get_audio(req: &mut Request) -> PencilResult {
                              false,
                              req.headers().get()))
             .set_static_cache()
+            .map(vary_by_accept)
             .refresh_cookie(&sess)
             .map_err(|e| match e {
                          PencilError::PenHTTPError(HTTPError::NotFound) => {
@@ -77,10 +88,15 @@ pub fn quiz_audio(req: &mut Request) -> PencilResult {
     let asked_id =
         asked_id.parse::<i32>().expect("Pencil guarantees that Line ID should be an integer.");
 
-    let (file_name, mime_type) = audio::for_quiz(&conn, sess.user_id, asked_id).map_err(|e| match e.kind() {
-                     &ErrorKind::FileNotFound => abort(404).unwrap_err(),
-                     e => internal_error(e),
-                 })?;
+    let accept = accept_header(req);
+    let (file_name, mime_type) = audio::for_quiz(&conn,
+                                                 sess.user_id,
+                                                 asked_id,
+                                                 accept.as_ref().map(|a| a.as_str()))
+            .map_err(|e| match e.kind() {
+                         &ErrorKind::FileNotFound => abort(404).unwrap_err(),
+                         e => internal_error(e),
+                     })?;
 
     use pencil::{PencilError, HTTPError};
 
@@ -93,6 +109,7 @@ pub fn quiz_audio(req: &mut Request) -> PencilResult {
                        false,
                        req.headers().get()))
         .set_static_cache()
+        .map(vary_by_accept)
         .refresh_cookie(&sess)
         .map_err(|e| match e {
             PencilError::PenHTTPError(HTTPError::NotFound) => {
@@ -157,18 +174,11 @@ pub fn new_quiz(req: &mut Request) -> PencilResult {
 pub fn new_quiz_testing(req: &mut Request) -> PencilResult {
     let (conn, sess) = auth_user(req, "")?;
 
-    let new_quiz = if let Some((ev, _)) = time_it!("is_ongoing pretest",
-                                                   ganbare::event::is_ongoing(&conn,
-                                                                              "pretest",
-                                                                              sess.user_id)
-                                                           .err_500())? {
-        debug!("Pretest questions!");
-        test::get_new_quiz_pretest(&conn, sess.user_id, &ev).err_500()?
-    } else if let Some((ev, _)) =
-        time_it!("is_ongoing posttest",
-                 ganbare::event::is_ongoing(&conn, "posttest", sess.user_id).err_500())? {
-        debug!("Posttest questions!");
-        test::get_new_quiz_posttest(&conn, sess.user_id, &ev).err_500()?
+    let new_quiz = if let Some((ev, _)) =
+        time_it!("ongoing test",
+                 ganbare::event::ongoing_of_kind(&conn, "test", sess.user_id).err_500())? {
+        debug!("Test questions for {}!", ev.name);
+        test::get_new_quiz_testing(&conn, sess.user_id, &ev).err_500()?
     } else {
         None
     };
@@ -264,11 +274,8 @@ pub fn next_quiz_testing(req: &mut Request) -> PencilResult {
                           req.form());
 
     let new_quiz = if let Some((ev, _)) =
-        ganbare::event::is_ongoing(&conn, "pretest", sess.user_id).err_500()? {
-        test::get_next_quiz_pretest(&conn, sess.user_id, answer, &ev).err_500()?
-    } else if let Some((ev, _)) =
-        ganbare::event::is_ongoing(&conn, "posttest", sess.user_id).err_500()? {
-        test::get_next_quiz_posttest(&conn, sess.user_id, answer, &ev).err_500()?
+        ganbare::event::ongoing_of_kind(&conn, "test", sess.user_id).err_500()? {
+        test::get_next_quiz_testing(&conn, sess.user_id, answer, &ev).err_500()?
     } else {
         None
     };
@@ -282,12 +289,16 @@ pub fn next_quiz_testing(req: &mut Request) -> PencilResult {
 
 
 pub fn get_item(req: &mut Request) -> PencilResult {
-    let (conn, sess) = auth_user(req, "editors")?;
+    let endpoint = req.endpoint().expect("Pencil guarantees this");
+    let permission = match endpoint.as_ref() {
+        "get_test_item" => role::MANAGE_EVENTS,
+        _ => role::EDIT_CONTENT,
+    };
+    let (conn, sess) = auth_permission(req, permission)?;
 
     let id =
         req.view_args.get("id").expect("Pencil guarantees that Line ID should exist as an arg.");
     let id = id.parse::<i32>().expect("Pencil guarantees that Line ID should be an integer.");
-    let endpoint = req.endpoint().expect("Pencil guarantees this");
     let json = match endpoint.as_ref() {
         "get_word" => {
             let item = manage::get_word(&conn, id).err_500()?
@@ -304,6 +315,11 @@ pub fn get_item(req: &mut Request) -> PencilResult {
                 .ok_or_else(|| abort(404).unwrap_err())?;
             jsonify(&item)
         }
+        "get_test_item" => {
+            let item = ganbare::test::get_item(&conn, id).err_500()?
+                .ok_or_else(|| abort(404).unwrap_err())?;
+            jsonify(&item)
+        }
         _ => return Err(internal_error("no such endpoint!")),
     };
 
@@ -311,57 +327,80 @@ pub fn get_item(req: &mut Request) -> PencilResult {
 }
 
 pub fn del_item(req: &mut Request) -> PencilResult {
-    let (conn, sess) = auth_user(req, "editors")?;
+    let endpoint = req.endpoint().expect("Pencil guarantees this");
+    let permission = match endpoint.as_ref() {
+        "del_user" | "del_due_and_pending_items" | "del_role" => role::MANAGE_USERS,
+        "del_test_item" | "del_event_transition" | "del_event_exp" => role::MANAGE_EVENTS,
+        _ => role::EDIT_CONTENT,
+    };
+    let (conn, sess) = auth_permission(req, permission)?;
 
     info!("del_item");
 
     let id =
         req.view_args.get("id").expect("Pencil guarantees that Line ID should exist as an arg.");
     let id = id.parse::<i32>().expect("Pencil guarantees that Line ID should be an integer.");
-    let endpoint = req.endpoint().expect("Pencil guarantees this");
     let json = match endpoint.as_ref() {
         "del_narrator" => {
-            if !audio::del_narrator(&conn, id).err_500()? {
+            if !audio::del_narrator(&conn, sess.user_id, id).err_500()? {
                 return abort(404);
             }
             jsonify(&())
         }
         "del_bundle" => {
-            if !audio::del_bundle(&conn, id).err_500()? {
+            if !audio::del_bundle(&conn, sess.user_id, id).err_500()? {
                 return abort(404);
             }
             jsonify(&())
         }
         "del_user" => {
-            if user::deactivate_user(&conn, id).err_500()?.is_none() {
+            if user::deactivate_user(&conn, Some(sess.user_id), id).err_500()?.is_none() {
                 return abort(404);
             }
             jsonify(&())
         }
         "del_due_and_pending_items" => {
-            manage::del_due_and_pending_items(&conn, id).err_500()?;
+            manage::del_due_and_pending_items(&conn, sess.user_id, id).err_500()?;
+            jsonify(&())
+        }
+        "del_role" => {
+            if role::remove_role(&conn, sess.user_id, id).err_500()?.is_none() {
+                return abort(404);
+            }
             jsonify(&())
         }
         "del_skill" => {
-            if skill::remove(&conn, id).err_500()?.is_none() {
+            if skill::remove(&conn, sess.user_id, id).err_500()?.is_none() {
                 return abort(404);
             }
             jsonify(&())
         }
         "del_word" => {
-            if manage::remove_word(&conn, id).err_500()?.is_none() {
+            if manage::remove_word(&conn, sess.user_id, id).err_500()?.is_none() {
                 return abort(404);
             }
             jsonify(&())
         }
         "del_question" => {
-            if !manage::remove_question(&conn, id).err_500()? {
+            if !manage::remove_question(&conn, sess.user_id, id).err_500()? {
                 return abort(404);
             }
             jsonify(&())
         }
         "del_exercise" => {
-            if !manage::remove_exercise(&conn, id).err_500()? {
+            if !manage::remove_exercise(&conn, sess.user_id, id).err_500()? {
+                return abort(404);
+            }
+            jsonify(&())
+        }
+        "del_test_item" => {
+            if !ganbare::test::remove_item(&conn, sess.user_id, id).err_500()? {
+                return abort(404);
+            }
+            jsonify(&())
+        }
+        "del_event_transition" => {
+            if !event::remove_transition(&conn, sess.user_id, id).err_500()? {
                 return abort(404);
             }
             jsonify(&())
@@ -372,7 +411,7 @@ pub fn del_item(req: &mut Request) -> PencilResult {
                 .expect("Pencil guarantees that Line ID should exist as an arg.");
             let user_id = user_id.parse::<i32>()
                 .expect("Pencil guarantees that Line ID should be an integer.");
-            if !event::remove_exp(&conn, id, user_id).err_500()? {
+            if !event::remove_exp(&conn, sess.user_id, id, user_id).err_500()? {
                 return abort(404);
             }
             jsonify(&())
@@ -384,8 +423,114 @@ pub fn del_item(req: &mut Request) -> PencilResult {
     json.refresh_cookie(&sess)
 }
 
+pub fn restore_item(req: &mut Request) -> PencilResult {
+    use ganbare::trash;
+
+    let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
+
+    let id =
+        req.view_args.get("id").expect("Pencil guarantees that Line ID should exist as an arg.");
+    let id = id.parse::<i32>().expect("Pencil guarantees that Line ID should be an integer.");
+    let endpoint = req.endpoint().expect("Pencil guarantees this");
+    let result = match endpoint.as_ref() {
+        "restore_word" => trash::restore_word(&conn, sess.user_id, id).map(|i| i.is_some()),
+        "restore_question" => {
+            trash::restore_question(&conn, sess.user_id, id).map(|i| i.is_some())
+        }
+        "restore_exercise" => {
+            trash::restore_exercise(&conn, sess.user_id, id).map(|i| i.is_some())
+        }
+        "restore_bundle" => trash::restore_bundle(&conn, sess.user_id, id).map(|i| i.is_some()),
+        _ => return Err(internal_error("no such endpoint!")),
+    };
+
+    match result {
+        Ok(true) => jsonify(&()).refresh_cookie(&sess),
+        Ok(false) => abort(404),
+        Err(Error(ErrorKind::InvalidInput, _)) => {
+            Ok(bad_request("Restore the audio bundles and words this item uses first!"))
+        }
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+pub fn get_revisions(req: &mut Request) -> PencilResult {
+    use ganbare::revision;
+
+    let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
+
+    let id =
+        req.view_args.get("id").expect("Pencil guarantees that Line ID should exist as an arg.");
+    let id = id.parse::<i32>().expect("Pencil guarantees that Line ID should be an integer.");
+    let endpoint = req.endpoint().expect("Pencil guarantees this");
+    let item_type = match endpoint.as_ref() {
+        "get_word_revisions" => revision::WORD,
+        "get_question_revisions" => revision::QUESTION,
+        "get_answer_revisions" => revision::ANSWER,
+        _ => return Err(internal_error("no such endpoint!")),
+    };
+
+    let revisions = revision::get_revisions(&conn, item_type, id).err_500()?;
+
+    jsonify(&revisions).refresh_cookie(&sess)
+}
+
+pub fn get_revision_diff(req: &mut Request) -> PencilResult {
+    use ganbare::revision;
+
+    let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
+
+    let id =
+        req.view_args.get("id").expect("Pencil guarantees that Line ID should exist as an arg.");
+    let id = id.parse::<i32>().expect("Pencil guarantees that Line ID should be an integer.");
+    let against = match req.args().get::<str>("against") {
+        Some(a) => Some(err_400!(a.parse::<i32>(), "against must be a revision id")),
+        None => None,
+    };
+
+    let revision = match revision::get_revision(&conn, id).err_500()? {
+        Some(r) => r,
+        None => return abort(404),
+    };
+    let other = match against {
+        Some(against) => {
+            match revision::get_revision(&conn, against).err_500()? {
+                Some(ref o) if o.item_type == revision.item_type &&
+                               o.item_id == revision.item_id => Some(o.clone()),
+                Some(_) => {
+                    return Ok(bad_request("The revisions must be of the same item!"));
+                }
+                None => return abort(404),
+            }
+        }
+        None => revision::get_previous(&conn, &revision).err_500()?,
+    };
+
+    let diff = revision::diff(other.as_ref().map(|o| o.content.as_str()).unwrap_or(""),
+                              &revision.content);
+
+    jsonify(&(revision, other, diff)).refresh_cookie(&sess)
+}
+
+pub fn revert_revision(req: &mut Request) -> PencilResult {
+    use ganbare::revision;
+
+    let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
+
+    let id =
+        req.view_args.get("id").expect("Pencil guarantees that Line ID should exist as an arg.");
+    let id = id.parse::<i32>().expect("Pencil guarantees that Line ID should be an integer.");
+
+    match revision::revert(&conn, sess.user_id, id) {
+        Ok(Some(revision)) => jsonify(&revision).refresh_cookie(&sess),
+        Ok(None) => abort(404),
+        Err(Error(ErrorKind::InvalidInput, _)) => Ok(bad_request("Unknown item type!")),
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
 pub fn merge_item(req: &mut Request) -> PencilResult {
-    let (conn, sess) = auth_user(req, "editors")?;
+    let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
 
     info!("merge_item");
 
@@ -400,11 +545,11 @@ pub fn merge_item(req: &mut Request) -> PencilResult {
     let endpoint = req.endpoint().expect("Pencil guarantees this");
     let json = match endpoint.as_ref() {
         "merge_narrator" => {
-            audio::merge_narrator(&conn, id_from, id_to).err_500()?;
+            audio::merge_narrator(&conn, sess.user_id, id_from, id_to).err_500()?;
             jsonify(&())
         }
         "merge_bundle" => {
-            audio::merge_audio_bundle(&conn, id_from, id_to).err_500()?;
+            audio::merge_audio_bundle(&conn, sess.user_id, id_from, id_to).err_500()?;
             jsonify(&())
         }
         _ => return Err(internal_error("no such endpoint!")),
@@ -415,9 +560,19 @@ pub fn merge_item(req: &mut Request) -> PencilResult {
 }
 
 pub fn get_all(req: &mut Request) -> PencilResult {
-    let (conn, sess) = auth_user(req, "editors")?;
-
     let endpoint = req.endpoint().expect("Pencil guarantees this");
+    let permission = match endpoint.as_ref() {
+        "get_users" | "get_groups" => role::VIEW_USERS,
+        "get_account_requests" | "get_roles" => role::MANAGE_USERS,
+        "get_events" | "get_event_transitions" | "get_tests" | "get_tests_by_event" => {
+            role::MANAGE_EVENTS
+        }
+        "get_emails" => role::SEND_MAIL,
+        "get_randomizations" | "get_consent_documents" => role::MANAGE_EXPERIMENTS,
+        _ => role::EDIT_CONTENT,
+    };
+    let (conn, sess) = auth_permission(req, permission)?;
+
     let json = match endpoint.as_ref() {
         "get_nuggets" => {
             let items = skill::get_skill_nuggets(&conn).err_500()?;
@@ -427,10 +582,23 @@ pub fn get_all(req: &mut Request) -> PencilResult {
             let items = audio::get_all_bundles(&conn).err_500()?;
             jsonify(&items)
         }
-        "get_users" => {
-            let items = ganbare::user::get_all(&conn).err_500()?;
+        "get_trash" => {
+            let items = ganbare::trash::get_all(&conn).err_500()?;
             jsonify(&items)
         }
+        "get_users" => {
+            let (mut users, groups, pending) = ganbare::user::get_all(&conn).err_500()?;
+            for user in &mut users {
+                ganbare::pseudonym::hide_anonymous(&mut user.3, &groups);
+            }
+            let anonymous = if sess_has_permission(&conn, &sess, role::VIEW_ANONYMOUS)
+                .err_500()? {
+                ganbare::pseudonym::anonymous_members(&conn).err_500()?
+            } else {
+                vec![]
+            };
+            jsonify(&(users, groups, pending, anonymous))
+        }
         "get_narrators" => {
             let items = audio::get_narrators(&conn).err_500()?;
             jsonify(&items)
@@ -443,6 +611,46 @@ pub fn get_all(req: &mut Request) -> PencilResult {
             let items = ganbare::event::get_all(&conn).err_500()?;
             jsonify(&items)
         }
+        "get_emails" => {
+            let status = req.view_args
+                .get("status")
+                .expect("Pencil guarantees that status should exist as an arg.");
+            let items = ganbare::email::get_queued_mails(&conn, status).err_500()?;
+            jsonify(&items)
+        }
+        "get_randomizations" => {
+            let items = ganbare::randomize::get_all(&conn).err_500()?;
+            jsonify(&items)
+        }
+        "get_consent_documents" => {
+            let items = ganbare::consent::get_all(&conn).err_500()?;
+            jsonify(&items)
+        }
+        "get_account_requests" => {
+            let items = ganbare::account::get_all(&conn).err_500()?;
+            jsonify(&items)
+        }
+        "get_roles" => {
+            let items = role::get_all(&conn).err_500()?;
+            jsonify(&items)
+        }
+        "get_event_transitions" => {
+            let items = ganbare::event::get_transitions(&conn).err_500()?;
+            jsonify(&items)
+        }
+        "get_tests" => {
+            let items = ganbare::test::get_all_items(&conn).err_500()?;
+            jsonify(&items)
+        }
+        "get_tests_by_event" => {
+            let event_id = req.view_args
+                .get("event_id")
+                .expect("Pencil guarantees that Line ID should exist as an arg.")
+                .parse::<i32>()
+                .expect("Pencil guarantees that Line ID should be an integer.");
+            let items = ganbare::test::get_items(&conn, event_id).err_500()?;
+            jsonify(&items)
+        }
         _ => return Err(internal_error("no such endpoint!")),
     };
 
@@ -450,7 +658,7 @@ pub fn get_all(req: &mut Request) -> PencilResult {
 }
 
 pub fn get_user_details(req: &mut Request) -> PencilResult {
-    let (conn, sess) = auth_user(req, "editors")?;
+    let (conn, sess) = auth_permission(req, role::VIEW_USERS)?;
 
     let id =
         req.view_args.get("id").expect("Pencil guarantees that Line ID should exist as an arg.");
@@ -471,8 +679,36 @@ pub fn get_user_details(req: &mut Request) -> PencilResult {
     json.refresh_cookie(&sess)
 }
 
+pub fn get_alias_details(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_permission(req, role::VIEW_ANONYMOUS)?;
+
+    let alias = req.view_args
+        .remove("alias")
+        .expect("Pencil guarantees that the alias should exist as an arg.");
+    let user_id = match ganbare::pseudonym::resolve(&conn, &alias).err_500()? {
+        Some(models::AnonAliases { user_id: Some(user_id), .. }) => user_id,
+        _ => return abort(404),
+    };
+
+    let endpoint = req.endpoint().expect("Pencil guarantees this");
+    let mut items = match endpoint.as_ref() {
+        "get_alias_skills" => {
+            let items = skill::get_skill_data(&conn, user_id).err_500()?;
+            serde_json::to_value(&items).err_500()?
+        }
+        "get_alias_asked_items" => {
+            let items = skill::get_asked_items(&conn, user_id).err_500()?;
+            serde_json::to_value(&items).err_500()?
+        }
+        _ => return Err(internal_error("no such endpoint!")),
+    };
+    ganbare::pseudonym::replace_user_ids(&mut items, &alias);
+
+    jsonify(&items).refresh_cookie(&sess)
+}
+
 pub fn set_published(req: &mut Request) -> PencilResult {
-    let (conn, sess) = auth_user(req, "editors")?;
+    let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
 
     let id =
         req.view_args.get("id").expect("Pencil guarantees that Line ID should exist as an arg.");
@@ -481,22 +717,22 @@ pub fn set_published(req: &mut Request) -> PencilResult {
 
     match endpoint.as_ref() {
         "publish_words" => {
-            manage::publish_word(&conn, id, true).err_500()?;
+            manage::publish_word(&conn, sess.user_id, id, true).err_500()?;
         }
         "publish_questions" => {
-            manage::publish_question(&conn, id, true).err_500()?;
+            manage::publish_question(&conn, sess.user_id, id, true).err_500()?;
         }
         "publish_exercises" => {
-            manage::publish_exercise(&conn, id, true).err_500()?;
+            manage::publish_exercise(&conn, sess.user_id, id, true).err_500()?;
         }
         "unpublish_words" => {
-            manage::publish_word(&conn, id, false).err_500()?;
+            manage::publish_word(&conn, sess.user_id, id, false).err_500()?;
         }
         "unpublish_questions" => {
-            manage::publish_question(&conn, id, false).err_500()?;
+            manage::publish_question(&conn, sess.user_id, id, false).err_500()?;
         }
         "unpublish_exercises" => {
-            manage::publish_exercise(&conn, id, false).err_500()?;
+            manage::publish_exercise(&conn, sess.user_id, id, false).err_500()?;
         }
         _ => return Err(internal_error("no such endpoint!")),
     };
@@ -505,9 +741,24 @@ pub fn set_published(req: &mut Request) -> PencilResult {
     resp.refresh_cookie(&sess)
 }
 
+/// Leaves only the `src` attribute to the images in the JSON text of an item.
+pub fn strip_img_attributes(text: &str) -> String {
+    lazy_static! {
+        // Taking JSON encoding into account: " is escaped as \"
+        static ref RE: regex::Regex =
+            regex::Regex::new(r##"<img ([^>]* )?src=\\"(?P<src>[^"]*)\\"( [^>]*)?>"##).unwrap();
+    }
+    RE.replace_all(text, r###"<img src=\"$src\">"###).into_owned()
+}
+
 pub fn update_item(req: &mut Request) -> PencilResult {
 
-    let (conn, sess) = auth_user(req, "editors")?;
+    let endpoint = req.endpoint().expect("Pencil guarantees this");
+    let permission = match endpoint.as_ref() {
+        "update_test_item" | "update_event" => role::MANAGE_EVENTS,
+        _ => role::EDIT_CONTENT,
+    };
+    let (conn, sess) = auth_permission(req, permission)?;
 
     let id = req.view_args
         .get("id")
@@ -517,14 +768,7 @@ pub fn update_item(req: &mut Request) -> PencilResult {
 
     let mut text = String::new();
     req.read_to_string(&mut text).err_500()?;
-
-    let endpoint = req.endpoint().expect("Pencil guarantees this");
-    lazy_static! {
-        // Taking JSON encoding into account: " is escaped as \"
-        static ref RE: regex::Regex =
-            regex::Regex::new(r##"<img ([^>]* )?src=\\"(?P<src>[^"]*)\\"( [^>]*)?>"##).unwrap();
-    }
-    let text = RE.replace_all(&text, r###"<img src=\"$src\">"###);
+    let text = strip_img_attributes(&text);
 
     let json;
     match endpoint.as_str() {
@@ -533,7 +777,7 @@ pub fn update_item(req: &mut Request) -> PencilResult {
             let item = err_400!(serde_json::from_str(&text), "Error decoding JSON");
 
             let updated_item = try_or!(
-                manage::update_word(&conn, id, item, &*IMAGES_DIR).err_500()?,
+                manage::update_word(&conn, sess.user_id, id, item, &*IMAGES_DIR).err_500()?,
                 else return abort(404)
             );
 
@@ -545,7 +789,7 @@ pub fn update_item(req: &mut Request) -> PencilResult {
             let item = err_400!(serde_json::from_str(&text), "Error decoding JSON");
 
             let updated_item = try_or!(
-                manage::update_exercise(&conn, id, item).err_500()?,
+                manage::update_exercise(&conn, sess.user_id, id, item).err_500()?,
                 else return abort(404)
             );
 
@@ -556,7 +800,7 @@ pub fn update_item(req: &mut Request) -> PencilResult {
             let item = err_400!(serde_json::from_str(&text), "Error decoding JSON");
 
             let updated_item = try_or!(
-                manage::update_question(&conn, id, item).err_500()?,
+                manage::update_question(&conn, sess.user_id, id, item).err_500()?,
                 else return abort(404)
             );
 
@@ -567,7 +811,7 @@ pub fn update_item(req: &mut Request) -> PencilResult {
             let item = err_400!(serde_json::from_str(&text), "Error decoding JSON");
 
             let updated_item = try_or!(
-                manage::update_answer(&conn, id, item, &*IMAGES_DIR).err_500()?,
+                manage::update_answer(&conn, sess.user_id, id, item, &*IMAGES_DIR).err_500()?,
                 else return abort(404)
             );
 
@@ -578,7 +822,7 @@ pub fn update_item(req: &mut Request) -> PencilResult {
             let item = err_400!(serde_json::from_str(&text), "Error decoding JSON");
 
             let updated_item = try_or!(
-                manage::update_variant(&conn, id, item).err_500()?,
+                manage::update_variant(&conn, sess.user_id, id, item).err_500()?,
                 else return abort(404)
             );
 
@@ -592,7 +836,7 @@ pub fn update_item(req: &mut Request) -> PencilResult {
                 return abort(400);
             }
             let updated_item = try_or!(
-                audio::change_bundle_name(&conn, id, &item.listname).err_500()?,
+                audio::change_bundle_name(&conn, sess.user_id, id, &item.listname).err_500()?,
                 else return abort(404)
             );
 
@@ -603,7 +847,7 @@ pub fn update_item(req: &mut Request) -> PencilResult {
             let item = err_400!(serde_json::from_str(&text), "Error decoding JSON");
 
             let updated_item = try_or!(
-                audio::update_file(&conn, id, &item).err_500()?,
+                audio::update_file(&conn, sess.user_id, id, &item).err_500()?,
                 else return abort(404)
             );
 
@@ -617,12 +861,28 @@ pub fn update_item(req: &mut Request) -> PencilResult {
                 return abort(400);
             }
             let updated_item = try_or!(
-                audio::update_narrator(&conn, &item).err_500()?,
+                audio::update_narrator(&conn, sess.user_id, &item).err_500()?,
                 else return abort(404)
             );
 
             json = jsonify(&updated_item);
         }
+        "update_test_item" => {
+
+            let item = err_400!(serde_json::from_str(&text), "Error decoding JSON");
+
+            let updated_item = match ganbare::test::update_item(&conn, sess.user_id, id, &item) {
+                Ok(Some(item)) => item,
+                Ok(None) => return abort(404),
+                Err(Error(ErrorKind::InvalidInput, _)) => {
+                    return Ok(bad_request("Retellings need audio_path and other test items \
+                                           need audio_id!"))
+                }
+                Err(e) => return Err(internal_error(e)),
+            };
+
+            json = jsonify(&updated_item);
+        }
         "update_event" => {
 
             let item: ganbare::models::UpdateEvent = err_400!(serde_json::from_str(&text),
@@ -631,8 +891,14 @@ pub fn update_item(req: &mut Request) -> PencilResult {
             if item.id != id {
                 return abort(400);
             }
-            let updated_item =
-                try_or!(event::update_event(&conn, &item).err_500()?, else return abort(404));
+            let updated_item = match event::update_event(&conn, sess.user_id, &item) {
+                Ok(Some(item)) => item,
+                Ok(None) => return abort(404),
+                Err(Error(ErrorKind::InvalidInput, _)) => {
+                    return Ok(bad_request("Unknown page kind!"))
+                }
+                Err(e) => return Err(internal_error(e)),
+            };
 
             json = jsonify(&updated_item);
         }
@@ -645,7 +911,7 @@ pub fn update_item(req: &mut Request) -> PencilResult {
 
 pub fn post_question(req: &mut Request) -> PencilResult {
 
-    let (conn, sess) = auth_user(req, "editors")?;
+    let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
 
     let mut text = String::new();
     req.read_to_string(&mut text).err_500()?;
@@ -697,7 +963,7 @@ pub fn post_question(req: &mut Request) -> PencilResult {
         new_aas.push(new_aa);
     }
 
-    let id = manage::post_question(&conn, new_qq, new_aas).err_500()?;
+    let id = manage::post_question(&conn, sess.user_id, new_qq, new_aas).err_500()?;
 
     let new_url = format!("/api/questions/{}", id);
 
@@ -706,7 +972,7 @@ pub fn post_question(req: &mut Request) -> PencilResult {
 
 pub fn post_exercise(req: &mut Request) -> PencilResult {
 
-    let (conn, sess) = auth_user(req, "editors")?;
+    let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
 
     let mut text = String::new();
     req.read_to_string(&mut text).err_500()?;
@@ -740,13 +1006,80 @@ pub fn post_exercise(req: &mut Request) -> PencilResult {
         new_aas.push(new_aa);
     }
 
-    let id = manage::post_exercise(&conn, new_qq, new_aas).err_500()?;
+    let id = manage::post_exercise(&conn, sess.user_id, new_qq, new_aas).err_500()?;
 
     let new_url = format!("/api/exercises/{}", id);
 
     redirect(&new_url, 303).refresh_cookie(&sess)
 }
 
+pub fn post_test_item(req: &mut Request) -> PencilResult {
+
+    let (conn, sess) = auth_permission(req, role::MANAGE_EVENTS)?;
+
+    let mut text = String::new();
+    req.read_to_string(&mut text).err_500()?;
+
+    use ganbare::models::{UpdateTestItem, NewTestItem};
+
+    let item: UpdateTestItem = err_400!(serde_json::from_str(&text),
+                                        "Error when parsing the JSON.");
+
+    fn parse_item(item: &UpdateTestItem) -> Result<NewTestItem> {
+        Ok(NewTestItem {
+               event_id: item.event_id.ok_or_else(|| Error::from_kind(ErrorKind::FormParseError))?,
+               ordering: item.ordering.unwrap_or(0),
+               item_type: item.item_type
+                   .as_ref()
+                   .ok_or_else(|| Error::from_kind(ErrorKind::FormParseError))?
+                   .as_str(),
+               item_text: item.item_text
+                   .as_ref()
+                   .ok_or_else(|| Error::from_kind(ErrorKind::FormParseError))?
+                   .as_str(),
+               audio_id: item.audio_id.unwrap_or(None),
+               audio_path: item.audio_path.as_ref().and_then(|p| p.as_ref()).map(|p| p.as_str()),
+           })
+    }
+
+    let new_item = err_400!(parse_item(&item), "Fields missing from the test item");
+
+    let id = match ganbare::test::add_item(&conn, sess.user_id, &new_item) {
+        Ok(item) => item.id,
+        Err(Error(ErrorKind::InvalidInput, _)) => {
+            return Ok(bad_request("Retellings need audio_path and other test items need \
+                                   audio_id!"))
+        }
+        Err(e) => return Err(internal_error(e)),
+    };
+
+    let new_url = format!("/api/tests/{}", id);
+
+    redirect(&new_url, 303).refresh_cookie(&sess)
+}
+
+pub fn post_event_transition(req: &mut Request) -> PencilResult {
+
+    let (conn, sess) = auth_permission(req, role::MANAGE_EVENTS)?;
+
+    let mut text = String::new();
+    req.read_to_string(&mut text).err_500()?;
+
+    let item: ganbare::models::NewEventTransition = err_400!(serde_json::from_str(&text),
+                                                             "Error when parsing the JSON.");
+
+    let transition = match event::add_transition(&conn, sess.user_id, &item) {
+        Ok(transition) => transition,
+        Err(Error(ErrorKind::InvalidInput, _)) => {
+            return Ok(bad_request("A transition fires on \"started\" or \"finished\" and \
+                                   must join or leave a group!"))
+        }
+        Err(e) => return Err(internal_error(e)),
+    };
+
+    jsonify(&transition).refresh_cookie(&sess)
+}
+
 pub fn save_eventdata(req: &mut Request) -> PencilResult {
     let (conn, sess) = auth_user(req, "")?;
 
@@ -769,7 +1102,7 @@ pub fn save_eventdata(req: &mut Request) -> PencilResult {
 }
 
 pub fn user(req: &mut Request) -> PencilResult {
-    let (conn, sess) = auth_user(req, "admins")?;
+    let (conn, sess) = auth_permission(req, role::MANAGE_USERS)?;
 
     let user_id = req.view_args
         .remove("user_id")
@@ -787,7 +1120,8 @@ pub fn user(req: &mut Request) -> PencilResult {
             let group_id = group_id.parse::<i32>()
                 .expect("Pencil guarantees that Line ID should be an integer.");
 
-            ganbare::user::join_user_group_by_id(&conn, user_id, group_id).err_500()?;
+            ganbare::user::join_user_group_by_id(&conn, Some(sess.user_id), user_id, group_id)
+                .err_500()?;
             jsonify(&())
         }
         "remove_group" => {
@@ -798,7 +1132,30 @@ pub fn user(req: &mut Request) -> PencilResult {
             let group_id = group_id.parse::<i32>()
                 .expect("Pencil guarantees that Line ID should be an integer.");
 
-            ganbare::user::remove_user_group_by_id(&conn, user_id, group_id).err_500()?;
+            ganbare::user::remove_user_group_by_id(&conn, Some(sess.user_id), user_id, group_id)
+                .err_500()?;
+            jsonify(&())
+        }
+        "add_role" => {
+
+            let role_id = req.view_args
+                .remove("role_id")
+                .expect("Pencil guarantees that Line ID should exist as an arg.");
+            let role_id = role_id.parse::<i32>()
+                .expect("Pencil guarantees that Line ID should be an integer.");
+
+            role::assign(&conn, Some(sess.user_id), user_id, role_id).err_500()?;
+            jsonify(&())
+        }
+        "remove_role" => {
+
+            let role_id = req.view_args
+                .remove("role_id")
+                .expect("Pencil guarantees that Line ID should exist as an arg.");
+            let role_id = role_id.parse::<i32>()
+                .expect("Pencil guarantees that Line ID should be an integer.");
+
+            role::unassign(&conn, Some(sess.user_id), user_id, role_id).err_500()?;
             jsonify(&())
         }
         "set_metrics" => {
@@ -816,15 +1173,493 @@ pub fn user(req: &mut Request) -> PencilResult {
                                        content!"));
             }
 
-            ganbare::user::set_metrics(&conn, &metrics).err_500()?;
+            ganbare::user::set_metrics(&conn, sess.user_id, &metrics).err_500()?;
             jsonify(&())
         }
+        "set_timezone" => {
+
+            use ganbare::models::UpdateUserTimezone;
+
+            let mut text = String::new();
+            req.read_to_string(&mut text).err_500()?;
+            let settings: UpdateUserTimezone = err_400!(serde_json::from_str(&text),
+                                                        "Can't decode JSON: {:?}",
+                                                        &text);
+
+            let user = match ganbare::user::set_timezone(&conn, sess.user_id, user_id, &settings) {
+                Ok(Some(user)) => user,
+                Ok(None) => return abort(404),
+                Err(Error(ErrorKind::InvalidInput, _)) => {
+                    return Ok(bad_request("Unknown timezone or invalid rollover hour!"))
+                }
+                Err(e) => return Err(internal_error(e)),
+            };
+            jsonify(&user)
+        }
+        "set_locale" => {
+
+            use ganbare::models::UpdateUserLocale;
+
+            let mut text = String::new();
+            req.read_to_string(&mut text).err_500()?;
+            let settings: UpdateUserLocale = err_400!(serde_json::from_str(&text),
+                                                      "Can't decode JSON: {:?}",
+                                                      &text);
+
+            let user = match ganbare::user::set_locale(&conn,
+                                                       Some(sess.user_id),
+                                                       user_id,
+                                                       &settings) {
+                Ok(Some(user)) => user,
+                Ok(None) => return abort(404),
+                Err(Error(ErrorKind::InvalidInput, _)) => {
+                    return Ok(bad_request("Unsupported locale!"))
+                }
+                Err(e) => return Err(internal_error(e)),
+            };
+            jsonify(&user)
+        }
+        _ => return Err(internal_error("no such endpoint!")),
+    };
+
+    json.refresh_cookie(&sess)
+}
+
+pub fn group(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_permission(req, role::MANAGE_USERS)?;
+
+    let group_id = req.view_args
+        .remove("group_id")
+        .expect("Pencil guarantees that Line ID should exist as an arg.");
+    let group_id =
+        group_id.parse::<i32>().expect("Pencil guarantees that Line ID should be an integer.");
+
+    let endpoint = req.endpoint().expect("Pencil guarantees this");
+    let json = match endpoint.as_ref() {
+        "set_group_memory_model" => {
+
+            let model = req.view_args
+                .remove("model")
+                .expect("Pencil guarantees that the model should exist as an arg.");
+
+            let result =
+                ganbare::user::set_group_memory_model(&conn, sess.user_id, group_id, &model);
+            let count = err_400!(result, "No such memory model: {}", &model);
+            jsonify(&count)
+        }
         _ => return Err(internal_error("no such endpoint!")),
     };
 
     json.refresh_cookie(&sess)
 }
 
+pub fn retry_email(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_permission(req, role::SEND_MAIL)?;
+
+    let id = req.view_args
+        .remove("id")
+        .expect("Pencil guarantees that Line ID should exist as an arg.")
+        .parse::<i32>()
+        .expect("Pencil guarantees that Line ID should be an integer.");
+
+    let mail = try_or!(ganbare::email::retry_mail(&conn, id).err_500()?,
+                       else return abort(404));
+
+    jsonify(&mail).refresh_cookie(&sess)
+}
+
+pub fn post_randomization(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_permission(req, role::MANAGE_EXPERIMENTS)?;
+
+    let mut text = String::new();
+    req.read_to_string(&mut text).err_500()?;
+
+    let spec: ganbare::randomize::RandomizationSpec = err_400!(serde_json::from_str(&text),
+                                                               "Error when parsing the JSON.");
+
+    let randomization = match ganbare::randomize::create(&conn, &spec) {
+        Ok(r) => r,
+        Err(Error(ErrorKind::InvalidInput, _)) => {
+            return Ok(bad_request("A randomization needs at least two distinct arms with \
+                                   positive ratios, and the block size must be a multiple \
+                                   of the sum of the ratios!"))
+        }
+        Err(e) => return Err(internal_error(e)),
+    };
+
+    jsonify(&randomization).refresh_cookie(&sess)
+}
+
+pub fn post_consent_document(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_permission(req, role::MANAGE_EXPERIMENTS)?;
+
+    let mut text = String::new();
+    req.read_to_string(&mut text).err_500()?;
+
+    let document: models::NewConsentDocument = err_400!(serde_json::from_str(&text),
+                                                        "Error when parsing the JSON.");
+
+    let document = match ganbare::consent::publish(&conn, &document) {
+        Ok(d) => d,
+        Err(Error(ErrorKind::InvalidInput, _)) => {
+            return Ok(bad_request("The version must be the newest version (for a translation) \
+                                   or the one after it, and the locale must be supported!"))
+        }
+        Err(e) => return Err(internal_error(e)),
+    };
+
+    jsonify(&document).refresh_cookie(&sess)
+}
+
+pub fn put_role(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_permission(req, role::MANAGE_USERS)?;
+
+    let role_name = req.view_args
+        .remove("role_name")
+        .expect("Pencil guarantees that the role name should exist as an arg.");
+
+    let mut text = String::new();
+    req.read_to_string(&mut text).err_500()?;
+
+    let permissions: Vec<String> = err_400!(serde_json::from_str(&text),
+                                            "Error when parsing the JSON.");
+
+    let role = match role::set_role(&conn, sess.user_id, &role_name, &permissions) {
+        Ok(r) => r,
+        Err(Error(ErrorKind::InvalidInput, _)) => {
+            return Ok(bad_request(format!("Unknown permission! The permissions are: {}",
+                                          role::PERMISSIONS.join(", "))))
+        }
+        Err(e) => return Err(internal_error(e)),
+    };
+
+    jsonify(&role).refresh_cookie(&sess)
+}
+
+pub fn get_api_tokens(req: &mut Request) -> PencilResult {
+    use ganbare::api_token;
+
+    let endpoint = req.endpoint().expect("Pencil guarantees this");
+    match endpoint.as_ref() {
+        "get_own_tokens" => {
+            let (conn, sess) = auth_account(req)?;
+            let tokens = api_token::get_tokens(&conn, sess.user_id).err_500()?;
+            let roles = role::get_role_names(&conn, sess.user_id).err_500()?;
+            jsonify(&(tokens, roles)).refresh_cookie(&sess)
+        }
+        "get_user_tokens" => {
+            let (conn, sess) = auth_permission(req, role::VIEW_USERS)?;
+            let id = req.view_args
+                .get("id")
+                .expect("Pencil guarantees that Line ID should exist as an arg.");
+            let id = id.parse::<i32>()
+                .expect("Pencil guarantees that Line ID should be an integer.");
+            let tokens = api_token::get_tokens(&conn, id).err_500()?;
+            jsonify(&tokens).refresh_cookie(&sess)
+        }
+        _ => Err(internal_error("no such endpoint!")),
+    }
+}
+
+pub fn post_api_token(req: &mut Request) -> PencilResult {
+    use ganbare::api_token;
+
+    let (conn, sess) = auth_account(req)?;
+
+    let mut text = String::new();
+    req.read_to_string(&mut text).err_500()?;
+
+    let spec: api_token::TokenSpec = err_400!(serde_json::from_str(&text),
+                                              "Error when parsing the JSON.");
+    let expires = match spec.expires() {
+        Ok(expires) => expires,
+        Err(_) => {
+            return Ok(bad_request(format!("expires_days must be between 1 and {}!",
+                                          api_token::MAX_EXPIRE_DAYS)))
+        }
+    };
+
+    match api_token::create(&conn,
+                            sess.user_id,
+                            &spec.name,
+                            api_token::PERSONAL,
+                            &spec.roles,
+                            expires,
+                            COOKIE_HMAC_KEY.as_slice()) {
+        Ok(token_and_bearer) => jsonify(&token_and_bearer).refresh_cookie(&sess),
+        Err(Error(ErrorKind::InvalidInput, _)) => {
+            Ok(bad_request("A token needs a name of at most 100 characters, and it must expire \
+                            in the future!"))
+        }
+        Err(Error(ErrorKind::AccessDenied, _)) => abort(403),
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+pub fn revoke_api_token(req: &mut Request) -> PencilResult {
+    use ganbare::api_token;
+
+    let endpoint = req.endpoint().expect("Pencil guarantees this");
+    let (conn, sess, user_id) = match endpoint.as_ref() {
+        "revoke_own_token" => {
+            let (conn, sess) = auth_account(req)?;
+            let user_id = sess.user_id;
+            (conn, sess, user_id)
+        }
+        "revoke_user_token" => {
+            let (conn, sess) = auth_permission(req, role::MANAGE_USERS)?;
+            let user_id = req.view_args
+                .get("user_id")
+                .expect("Pencil guarantees that Line ID should exist as an arg.");
+            let user_id = user_id.parse::<i32>()
+                .expect("Pencil guarantees that Line ID should be an integer.");
+            (conn, sess, user_id)
+        }
+        _ => return Err(internal_error("no such endpoint!")),
+    };
+
+    let id =
+        req.view_args.get("id").expect("Pencil guarantees that Line ID should exist as an arg.");
+    let id = id.parse::<i32>().expect("Pencil guarantees that Line ID should be an integer.");
+
+    match api_token::revoke(&conn, Some(sess.user_id), user_id, id).err_500()? {
+        Some(token) => jsonify(&token).refresh_cookie(&sess),
+        None => abort(404),
+    }
+}
+
+pub fn get_sessions(req: &mut Request) -> PencilResult {
+    let endpoint = req.endpoint().expect("Pencil guarantees this");
+    match endpoint.as_ref() {
+        "get_own_sessions" => {
+            let (conn, sess) = auth_account(req)?;
+            let sessions = session::get_sessions(&conn, sess.user_id, *SESSION_EXPIRE)
+                .err_500()?;
+            jsonify(&(sessions, sess.sess_id)).refresh_cookie(&sess)
+        }
+        "get_user_sessions" => {
+            let (conn, sess) = auth_permission(req, role::VIEW_USERS)?;
+            let id = req.view_args
+                .get("id")
+                .expect("Pencil guarantees that Line ID should exist as an arg.");
+            let id = id.parse::<i32>()
+                .expect("Pencil guarantees that Line ID should be an integer.");
+            let sessions = session::get_sessions(&conn, id, *SESSION_EXPIRE).err_500()?;
+            jsonify(&sessions).refresh_cookie(&sess)
+        }
+        _ => Err(internal_error("no such endpoint!")),
+    }
+}
+
+pub fn revoke_session(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_account(req)?;
+
+    let id =
+        req.view_args.get("id").expect("Pencil guarantees that Line ID should exist as an arg.");
+    let id = id.parse::<i32>().expect("Pencil guarantees that Line ID should be an integer.");
+
+    if id == sess.sess_id {
+        return Ok(bad_request("Log out to end the current session."));
+    }
+
+    match session::revoke(&conn, sess.user_id, sess.user_id, id).err_500()? {
+        Some(db_sess) => {
+            forget_session(&db_sess)?;
+            jsonify(&db_sess).refresh_cookie(&sess)
+        }
+        None => abort(404),
+    }
+}
+
+pub fn revoke_sessions(req: &mut Request) -> PencilResult {
+    let endpoint = req.endpoint().expect("Pencil guarantees this");
+    let (conn, sess, user_id, keep) = match endpoint.as_ref() {
+        "revoke_other_sessions" => {
+            let (conn, sess) = auth_account(req)?;
+            let (user_id, keep) = (sess.user_id, Some(sess.sess_id));
+            (conn, sess, user_id, keep)
+        }
+        "force_logout" => {
+            let (conn, sess) = auth_permission(req, role::MANAGE_USERS)?;
+            let id = req.view_args
+                .get("id")
+                .expect("Pencil guarantees that Line ID should exist as an arg.");
+            let id = id.parse::<i32>()
+                .expect("Pencil guarantees that Line ID should be an integer.");
+            (conn, sess, id, None)
+        }
+        _ => return Err(internal_error("no such endpoint!")),
+    };
+
+    let ended = session::revoke_all(&conn, sess.user_id, user_id, keep).err_500()?;
+    for db_sess in &ended {
+        forget_session(db_sess)?;
+    }
+    jsonify(&ended).refresh_cookie(&sess)
+}
+
+pub fn get_two_factor(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_account(req)?;
+
+    let status = totp::status(&conn, sess.user_id).err_500()?;
+    let required = !TWO_FACTOR_REQUIRED_ROLES.is_empty() &&
+                   role::get_role_names(&conn, sess.user_id)
+        .err_500()?
+        .iter()
+        .any(|r| TWO_FACTOR_REQUIRED_ROLES.contains(r));
+
+    jsonify(&(status, required)).refresh_cookie(&sess)
+}
+
+pub fn post_two_factor(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_account(req)?;
+
+    let endpoint = req.endpoint().expect("Pencil guarantees this");
+    if endpoint == "enroll_two_factor" {
+        let user = user::get_user(&conn, sess.user_id).err_500()?;
+        let secret = match totp::start_enrollment(&conn, sess.user_id) {
+            Ok(secret) => secret,
+            Err(Error(ErrorKind::InvalidInput, _)) => {
+                return Ok(bad_request("Two-factor authentication is already enabled."))
+            }
+            Err(e) => return Err(internal_error(e)),
+        };
+        let account = user.email.clone().unwrap_or_else(|| user.id.to_string());
+        let uri = totp::otpauth_uri(&secret, &account, &*SITE_DOMAIN);
+        return jsonify(&(secret, uri)).refresh_cookie(&sess);
+    }
+
+    let code = err_400!(req.form().get("code"), "code missing").to_string();
+
+    match endpoint.as_ref() {
+        "confirm_two_factor" => {
+            match totp::confirm_enrollment(&conn, sess.user_id, &code).err_500()? {
+                Some(recovery_codes) => jsonify(&recovery_codes).refresh_cookie(&sess),
+                None => Ok(bad_request("The code is wrong.")),
+            }
+        }
+        "new_recovery_codes" => {
+            match totp::regenerate_recovery_codes(&conn, sess.user_id, &code).err_500()? {
+                Some(recovery_codes) => jsonify(&recovery_codes).refresh_cookie(&sess),
+                None => Ok(bad_request("The code is wrong.")),
+            }
+        }
+        "disable_two_factor" => {
+            if !totp::check_code(&conn, sess.user_id, &code).err_500()? {
+                return Ok(bad_request("The code is wrong."));
+            }
+            totp::disable(&conn, sess.user_id, sess.user_id).err_500()?;
+            jsonify(&()).refresh_cookie(&sess)
+        }
+        _ => Err(internal_error("no such endpoint!")),
+    }
+}
+
+/// For the users that have lost both their phone and their recovery codes.
+pub fn reset_two_factor(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_permission(req, role::MANAGE_USERS)?;
+
+    let id =
+        req.view_args.get("id").expect("Pencil guarantees that Line ID should exist as an arg.");
+    let id = id.parse::<i32>().expect("Pencil guarantees that Line ID should be an integer.");
+
+    if totp::disable(&conn, sess.user_id, id).err_500()? {
+        jsonify(&()).refresh_cookie(&sess)
+    } else {
+        abort(404)
+    }
+}
+
+pub fn get_audit_log(req: &mut Request) -> PencilResult {
+    use ganbare::audit;
+
+    let (conn, sess) = auth_permission(req, role::VIEW_AUDIT_LOG)?;
+
+    let mut filter = audit::AuditFilter::default();
+    let mut limit = 100;
+    {
+        let args = req.args();
+        if let Some(actor) = args.get::<str>("actor") {
+            filter.actor = Some(err_400!(actor.parse::<i32>(), "actor must be a user id"));
+        }
+        if let Some(target_id) = args.get::<str>("target_id") {
+            filter.target_id = Some(err_400!(target_id.parse::<i32>(),
+                                             "target_id must be an integer"));
+        }
+        if let Some(before_id) = args.get::<str>("before_id") {
+            filter.before_id = Some(err_400!(before_id.parse::<i32>(),
+                                             "before_id must be an integer"));
+        }
+        if let Some(l) = args.get::<str>("limit") {
+            limit = err_400!(l.parse::<i64>(), "limit must be an integer");
+        }
+        filter.action = args.get::<str>("action").map(|a| a.to_string());
+        filter.target_type = args.get::<str>("target_type").map(|t| t.to_string());
+    }
+
+    let entries = audit::get_entries(&conn, &filter, limit).err_500()?;
+
+    jsonify(&entries).refresh_cookie(&sess)
+}
+
+pub fn randomization_log(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_permission(req, role::MANAGE_EXPERIMENTS)?;
+    use std::str::FromStr;
+
+    let id = req.view_args
+        .remove("id")
+        .expect("Pencil guarantees that Line ID should exist as an arg.")
+        .parse::<i32>()
+        .expect("Pencil guarantees that Line ID should be an integer.");
+
+    let csv = try_or!(ganbare::randomize::export_log(&conn, id).err_500()?,
+                      else return abort(404));
+
+    let mut resp = Response::from(csv);
+    let mime = mime::Mime::from_str("text/csv; charset=utf-8").unwrap();
+    resp.headers.set::<hyper::header::ContentType>(hyper::header::ContentType(mime));
+    resp.refresh_cookie(&sess)
+}
+
+pub fn research_table(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_permission(req, role::EXPORT_RESEARCH)?;
+    use std::str::FromStr;
+
+    let table = req.view_args
+        .remove("table")
+        .expect("Pencil guarantees that the table should exist as an arg.");
+
+    let consenting_only = req.args().get("consenting_only").is_some();
+
+    let csv = try_or!(ganbare::research::export_table(&conn, &table, consenting_only)
+                          .err_500()?,
+                      else return abort(404));
+
+    let mut resp = Response::from(csv);
+    let mime = mime::Mime::from_str("text/csv; charset=utf-8").unwrap();
+    resp.headers.set::<hyper::header::ContentType>(hyper::header::ContentType(mime));
+    resp.refresh_cookie(&sess)
+}
+
+pub fn research_recording(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_permission(req, role::EXPORT_RESEARCH)?;
+
+    let file_name = req.view_args
+        .remove("file_name")
+        .expect("Pencil guarantees that the file name should exist as an arg.");
+
+    if !ganbare::research::is_recording(&conn, &file_name).err_500()? {
+        return abort(404);
+    }
+
+    send_from_directory_range(USER_AUDIO_DIR.to_str()
+                                  .expect("The user audio dir path should be valid unicode!"),
+                              &file_name,
+                              false,
+                              req.headers().get())
+            .refresh_cookie(&sess)
+}
+
 pub fn post_useraudio(req: &mut Request) -> PencilResult {
     let (conn, sess) = auth_user(req, "")?;
     use std::fs;
@@ -841,14 +1676,14 @@ pub fn post_useraudio(req: &mut Request) -> PencilResult {
             .0;
     debug!("post_useraudio. Content-Length: {:?}", cl);
 
-    if cl > 60_000 && event_name != "pretest_retelling" && event_name != "posttest_retelling" {
+    let (event, _) = event::require_ongoing(&conn, &event_name, sess.user_id).err_401()?;
+
+    if cl > 60_000 && event.page_kind != "retelling" {
         return Ok(bad_request("Too big audio file! It must be under 60kB"));
     } else if cl > 180_000 {
         return Ok(bad_request("Too big audio file! It must be under 180kB"));
     }
 
-    let (event, _) = event::require_ongoing(&conn, &event_name, sess.user_id).err_401()?;
-
     let mut new_path = USER_AUDIO_DIR.to_owned();
     let mut filename = "%FT%H-%M-%SZ".to_string();
     filename.extend(thread_rng().gen_ascii_chars().take(10));
@@ -1011,15 +1846,11 @@ pub fn new_retelling(req: &mut Request) -> PencilResult {
         .expect("Pencil guarantees that event name should exist as an arg.");
     let (event, _) = event::require_ongoing(&conn, &event_name, sess.user_id).err_401()?;
 
-    let retelling = match event_name.as_ref() {
-        "pretest_retelling" => {
-            test::get_new_retelling_pretest(&conn, sess.user_id, &event).err_500()?
-        }
-        "posttest_retelling" => {
-            test::get_new_retelling_posttest(&conn, sess.user_id, &event).err_500()?
-        }
-        _ => unreachable!(),
-    };
+    if event.page_kind != "retelling" {
+        return Ok(bad_request("Not a retelling event!"));
+    }
+
+    let retelling = ganbare::test::get_new_retelling(&conn, sess.user_id, &event).err_500()?;
 
     jsonify(&retelling).refresh_cookie(&sess)
 }
@@ -1031,15 +1862,11 @@ pub fn next_retelling(req: &mut Request) -> PencilResult {
         .expect("Pencil guarantees that event name should exist as an arg.");
     let (event, _) = event::require_ongoing(&conn, &event_name, sess.user_id).err_401()?;
 
-    let retelling = match event_name.as_ref() {
-        "pretest_retelling" => {
-            test::get_next_retelling_pretest(&conn, sess.user_id, &event).err_500()?
-        }
-        "posttest_retelling" => {
-            test::get_next_retelling_posttest(&conn, sess.user_id, &event).err_500()?
-        }
-        _ => unreachable!(),
-    };
+    if event.page_kind != "retelling" {
+        return Ok(bad_request("Not a retelling event!"));
+    }
+
+    let retelling = test::get_next_retelling(&conn, sess.user_id, &event).err_500()?;
 
     match retelling {
             Some(ref retelling) => jsonify(retelling),
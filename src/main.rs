@@ -29,6 +29,8 @@ extern crate crypto;
 extern crate lettre;
 extern crate data_encoding;
 extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate serde_json;
 
 extern crate r2d2;
@@ -39,6 +41,7 @@ mod helpers;
 mod app_pages;
 mod manager_pages;
 mod http_api;
+mod api_v2;
 mod test;
 
 pub use ganbare_backend as ganbare;
@@ -52,7 +55,6 @@ pub use ganbare::errors::ErrorKind::Msg as ErrMsg;
 pub use ganbare::errors::Result;
 pub use ganbare::errors::{Error, ErrorKind};
 pub use ganbare::Connection;
-use lettre::transport::EmailTransport;
 pub use ganbare::helpers::Cache;
 
 pub fn favicon(_: &mut Request) -> PencilResult {
@@ -74,18 +76,12 @@ pub fn source_maps(req: &mut Request) -> PencilResult {
     send_from_directory("src", file_path, false)
 }
 
-use std::collections::VecDeque;
-use std::sync::RwLock;
-use lettre::email::Email;
-use lettre::transport::smtp::SmtpTransportBuilder;
 
 lazy_static! {
     pub static ref AUDIO_CACHE: Cache<u64, Vec<u8>> =
         Cache::new(Duration::from_secs(60*2));
     pub static ref LOGGED_OUT_CACHE: Cache<i32, ganbare::session::UserSession> =
         Cache::new(Duration::from_secs(60*10));
-    pub static ref MAIL_QUEUE: RwLock<VecDeque<Email>> =
-        RwLock::new(VecDeque::new());
 }
 
 #[allow(unused_mut)]
@@ -106,19 +102,24 @@ pub fn background_control_thread() {
     }
 
     let mut app = Pencil::new(".");
-    include_templates!(app, "templates", "slacker_heatenings.html");
-
-    let mut mailer = SmtpTransportBuilder::new(&*EMAIL_SERVER)
-        .expect("Couldn't setup the email transport!")
-        .encrypt()
-        .credentials(EMAIL_SMTP_USERNAME.as_str(), EMAIL_SMTP_PASSWORD.as_str())
-        .build();
+    include_templates!(app,
+                       "templates",
+                       "slacker_heatenings.html");
+
+    let mut mailer = ganbare::mailer::from_config(&*EMAIL_TRANSPORT, &*EMAIL_DIR, || {
+            ganbare::mailer::SmtpConfig {
+                server: *EMAIL_SERVER,
+                username: &*EMAIL_SMTP_USERNAME,
+                password: &*EMAIL_SMTP_PASSWORD,
+                encrypt: *EMAIL_SMTP_ENCRYPT,
+            }
+        })
+        .expect("Couldn't setup the email transport!");
 
     loop {
         sleep(Duration::from_secs(5));
 
-        match ganbare::email::send_nag_emails(&*MAIL_QUEUE,
-                                              &conn,
+        match ganbare::email::send_nag_emails(&conn,
                                               *NAG_EMAIL_ABSENCE_PERIOD,
                                               *NAG_EMAIL_GRACE_PERIOD,
                                               &*SITE_DOMAIN,
@@ -186,23 +187,77 @@ pub fn background_control_thread() {
             _ => (),
         }
 
-        let mut outgoing_mails = vec![];
-        if let Ok(mut mails) =
-            MAIL_QUEUE.try_write().or_else(|e| {
-                                               debug!("The queue is locked. Skipping.");
-                                               Err(e)
-                                           }) {
-            outgoing_mails.extend(mails.drain(..));
+        match ganbare::account::execute_due_requests(&conn, &*USER_AUDIO_DIR) {
+            Ok(executed) => {
+                if !executed.is_empty() {
+                    info!("Executed {} account deletion or anonymization requests.",
+                          executed.len());
+                }
+            }
+            Err(e) => {
+                error!("background_control_thread::execute_due_requests: Error: {}",
+                       e)
+            }
+        }
+
+        match ganbare::trash::purge(&conn, *TRASH_RETENTION) {
+            Ok(count) => {
+                if count != 0 {
+                    info!("Purged {} items from the trash.", count);
+                }
+            }
+            Err(e) => {
+                error!("background_control_thread::purge_trash: Error: {}",
+                       e)
+            }
+        }
+
+        match ganbare::api_token::clean_expired(&conn) {
+            Ok(count) => {
+                if count != 0 {
+                    info!("Deleted {} expired API tokens.", count);
+                }
+            }
+            Err(e) => {
+                error!("background_control_thread::clean_expired_tokens: Error: {}",
+                       e)
+            }
+        }
+
+        match ganbare::totp::clean_pending_logins(&conn) {
+            Ok(count) => {
+                if count != 0 {
+                    info!("Deleted {} expired two-factor logins.", count);
+                }
+            }
+            Err(e) => {
+                error!("background_control_thread::clean_pending_logins: Error: {}",
+                       e)
+            }
+        }
+
+        match ganbare::email::clean_sent_mails(&conn, *EMAIL_EXPIRE) {
+            Ok(count) => {
+                if count != 0 {
+                    info!("Deleted {} old sent mails from the queue.", count);
+                }
+            }
+            Err(e) => {
+                error!("background_control_thread::clean_sent_mails: Error: {}",
+                       e)
+            }
         }
-        if ! outgoing_mails.is_empty() {
-            info!("Sending {} mails!", outgoing_mails.len());
-            for email in outgoing_mails.drain(..) {
-                match mailer.send(email) {
-                    Ok(_) => (),
-                    Err(e) => error!("Couldn't send email! Error: {}", e),
+
+        match ganbare::email::send_queued_mails(&conn, *EMAIL_MAX_ATTEMPTS, &mut *mailer) {
+            Ok((sent, failed)) => {
+                if sent != 0 || failed != 0 {
+                    info!("Sent {} mails! {} mails failed permanently.", sent, failed);
                 }
             }
-            debug!("Mails sent!");
+            Err(e) => {
+                error!("background_control_thread::send_queued_mails: Error: {}",
+                       e)
+            }
         }
     }
 }
@@ -222,7 +277,11 @@ fn csrf_check(req: &mut Request) -> Option<PencilResult> {
                                            req.host.hostname))));
     }
 
-    if method_mutating || (*PARANOID && url.starts_with("/api")) {
+    // Browsers don't send bearer tokens on their own, so requests that carry one
+    // can't be forged by other sites, and they don't need to come from a browser.
+    let bearer = get_bearer_token(req).is_some();
+
+    if !bearer && (method_mutating || (*PARANOID && url.starts_with("/api"))) {
         // Enable anti-CSRF heuristics:
         // when the method is POST, DELETE etc., or if the request uses the HTTP API.
 
@@ -340,14 +399,21 @@ pub fn main() {
                        "audio.html",
                        "send_pw_reset_email.html",
                        "events.html",
+                       "audit_log.html",
+                       "trash.html",
+                       "api_tokens.html",
                        "manage.html",
                        "change_password.html",
+                       "sessions.html",
+                       "two_factor.html",
+                       "login_code.html",
+                       "my_data.html",
                        "add_users.html",
                        "email_confirm_email.html",
                        "pw_reset_email.html",
                        "users.html",
                        "slacker_heatenings.html",
-                       "agreement.html",
+                       "consent.html",
                        "info.html",
                        "pretest_info.html",
                        "pretest_done.html",
@@ -373,7 +439,9 @@ pub fn main() {
     app.get("/favicon.ico", "favicon", favicon);
     app.get("/", "hello", app_pages::hello);
     app.get("/welcome", "welcome", app_pages::text_pages);
-    app.get("/agreement", "agreement", app_pages::text_pages);
+    app.get("/agreement", "agreement", app_pages::consent_pages);
+    app.get("/consent", "consent_form", app_pages::consent_form);
+    app.post("/consent", "consent_post", app_pages::consent_post);
     app.get("/info", "info", app_pages::text_pages);
     app.get("/survey", "survey", app_pages::survey);
     app.get("/pretest_info", "pretest_info", app_pages::text_pages);
@@ -389,9 +457,11 @@ pub fn main() {
             "posttest_retelling",
             app_pages::retelling);
     app.get("/posttest_done", "posttest_done", app_pages::text_pages);
+    app.get("/event/<event_name:string>", "event_page", app_pages::event_page);
     app.post("/ok", "ok", app_pages::ok);
     app.get("/login", "login_form", app_pages::login_form);
     app.post("/login", "login_post", app_pages::login_post);
+    app.post("/login/code", "login_code_post", app_pages::login_code_post);
     app.post("/logout", "logout", app_pages::logout);
     app.get("/logout?doit", "logout_get", app_pages::logout);
     app.get("/confirm", "confirm_form", app_pages::confirm_form);
@@ -402,6 +472,20 @@ pub fn main() {
     app.post("/change_password",
              "change_password",
              app_pages::change_password);
+    app.get("/sessions", "sessions", app_pages::sessions);
+    app.get("/two_factor", "two_factor", app_pages::two_factor);
+    app.get("/my_data", "my_data_form", app_pages::my_data_form);
+    app.post("/my_data", "my_data_request", app_pages::my_data_request);
+    app.post("/my_data/locale", "my_data_locale", app_pages::my_data_locale);
+    app.get("/my_data/archive",
+            "my_data_archive",
+            app_pages::my_data_archive);
+    app.post("/my_data/confirm",
+             "my_data_confirm",
+             app_pages::my_data_confirm);
+    app.post("/my_data/cancel",
+             "my_data_cancel",
+             app_pages::my_data_cancel);
     app.get("/reset_password?secret=<secret:string>",
             "reset_password_form",
             app_pages::confirm_password_reset_form);
@@ -436,6 +520,9 @@ pub fn main() {
     app.get("/manage", "manage", manager_pages::manage);
     app.get("/users", "users", manager_pages::users);
     app.get("/events", "events", manager_pages::events);
+    app.get("/audit_log", "audit_log", manager_pages::audit_log);
+    app.get("/trash", "trash", manager_pages::trash);
+    app.get("/tokens", "api_tokens", manager_pages::api_tokens);
     app.get("/audio", "audio", manager_pages::audio);
     app.get("/send_mail",
             "send_mail_form",
@@ -475,10 +562,99 @@ pub fn main() {
     app.get("/api/users/<id:int>/asked_items",
             "get_asked_items",
             http_api::get_user_details);
+    app.get("/api/aliases/<alias:string>/skills",
+            "get_alias_skills",
+            http_api::get_alias_details);
+    app.get("/api/aliases/<alias:string>/asked_items",
+            "get_alias_asked_items",
+            http_api::get_alias_details);
     app.get("/api/events", "get_events", http_api::get_all);
     app.put("/api/events/<id:int>",
             "update_event",
             http_api::update_item);
+    app.get("/api/events/transitions",
+            "get_event_transitions",
+            http_api::get_all);
+    app.post("/api/events/transitions",
+             "post_event_transition",
+             http_api::post_event_transition);
+    app.delete("/api/events/transitions/<id:int>",
+               "del_event_transition",
+               http_api::del_item);
+    app.get("/api/emails?status=<status:string>",
+            "get_emails",
+            http_api::get_all);
+    app.put("/api/emails/<id:int>?retry",
+            "retry_email",
+            http_api::retry_email);
+    app.get("/api/randomizations",
+            "get_randomizations",
+            http_api::get_all);
+    app.post("/api/randomizations",
+             "post_randomization",
+             http_api::post_randomization);
+    app.get("/api/randomizations/<id:int>/log",
+            "randomization_log",
+            http_api::randomization_log);
+    app.get("/api/consent_documents",
+            "get_consent_documents",
+            http_api::get_all);
+    app.post("/api/consent_documents",
+             "post_consent_document",
+             http_api::post_consent_document);
+    app.get("/api/account_requests",
+            "get_account_requests",
+            http_api::get_all);
+    app.get("/api/roles", "get_roles", http_api::get_all);
+    app.get("/api/audit_log", "get_audit_log", http_api::get_audit_log);
+    app.get("/api/tokens", "get_own_tokens", http_api::get_api_tokens);
+    app.post("/api/tokens", "post_token", http_api::post_api_token);
+    app.delete("/api/tokens/<id:int>",
+               "revoke_own_token",
+               http_api::revoke_api_token);
+    app.get("/api/users/<id:int>/tokens",
+            "get_user_tokens",
+            http_api::get_api_tokens);
+    app.delete("/api/users/<user_id:int>/tokens/<id:int>",
+               "revoke_user_token",
+               http_api::revoke_api_token);
+    app.get("/api/two_factor", "get_two_factor", http_api::get_two_factor);
+    app.post("/api/two_factor/enroll",
+             "enroll_two_factor",
+             http_api::post_two_factor);
+    app.post("/api/two_factor/confirm",
+             "confirm_two_factor",
+             http_api::post_two_factor);
+    app.post("/api/two_factor/recovery_codes",
+             "new_recovery_codes",
+             http_api::post_two_factor);
+    app.post("/api/two_factor/disable",
+             "disable_two_factor",
+             http_api::post_two_factor);
+    app.delete("/api/users/<id:int>/two_factor",
+               "reset_two_factor",
+               http_api::reset_two_factor);
+    app.get("/api/sessions", "get_own_sessions", http_api::get_sessions);
+    app.delete("/api/sessions",
+               "revoke_other_sessions",
+               http_api::revoke_sessions);
+    app.delete("/api/sessions/<id:int>",
+               "revoke_own_session",
+               http_api::revoke_session);
+    app.get("/api/users/<id:int>/sessions",
+            "get_user_sessions",
+            http_api::get_sessions);
+    app.delete("/api/users/<id:int>/sessions",
+               "force_logout",
+               http_api::revoke_sessions);
+    app.put("/api/roles/<role_name:string>", "put_role", http_api::put_role);
+    app.delete("/api/roles/<id:int>", "del_role", http_api::del_item);
+    app.get("/api/research/<table:string>",
+            "research_table",
+            http_api::research_table);
+    app.get("/api/research/recordings/<file_name:string>",
+            "research_recording",
+            http_api::research_recording);
     app.get("/api/bundles", "get_bundles", http_api::get_all);
     app.delete("/api/bundles/<id_from:int>?merge_with=<id_to:int>",
                "merge_bundle",
@@ -516,16 +692,57 @@ pub fn main() {
     app.delete("/api/exercises/<id:int>",
                "del_exercise",
                http_api::del_item);
+    app.get("/api/trash", "get_trash", http_api::get_all);
+    app.put("/api/trash/words/<id:int>", "restore_word", http_api::restore_item);
+    app.put("/api/trash/questions/<id:int>",
+            "restore_question",
+            http_api::restore_item);
+    app.put("/api/trash/exercises/<id:int>",
+            "restore_exercise",
+            http_api::restore_item);
+    app.put("/api/trash/bundles/<id:int>",
+            "restore_bundle",
+            http_api::restore_item);
+    app.get("/api/words/<id:int>/revisions",
+            "get_word_revisions",
+            http_api::get_revisions);
+    app.get("/api/questions/<id:int>/revisions",
+            "get_question_revisions",
+            http_api::get_revisions);
+    app.get("/api/questions/answers/<id:int>/revisions",
+            "get_answer_revisions",
+            http_api::get_revisions);
+    app.get("/api/revisions/<id:int>/diff",
+            "get_revision_diff",
+            http_api::get_revision_diff);
+    app.post("/api/revisions/<id:int>/revert",
+             "revert_revision",
+             http_api::revert_revision);
     app.put("/api/users/<user_id:int>?add_group=<group_id:int>",
             "add_group",
             http_api::user);
     app.put("/api/users/<user_id:int>?remove_group=<group_id:int>",
             "remove_group",
             http_api::user);
+    app.put("/api/users/<user_id:int>?add_role=<role_id:int>",
+            "add_role",
+            http_api::user);
+    app.put("/api/users/<user_id:int>?remove_role=<role_id:int>",
+            "remove_role",
+            http_api::user);
     app.put("/api/users/<user_id:int>?settings=metrics",
             "set_metrics",
             http_api::user);
+    app.put("/api/users/<user_id:int>?settings=timezone",
+            "set_timezone",
+            http_api::user);
+    app.put("/api/users/<user_id:int>?settings=locale",
+            "set_locale",
+            http_api::user);
     app.get("/api/groups", "get_groups", http_api::get_all);
+    app.put("/api/groups/<group_id:int>?memory_model=<model:string>",
+            "set_group_memory_model",
+            http_api::group);
     app.delete("/api/users/<id:int>", "del_user", http_api::del_item);
     app.delete("/api/users/<id:int>/due_and_pending_items",
                "del_due_and_pending_items",
@@ -566,6 +783,19 @@ pub fn main() {
             "update_variant",
             http_api::update_item);
 
+    app.get("/api/tests", "get_tests", http_api::get_all);
+    app.get("/api/tests?event=<event_id:int>",
+            "get_tests_by_event",
+            http_api::get_all);
+    app.post("/api/tests", "post_test_item", http_api::post_test_item);
+    app.get("/api/tests/<id:int>", "get_test_item", http_api::get_item);
+    app.put("/api/tests/<id:int>",
+            "update_test_item",
+            http_api::update_item);
+    app.delete("/api/tests/<id:int>",
+               "del_test_item",
+               http_api::del_item);
+
     app.get("/api/new_quiz", "new_quiz", http_api::new_quiz);
     app.post("/api/next_quiz", "next_quiz", http_api::next_quiz);
 
@@ -592,6 +822,17 @@ pub fn main() {
              "post_eventdata",
              http_api::save_eventdata);
 
+    app.get("/api/v2/openapi.json", "v2_openapi", api_v2::openapi);
+    for route in api_v2::ROUTES.iter() {
+        match route.method {
+            "GET" => app.get(route.path, route.endpoint, route.handler),
+            "POST" => app.post(route.path, route.endpoint, route.handler),
+            "PUT" => app.put(route.path, route.endpoint, route.handler),
+            "DELETE" => app.delete(route.path, route.endpoint, route.handler),
+            _ => unreachable!(),
+        }
+    }
+
     std::thread::spawn(background_control_thread);
 
     info!("Ready. Running on {}, serving at {} with {} threads",
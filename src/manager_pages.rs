@@ -3,15 +3,17 @@ use super::*;
 use pencil::redirect;
 use pencil::abort;
 use ganbare::user;
+use ganbare::role;
 use ganbare::manage;
+use ganbare::i18n;
 use std::collections::HashSet;
 
 pub fn fresh_install_form(req: &mut Request) -> PencilResult {
     if ganbare::db::is_installed() {
         return abort(401);
     };
-    let context = new_template_context();
-    req.app.render_template("fresh_install.html", &context)
+    let context = new_template_context(request_locale(req));
+    render_template(req.app, "fresh_install.html", &context)
 }
 
 pub fn fresh_install_post(req: &mut Request) -> PencilResult {
@@ -35,8 +37,7 @@ pub fn fresh_install_post(req: &mut Request) -> PencilResult {
                               new_password,
                               &*RUNTIME_PEPPER,
                               *PASSWORD_STRETCHING_TIME).err_500()?;
-    user::join_user_group_by_name(&conn, user.id, "admins").err_500()?;
-    user::join_user_group_by_name(&conn, user.id, "editors").err_500()?;
+    role::assign_by_name(&conn, None, user.id, role::ADMIN).err_500()?;
     user::join_user_group_by_name(&conn, user.id, "questions").err_500()?;
     user::join_user_group_by_name(&conn, user.id, "exercises").err_500()?;
 
@@ -48,13 +49,14 @@ pub fn fresh_install_post(req: &mut Request) -> PencilResult {
 
     match do_login(&conn,
                    &user.email.expect("The email is known to exist."),
-                   new_password).err_500()? {
-        Some((_, sess)) => {
-            let mut context = new_template_context();
+                   new_password,
+                   &get_client(&*req)).err_500()? {
+        Some(LoginStep::Done(_, sess)) => {
+            let mut context = new_template_context(&user.locale);
             context.insert("install_success", "success");
-            req.app.render_template("fresh_install.html", &context).refresh_cookie(&sess)
+            render_template(req.app, "fresh_install.html", &context).refresh_cookie(&sess)
         }
-        None => {
+        _ => {
             Err(internal_error(Error::from(ErrMsg("We just added the user, yet we can't login \
                                                    them in. A bug?"
                                                           .to_string()))))
@@ -64,26 +66,36 @@ pub fn fresh_install_post(req: &mut Request) -> PencilResult {
 
 pub fn manage(req: &mut Request) -> PencilResult {
 
-    let (_, sess) = auth_user(req, "editors")?; // Unauthorized
+    let (conn, sess) = auth_user(req, "")?;
+    let permissions = role::get_permissions(&conn, sess.user_id).err_500()?;
+    if permissions.is_empty() {
+        return abort(401); // The user doesn't have any role
+    }
+    if ganbare::totp::is_missing(&conn, sess.user_id, &TWO_FACTOR_REQUIRED_ROLES).err_500()? {
+        return redirect("/two_factor", 303).refresh_cookie(&sess);
+    }
 
     let show_id = req.args()
         .get::<str>("show_id")
         .map(|_| "true")
         .unwrap_or("false");
 
-    let mut context = new_template_context();
+    let mut context = new_template_context(&user_locale(&conn, sess.user_id)?);
     context.insert("show_id", show_id);
+    if permissions.contains(role::VIEW_AUDIT_LOG) {
+        context.insert("audit_log_link", "true");
+    }
 
-    req.app.render_template("manage.html", &context).refresh_cookie(&sess)
+    render_template(req.app, "manage.html", &context).refresh_cookie(&sess)
 }
 
 pub fn add_quiz_form(req: &mut Request) -> PencilResult {
 
-    let (_, sess) = auth_user(req, "editors")?;
+    let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
 
-    let context = new_template_context();
+    let context = new_template_context(&user_locale(&conn, sess.user_id)?);
 
-    req.app.render_template("add_quiz.html", &context).refresh_cookie(&sess)
+    render_template(req.app, "add_quiz.html", &context).refresh_cookie(&sess)
 }
 
 pub fn add_quiz_post(req: &mut Request) -> PencilResult {
@@ -173,10 +185,10 @@ pub fn add_quiz_post(req: &mut Request) -> PencilResult {
             fieldsets))
     }
 
-    let (conn, sess) = auth_user(req, "editors")?;
+    let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
 
     let form = err_400!(parse_form(&mut *req), "Error with parsing form!");
-    let result = manage::create_quiz(&conn, form.0, form.1, &*AUDIO_DIR);
+    let result = manage::create_quiz(&conn, sess.user_id, form.0, form.1, &*AUDIO_DIR);
     result.map_err(|e| match *e.kind() {
                        ErrorKind::FormParseError => abort(400).unwrap_err(),
                        _ => abort(500).unwrap_err(),
@@ -186,11 +198,11 @@ pub fn add_quiz_post(req: &mut Request) -> PencilResult {
 }
 
 pub fn add_word_form(req: &mut Request) -> PencilResult {
-    let (_, sess) = auth_user(req, "editors")?;
+    let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
 
-    let context = new_template_context();
+    let context = new_template_context(&user_locale(&conn, sess.user_id)?);
 
-    req.app.render_template("add_word.html", &context).refresh_cookie(&sess)
+    render_template(req.app, "add_word.html", &context).refresh_cookie(&sess)
 }
 
 pub fn add_word_post(req: &mut Request) -> PencilResult {
@@ -237,30 +249,32 @@ pub fn add_word_post(req: &mut Request) -> PencilResult {
            })
     }
 
-    let (conn, sess) = auth_user(req, "editors")?;
+    let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
 
     let word = parse_form(req).map_err(|_| abort(400).unwrap_err())?;
 
-    manage::create_or_update_word(&conn, word, &*AUDIO_DIR).err_500()?;
+    manage::create_or_update_word(&conn, Some(sess.user_id), word, &*AUDIO_DIR).err_500()?;
 
     redirect("/add_word", 303).refresh_cookie(&sess)
 }
 
 pub fn add_users_form(req: &mut Request) -> PencilResult {
 
-    let (_, sess) = auth_user(req, "admins")?;
+    let (conn, sess) = auth_permission(req, role::MANAGE_USERS)?;
 
-    let context = new_template_context();
-    req.app.render_template("add_users.html", &context).refresh_cookie(&sess)
+    let context = new_template_context(&user_locale(&conn, sess.user_id)?);
+    render_template(req.app, "add_users.html", &context).refresh_cookie(&sess)
 }
 
 pub fn add_users(req: &mut Request) -> PencilResult {
     use ganbare::email;
 
-    let (conn, sess) = auth_user(req, "admins")?;
+    let (conn, sess) = auth_permission(req, role::MANAGE_USERS)?;
 
     let form = req.form();
     let emails = err_400!(form.get::<str>("emailList"), "emailList missing?");
+    let locale = form.get::<str>("locale").unwrap_or(i18n::DEFAULT_LOCALE);
+    err_400!(i18n::check_locale(locale), "No such locale: {}", locale);
     for row in emails.split('\n') {
         let mut fields = row.split_whitespace();
         let email = err_400!(fields.next(), "email field missing?");
@@ -274,8 +288,9 @@ pub fn add_users(req: &mut Request) -> PencilResult {
             email::add_pending_email_confirm(&conn,
                                              COOKIE_HMAC_KEY.as_slice(),
                                              email,
-                                             groups.as_ref()).err_500()?;
-        email::send_confirmation(&*MAIL_QUEUE,
+                                             groups.as_ref(),
+                                             locale).err_500()?;
+        email::send_confirmation(&conn,
                                  email,
                                  &secret,
                                  &hmac,
@@ -285,37 +300,38 @@ pub fn add_users(req: &mut Request) -> PencilResult {
                                      .handlebars_registry
                                      .read()
                                      .expect("The registry is basically read-only after startup."),
-                                 (&*EMAIL_ADDRESS, &*EMAIL_NAME)).err_500()?;
+                                 (&*EMAIL_ADDRESS, &*EMAIL_NAME),
+                                 locale).err_500()?;
     }
 
-    let context = new_template_context();
-    req.app.render_template("add_users.html", &context).refresh_cookie(&sess)
+    let context = new_template_context(&user_locale(&conn, sess.user_id)?);
+    render_template(req.app, "add_users.html", &context).refresh_cookie(&sess)
 }
 
 pub fn users(req: &mut Request) -> PencilResult {
-    let (_, sess) = auth_user(req, "admins")?;
+    let (conn, sess) = auth_permission(req, role::VIEW_USERS)?;
 
-    let context = new_template_context();
+    let context = new_template_context(&user_locale(&conn, sess.user_id)?);
 
-    req.app.render_template("users.html", &context).refresh_cookie(&sess)
+    render_template(req.app, "users.html", &context).refresh_cookie(&sess)
 }
 
 pub fn audio(req: &mut Request) -> PencilResult {
-    let (_, sess) = auth_user(req, "editors")?;
+    let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
 
-    let context = new_template_context();
+    let context = new_template_context(&user_locale(&conn, sess.user_id)?);
 
-    req.app.render_template("audio.html", &context).refresh_cookie(&sess)
+    render_template(req.app, "audio.html", &context).refresh_cookie(&sess)
 }
 
 pub fn send_mail_form(req: &mut Request) -> PencilResult {
     use hyper::header::Referer;
 
-    let (_, sess) = auth_user(req, "editors")?;
+    let (conn, sess) = auth_permission(req, role::SEND_MAIL)?;
 
     let sent = req.headers().get::<Referer>();
 
-    let mut context = new_template_context();
+    let mut context = new_template_context(&user_locale(&conn, sess.user_id)?);
     context.insert("sender_address", EMAIL_ADDRESS.to_string());
 
     if let Some(&Referer(ref sent)) = sent {
@@ -324,11 +340,11 @@ pub fn send_mail_form(req: &mut Request) -> PencilResult {
         }
     }
 
-    req.app.render_template("send_mail.html", &context).refresh_cookie(&sess)
+    render_template(req.app, "send_mail.html", &context).refresh_cookie(&sess)
 }
 
 pub fn send_mail_post(req: &mut Request) -> PencilResult {
-    let (conn, sess) = auth_user(req, "editors")?;
+    let (conn, sess) = auth_permission(req, role::SEND_MAIL)?;
 
     let empty_vec = vec![];
 
@@ -361,7 +377,8 @@ pub fn send_mail_post(req: &mut Request) -> PencilResult {
         }
     }
 
-    ganbare::email::send_freeform_email(&*MAIL_QUEUE,
+    ganbare::email::send_freeform_email(&conn,
+                                        sess.user_id,
                                         (&*EMAIL_ADDRESS, &*EMAIL_NAME),
                                         email_addrs.iter().map(|s| &**s),
                                         subject,
@@ -370,10 +387,37 @@ pub fn send_mail_post(req: &mut Request) -> PencilResult {
     redirect("/send_mail", 303).refresh_cookie(&sess)
 }
 
+pub fn audit_log(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_permission(req, role::VIEW_AUDIT_LOG)?;
+
+    let context = new_template_context(&user_locale(&conn, sess.user_id)?);
+
+    render_template(req.app, "audit_log.html", &context).refresh_cookie(&sess)
+}
+
+pub fn trash(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_permission(req, role::EDIT_CONTENT)?;
+
+    let mut context = new_template_context(&user_locale(&conn, sess.user_id)?);
+    context.insert("retention_days", TRASH_RETENTION.num_days().to_string());
+
+    render_template(req.app, "trash.html", &context).refresh_cookie(&sess)
+}
+
+/// Any user can create API tokens for themselves; the roles of a token are limited to their own.
+pub fn api_tokens(req: &mut Request) -> PencilResult {
+    let (conn, sess) = auth_account(req)?;
+
+    let mut context = new_template_context(&user_locale(&conn, sess.user_id)?);
+    context.insert("session_token_minutes", API_TOKEN_EXPIRE.num_minutes().to_string());
+
+    render_template(req.app, "api_tokens.html", &context).refresh_cookie(&sess)
+}
+
 pub fn events(req: &mut Request) -> PencilResult {
-    let (_, sess) = auth_user(req, "editors")?;
+    let (conn, sess) = auth_permission(req, role::MANAGE_EVENTS)?;
 
-    let context = new_template_context();
+    let context = new_template_context(&user_locale(&conn, sess.user_id)?);
 
-    req.app.render_template("events.html", &context).refresh_cookie(&sess)
+    render_template(req.app, "events.html", &context).refresh_cookie(&sess)
 }
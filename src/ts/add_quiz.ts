@@ -1,4 +1,5 @@
 /// <reference path="typings/globals/jquery/index.d.ts" />
+/// <reference path="messages.d.ts" />
 
 
 $(function(){
@@ -28,7 +29,7 @@ function add_q_audio() {
 
 	new_variant.children("input").prop("name", choice_number+"_q_variant_"+variant_number)
 		.change(change_upload_button_red);
-	new_variant.children("span").text("Kysymys (audio "+variant_number+")");
+	new_variant.children("span").text(msg("question_audio_n", variant_number));
 
 	new_variant.insertBefore(plus_button);
 }
@@ -41,7 +42,7 @@ function add_answer_fieldset() {
 
 	lowest_fieldset.prop("id", "choice_"+lowest_fieldset_number );
 	lowest_fieldset.children("label")
-		.text(lowest_fieldset_number+". vastausvaihtoehto");
+		.text(msg("answer_choice_n", lowest_fieldset_number));
 	lowest_fieldset.find(".answer_audio")
 		.prop("name", "choice_"+lowest_fieldset_number+"_answer_audio" )
 		.change(change_upload_button_red);
@@ -1,4 +1,5 @@
 /// <reference path="typings/globals/jquery/index.d.ts" />
+/// <reference path="messages.d.ts" />
 
 
 $(function(){
@@ -21,7 +22,7 @@ function add_audio() {
 
 	new_variant.children("input").prop("name", "audio_variant_"+variants)
 		.change(change_upload_button_red);
-	new_variant.children("span").text("Ääntämys "+variants);
+	new_variant.children("span").text(msg("pronunciation_n", variants));
 
 	new_variant.insertBefore(plus_button);
 }
@@ -0,0 +1,3 @@
+/** Looks up a message of the user's locale from `window.messages`, replacing
+ * the placeholders {0}, {1} etc. with the arguments. Defined in base.html. */
+declare function msg(key: string, ...args: (string | number)[]): string;
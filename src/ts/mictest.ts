@@ -1,6 +1,7 @@
 /// <reference path="typings/globals/jquery/index.d.ts" />
 /// <reference path="typings/globals/howler/index.d.ts" />
 /// <reference path="recorder.d.ts" />
+/// <reference path="messages.d.ts" />
 
 $(function() {
 
@@ -52,8 +53,8 @@ function createSemaphore(count: number) : (argument?: any) =>void {
 function connectionFailMessage(e) : void {
 	console.log("Bug?", e);
 	errorSection.show();
-	errorStatus.text("Palvelimeen ei saada yhteyttä :(");
-	setTimeout(function() { errorStatus.html("Palvelimeen ei saada yhteyttä :(<br>Kokeillaan uudestaan..."); },2000);
+	errorStatus.text(msg("no_connection"));
+	setTimeout(function() { errorStatus.html(msg("no_connection_retrying")); },2000);
 	main.addClass("errorOn");
 }
 
@@ -117,7 +118,7 @@ function startRecording(eventName: string, callback: (recording: boolean, startC
 			console.log("Recorded data is available!", ev);
 
 			if (ev.detail.length > 60000) {
-				errorMessage("Ääni oli niin pitkä että sitä ei voida lähettää :(");
+				errorMessage(msg("recording_too_long"));
 				getReadyForFirstTest();
 				setTimeout(function() { clearError() }, 3000);
 				return;
@@ -156,23 +157,23 @@ function startRecording(eventName: string, callback: (recording: boolean, startC
 			console.log("Init stream");
 
 			rec.addEventListener( "streamError", (err: ErrorEvent) => {
-				let msg = err.error.message;
-				if (msg === "" && err.error.name == "DevicesNotFoundError") {
-					msg = "Ei ole mikrofonia millä nauhoittaa!";
+				let reason = err.error.message;
+				if (reason === "" && err.error.name == "DevicesNotFoundError") {
+					reason = msg("no_microphone");
 				}
-				if (msg === "" && err.error.name == "PermissionDeniedError") {
-					msg = "Selaimesi ei anna käyttää mikrofonia! Tarkista asetuksista että valittuna on \"Salli\" tai \"Kysy\".";
+				if (reason === "" && err.error.name == "PermissionDeniedError") {
+					reason = msg("microphone_denied");
 				}
-				if (msg === "") {
-					msg = err.error.name;
+				if (reason === "") {
+					reason = err.error.name;
 				}
-				if (msg === "") {
-					msg = "En osaa näyttää, mikä virhe :(";
+				if (reason === "") {
+					reason = msg("unknown_error");
 				}
-				errorMessage("Virhe alustaessa nauhoitusta: "+msg);
+				errorMessage(msg("recording_error", reason));
 			});
 
-			errorMessage("Tarvitsemme selaimesi nauhoitusominaisuutta!<br>Ole hyvä ja myönnä lupa nauhoitukselle.");
+			errorMessage(msg("recording_permission"));
 			global_rec = rec;
 			rec.initStream();
 		} else {
@@ -194,16 +195,16 @@ function checkMic() {
 	startRecording("miccheck", (recording, start_rec, finished_rec, after_done_rec) => {
 		let errors = "";
 		if ( ! recording) {
-			errors += "Selaimesi ei tue äänen nauhoitusta!<br>"
+			errors += msg("mic_check_no_recording");
 		}
 		if ( ! Howler.codecs("opus")) {
-			errors += "Selaimesi ei tue opus-ääniformaattia!<br>"
+			errors += msg("mic_check_no_opus");
 		}
 		if ( mediaPlaybackRequiresUserGesture()) {
-			errors += "Selaimesi ei tue äänen toistamista ilman käyttäjän syötettä!<br>"
+			errors += msg("mic_check_no_autoplay");
 		}
 		if (errors !== "") {
-			errorMessage(errors+"Kokeile Firefoxia tai Chromea.<br>(työpöytä-, ei mobiiliversio)");
+			errorMessage(errors + msg("mic_check_try_desktop"));
 		}
 		start_rec();
 
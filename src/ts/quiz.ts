@@ -1,6 +1,7 @@
 /// <reference path="typings/globals/jquery/index.d.ts" />
 /// <reference path="typings/globals/howler/index.d.ts" />
 /// <reference path="recorder.d.ts" />
+/// <reference path="messages.d.ts" />
 
 $(function() {
 
@@ -174,7 +175,7 @@ function accentuate(word: string, showAccent: boolean): string {
 
 function checkRecordingSupport(): boolean {
 	if (testing && !Recorder.isRecordingSupported()) {
-		errorMessage("Selaimesi ei tue äänen nauhoitusta!<br>Kokeile Firefoxia tai Chromea.");
+		errorMessage(msg("no_recording_support"));
 		return false;
 	}
 	return true;
@@ -245,10 +246,10 @@ function startRecording(eventName: string, callback: (recording: boolean, startC
 			console.log("Init stream");
 
 			rec.addEventListener( "streamError", (err: ErrorEvent) => {
-				errorMessage("Virhe alustaessa nauhoitusta: "+err.error.message);
+				errorMessage(msg("recording_error", err.error.message));
 			});
 
-			errorMessage("Tarvitsemme selaimesi nauhoitusominaisuutta!<br>Ole hyvä ja myönnä lupa nauhoitukselle.");
+			errorMessage(msg("recording_permission"));
 			global_rec = rec;
 			rec.initStream();
 		} else {
@@ -398,8 +399,8 @@ var buttonSection = $("#buttonSection");
 function connectionFailMessage(e) : void {
 	console.log("Bug?", e);
 	errorSection.show();
-	errorStatus.text("Palvelimeen ei saada yhteyttä :(");
-	setTimeout(() => { errorStatus.html("Palvelimeen ei saada yhteyttä :(<br>Kokeillaan uudestaan..."); }, 2000);
+	errorStatus.text(msg("no_connection"));
+	setTimeout(() => { errorStatus.html(msg("no_connection_retrying")); }, 2000);
 	main.addClass("errorOn");
 }
 
@@ -486,17 +487,13 @@ function breakTime(future: FutureJson) : void {
 	}
 
 	if (dur_hours > 0) {
-		questionStatus.html("Tauon paikka!<br>Seuraava kysymys avautuu<br>"
-			+ dur_hours +" tunnin ja "+dur_minutes_remainder+" minuutin päästä");
+		questionStatus.html(msg("break_hours_minutes", dur_hours, dur_minutes_remainder));
 	} else if (dur_hours === 0 && dur_minutes_remainder > 4) {
-		questionStatus.html("Tauon paikka!<br>Seuraava kysymys avautuu<br>"
-			+ dur_minutes_remainder+" minuutin päästä");
+		questionStatus.html(msg("break_minutes", dur_minutes_remainder));
 	} else if (dur_hours === 0 && dur_minutes_remainder > 0) {
-		questionStatus.html("Tauon paikka!<br>Seuraava kysymys avautuu<br>"
-			+ dur_minutes_remainder+" minuutin ja "+ dur_seconds_remainder +" sekunnin päästä");
+		questionStatus.html(msg("break_minutes_seconds", dur_minutes_remainder, dur_seconds_remainder));
 	} else if (dur_hours === 0 && dur_minutes_remainder === 0 && dur_seconds_remainder > 0) {
-		questionStatus.html("Tauon paikka!<br>Seuraava kysymys avautuu<br>"
-			+ dur_seconds_remainder +" sekunnin päästä");
+		questionStatus.html(msg("break_seconds", dur_seconds_remainder));
 	}
 	questionSection.show();
 	questionSectionFlexContainer.show();
@@ -665,15 +662,15 @@ function answerQuestion(ansId: number, isCorrect: boolean, question: QuestionJso
 	if (!testing) {
 		if (isCorrect) {
 			mark = maru;
-			questionStatus.text("Oikein! Seuraava kysymys.");
+			questionStatus.text(msg("correct_next"));
 			correct.play();
 		} else if (ansId > 0) {
 			mark = batsu;
-			questionStatus.text("Pieleen meni, kokeile uudestaan!");
+			questionStatus.text(msg("wrong_try_again"));
 			wrong.play();
 		} else if (ansId === -1) {
 			mark = batsu;
-			questionStatus.text("Aika loppui!");
+			questionStatus.text(msg("time_up"));
 			wrong.play();
 		}
 		mark.css("top", top + "px");
@@ -681,7 +678,7 @@ function answerQuestion(ansId: number, isCorrect: boolean, question: QuestionJso
 		mark.removeClass("hidden");
 		setTimeout(function() { mark.fadeOut(normalSpeed); }, 1700);
 	} else {
-		questionStatus.text("Vastattu!");
+		questionStatus.text(msg("answered"));
 	}
 	questionStatus.show();
 	questionExplanation.hide();
@@ -695,7 +692,7 @@ function answerQuestion(ansId: number, isCorrect: boolean, question: QuestionJso
 	var timeAfterClick = testing?500:2200; // If we are in testing mode, we don't have to give so much time to reflect on the answer
 	setTimeout(function() { answerList.slideUp(normalSpeed, function() {
 		topmessage.fadeOut();
-		questionExplanation.text("Loading...");
+		questionExplanation.text(msg("loading"));
 		questionExplanation.slideDown(normalSpeed);
 		nextQuestion();
 	}); }, timeAfterClick);
@@ -766,14 +763,14 @@ function showQuestion(question: QuestionJson) {
 
 		qAudio.once('end', function() {
 			quiz_data.playbackEndedInstant = Date.now();
-			topmessage.text("Vastausaikaa 8 s");
+			topmessage.text(msg("answer_time", 8));
 			topmessage.fadeIn();
 			questionText.html(question.question);
 		
 			answerList.slideDown(normalSpeed);
-			window.setTimeout(function() { if (quiz_data.answered) {return}; topmessage.text("Vastausaikaa 3 s"); }, 5000);
-			window.setTimeout(function() { if (quiz_data.answered) {return}; topmessage.text("Vastausaikaa 2 s"); }, 6000);
-			window.setTimeout(function() { if (quiz_data.answered) {return}; topmessage.text("Vastausaikaa 1 s"); }, 7000);
+			window.setTimeout(function() { if (quiz_data.answered) {return}; topmessage.text(msg("answer_time", 3)); }, 5000);
+			window.setTimeout(function() { if (quiz_data.answered) {return}; topmessage.text(msg("answer_time", 2)); }, 6000);
+			window.setTimeout(function() { if (quiz_data.answered) {return}; topmessage.text(msg("answer_time", 1)); }, 7000);
 			window.setTimeout(function() {
 				if (quiz_data.answered) {return};
 				topmessage.fadeOut(); 
@@ -846,7 +843,7 @@ function showWord(word: WordJson) {
 function showExercise(exercise: ExerciseJson) {
 	startRecording(exercise.event_name, (recording_supported, start_recording, finished_recording, when_recording_done) => {
 		if (exercise.must_record && !recording_supported) {
-			errorMessage("Selaimesi ei tue äänen nauhoitusta!<br>Kokeile Firefoxia tai Chromea.");
+			errorMessage(msg("no_recording_support"));
 			return;
 		}
 		wordSection.show();
@@ -854,12 +851,12 @@ function showExercise(exercise: ExerciseJson) {
 		word_avatar.show();
 		word_avatar.css('opacity', '0');
 		if (recording_supported) {
-			wordStatus.html('Äännä parhaasi mukaan!<br>Äänesi nauhoitetaan.<img src="/static/images/record.png" class="recordIcon">').show();
+			wordStatus.html(msg("pronounce_recording") + '<img src="/static/images/record.png" class="recordIcon">').show();
 		} else {
-			wordStatus.html("Äännä parhaasi mukaan!<br>(Selaimesi ei tue äänen nauhoitusta).").show();
+			wordStatus.html(msg("pronounce_no_recording")).show();
 		}
 		if (testing) {
-			exerciseOkButton.text("Ok, sana äännetty!");
+			exerciseOkButton.text(msg("word_pronounced"));
 		}
 		wordShowSection.hide();
 		wordStatus.slideDown(normalSpeed, function() { word_avatar.fadeTo(normalSpeed, 1); });
@@ -894,7 +891,7 @@ function showExercise(exercise: ExerciseJson) {
 
 				setTimeout(function() {
 					audioFinishedAndUserAudioUploaded();
-					wordButtonLabel.text("Itsearvio");
+					wordButtonLabel.text(msg("self_assessment"));
 					wordButtonLabel.show();
 					exerciseFailureButton.show();
 					exerciseSuccessButton.show();
@@ -933,17 +930,17 @@ function showExercise(exercise: ExerciseJson) {
 						}
 					});
 				} else {
-					wordButtonLabel.text("Vastattu!");
+					wordButtonLabel.text(msg("answered"));
 					when_recording_done(() => { answerExercise(true, exercise, quiz_data); });
 				}
 			});
 		
-			topmessage.text("Vastausaikaa 8 s");
+			topmessage.text(msg("answer_time", 8));
 			topmessage.fadeIn();
 		
-			window.setTimeout(function() { if (quiz_data.answered) {return}; topmessage.text("Vastausaikaa 3 s"); }, 5000);
-			window.setTimeout(function() { if (quiz_data.answered) {return}; topmessage.text("Vastausaikaa 2 s"); }, 6000);
-			window.setTimeout(function() { if (quiz_data.answered) {return}; topmessage.text("Vastausaikaa 1 s"); }, 7000);
+			window.setTimeout(function() { if (quiz_data.answered) {return}; topmessage.text(msg("answer_time", 3)); }, 5000);
+			window.setTimeout(function() { if (quiz_data.answered) {return}; topmessage.text(msg("answer_time", 2)); }, 6000);
+			window.setTimeout(function() { if (quiz_data.answered) {return}; topmessage.text(msg("answer_time", 1)); }, 7000);
 			window.setTimeout(function() {
 				if (quiz_data.answered) {return};
 				finished_recording();
@@ -987,7 +984,7 @@ function showQuiz(quiz: Quiz): void {
 			console.log("No cards!");
 			questionSection.show();
 			questionSectionFlexContainer.show();
-			questionStatus.text("Ei ole mitään kysyttävää ☹️");
+			questionStatus.text(msg("nothing_to_ask"));
 			questionStatus.slideDown(normalSpeed);
 			avatar.fadeOut(superFast);
 			return;
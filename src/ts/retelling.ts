@@ -1,6 +1,7 @@
 /// <reference path="typings/globals/jquery/index.d.ts" />
 /// <reference path="typings/globals/howler/index.d.ts" />
 /// <reference path="recorder.d.ts" />
+/// <reference path="messages.d.ts" />
 
 $(function() {
 
@@ -72,8 +73,8 @@ function createSemaphore(count: number) : (callback?: ()=>void) =>void {
 function connectionFailMessage(e) : void {
 	console.log("Bug?", e);
 	errorSection.show();
-	errorStatus.text("Palvelimeen ei saada yhteyttä :(");
-	setTimeout(function() { errorStatus.html("Palvelimeen ei saada yhteyttä :(<br>Kokeillaan uudestaan..."); },2000);
+	errorStatus.text(msg("no_connection"));
+	setTimeout(function() { errorStatus.html(msg("no_connection_retrying")); },2000);
 	main.addClass("errorOn");
 }
 
@@ -93,7 +94,7 @@ function clearError() : void {
 
 function checkRecordingSupport(): boolean {
 	if (testing && !Recorder.isRecordingSupported()) {
-		errorMessage("Selaimesi ei tue äänen nauhoitusta!<br>Kokeile Firefoxia tai Chromea.");
+		errorMessage(msg("no_recording_support"));
 		return false;
 	}
 	return true;
@@ -142,7 +143,7 @@ function startRecording(eventName: string, callback: (recording: boolean, startC
 			console.log("Recorded data is available!", ev);
 
 			if (ev.detail.length > 180000) {
-				errorMessage("Ääni oli niin pitkä että sitä ei voida lähettää :(");
+				errorMessage(msg("recording_too_long"));
 				setTimeout(function() { clearError() }, 3000);
 				return;
 			}
@@ -171,10 +172,10 @@ function startRecording(eventName: string, callback: (recording: boolean, startC
 			console.log("Init stream");
 
 			rec.addEventListener( "streamError", (err: ErrorEvent) => {
-				errorMessage("Virhe alustaessa nauhoitusta: "+err.error.message);
+				errorMessage(msg("recording_error", err.error.message));
 			});
 
-			errorMessage("Tarvitsemme selaimesi nauhoitusominaisuutta!<br>Ole hyvä ja myönnä lupa nauhoitukselle.");
+			errorMessage(msg("recording_permission"));
 			global_rec = rec;
 			rec.initStream();
 		} else {
@@ -214,10 +215,10 @@ function showRetelling(retelling: Retelling) {
 	startRecording(event_name, (recording_supported, start_recording, finished_recording, when_recording_done) => {
 		answerList.hide();
 		questionText.hide();
-		questionText.html('Kerro, mitä kuvassa tapahtuu.<br>Aikaa on max 24 sekuntia.<br>Nauhoitus käynnissä.<img src="/static/images/record.png" class="recordIcon">');
+		questionText.html(msg("retelling_recording") + '<img src="/static/images/record.png" class="recordIcon">');
 		questionSectionFlexContainer.show();
 		questionSection.show();
-		questionExplanation.html("Kuuntele, mitä kuvassa tapahtuu.<br>Selitys loppuu äänimerkkiin. Selityksen loputtua<br>on sinun vuorosi kertoa kuulemasi uudestaan.<br>Äänesi nauhoitetaan.");
+		questionExplanation.html(msg("retelling_listen"));
 		avatar.show();
 		avatar.css('opacity', '0');
 		questionExplanation.slideDown(normalSpeed, function() { avatar.fadeTo(normalSpeed, 1); });
@@ -239,7 +240,7 @@ function showRetelling(retelling: Retelling) {
 		});
 
 		when_recording_done(() => {
-			questionExplanation.text("Vastattu. Seuraava kysymys!");
+			questionExplanation.text(msg("answered_next"));
 			questionExplanation.fadeIn();
 			setTimeout(() => { questionExplanation.slideUp(normalSpeed, ()=> {
 				var jqxhr = $.getJSON("/api/next_retelling?event="+event_name, showRetelling);
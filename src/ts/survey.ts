@@ -1,4 +1,5 @@
 /// <reference path="typings/globals/jquery/index.d.ts" />
+/// <reference path="messages.d.ts" />
 
 $(function() {
 
@@ -30,8 +31,28 @@ $("#settingsMenu").click(function( event ) { event.stopPropagation(); });
 
 /* menu ends */
 
+/* The texts of the survey come from the client catalog. The answers of each kind
+and the questions are numbered, e.g. "survey_answer_1_0" and "survey_question_0". */
+function msgList(prefix: string, count: number): string[] {
+	var list: string[] = [];
+	for (var i = 0; i < count; i++) {
+		list.push(msg(prefix + i));
+	}
+	return list;
+}
+
+var answerCounts = [1, 5, 5, 4, 4, 5, 4, 5, 5, 6, 6, 5];
+
+var texts = {
+	ready: msg("survey_ready"),
+	fourfold: msgList("survey_fourfold_", 8),
+	languages: msgList("survey_language_", 3),
+	answers: answerCounts.map(function(count, n) { return msgList("survey_answer_" + n + "_", count); }),
+	questions: msgList("survey_question_", 30),
+};
+
 function surveyReady() {
-	$("#questionText").text("Kiitos vastauksista!");
+	$("#questionText").text(texts.ready);
 	$('<form action="/ok" method="POST">\
 		<input type="hidden" value="survey" name="event_ok">\
 		<input type="submit" value="Ok" style="min-width: 50%; margin-top: 1em;">\
@@ -40,135 +61,47 @@ function surveyReady() {
 
 function incrementFactory() {
 
-	var answers_0 = [
-		"Aloita kysely!",
-	];
-
-	var answers_1 = [
-		'satunnaisesti; <br class="smallscreen">joitakin kertoja<br>vuodessa tai harvemmin',
-		'silloin tällöin, mutta <br class="smallscreen">joskus on viikkojen<br>tai kuukausien taukoja',
-		'viikoittain tai ainakin<br>monta kertaa kuussa',
-		'monta kertaa viikossa',
-		'päivittäin',
-	];
-
-	var answers_2 = [
-		"en ole käynyt",
-		"olen käynyt kerran",
-		"olen käynyt joitakin kertoja",
-		"käyn vähintään kerran muutamassa vuodessa",
-		"käyn kerran vuodessa tai useammin",
-	];
-
-	var answers_3 = [
-		"käyn monta tuntia viikossa",
-		"käyn kerran viikossa",
-		"käyn satunnaisesti/<wbr>lyhytkestoisesti",
-		"en tällä hetkellä",
-	];
-
-	var answers_4 = [
-		"en kiinnitä huomiota",
-		"välillä yritän kuunnella tarkkaavaisesti",
-		"kuuntelen usein tarkkaavaisesti",
-		"kiinnitän huomioni jatkuvasti ääntämiseen",
-	];
-
-	var answers_5 = [
-		"asialla ei ole minulle suurta väliä",
-		"olisi ihan kiva, mutta se ei ole prioriteettini",
-		"haluan ääntää ainakin kohtalaisen hyvin",
-		"hyvä ääntämys on minulle tärkeä asia",
-		"haluaisin kuulostaa japanilaiselta",
-	];
-
-	var answers_6 = [
-		"en ole koskaan opiskellut erityisesti ääntämistä",
-		"olen kiinnittänyt johonkin yksittäiseen asiaan huomiota jos minulle on huomautettu siitä",
-		"olen oma-aloitteisesti opiskellut ääntämistä",
-		"käytän paljon aikaa ääntämisen opiskeluun",
-	];
-
-	var answers_7 = [
-		"en ole asunut lainkaan",
-		"olen asunut kuukauden tai vähemmän",
-		"olen asunut puoli vuotta tai vähemmän",
-		"olen asunut kaksi vuotta tai vähemmän",
-		"olen asunut pidempään kuin kaksi vuotta",
-	];
-
-	var answers_8 = [
-		"en ole juurikaan puhunut japaniksi (opetustilanteiden ulkopuolella)",
-		"olen kokeillut jutustella, mutta keskustelu on tökkivää eikä siitä tule mitään",
-		"pystyn välittämään mitä haluan sanoa, vaikka usein tapahtuu kommunikaatiokatkoksia",
-		"pystyn juttelemaan kohtalaisen sujuvasti",
-		"en koe, että minulla olisi mitään suurempia ongelmia jutella japaniksi",
-	];
-
-	var answers_9 = [
-		"En ole.",
-		"Olen läpäissyt N5-tason.",
-		"Olen läpäissyt N4-tason.",
-		"Olen läpäissyt N3-tason.",
-		"Olen läpäissyt N2-tason.",
-		"Olen läpäissyt N1-tason.",
-	];
-
-	var answers_10 = [
-		"En ole.",
-		"0 - 4 kk (lukukausi) viikoittaista opiskelua",
-		"5 - 8 kk (lukuvuosi) viikoittaista opiskelua",
-		"9 - 16 kk (2 lukuvuotta) viikoittaista opiskelua",
-		"13 - 24 kk (3 lukuvuotta) viikoittaista opiskelua",
-		"25 kk tai enemmän viikoittaista opiskelua",
-	];
-
-	var answers_11 = [
-		"En ole.",
-		"Olen käynyt lyhytkestoisessa vaihdossa (max 3 kk)",
-		"Olen opiskellut 3 kk - vuoden",
-		"Olen opiskellut 1 - 3 vuotta",
-		"Olen opiskellut pidempään kuin 3 vuotta",
-	];
+	var answers = texts.answers;
 
 	var textfield = "textfield";
 	var fourfold = "fourfold";
 	var languages = "languages";
 
-	var questions = [
-
-	{q: "<p>Kysymme alkuun siitä, millä tavalla olet yleensä tekemisissä japanin kielen kanssa. Kyselyssä oletetaan että olet ainakin jossain määrin aktiivinen japanin kielen opiskelija.</p><p>Kyselyn lopussa on mahdollisuus tarkentaa vastauksia omin sanoin, ja voit aina peruuttaa ja vastata uudelleen kysymyksiin.</p><p>Valitse vaihtoehto, joka kuvaa sinua parhaiten.</p>", a: answers_0},
-	{q: "Juttelen ja/tai luen japaniksi sosiaalisessa mediassa, esim. Facebookissa, Twitterissä tai Linessä.", a: answers_1},
-	{q: "Luen japanilaisia tekstipainotteisia web-sivuja (esim. blogit, Q&A-sivustot, reseptisivustot...)", a: answers_1},
-	{q: "Katson YouTubesta ym. videopalveluista japaninkielisiä v-blogeja tai let's play -videoita.", a: answers_1},
-	{q: "Kuuntelen japanilaisia radio-ohjelmia tai podcasteja.", a: answers_1},
-	{q: "Kuuntelen japanilaista musiikkia niin että kuuntelen tai selvitän, mitä sanat tarkoittavat.", a: answers_1},
-	{q: "Puhun japania livenä japanilaisten<br/>tuttavien kanssa", a: answers_1},
-	{q: "Kuuntelen tarkkaavaisesti, miten japanilaiset ääntävät japania.", a: answers_4},
-	{q: "Katson animea ilman tekstityksiä.", a: answers_1},
-	{q: "Katson japanilaisia draamasarjoja tai näytelmäelokuvia ilman tekstityksiä.", a: answers_1},
-	{q: "Katson japanilaisia ajankohtaisohjelmia, komediaa ym. TV-ohjelmia ilman tekstityksiä.", a: answers_1},
-	{q: "Katson animea tekstitysten kanssa.", a: answers_1},
-	{q: "Katson japanilaisia draamasarjoja tai näytelmäelokuvia tekstitysten kanssa.", a: answers_1},
-	{q: "Katson japanilaisia ajankohtaisohjelmia, komediaa ym. TV-ohjelmia tekstitysten kanssa.", a: answers_1},
-	{q: "Luen mangaa japaniksi.", a: answers_1},
-	{q: "Luen japanilaisia romaaneja, nuortenkirjoja ym. proosaa japaniksi.", a: answers_1},
-	{q: "Olen asunut Japanissa yksin tai ei-japaninkielisessä kodissa (asuntola, suomalaisen puolison kanssa tms.)", a: answers_7},
-	{q: "Olen asunut Japanissa japaninkielisessä kodissa (vaihtoperhe, japanilainen puoliso tms.)", a: answers_7},
-	{q: "Olen opiskellut japanilaisessa lukiossa, yliopistossa tai muussa koulussa.", a: answers_11},
-	{q: "Olen matkustanut Japaniin.", a: answers_2},
-	{q: "Rento rupattelu japaniksi sujuu minulta.", a: answers_8},
-	{q: "Haluaisin osata ääntää japania todella hyvin.", a: answers_5},
-	{q: "Olen suorittanut JLPT-kokeen.", a: answers_9},
-	{q: "Mihin kohtaa nelikenttää sijoittaisit vahvuutesi japanin kielitaidossasi?", a: fourfold},
-	{q: "Opiskelen tällä hetkellä japania käymällä kursseilla.", a: answers_3},
-	{q: "Olen opiskellut japania elämäni varrella kursseilla. (Arvio riittää, mutta älä laske mukaan kesälomia yms. vaan varsinaiset opiskeluviikot)", a: answers_10},
-	{q: "Olen opiskellut ääntämistä.", a: answers_6},
-	{q: "Opiskelen japania jollain muulla tavalla, millä?", a: textfield},
-	{q: "Mikä on äidinkielesi?", a: languages},
-	{q: "Jos haluat tarkentaa aiempia vastauksia, sana on vapaa:", a: textfield},
+	var kinds = [
+		answers[0],
+		answers[1],
+		answers[1],
+		answers[1],
+		answers[1],
+		answers[1],
+		answers[1],
+		answers[4],
+		answers[1],
+		answers[1],
+		answers[1],
+		answers[1],
+		answers[1],
+		answers[1],
+		answers[1],
+		answers[1],
+		answers[7],
+		answers[7],
+		answers[11],
+		answers[2],
+		answers[8],
+		answers[5],
+		answers[9],
+		fourfold,
+		answers[3],
+		answers[10],
+		answers[6],
+		textfield,
+		languages,
+		textfield,
 	];
 
+	var questions = texts.questions.map(function(q, n) { return {q: q, a: kinds[n]}; });
+
 	var i = parseInt($("#answered_questions").val()) + 1 || 0;
 	var main = $("#main");
 	var surveyBox = $("#surveyBox");
@@ -271,14 +204,14 @@ function incrementFactory() {
 			var ymmarrtaminen_tuottaminen = 50;
 
 			var fourfold = $('<div style="border: 1px solid grey; position: relative; margin: auto; margin-bottom: 1em; width: 16em; height: 16em;">\
-				<span style="position: absolute; left: -14%; top: -12%;">kuuntelu</span>\
-				<span style="position: absolute; left: -14%; bottom: -12%;">puhuminen</span>\
-				<span style="position: absolute; right: -14%; top: -12%;">lukeminen</span>\
-				<span style="position: absolute; right: -14%; bottom: -12%;">kirjoittaminen</span>\
-				<span style="position: absolute; text-align: center; right: 0; left: 0; top: 0.1em;">ymmärtäminen</span>\
-				<span style="position: absolute; text-align: center; right: 0; left: 0; bottom: 0.1em;">tuottaminen</span>\
-				<span style="position: absolute; transform: translate(-7em, 7em) rotate(90deg);text-align: center; left: 0; right: 0;">suullinen</span>\
-				<span style="position: absolute; transform: translate(7em, 7em) rotate(-90deg);text-align: center; left: 0; right: 0;">kirjallinen</span>\
+				<span style="position: absolute; left: -14%; top: -12%;">'+texts.fourfold[0]+'</span>\
+				<span style="position: absolute; left: -14%; bottom: -12%;">'+texts.fourfold[1]+'</span>\
+				<span style="position: absolute; right: -14%; top: -12%;">'+texts.fourfold[2]+'</span>\
+				<span style="position: absolute; right: -14%; bottom: -12%;">'+texts.fourfold[3]+'</span>\
+				<span style="position: absolute; text-align: center; right: 0; left: 0; top: 0.1em;">'+texts.fourfold[4]+'</span>\
+				<span style="position: absolute; text-align: center; right: 0; left: 0; bottom: 0.1em;">'+texts.fourfold[5]+'</span>\
+				<span style="position: absolute; transform: translate(-7em, 7em) rotate(90deg);text-align: center; left: 0; right: 0;">'+texts.fourfold[6]+'</span>\
+				<span style="position: absolute; transform: translate(7em, 7em) rotate(-90deg);text-align: center; left: 0; right: 0;">'+texts.fourfold[7]+'</span>\
 				<span id="fourfoldSpot" style="border: 1em solid red; border-radius: 1em; position: absolute; margin: -1em; left: 50%; top: 50%;"></span>\
 				<div id="fourfoldTouchpad" style="position: absolute; left: 0; right: 0; top: 0; bottom: 0;"></div>\
 				</div>').appendTo(answerButtons);
@@ -296,9 +229,9 @@ function incrementFactory() {
 				.one('click', function() { answerQuestion(answerData, i) });
 
 		} else if (answers === "languages") {
-			$(' <input type="checkbox" id="suomi"><label for="suomi">suomi</label>\
-				<input type="checkbox" id="ruotsi"><label for="ruotsi">ruotsi</label>\
-				<label>muu, mikä?</label>\
+			$(' <input type="checkbox" id="suomi"><label for="suomi">'+texts.languages[0]+'</label>\
+				<input type="checkbox" id="ruotsi"><label for="ruotsi">'+texts.languages[1]+'</label>\
+				<label>'+texts.languages[2]+'</label>\
 				<input type="text" id="muukieli">').appendTo(answerButtons);
 
 			$('<button style="min-width: 50%; margin-top: 1em;">Ok</button>')
@@ -2,23 +2,19 @@
 
 $(function() {
 
-	$("#show_anon").change(function() {
-		groupHeader.html("<th>Users</th>");
-		usersList.html("");
-		pendingUsersList.html("");
-		userActivityRows.html("");
-		showUserTable(usersResp, $(this).is(":checked"));
-	});
-
 	var groupHeader = $("#groupHeader");
 	var usersList = $("#usersList");
 	var pendingUsersList = $("#pendingUsersList");
 	var userActivityRows = $("#userActivityRows");
+	var anonMembersList = $("#anonMembersList");
+	var roleHeader = $("#roleHeader");
+	var userRolesList = $("#userRolesList");
 
-	function showUserTable(resp, showAnon: boolean) {
+	function showUserTable(resp) {
 		var users = resp[0];
 		var groups = resp[1];
 		var pending_users = resp[2];
+		var anon_members = resp[3];
 
 		groups.forEach(function(group) {
 			var group_header = $('<th scope="col">'+group.group_name+'</th>').appendTo(groupHeader);
@@ -41,13 +37,13 @@ $(function() {
 			var user_groups = new Array();
 			groups.forEach(function(group) {
 				user_groups[group.id] = false;
-				if (group.anonymous && !showAnon) {
+				if (group.anonymous) {
 					user_groups[group.id] = null;
 				}
 			});
 
 			group_memberships.forEach(function(group_membership) {
-				if((showAnon || !group_membership.anonymous) && user_groups[group_membership.group_id] === false) {
+				if(user_groups[group_membership.group_id] === false) {
 					user_groups[group_membership.group_id] = true;
 				}
 			});
@@ -255,13 +251,67 @@ $(function() {
 			var user_list = $('<li></li>').appendTo(pendingUsersList);
 			user_list.text(pending_user.email);
 		});
+
+		anon_members.forEach(function(member) {
+			var group = groups.filter((g) => g.id === member.group_id)[0];
+			var member_item = $('<li></li>').appendTo(anonMembersList);
+			member_item.text(group.group_name+": "+member.alias);
+			member_item.click(() => list_alias_skills(member.alias));
+		});
+	}
+
+	function showRoleTable(users, resp) {
+		var roles = resp[0];
+		var user_roles = resp[1];
+
+		roles.forEach(function(r) {
+			var role = r[0];
+			var permissions = r[1];
+			$('<th scope="col"></th>').text(role.role_name).attr("title", permissions.join(", ")).appendTo(roleHeader);
+		});
+
+		users.forEach(function(u) {
+			var user = u[0];
+			if (user.email === null) {
+				return;
+			}
+			var row = $('<tr></tr>').appendTo(userRolesList);
+			$('<th scope="row"></th>').text(user.email).appendTo(row);
+
+			roles.forEach(function(r) {
+				var role = r[0];
+				var cell = $('<td></td>').appendTo(row);
+				var id = 'u'+user.id+'r'+role.id;
+				var checkbox = $('<input type="checkbox" id="'+id+'">').appendTo(cell);
+				$('<label for="'+id+'"></label>').appendTo(cell);
+				if (user_roles.some((ur) => ur.user_id === user.id && ur.role_id === role.id)) {
+					checkbox.prop('checked', 'true');
+				}
+				checkbox.change(function() {
+					var url;
+					if ($(this).prop('checked') === true) {
+						url = "/api/users/"+user.id+"?add_role="+role.id;
+					} else {
+						url = "/api/users/"+user.id+"?remove_role="+role.id;
+					}
+					$.ajax({
+						type: 'PUT',
+						url: url,
+						contentType: "application/json",
+						data: "",
+					});
+				});
+			});
+		});
 	}
 
-	let usersResp;
-	
 	$.getJSON("/api/users", (resp) => {
-		usersResp = resp;
-		showUserTable(resp, false);
+		showUserTable(resp);
+		// Only users that can manage users see the roles.
+		$.getJSON("/api/roles", (roles_resp) => {
+			$("#roles").show();
+			showRoleTable(resp[0], roles_resp);
+		});
 	});
 
 function format_date(d) {
@@ -272,7 +322,70 @@ function format_date(d) {
 }
 
 function list_user_skills(user_id: number) {
-	$.getJSON("/api/users/"+user_id+"/skills", function(skill_resp) {
+	list_skills("/api/users/"+user_id);
+	list_user_tokens(user_id);
+	list_user_sessions(user_id);
+	$("#resetTwoFactor").show().off("click").click(() => {
+		if (!confirm("Turn off two-factor authentication for this user? Do this only after checking their identity.")) {
+			return;
+		}
+		$.ajax({
+			type: "DELETE",
+			url: "/api/users/"+user_id+"/two_factor",
+			success: () => alert("Two-factor authentication was turned off."),
+			error: (xhr) => alert(xhr.status === 404 ? "The user doesn't have two-factor authentication enabled." : "Error: "+xhr.status),
+		});
+	});
+}
+
+function list_user_tokens(user_id: number) {
+	let userTokens = $("#userTokens");
+	userTokens.html("");
+	$.getJSON("/api/users/"+user_id+"/tokens", function(tokens) {
+		tokens.forEach(function(t) {
+			let token = t[0];
+			let expires = token.expires === null ? "never" : format_date(token.expires);
+			let item = $("<li></li>").text(token.name+" ("+token.kind+", roles: "+t[1].join(", ")+", expires: "+expires+") ").appendTo(userTokens);
+			let button = $('<button class="button">Revoke</button>').appendTo(item);
+			button.click(() => {
+				$.ajax({
+					type: "DELETE",
+					url: "/api/users/"+user_id+"/tokens/"+token.id,
+					success: () => list_user_tokens(user_id),
+				});
+			});
+		});
+	});
+}
+
+function list_user_sessions(user_id: number) {
+	let userSessions = $("#userSessions");
+	userSessions.html("");
+	$.getJSON("/api/users/"+user_id+"/sessions", function(sessions) {
+		sessions.forEach(function(s) {
+			let client = s.user_agent === null ? "unknown client" : s.user_agent;
+			$("<li></li>").text(client+" ("+s.ip+", last seen: "+format_date(s.last_seen)+")").appendTo(userSessions);
+		});
+		if (sessions.length === 0) {
+			return;
+		}
+		let button = $('<button class="button">Log out everywhere</button>').appendTo(userSessions);
+		button.click(() => {
+			$.ajax({
+				type: "DELETE",
+				url: "/api/users/"+user_id+"/sessions",
+				success: () => list_user_sessions(user_id),
+			});
+		});
+	});
+}
+
+function list_alias_skills(alias: string) {
+	list_skills("/api/aliases/"+alias);
+}
+
+function list_skills(user_url: string) {
+	$.getJSON(user_url+"/skills", function(skill_resp) {
 		let userDetails = $("#userDetails");
 		userDetails.html("");
 		let skills = new Array();
@@ -281,7 +394,7 @@ function list_user_skills(user_id: number) {
 			let data = s[1];
 			skills[skill.id] = {skill: skill, skill_data: data, asked: new Array()};
 		});
-		$.getJSON("/api/users/"+user_id+"/asked_items", function(asked_resp) {
+		$.getJSON(user_url+"/asked_items", function(asked_resp) {
 			console.log(asked_resp);
 			let q_data = asked_resp[0];
 			let e_data = asked_resp[1];